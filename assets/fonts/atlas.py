@@ -0,0 +1,265 @@
+#!/usr/bin/env python3
+"""Render the glyph atlas social images are drawn with, `dejavu-sans-bold.atlas`.
+
+The atlas is DejaVu Sans Bold (see LICENSE-DejaVu), pre-rendered at SIZE
+pixels per em, for Basic Latin and Latin-1. It's a zlib stream of:
+
+    b"RVNF", u8 version, u16 size, i16 ascent, i16 descent, u16 glyph count
+    per glyph: u32 code point, u16 advance (1/64 px), i16 left, i16 top,
+               u16 width, u16 height, width * height bytes of coverage
+
+All little-endian. `left` and `top` place the bitmap's top-left corner
+relative to the pen position on the baseline, with `top` going up.
+
+Usage: atlas.py [DejaVuSans-Bold.ttf] > dejavu-sans-bold.atlas
+"""
+
+import math
+import struct
+import sys
+import zlib
+
+SIZE = 96
+SUBSCANLINES = 8
+CODE_POINTS = list(range(0x20, 0x7F)) + list(range(0xA0, 0x100)) + [0x2026]
+
+
+class Font:
+    def __init__(self, data):
+        self.data = data
+        count = struct.unpack_from(">H", data, 4)[0]
+        self.tables = {}
+        for i in range(count):
+            tag, _, offset, length = struct.unpack_from(">4sIII", data, 12 + 16 * i)
+            self.tables[tag.decode()] = (offset, length)
+        head = self.tables["head"][0]
+        self.units_per_em = struct.unpack_from(">H", data, head + 18)[0]
+        self.long_loca = struct.unpack_from(">h", data, head + 50)[0] == 1
+        hhea = self.tables["hhea"][0]
+        self.ascent, self.descent = struct.unpack_from(">hh", data, hhea + 4)
+        self.metrics = struct.unpack_from(">H", data, hhea + 34)[0]
+        self.cmap = self.read_cmap()
+
+    def read_cmap(self):
+        cmap = self.tables["cmap"][0]
+        count = struct.unpack_from(">H", self.data, cmap + 2)[0]
+        for i in range(count):
+            platform, encoding, offset = struct.unpack_from(">HHI", self.data, cmap + 4 + 8 * i)
+            if (platform, encoding) == (3, 1):
+                return self.read_format_4(cmap + offset)
+        raise ValueError("No Unicode BMP cmap")
+
+    def read_format_4(self, at):
+        segments = struct.unpack_from(">H", self.data, at + 6)[0] // 2
+        ends = struct.unpack_from(f">{segments}H", self.data, at + 14)
+        starts = struct.unpack_from(f">{segments}H", self.data, at + 16 + 2 * segments)
+        deltas = struct.unpack_from(f">{segments}h", self.data, at + 16 + 4 * segments)
+        range_at = at + 16 + 6 * segments
+        ranges = struct.unpack_from(f">{segments}H", self.data, range_at)
+        glyphs = {}
+        for i in range(segments):
+            for c in range(starts[i], ends[i] + 1):
+                if c == 0xFFFF:
+                    continue
+                if ranges[i] == 0:
+                    glyphs[c] = (c + deltas[i]) & 0xFFFF
+                else:
+                    index_at = range_at + 2 * i + ranges[i] + 2 * (c - starts[i])
+                    glyph = struct.unpack_from(">H", self.data, index_at)[0]
+                    glyphs[c] = (glyph + deltas[i]) & 0xFFFF if glyph else 0
+        return glyphs
+
+    def advance(self, glyph):
+        hmtx = self.tables["hmtx"][0]
+        return struct.unpack_from(">H", self.data, hmtx + 4 * min(glyph, self.metrics - 1))[0]
+
+    def glyph_range(self, glyph):
+        loca = self.tables["loca"][0]
+        if self.long_loca:
+            start, end = struct.unpack_from(">II", self.data, loca + 4 * glyph)
+        else:
+            start, end = (2 * x for x in struct.unpack_from(">HH", self.data, loca + 2 * glyph))
+        return self.tables["glyf"][0] + start, end - start
+
+    def contours(self, glyph):
+        """The glyph's contours, as lists of (x, y, on_curve) in font units."""
+        at, length = self.glyph_range(glyph)
+        if length == 0:
+            return []
+        count = struct.unpack_from(">h", self.data, at)[0]
+        if count < 0:
+            return self.composite(at + 10)
+        ends = struct.unpack_from(f">{count}H", self.data, at + 10)
+        points = ends[-1] + 1
+        at += 10 + 2 * count
+        instructions = struct.unpack_from(">H", self.data, at)[0]
+        at += 2 + instructions
+        flags = []
+        while len(flags) < points:
+            flag = self.data[at]
+            at += 1
+            flags.append(flag)
+            if flag & 8:
+                flags.extend([flag] * self.data[at])
+                at += 1
+        coordinates = []
+        for short, same in ((2, 16), (4, 32)):
+            value, values = 0, []
+            for flag in flags:
+                if flag & short:
+                    delta = self.data[at]
+                    at += 1
+                    value += delta if flag & same else -delta
+                elif not flag & same:
+                    value += struct.unpack_from(">h", self.data, at)[0]
+                    at += 2
+                values.append(value)
+            coordinates.append(values)
+        contours, start = [], 0
+        for end in ends:
+            contours.append(
+                [(coordinates[0][i], coordinates[1][i], flags[i] & 1) for i in range(start, end + 1)]
+            )
+            start = end + 1
+        return contours
+
+    def composite(self, at):
+        contours = []
+        while True:
+            flags, glyph = struct.unpack_from(">HH", self.data, at)
+            at += 4
+            if flags & 1:
+                dx, dy = struct.unpack_from(">hh", self.data, at)
+                at += 4
+            else:
+                dx, dy = struct.unpack_from(">bb", self.data, at)
+                at += 2
+            if not flags & 2:
+                raise ValueError("Point-matched composites aren't supported")
+            xx, xy, yx, yy = 1.0, 0.0, 0.0, 1.0
+            if flags & 8:
+                xx = yy = struct.unpack_from(">h", self.data, at)[0] / 16384
+                at += 2
+            elif flags & 0x40:
+                xx, yy = (x / 16384 for x in struct.unpack_from(">hh", self.data, at))
+                at += 4
+            elif flags & 0x80:
+                xx, xy, yx, yy = (x / 16384 for x in struct.unpack_from(">hhhh", self.data, at))
+                at += 8
+            for contour in self.contours(glyph):
+                contours.append(
+                    [(x * xx + y * yx + dx, x * xy + y * yy + dy, on) for x, y, on in contour]
+                )
+            if not flags & 0x20:
+                return contours
+
+
+def flatten(contour, steps=8):
+    """The contour's quadratic curves as line segments, as a closed polygon."""
+    if not contour:
+        return []
+    # Put in the implied on-curve points between two off-curve ones.
+    points = []
+    for i, (x, y, on) in enumerate(contour):
+        px, py, pon = contour[i - 1]
+        if not on and not pon:
+            points.append(((x + px) / 2, (y + py) / 2, 1))
+        points.append((x, y, on))
+    start = next(i for i, x in enumerate(points) if x[2])
+    points = points[start:] + points[:start]
+    polygon = [points[0][:2]]
+    i = 1
+    while i <= len(points):
+        x, y, on = points[i % len(points)]
+        if on:
+            polygon.append((x, y))
+            i += 1
+        else:
+            ex, ey, _ = points[(i + 1) % len(points)]
+            sx, sy = polygon[-1]
+            for step in range(1, steps + 1):
+                t = step / steps
+                polygon.append(
+                    (
+                        (1 - t) ** 2 * sx + 2 * (1 - t) * t * x + t * t * ex,
+                        (1 - t) ** 2 * sy + 2 * (1 - t) * t * y + t * t * ey,
+                    )
+                )
+            i += 2
+    return polygon
+
+
+def rasterize(polygons, width, height):
+    """Coverage of the polygons, in pixels with y going down, by non-zero
+    winding."""
+    coverage = [0.0] * (width * height)
+    edges = []
+    for polygon in polygons:
+        for (x0, y0), (x1, y1) in zip(polygon, polygon[1:] + polygon[:1]):
+            if y0 != y1:
+                edges.append((x0, y0, x1, y1))
+    for row in range(height):
+        for sub in range(SUBSCANLINES):
+            y = row + (sub + 0.5) / SUBSCANLINES
+            crossings = []
+            for x0, y0, x1, y1 in edges:
+                if (y0 <= y < y1) or (y1 <= y < y0):
+                    x = x0 + (y - y0) * (x1 - x0) / (y1 - y0)
+                    crossings.append((x, 1 if y1 > y0 else -1))
+            crossings.sort()
+            winding = 0
+            for (x, direction), (next_x, _) in zip(crossings, crossings[1:] + [(None, 0)]):
+                winding += direction
+                if winding != 0 and next_x is not None:
+                    add_span(coverage, row * width, width, x, next_x)
+    return bytes(min(255, round(x / SUBSCANLINES * 255)) for x in coverage)
+
+
+def add_span(coverage, offset, width, start, end):
+    start, end = max(0.0, start), min(float(width), end)
+    if end <= start:
+        return
+    first, last = int(start), min(int(end), width - 1)
+    if first == last:
+        coverage[offset + first] += end - start
+        return
+    coverage[offset + first] += first + 1 - start
+    for x in range(first + 1, last):
+        coverage[offset + x] += 1
+    coverage[offset + last] += end - last
+
+
+def main():
+    path = sys.argv[1] if len(sys.argv) > 1 else "/usr/share/fonts/truetype/dejavu/DejaVuSans-Bold.ttf"
+    font = Font(open(path, "rb").read())
+    scale = SIZE / font.units_per_em
+    glyphs = []
+    for c in CODE_POINTS:
+        glyph = font.cmap.get(c, 0)
+        advance = round(font.advance(glyph) * scale * 64)
+        contours = font.contours(glyph)
+        points = [(x, y) for contour in contours for x, y, _ in contour]
+        if not points:
+            glyphs.append(struct.pack("<IHhhHH", c, advance, 0, 0, 0, 0))
+            continue
+        left = math.floor(min(x for x, _ in points) * scale) - 1
+        top = math.ceil(max(y for _, y in points) * scale) + 1
+        width = math.ceil(max(x for x, _ in points) * scale) + 1 - left
+        height = top - math.floor(min(y for _, y in points) * scale) + 1
+        polygons = [[(x * scale - left, top - y * scale) for x, y in flatten(c)] for c in contours]
+        bitmap = rasterize(polygons, width, height)
+        glyphs.append(struct.pack("<IHhhHH", c, advance, left, top, width, height) + bitmap)
+    header = struct.pack(
+        "<4sBHhhH",
+        b"RVNF",
+        1,
+        SIZE,
+        round(font.ascent * scale),
+        round(font.descent * scale),
+        len(glyphs),
+    )
+    sys.stdout.buffer.write(zlib.compress(header + b"".join(glyphs), 9))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,57 @@
+// Make the panels of `tabs` shortcodes switchable, and keep tabs in the same
+// sync group on the same title. Without it every panel is shown.
+(() => {
+    const storagePrefix = "raven-tabs-";
+    const tabsOf = (container) => [...container.querySelectorAll(":scope > .raven-tablist > [role=tab]")];
+
+    // Show the panel of `tab` and hide the others in its block.
+    function select(tab)
+    {
+        for (const other of tabsOf(tab.closest(".raven-tabs"))) {
+            const selected = other === tab;
+            other.setAttribute("aria-selected", selected);
+            other.tabIndex = selected ? 0 : -1;
+            document.getElementById(other.getAttribute("aria-controls")).hidden = !selected;
+        }
+    }
+
+    // Select `tab`, and the tabs with its title in the same sync group.
+    function pick(tab)
+    {
+        const group = tab.closest(".raven-tabs").dataset.ravenSync;
+        if (!group) {
+            select(tab);
+            return;
+        }
+
+        localStorage.setItem(storagePrefix + group, tab.dataset.ravenTab);
+        const selector = `.raven-tabs[data-raven-sync="${group}"] > .raven-tablist > [role=tab]`;
+        for (const other of document.querySelectorAll(selector)) {
+            if (other.dataset.ravenTab === tab.dataset.ravenTab) {
+                select(other);
+            }
+        }
+    }
+
+    for (const container of document.querySelectorAll(".raven-tabs")) {
+        const tabs = tabsOf(container);
+        if (tabs.length === 0) {
+            continue;
+        }
+        const group = container.dataset.ravenSync;
+        const saved = group && localStorage.getItem(storagePrefix + group);
+        select(tabs.find((tab) => tab.dataset.ravenTab === saved) || tabs[0]);
+
+        tabs.forEach((tab, i) => {
+            tab.addEventListener("click", () => pick(tab));
+            tab.addEventListener("keydown", (e) => {
+                const step = { ArrowRight: 1, ArrowLeft: -1 }[e.key];
+                if (step) {
+                    const next = tabs[(i + step + tabs.length) % tabs.length];
+                    next.focus();
+                    pick(next);
+                }
+            });
+        });
+    }
+})();
@@ -2,8 +2,7 @@ use std::{path::PathBuf, sync::Arc, time::Duration};
 
 // This is a struct that tells Criterion.rs to use the "futures" crate's current-thread executor
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use dashmap::DashMap;
-use rustic_raven::{build::Website, defaults, Config};
+use rustic_raven::{asset_cache::AssetCache, build::Website, defaults, Config};
 use syntect::{highlighting, parsing::SyntaxSet};
 
 fn benchmark_parse_markdown(c: &mut Criterion)
@@ -13,7 +12,7 @@ fn benchmark_parse_markdown(c: &mut Criterion)
         .themes
         .remove(&config.syntax_theme)
         .unwrap();
-    let assets: Arc<DashMap<PathBuf, String>> = Arc::new(DashMap::new());
+    let assets = Arc::new(AssetCache::new());
     let site = Website::new(config, SyntaxSet::load_defaults_newlines(), assets, theme);
     let markdown = DEFAULT_MD_BENCHMARK_SRC;
     let mut group = c.benchmark_group("throughput");
@@ -37,7 +36,7 @@ fn benchmark_integrate_html_into_template(c: &mut Criterion)
         .themes
         .remove(&config.syntax_theme)
         .unwrap();
-    let assets: Arc<DashMap<PathBuf, String>> = Arc::new(DashMap::new());
+    let assets = Arc::new(AssetCache::new());
     let site = Website::new(config.clone(), SyntaxSet::load_defaults_newlines(), assets, theme);
     let markdown = DEFAULT_MD_BENCHMARK_SRC;
     let (html, page_info) = site.parse_markdown(black_box(markdown), PathBuf::new()).unwrap();
@@ -0,0 +1,140 @@
+//! Callouts: GitHub's `> [!NOTE]` blockquotes and `:::warning` containers,
+//! rendered as `<div class="admonition note">` blocks.
+//!
+//! A blockquote whose first line is `[!KIND]` becomes a callout of that kind,
+//! titled after it, or after the text following it on the line. A `:::kind`
+//! line opens a container callout, optionally followed by its title, and a
+//! `:::` line closes it. Containers nest, and callouts in fenced code blocks
+//! are left alone.
+
+/// The component whose CSS callouts use, and the class they have.
+pub(crate) const COMPONENT: &str = "admonition";
+
+const CONTAINER: &str = ":::";
+
+/// The end of a callout. The blank line after it ends the HTML block.
+const CLOSE_TAG: &str = "\n</div>\n\n";
+
+/// Expand every callout in the markdown `source`, or `None` if it has none.
+pub(crate) fn expand(source: &str) -> Option<String>
+{
+    if !source.contains("[!") && !source.contains(CONTAINER) {
+        return None;
+    }
+
+    let mut output = String::with_capacity(source.len());
+    let mut expanded = false;
+    let mut open = 0;
+    let mut fences = Fences::default();
+    let mut lines = source.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        if fences.contains(line) {
+            output.push_str(line);
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if let Some((kind, title)) = quote_kind(line) {
+            let mut body = String::new();
+            while let Some(line) = lines.next_if(|x| unquote(x).is_some()) {
+                body.push_str(unquote(line).unwrap_or_default());
+            }
+            let body = expand(&body).unwrap_or(body);
+            output.push_str(&open_tag(kind, title));
+            output.push_str(&body);
+            output.push_str(CLOSE_TAG);
+            expanded = true;
+        }
+        else if trimmed == CONTAINER && open > 0 {
+            output.push_str(CLOSE_TAG);
+            open -= 1;
+        }
+        else if let Some((kind, title)) = container_kind(trimmed) {
+            output.push_str(&open_tag(kind, title));
+            open += 1;
+            expanded = true;
+        }
+        else {
+            output.push_str(line);
+        }
+    }
+    for _ in 0..open {
+        output.push_str(CLOSE_TAG);
+    }
+    expanded.then_some(output)
+}
+
+/// The opening of a callout. The blank line after it lets the body be parsed
+/// as markdown.
+fn open_tag(kind: &str, title: &str) -> String
+{
+    let kind = kind
+        .to_lowercase()
+        .chars()
+        .filter(|x| x.is_alphanumeric() || *x == '-')
+        .collect::<String>();
+    let title = match title.trim() {
+        "" => {
+            let mut chars = kind.chars();
+            chars
+                .next()
+                .map(|x| x.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        title => title.to_string(),
+    };
+    format!(
+        "\n<div class=\"{} {kind}\">\n<p class=\"{}-title\">{}</p>\n\n",
+        COMPONENT,
+        COMPONENT,
+        htmlescape::encode_minimal(&title)
+    )
+}
+
+/// The kind and title of a `> [!KIND] title` line.
+fn quote_kind(line: &str) -> Option<(&str, &str)>
+{
+    let rest = unquote(line)?.trim().strip_prefix("[!")?;
+    let (kind, title) = rest.split_once(']')?;
+    (!kind.is_empty() && kind.chars().all(char::is_alphabetic)).then_some((kind, title))
+}
+
+/// The kind and title of a `:::kind title` line.
+fn container_kind(trimmed: &str) -> Option<(&str, &str)>
+{
+    let rest = trimmed.strip_prefix(CONTAINER)?.trim_start();
+    let (kind, title) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    (!kind.is_empty() && kind.chars().all(|x| x.is_alphanumeric() || x == '-')).then_some((kind, title))
+}
+
+/// A blockquote line without its `>` and the space after it.
+fn unquote(line: &str) -> Option<&str>
+{
+    let rest = line.trim_start().strip_prefix('>')?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that both callout syntaxes expand, nest, and are left alone in
+    /// code blocks
+    fn test_expand()
+    {
+        assert_eq!(expand("> Just a quote\n"), None);
+        assert_eq!(
+            expand("> [!NOTE]\n> Read *this*.\n\nAfter\n").unwrap(),
+            "\n<div class=\"admonition note\">\n<p class=\"admonition-title\">Note</p>\n\nRead \
+             *this*.\n\n</div>\n\n\nAfter\n"
+        );
+        assert_eq!(
+            expand(":::warning Hot & sharp\n:::tip\nA\n:::\n:::\n```\n:::note\n```\n").unwrap(),
+            "\n<div class=\"admonition warning\">\n<p class=\"admonition-title\">Hot &amp; sharp</p>\n\n\n<div \
+             class=\"admonition tip\">\n<p \
+             class=\"admonition-title\">Tip</p>\n\nA\n\n</div>\n\n\n</div>\n\n```\n:::note\n```\n"
+        );
+    }
+}
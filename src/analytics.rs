@@ -0,0 +1,140 @@
+//! The `[analytics]` tracking code, put in every page before `</head>`.
+//!
+//! The code is either a known provider's, for the configured site ID, or a
+//! snippet file of the project's. `enabled = false` turns it off, and it's
+//! left out with the `dev` profile unless `dev = true`.
+
+use crate::{placeholders::escape_attribute, Analytics, AnalyticsProvider, Config, Error, PathBuf, Result};
+
+/// The tracking code to put in every page, or `None` if there isn't any, or
+/// it's turned off.
+///
+/// # Errors
+///
+/// Will return an error if the snippet file cannot be read, or a provider is
+/// given without a site ID.
+pub fn snippet(config: &Config) -> Result<Option<String>>
+{
+    let Some(analytics) = enabled(config)
+    else {
+        return Ok(None);
+    };
+
+    if let Some(snippet) = &analytics.snippet {
+        let path = config.resolve(snippet);
+        return std::fs::read_to_string(&path)
+            .map(Some)
+            .map_err(|e| Error::Io { err: e, path });
+    }
+    match (analytics.provider, &analytics.site_id) {
+        (Some(provider), Some(site_id)) => Ok(Some(provider_snippet(provider, site_id))),
+        (Some(_), None) => {
+            Err(Error::AnalyticsConfig(String::from(
+                "`analytics.provider` needs an `analytics.site_id`",
+            )))
+        }
+        (None, _) => {
+            Err(Error::AnalyticsConfig(String::from(
+                "Either `analytics.provider` or `analytics.snippet` is needed",
+            )))
+        }
+    }
+}
+
+/// The snippet file the tracking code is read from, if it's used.
+pub fn snippet_file(config: &Config) -> Option<PathBuf>
+{
+    enabled(config)?.snippet.as_deref().map(|x| config.resolve(x))
+}
+
+/// The `[analytics]` table, if tracking code is put in pages.
+fn enabled(config: &Config) -> Option<&Analytics>
+{
+    let analytics = config.analytics.as_ref()?;
+    let dev = config.profile.as_deref() == Some("dev");
+    (analytics.enabled.unwrap_or(true) && (!dev || analytics.dev.unwrap_or(false))).then_some(analytics)
+}
+
+/// The tracking code of `provider` for the site `site_id`.
+fn provider_snippet(provider: AnalyticsProvider, site_id: &str) -> String
+{
+    let id = escape_attribute(site_id);
+    match provider {
+        AnalyticsProvider::Plausible => {
+            format!("<script defer data-domain=\"{id}\" src=\"https://plausible.io/js/script.js\"></script>")
+        }
+        AnalyticsProvider::Fathom => {
+            format!("<script defer data-site=\"{id}\" src=\"https://cdn.usefathom.com/script.js\"></script>")
+        }
+        AnalyticsProvider::GoatCounter => {
+            format!(
+                "<script async data-goatcounter=\"https://{id}.goatcounter.com/count\" \
+                 src=\"https://gc.zgo.at/count.js\"></script>"
+            )
+        }
+        AnalyticsProvider::Google => {
+            let id = site_id.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "");
+            format!(
+                "<script async src=\"https://www.googletagmanager.com/gtag/js?id={id}\"></script><script>\
+                 window.dataLayer=window.dataLayer||[];function gtag(){{dataLayer.push(arguments);}}\
+                 gtag('js',new Date());gtag('config','{id}');</script>"
+            )
+        }
+    }
+}
+
+/// Put `snippet` in `html` before its `</head>`, or its `</body>` if it has
+/// no head, or at the end.
+pub fn inject(html: String, snippet: &str) -> String
+{
+    let lowercase = html.to_ascii_lowercase();
+    let at = lowercase
+        .find("</head>")
+        .or_else(|| lowercase.rfind("</body>"))
+        .unwrap_or(html.len());
+    let mut html = html;
+    html.insert_str(at, snippet);
+    html
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that the provider's code is used, it's left out with the dev
+    /// profile, and it goes in the head
+    fn test_analytics()
+    {
+        let analytics = Analytics {
+            enabled:  None,
+            provider: Some(AnalyticsProvider::Plausible),
+            site_id:  Some(String::from("example.com")),
+            snippet:  None,
+            dev:      None,
+        };
+        let mut config = Config {
+            analytics: Some(analytics),
+            ..Config::default()
+        };
+        let code = snippet(&config).unwrap().unwrap();
+        assert!(code.contains("data-domain=\"example.com\""));
+        assert_eq!(
+            inject(
+                String::from("<html><HEAD><title>A</title></HEAD><body></body></html>"),
+                "<x>"
+            ),
+            "<html><HEAD><title>A</title><x></HEAD><body></body></html>"
+        );
+        assert_eq!(inject(String::from("<p>A</p>"), "<x>"), "<p>A</p><x>");
+
+        assert_eq!(snippet_file(&config), None);
+
+        config.profile = Some(String::from("dev"));
+        assert_eq!(snippet(&config).unwrap(), None);
+        config.analytics.as_mut().unwrap().site_id = None;
+        config.analytics.as_mut().unwrap().dev = Some(true);
+        assert!(snippet(&config).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! The text-based assets a site has loaded into memory, such as templates,
+//! inlined stylesheets, and favicons, so they're only read once per build.
+//!
+//! Each asset is kept with the modification time and size its file had when
+//! it was cached, and dropped when they change, so a [`Website`] that's kept
+//! around picks up edits to its assets.
+//!
+//! [`Website`]: crate::build::Website
+
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+
+use crate::{Path, PathBuf};
+
+/// What a cached asset's file looked like, to tell if it has changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp
+{
+    modified: Option<SystemTime>,
+    len:      u64,
+}
+
+impl Stamp
+{
+    /// The stamp of the file at `path`, or `None` if there isn't one.
+    fn of(path: &Path) -> Option<Self>
+    {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len:      metadata.len(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Asset
+{
+    stamp:    Option<Stamp>,
+    contents: String,
+}
+
+/// Assets keyed by their canonical path.
+///
+/// We use a [`DashMap`] over a `Mutex<HashMap>` for finer-grained locking.
+#[derive(Debug, Default)]
+pub struct AssetCache
+{
+    assets: DashMap<PathBuf, Asset>,
+}
+
+impl AssetCache
+{
+    pub fn new() -> Self { Self::default() }
+
+    /// The cached asset at `path`, unless its file has changed since it was
+    /// cached.
+    pub fn get(&self, path: &Path) -> Option<String>
+    {
+        let asset = self.assets.get(path)?;
+        if asset.stamp == Stamp::of(path) {
+            return Some(asset.contents.clone());
+        }
+        drop(asset);
+        self.assets.remove(path);
+        None
+    }
+
+    /// Cache `contents` as the asset at `path`, as its file is now.
+    pub fn insert(&self, path: PathBuf, contents: String)
+    {
+        let stamp = Stamp::of(&path);
+        self.assets.insert(path, Asset { stamp, contents });
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that an asset is dropped once its file changes
+    fn test_revalidate()
+    {
+        let path = std::env::temp_dir().join(format!("raven-asset-cache-{}.css", std::process::id()));
+        std::fs::write(&path, "a {}").unwrap();
+        let cache = AssetCache::new();
+        cache.insert(path.clone(), String::from("<style>a{}</style>"));
+        assert_eq!(cache.get(&path).as_deref(), Some("<style>a{}</style>"));
+
+        std::fs::write(&path, "a { color: red }").unwrap();
+        assert_eq!(cache.get(&path), None);
+        std::fs::remove_file(&path).unwrap();
+
+        // A missing file stays missing.
+        cache.insert(path.clone(), String::new());
+        assert_eq!(cache.get(&path).as_deref(), Some(""));
+    }
+}
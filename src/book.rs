@@ -0,0 +1,206 @@
+//! Books: sections whose pages are also built into one long page, like
+//! mdBook's print page.
+//!
+//! Every chapter keeps its own page, and the combined page has each chapter
+//! in a `<section>` with the chapter's slug as its ID, after a table of
+//! contents. IDs in a chapter are prefixed with its slug (`install--usage`)
+//! so they stay unique, and links between chapters point into the combined
+//! page.
+
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use regex::{Captures, Regex};
+
+use crate::{
+    build::{is_included, walk_directory, Website},
+    markdown::slugify,
+    mdbook::{normalize, Summary},
+    report::Warnings,
+    sections, Book, Config, Error, PageInfo, Path, PathBuf, Result,
+};
+
+/// The book's chapters, relative to the project, in order.
+///
+/// # Errors
+///
+/// Will return an error if the book's summary cannot be read.
+pub fn chapters(config: &Config, book: &Book) -> Result<Vec<PathBuf>>
+{
+    let section = config.source.join(&book.section);
+    let chapters = if let Some(chapters) = &book.chapters {
+        chapters.iter().map(|x| normalize(&section.join(x))).collect()
+    }
+    else if let Some(summary) = Summary::of_book(config, book)? {
+        summary
+            .chapters()
+            .map(|(_, path)| normalize(&section.join(path)))
+            .collect()
+    }
+    else {
+        // Unreadable entries are warned about when the source files are walked.
+        let mut chapters = walk_directory(config, &section, &Warnings::new(false))
+            .into_iter()
+            .filter(|(_, extention)| extention == "md" || extention == "markdown")
+            .map(|(path, _)| normalize(&path))
+            .collect::<Vec<_>>();
+        chapters.sort();
+        chapters
+    };
+    Ok(chapters
+        .into_iter()
+        .filter(|x| is_included(config, sections::scan_page_info(config, x).as_ref()))
+        .collect::<Vec<_>>())
+}
+
+/// Where the book's combined page is written.
+pub fn dest(config: &Config, book: &Book) -> PathBuf
+{
+    match &book.output {
+        Some(output) => config.dest.join(output),
+        None => config.dest.join(&book.section).join("print.html"),
+    }
+}
+
+/// Render the book's chapters into its combined page.
+///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - The book's summary, or a chapter, cannot be read or parsed
+/// - The book's template, favicon, or stylesheet cannot be read
+pub async fn render(site: &Website, book: &Book) -> Result<String>
+{
+    let config = site.config();
+    let section = config.source.join(&book.section);
+    let chapters = chapters(config, book)?;
+    let slugs = chapters
+        .iter()
+        .map(|path| (path.clone(), chapter_slug(&section, path)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut toc = String::from("<nav class=\"raven-book-toc\"><ol>");
+    let mut body = String::new();
+    for path in &chapters {
+        let source = tokio::fs::read_to_string(path).await.map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: path.clone(),
+            }
+        })?;
+        let (html, page_info) = site.parse_markdown(&source, path.clone())?;
+        let slug = &slugs[path];
+        let html = prefix_ids(&html, path, &slugs);
+
+        toc.push_str(&format!(
+            "<li><a href=\"#{slug}\">{}</a>",
+            htmlescape::encode_minimal(&page_info.title)
+        ));
+        let headings = section_headings(&html);
+        if !headings.is_empty() {
+            toc.push_str("<ol>");
+            for (id, text) in headings {
+                toc.push_str(&format!("<li><a href=\"#{id}\">{text}</a></li>"));
+            }
+            toc.push_str("</ol>");
+        }
+        toc.push_str("</li>");
+        body.push_str(&format!(
+            "<section class=\"raven-chapter\" id=\"{slug}\">{html}</section>\n"
+        ));
+    }
+    toc.push_str("</ol></nav>\n");
+
+    let page_info = PageInfo {
+        template: Some(site.format_template(book.template.as_ref().unwrap_or(&config.default.template), "print")),
+        ..PageInfo::new(book.title.clone(), book.description.clone().unwrap_or_default())
+    };
+    let html = site
+        .integrate_html_into_template(page_info, section.clone(), toc + &body)
+        .await?;
+    Ok(site.post_process(&section, html))
+}
+
+/// The ID of a chapter's section: its path in the book, slugified.
+pub(crate) fn chapter_slug(section: &Path, path: &Path) -> String
+{
+    let relative = path.strip_prefix(section).unwrap_or(path).with_extension("");
+    slugify(&relative.to_string_lossy().replace(['/', '\\'], "-"))
+}
+
+/// Prefix the IDs in a chapter's HTML, and the links to them, with the
+/// chapter's slug. Links to other chapters are pointed at their sections.
+pub(crate) fn prefix_ids(html: &str, chapter: &Path, slugs: &BTreeMap<PathBuf, String>) -> String
+{
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    let attribute = ATTRIBUTE.get_or_init(|| Regex::new(r#"(\s)(id|href)="([^"]*)""#).unwrap());
+    let slug = &slugs[chapter];
+    let directory = chapter.parent().unwrap_or(Path::new(""));
+
+    attribute
+        .replace_all(html, |captures: &Captures| {
+            let (space, name, value) = (&captures[1], &captures[2], &captures[3]);
+            if name == "id" {
+                return format!("{space}id=\"{slug}--{value}\"");
+            }
+            if let Some(fragment) = value.strip_prefix('#') {
+                return format!("{space}href=\"#{slug}--{fragment}\"");
+            }
+            let (target, fragment) = value.split_once('#').unwrap_or((value, ""));
+            let linked = target.strip_suffix(".html").and_then(|stem| {
+                ["md", "markdown"]
+                    .iter()
+                    .find_map(|extention| slugs.get(&normalize(&directory.join(format!("{stem}.{extention}")))))
+            });
+            match linked {
+                Some(linked) if fragment.is_empty() => format!("{space}href=\"#{linked}\""),
+                Some(linked) => format!("{space}href=\"#{linked}--{fragment}\""),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// The IDs and contents of the `<h2>` headings in a chapter's HTML.
+pub(crate) fn section_headings(html: &str) -> Vec<(String, String)>
+{
+    static HEADING: OnceLock<Regex> = OnceLock::new();
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let heading = HEADING.get_or_init(|| Regex::new(r#"<h2 id="([^"]*)"[^>]*>(.*?)</h2>"#).unwrap());
+    let tag = TAG.get_or_init(|| Regex::new(r"<[^>]+>").unwrap());
+    heading
+        .captures_iter(html)
+        .map(|x| (x[1].to_string(), tag.replace_all(&x[2], "").into_owned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that IDs, fragment links, and links between chapters are
+    /// rewritten to point into the combined page
+    fn test_prefix_ids()
+    {
+        let slugs = BTreeMap::from([
+            (PathBuf::from("src/book/intro.md"), String::from("intro")),
+            (
+                PathBuf::from("src/book/setup/install.md"),
+                String::from("setup-install"),
+            ),
+        ]);
+        let html = "<h2 id=\"usage\">Usage</h2><a href=\"#usage\">a</a><a href=\"setup/install.html#linux\">b</a>\
+                    <a href=\"https://example.com\" data-id=\"x\">c</a>";
+        assert_eq!(
+            prefix_ids(html, Path::new("src/book/intro.md"), &slugs),
+            "<h2 id=\"intro--usage\">Usage</h2><a href=\"#intro--usage\">a</a><a \
+             href=\"#setup-install--linux\">b</a><a href=\"https://example.com\" data-id=\"x\">c</a>"
+        );
+        assert_eq!(
+            section_headings("<h2 id=\"intro--usage\">Using <code>raven</code></h2>"),
+            [(String::from("intro--usage"), String::from("Using raven"))]
+        );
+    }
+}
@@ -1,23 +1,43 @@
-use std::{borrow::Cow, ffi::OsString, sync::Arc};
-
-use chrono::{DateTime, Local};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    sync::Arc,
+    time::Instant,
+};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveTime};
 use dashmap::DashMap;
 use gh_emoji::Replacer;
 use indicatif::ProgressStyle;
 use pulldown_cmark::{CodeBlockKind, Event};
 use syntect::{highlighting, parsing::SyntaxSet};
-use tokio::fs;
+use tokio::{fs, runtime::RuntimeFlavor, sync::Semaphore, task::JoinSet};
+use toml::value::Datetime;
 use walkdir::WalkDir;
 
-use crate::{Config, Error, PageInfo, Path, PathBuf, Result};
-
-const TEMPLATE_NAME_BODY: &str = "[/rustic_body/]";
-const TEMPLATE_NAME_TITLE: &str = "[/rustic_title/]";
-const TEMPLATE_NAME_DESC: &str = "[/rustic_description/]";
-const TEMPLATE_NAME_FAVICON: &str = "[/rustic_favicon/]";
-const TEMPLATE_NAME_STYLESHEET: &str = "[/rustic_stylesheet/]";
-const TEMPLATE_NAME_SITENAME: &str = "[/rustic_name/]";
-const TEMPLATE_NAME_AUTHORS: &str = "[/rustic_authors/]";
+use crate::{
+    analytics,
+    asset_cache::AssetCache,
+    book,
+    cancel::Cancellation,
+    cms, defaults, deflists, epub, extensions, feeds,
+    graph::PageDependencies,
+    hash, headless,
+    hooks::{self, HookContext, Hooks},
+    ignore::Ignore,
+    includes, inheritance,
+    links::{self, PageIndex},
+    lock, markdown, mdbook, mime, minify, offline, og,
+    placeholders::{PlaceholderContext, PlaceholderProvider, Placeholders, TOKEN_PREFIX, TOKEN_SUFFIX},
+    redirects,
+    report::{self, BuildReport, PageReport, PageStatus, Warning, Warnings},
+    sass, sections, shortcodes, shorturls, space, styles, syntax_cache,
+    timings::{Recorder, Stage},
+    variables,
+    wikilinks::WikiIndex,
+    Book, Config, Error, HeadExtra, PageInfo, Path, PathBuf, Result, Symlinks,
+};
 
 #[inline]
 async fn read_to_base64_string(path: PathBuf) -> Result<String>
@@ -38,25 +58,87 @@ async fn read_to_base64_string(path: PathBuf) -> Result<String>
 ///
 /// - There are no source files
 /// - Progress bar initialization fails
+/// - A page fails to build
 ///
 /// # Panics
 ///
 /// Will panic if:
 ///
-/// - Markdown to html conversion fails
 /// - Couldn't join a thread
 pub async fn build(site: Website, rebuild_all: bool) -> Result<()>
+{
+    let report = build_with_report(site, rebuild_all).await?;
+    match report.failed {
+        0 => Ok(()),
+        failed => Err(Error::BuildFailed(failed)),
+    }
+}
+
+/// Build like [`build`], and report what was done with each source file.
+///
+/// A page that fails to build doesn't stop the others, it's reported as
+/// failed and its error is printed once the build is done. With
+/// `generation.fail_fast`, the first failure cancels the build and is
+/// returned instead. So does [cancelling](Website::cancellation) it, e.g. on
+/// Ctrl-C, with [`Error::Cancelled`]: the pages still building are stopped
+/// before they write anything.
+///
+/// The `hooks.pre_build` command runs first, `hooks.post_page` after each
+/// page is written, and `hooks.post_build` last, if no page failed.
+///
+/// # Errors
+///
+/// Will return errors if:
+///
+/// - There are no source files
+/// - The output wouldn't fit on the dest dir's volume, or is over
+///   `generation.max_output_size`
+/// - Progress bar initialization fails
+/// - The `pre_build` or `post_build` hook fails
+/// - A page fails with `generation.fail_fast`, or the build is cancelled
+pub async fn build_with_report(site: Website, rebuild_all: bool) -> Result<BuildReport>
 {
     use indicatif::ProgressBar;
+    let started = Instant::now();
+    let _lock = lock::acquire(Path::new(lock::LOCK_FILE), site.config.wait(), &site.cancellation).await?;
+    let mut site = site;
+    if let Some(line) = site.config.hooks.as_ref().and_then(|hooks| hooks.pre_build.as_deref()) {
+        hooks::run_command("pre_build", line, &site.config, Vec::new()).await?;
+    }
+    for (path, markdown) in cms::pages(&site.config)? {
+        site.add_page(path, markdown);
+    }
+    let walk_started = Instant::now();
+    let mut sources = walk_sources(&site.config, &site.warnings);
+    site.page_infos = scan_page_infos(&site.config, &sources);
+    for (path, markdown) in &site.virtual_pages {
+        let page_info = sections::scan_source_page_info(&site.config, path, markdown);
+        site.page_infos.insert(path.clone(), page_info);
+        sources.push((path.clone(), String::from("md")));
+    }
+    let source_file_dir = sources
+        .into_iter()
+        .filter(|(source_file, _)| is_included(&site.config, site.page_infos.get(source_file).and_then(Option::as_ref)))
+        .collect::<Vec<_>>();
+    let walk = walk_started.elapsed();
+    let source_file_count = source_file_dir.len();
+    let scheduled = scheduled_count(&site.config, &site.page_infos);
     let site = Arc::new(site);
     let config = &site.config;
-    let source_file_dir = walk_directory(&config.source);
-    let source_file_count = source_file_dir.len();
+    let command_hooks = config.hooks.as_ref();
+    if scheduled > 0 {
+        eprintln!(
+            "[{}] Left out {scheduled} page(s) dated in the future, build with `--future` to include them",
+            crate::NAME
+        );
+    }
 
     // If there's no source files we exit with an error
     if source_file_count == 0 {
         return Err(Error::MissingSourceFiles(config.source.clone()));
     }
+    space::preflight(config, &site.page_infos, &source_file_dir)?;
+    remove_partial_outputs(config);
 
     let pb = ProgressBar::new(source_file_count as u64);
     pb.set_style(
@@ -66,103 +148,708 @@ pub async fn build(site: Website, rebuild_all: bool) -> Result<()>
             .progress_chars("#>-"),
     );
 
-    // Create a task for each
-    let builds = source_file_dir
-        .into_iter()
-        .map(|source_file| {
-            let site = site.clone(); // Clone the Arc
-            let pb = pb.clone();
-            tokio::spawn(async move {
-                Error::unwrap_gracefully(
-                    site.make_html_from_md(source_file, pb.clone(), rebuild_all)
-                        .await
-                        .map_err(|e| {
-                            pb.set_message("Failed");
-                            e
-                        }),
-                );
-            })
-        })
-        .collect::<Vec<_>>();
+    // Create a task for each, but only let `jobs` of them work at once.
+    let semaphore = Arc::new(Semaphore::new(config.jobs()));
+    let mut builds = JoinSet::new();
+    for (index, source_file) in source_file_dir.into_iter().enumerate() {
+        let site = site.clone(); // Clone the Arc
+        let pb = pb.clone();
+        let semaphore = semaphore.clone();
+        builds.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.unwrap();
+            build_page(&site, source_file, pb, rebuild_all)
+                .await
+                .map(|page| (index, page))
+        });
+    }
+
+    if styles::linked(config) {
+        write_components_stylesheet(config).await?;
+    }
 
     pb.set_message("Generating ...");
-    // Wait for builds to finish
-    for build in builds {
-        build.await.unwrap();
+    // Wait for builds to finish, cancelling the rest at the first fatal error.
+    let mut pages = Vec::with_capacity(builds.len());
+    while let Some(build) = builds.join_next().await {
+        // A page's task only fails if it panicked.
+        let build = build.unwrap_or_else(|e| Err(Error::PageTask(e.to_string())));
+        match build {
+            Ok(page) => pages.push(page),
+            Err(e) => {
+                site.cancellation.cancel();
+                builds.abort_all();
+                pb.set_message("Failed");
+                pb.abandon();
+                return Err(e);
+            }
+        }
+    }
+    // Report pages in the order they were found, not finished in.
+    pages.sort_by_key(|(index, _)| *index);
+    let mut pages = pages.into_iter().map(|(_, page)| page).collect::<Vec<_>>();
+    site.check_cancelled()?;
+
+    for book in config.books.iter().flatten() {
+        let started = Instant::now();
+        let source = config.source.join(&book.section);
+        let dest_file = book::dest(config, book);
+        match write_book(&site, book, &dest_file).await {
+            Ok(()) => pages.push(PageReport::new(source, dest_file, PageStatus::Built, started.elapsed())),
+            Err(e) if config.fail_fast() || matches!(e, Error::Cancelled) => return Err(e),
+            Err(e) => pages.push(PageReport::failed(source, dest_file, &e, started.elapsed())),
+        }
+        if let Some(dest_file) = epub::dest(config, book) {
+            let started = Instant::now();
+            let source = config.source.join(&book.section);
+            match write_epub(&site, book, &dest_file).await {
+                Ok(()) => pages.push(PageReport::new(source, dest_file, PageStatus::Built, started.elapsed())),
+                Err(e) if config.fail_fast() || matches!(e, Error::Cancelled) => return Err(e),
+                Err(e) => pages.push(PageReport::failed(source, dest_file, &e, started.elapsed())),
+            }
+        }
+    }
+
+    if config.json_index() {
+        let started = Instant::now();
+        let dest_file = config.dest.join(headless::INDEX_FILE);
+        match headless::write_index(&site).await {
+            Ok(()) => {
+                pages.push(PageReport::new(
+                    config.source.clone(),
+                    dest_file,
+                    PageStatus::Built,
+                    started.elapsed(),
+                ));
+            }
+            Err(e) if config.fail_fast() || matches!(e, Error::Cancelled) => return Err(e),
+            Err(e) => {
+                pages.push(PageReport::failed(
+                    config.source.clone(),
+                    dest_file,
+                    &e,
+                    started.elapsed(),
+                ))
+            }
+        }
+    }
+
+    site.copy_linked_favicons().await?;
+    shorturls::write(config).await?;
+    og::write(config).await?;
+    feeds::write(&site).await?;
+    let headings = site
+        .page_headings
+        .iter()
+        .map(|x| (x.key().clone(), x.value().clone()))
+        .collect();
+    let changes = redirects::update(config, &site.page_index, &headings).await?;
+    if config.service_worker() {
+        // Last, so it lists everything the build wrote.
+        offline::write(config).await?;
+    }
+
+    let warnings = site.warnings.take();
+    if config.strict() {
+        BuildReport::promote_warnings(&mut pages, &warnings);
+    }
+    let mut build_report = BuildReport::new(pages, &warnings, started.elapsed());
+    build_report.changes = changes.iter().map(ToString::to_string).collect();
+    if site.timings.enabled() {
+        build_report.timings = Some(site.timings.take(walk));
+    }
+    if !changes.is_empty() {
+        eprintln!(
+            "[{}] {} URL(s) changed since the last build:",
+            crate::NAME,
+            changes.len()
+        );
+        for change in &changes {
+            eprintln!("  {change}");
+        }
+    }
+    if build_report.failed == 0 {
+        pb.set_message("Done");
+        pb.finish();
+        if let Some(line) = command_hooks.and_then(|hooks| hooks.post_build.as_deref()) {
+            let built = build_report.built + build_report.copied;
+            hooks::run_command(
+                "post_build",
+                line,
+                config,
+                vec![("RAVEN_PAGES_BUILT", built.to_string().into())],
+            )
+            .await?;
+        }
+    }
+    else {
+        pb.set_message("Failed");
+        pb.finish();
+        eprintln!("[{}] {} page(s) failed to build:", crate::NAME, build_report.failed);
+        for page in build_report.failures() {
+            eprintln!("  {}", page.error.as_deref().unwrap_or_default());
+        }
+    }
+    Ok(build_report)
+}
+
+/// Build the page at `source_file`, and run the `post_page` hook if it was
+/// written. A page that fails is reported as failed, unless the build fails
+/// fast or is cancelled.
+async fn build_page(
+    site: &Website,
+    source_file: (PathBuf, String),
+    pb: indicatif::ProgressBar,
+    rebuild_all: bool,
+) -> Result<PageReport>
+{
+    site.check_cancelled()?;
+    let started = Instant::now();
+    let (source, extention) = source_file.clone();
+    let page = match site.make_html_from_md(source_file, pb, rebuild_all).await {
+        Ok(page) if matches!(page.status, PageStatus::Built | PageStatus::Copied) => {
+            site.run_post_page_hook(&page.source, &page.dest).await.map(|()| page)
+        }
+        page => page,
+    };
+    match page {
+        Ok(page) => Ok(page),
+        Err(e) if site.config.fail_fast() || matches!(e, Error::Cancelled) => Err(e),
+        Err(e) => {
+            let dest = site.dest_path(&source, &extention).unwrap_or_default();
+            Ok(PageReport::failed(source, dest, &e, started.elapsed()))
+        }
+    }
+}
+
+/// Write the CSS of every built-in component to the dest dir, for pages
+/// that link to it.
+async fn write_components_stylesheet(config: &Config) -> Result<()>
+{
+    write_output(&config.dest.join(styles::COMPONENTS_STYLESHEET), styles::stylesheet()).await
+}
+
+/// Where in the dest dir the favicon at `favicon` is copied to, if it's
+/// linked to instead of embedded: where it is in its source dir, so favicons
+/// with the same name don't overwrite each other, or the root of the dest dir
+/// if it's in none of them.
+pub(crate) fn favicon_dest(config: &Config, favicon: &Path) -> PathBuf
+{
+    match config.source_relative(favicon) {
+        Some((relative, Some(prefix))) => config.dest.join(prefix).join(relative),
+        Some((relative, None)) => config.dest.join(relative),
+        None => {
+            config
+                .dest
+                .join(favicon.file_name().unwrap_or(std::ffi::OsStr::new("favicon.ico")))
+        }
+    }
+}
+
+/// The MIME type of the favicon at `favicon`, from its extension.
+fn favicon_type(favicon: &Path) -> &'static str
+{
+    match mime::from_path(favicon) {
+        x if x.starts_with("image/") => x,
+        _ => "image/x-icon",
+    }
+}
+
+/// The suffix of the temporary files outputs are written to before they're
+/// renamed into place.
+const TEMP_SUFFIX: &str = ".raven-tmp";
+
+/// Write `contents` to `dest_file`, creating its parent dirs.
+///
+/// The contents are written to a temporary file next to `dest_file` and
+/// renamed over it, so a crash or a full disk leaves the previous output (or
+/// none), never a truncated one. Since the previous output is older than
+/// its source, the page is rebuilt next time.
+///
+/// # Errors
+///
+/// Will return an error if `dest_file` cannot be written to.
+pub(crate) async fn write_output(dest_file: &Path, contents: impl AsRef<[u8]>) -> Result<()>
+{
+    if let Some(dest_path_parent) = dest_file.parent() {
+        fs::create_dir_all(dest_path_parent).await.map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: dest_path_parent.to_path_buf(),
+            }
+        })?;
+    }
+
+    let temp_file = temp_file(dest_file);
+    let written = match fs::write(&temp_file, contents).await {
+        Ok(()) => fs::rename(&temp_file, dest_file).await,
+        Err(e) => Err(e),
+    };
+    written.map_err(|e| {
+        // Don't leave the partial file behind.
+        let _ = std::fs::remove_file(&temp_file);
+        Error::Io {
+            err:  e,
+            path: dest_file.to_path_buf(),
+        }
+    })
+}
+
+/// Where the output at `dest_file` is written before it's renamed into place.
+fn temp_file(dest_file: &Path) -> PathBuf
+{
+    let mut temp_name = dest_file.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(TEMP_SUFFIX);
+    dest_file.with_file_name(temp_name)
+}
+
+/// Write a symlink at `dest_file` to the file the symlink at `source_file`
+/// points to, renaming it over any existing file like [`write_output`]. Where
+/// symlinks aren't supported, the file is copied instead.
+///
+/// # Errors
+///
+/// Will return an error if the link cannot be resolved or written.
+async fn write_link(source_file: &Path, dest_file: &Path) -> Result<()>
+{
+    let target = fs::canonicalize(source_file).await.map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: source_file.to_path_buf(),
+        }
+    })?;
+    #[cfg(unix)]
+    {
+        if let Some(dest_path_parent) = dest_file.parent() {
+            fs::create_dir_all(dest_path_parent).await.map_err(|e| {
+                Error::Io {
+                    err:  e,
+                    path: dest_path_parent.to_path_buf(),
+                }
+            })?;
+        }
+        let temp_file = temp_file(dest_file);
+        let _ = fs::remove_file(&temp_file).await;
+        let written = match fs::symlink(&target, &temp_file).await {
+            Ok(()) => fs::rename(&temp_file, dest_file).await,
+            Err(e) => Err(e),
+        };
+        written.map_err(|e| {
+            let _ = std::fs::remove_file(&temp_file);
+            Error::Io {
+                err:  e,
+                path: dest_file.to_path_buf(),
+            }
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        let contents = fs::read(&target)
+            .await
+            .map_err(|e| Error::Io { err: e, path: target })?;
+        write_output(dest_file, contents).await
+    }
+}
+
+/// Delete the temporary files an interrupted build left in the dest dir.
+/// Their pages' outputs were never replaced, so they're rebuilt.
+fn remove_partial_outputs(config: &Config)
+{
+    let partial = WalkDir::new(&config.dest)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| !entry.file_type().is_dir() && entry.file_name().to_string_lossy().ends_with(TEMP_SUFFIX));
+    for entry in partial {
+        let _ = std::fs::remove_file(entry.path());
+    }
+}
+
+/// Render `book` and write it to `dest_file`.
+async fn write_book(site: &Website, book: &Book, dest_file: &Path) -> Result<()>
+{
+    let html = book::render(site, book).await?;
+    site.check_cancelled()?;
+    write_output(dest_file, &html).await?;
+    let source = site.config().source.join(&book.section);
+    site.page_rendered(&source, dest_file, &html);
+    site.run_post_page_hook(&source, dest_file).await
+}
+
+/// Render the EPUB of `book` and write it to `dest_file`.
+async fn write_epub(site: &Website, book: &Book, dest_file: &Path) -> Result<()>
+{
+    let epub = epub::render(site, book).await?;
+    site.check_cancelled()?;
+    write_output(dest_file, &epub).await?;
+    let source = site.config().source.join(&book.section);
+    site.run_post_page_hook(&source, dest_file).await
+}
+
+/// The hash a locked page's `approved_hash` is compared with: the MD5 hash
+/// of its source, without the `approved_hash` line.
+pub fn page_hash(source: &str) -> String
+{
+    let source = source
+        .split_inclusive('\n')
+        .filter(|x| !x.trim_start().starts_with("approved_hash"))
+        .collect::<String>();
+    hash::md5_hex(source.as_bytes())
+}
+
+/// Run the CPU-bound `f`, e.g. highlighting or minifying a page, without
+/// holding up the other pages' tasks on this worker thread.
+///
+/// Only a multi-threaded runtime can move its other tasks off the thread, so
+/// on any other `f` is just run.
+fn cpu_bound<T>(f: impl FnOnce() -> T) -> T
+{
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+/// The files in the source dirs that are built or copied, without the pages
+/// that aren't for the audience being built, or are dated in the future.
+/// Entries that cannot be read are added to `warnings`.
+pub(crate) fn source_files(config: &Config, warnings: &Warnings) -> Vec<(PathBuf, String)>
+{
+    let sources = walk_sources(config, warnings);
+    let page_infos = scan_page_infos(config, &sources);
+    sources
+        .into_iter()
+        .filter(|(source_file, _)| is_included(config, page_infos.get(source_file).and_then(Option::as_ref)))
+        .collect()
+}
+
+/// The page info of each markdown page, or `None` for one without any, by
+/// source file.
+pub type PageInfos = HashMap<PathBuf, Option<PageInfo>>;
+
+/// Scan the page info of each markdown page of `source_files` once, for
+/// deciding whether it's built and where it's written.
+pub(crate) fn scan_page_infos(config: &Config, source_files: &[(PathBuf, String)]) -> PageInfos
+{
+    source_files
+        .iter()
+        .filter(|(_, extention)| matches!(&**extention, "md" | "markdown"))
+        .map(|(source_file, _)| (source_file.clone(), sections::scan_page_info(config, source_file)))
+        .collect()
+}
+
+/// If a source file with the scanned `page_info` is built: a file that isn't
+/// a markdown page, or has no page info, or a page that's for the audience
+/// being built and isn't [scheduled](is_scheduled).
+pub(crate) fn is_included(config: &Config, page_info: Option<&PageInfo>) -> bool
+{
+    match page_info {
+        Some(page_info) => is_for_audience(config, page_info) && !is_scheduled(config, page_info),
+        None => true,
+    }
+}
+
+/// If the page is part of the audience being built: it has no `audience`, or
+/// its `audience` has it. Every page is, if no audience is being built.
+fn is_for_audience(config: &Config, page_info: &PageInfo) -> bool
+{
+    let Some(audience) = &config.audience
+    else {
+        return true;
+    };
+    page_info
+        .audience
+        .as_ref()
+        .is_none_or(|audiences| audiences.contains(audience))
+}
+
+/// If the page is left out of the build until its `date`, which is in the
+/// future, unless `generation.future` is set.
+fn is_scheduled(config: &Config, page_info: &PageInfo) -> bool
+{
+    !config.future()
+        && page_info
+            .date
+            .as_ref()
+            .is_some_and(|date| is_future(date, config.now()))
+}
+
+/// If `date` is after `now`. A date without a time is after `now` from the
+/// next day on, and a time without an offset is in `now`'s time zone.
+fn is_future(date: &Datetime, now: DateTime<FixedOffset>) -> bool
+{
+    let Some(day) = date
+        .date
+        .and_then(|x| NaiveDate::from_ymd_opt(i32::from(x.year), u32::from(x.month), u32::from(x.day)))
+    else {
+        return false;
+    };
+    match (date.time, date.offset) {
+        (None, _) => day > now.date_naive(),
+        (Some(_), Some(_)) => DateTime::parse_from_rfc3339(&date.to_string()).is_ok_and(|x| x > now),
+        (Some(time), None) => {
+            NaiveTime::from_hms_opt(u32::from(time.hour), u32::from(time.minute), u32::from(time.second))
+                .is_some_and(|time| day.and_time(time) > now.naive_local())
+        }
     }
+}
+
+/// How many of the markdown pages of `page_infos` are
+/// [scheduled](is_scheduled), and so left out.
+pub(crate) fn scheduled_count(config: &Config, page_infos: &PageInfos) -> usize
+{
+    page_infos
+        .values()
+        .flatten()
+        .filter(|x| is_for_audience(config, x) && is_scheduled(config, x))
+        .count()
+}
 
-    pb.set_message("Done");
-    pb.finish();
-    Ok(())
+/// The files in every source dir that are built or copied.
+pub(crate) fn walk_sources(config: &Config, warnings: &Warnings) -> Vec<(PathBuf, String)>
+{
+    config
+        .source_dirs()
+        .into_iter()
+        .flat_map(|(dir, _)| walk_directory(config, dir, warnings))
+        .collect()
 }
 
-fn walk_directory(path: &Path) -> Vec<(PathBuf, String)>
+pub(crate) fn walk_directory(config: &Config, path: &Path, warnings: &Warnings) -> Vec<(PathBuf, String)>
 {
+    let ignore = Ignore::from_config(config);
     // Walk the source directory and filter the results to only include files
-    // that have a markdown file extention
-    #[allow(clippy::unnecessary_unwrap)]
+    // that are built or copied
     let contents: Vec<(PathBuf, String)> = WalkDir::new(path)
+        .follow_links(config.follow_symlinks())
         .into_iter()
+        .filter_entry(|x| {
+            let relative = config.source_relative(x.path()).map_or(x.path(), |(x, _)| x);
+            x.depth() == 0 || !ignore.is_ignored(relative)
+        })
         .filter_map(|x| {
-            let extention: &str = &x
-                .as_ref()
-                .unwrap()
+            // If x is an error, e.g. a symlink loop, we print an error, but we
+            // continue.
+            let x = match x {
+                Ok(x) => x,
+                Err(e) => {
+                    warnings.warn(Warning::ReadSourceDir {
+                        path: e.path().map_or_else(|| PathBuf::from("UNKNOWNPATH"), Path::to_path_buf),
+                        err:  e.to_string(),
+                    });
+                    return None;
+                }
+            };
+            let extention = x
                 .path()
                 .extension()
                 .unwrap_or(&OsString::new())
                 .to_string_lossy()
                 .to_lowercase();
-            let x = x;
-            if x.is_ok() && {
-                x.as_ref().unwrap().path().is_file()
-                    && (extention == "markdown" || extention == "md" || extention == "html" || extention == "htm")
-            } {
-                Some((x.unwrap().path().to_path_buf(), extention.to_string()))
-            }
-            else {
-                // If x is an error we print an error, but we continue.
-                if x.is_err() {
-                    let e = Error::ReadSourceDir {
-                        err:  x.as_ref().err().unwrap().to_string(),
-                        path: PathBuf::from("UNKNOWNPATH"),
-                    };
-                    e.report();
-                }
-                None
-            }
+            let kind = extensions::kind(config, &extention)?;
+            x.path().is_file().then(|| (x.into_path(), kind))
         })
         .collect();
     contents
 }
 
+/// Where the output generated from `source_file` is written.
+///
+/// # Errors
+///
+/// Will return an error if `source_file` isn't in the source directory and
+/// `./` cannot be canonicalized.
+///
+/// # Panics
+///
+/// Will panic if `source_file` has no file name.
+pub(crate) fn dest_path(config: &Config, source_file: &Path, extention: &str) -> Result<PathBuf>
+{
+    let page_info = matches!(extention, "md" | "markdown")
+        .then(|| sections::scan_page_info(config, source_file))
+        .flatten();
+    scanned_dest_path(config, source_file, extention, page_info.as_ref())
+}
+
+/// Where the output generated from `source_file` is written, using its page
+/// info from `page_infos` if it was scanned there.
+///
+/// # Errors
+///
+/// Will return an error if `source_file` isn't in the source directory and
+/// `./` cannot be canonicalized.
+pub(crate) fn dest_path_in(
+    config: &Config,
+    page_infos: &PageInfos,
+    source_file: &Path,
+    extention: &str,
+) -> Result<PathBuf>
+{
+    match page_infos.get(source_file) {
+        Some(page_info) => scanned_dest_path(config, source_file, extention, page_info.as_ref()),
+        None => dest_path(config, source_file, extention),
+    }
+}
+
+/// Where the output generated from `source_file`, a page with `page_info`
+/// if it's a markdown one, is written.
+fn scanned_dest_path(
+    config: &Config,
+    source_file: &Path,
+    extention: &str,
+    page_info: Option<&PageInfo>,
+) -> Result<PathBuf>
+{
+    let (source_path_stem, dest) = match config.source_relative(source_file) {
+        Some((relative, Some(prefix))) => (relative.to_path_buf(), config.dest.join(prefix)),
+        Some((relative, None)) => (relative.to_path_buf(), config.dest.clone()),
+        None => {
+            let here = PathBuf::from(".").canonicalize().map_err(|e| {
+                Error::Io {
+                    err:  e,
+                    path: PathBuf::from("."),
+                }
+            })?;
+            let stem = source_file
+                .iter()
+                .skip_while(|x| *x != here.file_name().unwrap())
+                .skip(2)
+                .collect::<PathBuf>();
+            (stem, config.dest.clone())
+        }
+    };
+    let dest_dir = dest.join(source_path_stem.parent().unwrap_or(&source_path_stem));
+
+    Ok(match extention {
+        "md" | "markdown" => {
+            match permalink(config, page_info, &source_path_stem) {
+                Some(permalink) => dest.join(permalink),
+                None => dest_dir.join(format!("{}.html", source_file.file_stem().unwrap().to_string_lossy())),
+            }
+        }
+        extensions::CONVERT => dest_dir.join(format!("{}.html", source_file.file_stem().unwrap().to_string_lossy())),
+        _ => dest_dir.join(source_file.file_name().unwrap()),
+    })
+}
+
+/// Where the page's `page_info`, or the `permalink` pattern, puts the page,
+/// relative to the dest dir. `relative` is the page's path in the source
+/// dir. `None` if the page mirrors its source path.
+///
+/// A page's `path` comes first, then the pattern, then its `slug` in place
+/// of its file name. `index` pages, and pages without a `date` when the
+/// pattern has one, aren't moved by the pattern.
+fn permalink(config: &Config, page_info: Option<&PageInfo>, relative: &Path) -> Option<PathBuf>
+{
+    if let Some(path) = page_info.and_then(|x| x.path.as_deref()) {
+        return permalink_file(path);
+    }
+
+    let stem = relative.file_stem()?.to_string_lossy();
+    let slug = page_info
+        .and_then(|x| x.slug.clone())
+        .unwrap_or_else(|| stem.to_string());
+    let section = relative.parent().unwrap_or(Path::new("")).to_string_lossy();
+    let date = page_info.and_then(|x| x.date).and_then(|x| x.date);
+    let pattern = config
+        .permalink
+        .as_deref()
+        .filter(|_| stem != "index")
+        .and_then(|pattern| expand_permalink(pattern, &slug, &section, date));
+    match pattern {
+        Some(path) => permalink_file(&path),
+        None => {
+            page_info
+                .and_then(|x| x.slug.as_deref())
+                .and_then(|slug| permalink_file(&format!("{section}/{slug}")))
+        }
+    }
+}
+
+/// Replace the `:year`, `:month`, `:day`, `:slug`, and `:section` of a
+/// `permalink` pattern, or `None` if it has a date part and there's no
+/// `date`.
+fn expand_permalink(pattern: &str, slug: &str, section: &str, date: Option<toml::value::Date>) -> Option<String>
+{
+    let dated = [":year", ":month", ":day"].iter().any(|x| pattern.contains(x));
+    let mut path = pattern.replace(":slug", slug).replace(":section", section);
+    if dated {
+        let date = date?;
+        path = path
+            .replace(":year", &date.year.to_string())
+            .replace(":month", &format!("{:02}", date.month))
+            .replace(":day", &format!("{:02}", date.day));
+    }
+    Some(path)
+}
+
+/// The file a permalink `path` is written to, relative to the dest dir: the
+/// `index.html` in it if it ends with a `/`, or it with `.html` added if it
+/// has no extension. Empty, `.` and `..` parts are dropped, so a page can't
+/// be written outside the dest dir. `None` if nothing's left.
+fn permalink_file(path: &str) -> Option<PathBuf>
+{
+    let parts = path
+        .split(['/', '\\'])
+        .filter(|x| !matches!(*x, "" | "." | ".."))
+        .collect::<Vec<_>>();
+    let mut file = parts.iter().collect::<PathBuf>();
+    if path.ends_with('/') {
+        file.push("index.html");
+    }
+    else if parts.is_empty() {
+        return None;
+    }
+    else if file.extension().is_none() {
+        file.set_extension("html");
+    }
+    Some(file)
+}
+
+/// The name of the variant of `template` for the output `format`, whether
+/// or not it exists.
+pub(crate) fn format_variant(template: &Path, format: &str) -> PathBuf
+{
+    let mut name = template.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".{format}"));
+    if let Some(extention) = template.extension() {
+        name.push(".");
+        name.push(extention);
+    }
+    template.with_file_name(name)
+}
+
+/// If `dest` is missing, or any of the `sources` it's generated from were
+/// modified after it.
+///
 /// # Errors
 ///
 /// Will returns errors if:
 ///
-/// - `source` path doesn't exist
+/// - A `sources` path doesn't exist
 /// - `dest` path doesn't exist
-fn should_regenerate_file(source: &Path, dest: &Path) -> Result<bool>
+fn should_regenerate_file(sources: &[&Path], dest: &Path) -> Result<bool>
 {
     if dest.exists() {
-        let source_path_metadata = source.metadata().map_err(|e| {
-            Error::Io {
-                err:  e,
-                path: source.to_path_buf(),
-            }
-        })?;
         let dest_path_metadata = dest.metadata().map_err(|e| {
             Error::Io {
                 err:  e,
-                path: source.to_path_buf(),
+                path: dest.to_path_buf(),
             }
         })?;
-
-        let source_last_modified: DateTime<Local> = source_path_metadata.modified().unwrap().into();
         let dest_last_modified: DateTime<Local> = dest_path_metadata.modified().unwrap().into();
 
-        if source_last_modified < dest_last_modified {
-            return Ok(false);
+        for source in sources {
+            let source_path_metadata = source.metadata().map_err(|e| {
+                Error::Io {
+                    err:  e,
+                    path: source.to_path_buf(),
+                }
+            })?;
+            let source_last_modified: DateTime<Local> = source_path_metadata.modified().unwrap().into();
+            if source_last_modified >= dest_last_modified {
+                return Ok(true);
+            }
         }
+        return Ok(false);
     }
 
     Ok(true)
@@ -181,9 +868,32 @@ pub fn get_syntaxes(
     std::collections::BTreeMap<String, highlighting::Theme>,
 )>
 {
-    let syntax_dir = &config.syntaxes;
-    let custom_syntax_themes_dir = &config.custom_syntax_themes;
+    Ok((syntax_set_builder(config)?, syntax_themes(config)?))
+}
+
+/// The syntaxes, from [`syntax_cache`] if the syntaxes dir hasn't changed
+/// since they were last built, or else built, and cached if `persist`.
+///
+/// # Errors
+///
+/// Will return an error if the custom syntaxes cannot be loaded.
+pub fn load_syntax_set(config: &Config, persist: bool) -> Result<SyntaxSet>
+{
+    let cache_file = syntax_cache::cache_file();
+    if let Some(syntax_set) = syntax_cache::load(config, &cache_file) {
+        return Ok(syntax_set);
+    }
+    let syntax_set = syntax_set_builder(config)?.build();
+    if persist {
+        syntax_cache::save(config, &cache_file, &syntax_set);
+    }
+    Ok(syntax_set)
+}
 
+/// The default syntaxes, with the ones in the syntaxes dir added.
+fn syntax_set_builder(config: &Config) -> Result<syntect::parsing::SyntaxSetBuilder>
+{
+    let syntax_dir = &config.syntaxes;
     let mut syntax_set_builder = SyntaxSet::load_defaults_newlines().into_builder();
     if syntax_dir.is_dir() {
         syntax_set_builder.add_from_folder(syntax_dir, true).map_err(|e| {
@@ -193,7 +903,14 @@ pub fn get_syntaxes(
             }
         })?;
     }
+    Ok(syntax_set_builder)
+}
 
+/// The default syntax themes, with the ones in the syntax themes dir added,
+/// by name.
+fn syntax_themes(config: &Config) -> Result<std::collections::BTreeMap<String, highlighting::Theme>>
+{
+    let custom_syntax_themes_dir = &config.custom_syntax_themes;
     let mut themes = highlighting::ThemeSet::load_defaults().themes;
     if custom_syntax_themes_dir.is_dir() {
         let custom_theme_files =
@@ -226,7 +943,7 @@ pub fn get_syntaxes(
             themes.insert(name, theme);
         }
     }
-    Ok((syntax_set_builder, themes))
+    Ok(themes)
 }
 
 
@@ -237,25 +954,395 @@ pub struct Website
     syntax_set:     SyntaxSet,
     syntax_theme:   highlighting::Theme,
 
+    /// The other syntax themes pages can choose with `syntax_theme`
+    syntax_themes: BTreeMap<String, highlighting::Theme>,
+
     /// The text-based assets loaded into memory
-    assets: Arc<DashMap<PathBuf, String>>,
-}
+    assets: Arc<AssetCache>,
 
-impl Website
-{
-    pub fn new(
+    /// The favicons pages link to, and where in the dest dir they're copied
+    linked_favicons: DashMap<PathBuf, PathBuf>,
+
+    /// What the template placeholders are replaced with
+    placeholders: Placeholders,
+
+    /// The titles mdBook summaries give pages without page info
+    page_titles: BTreeMap<PathBuf, String>,
+
+    /// The pages with stable IDs that `{{ link }}` refers to
+    page_index: PageIndex,
+
+    /// The pages `[[Page Name]]` links refer to, if `markdown.wiki_links`
+    wiki_index: Option<WikiIndex>,
+
+    /// The page info of the markdown pages, scanned when the build collects
+    /// its source files
+    page_infos: PageInfos,
+
+    /// The heading IDs of each rendered page with a stable ID
+    page_headings: DashMap<String, Vec<String>>,
+
+    /// Templates and stylesheets added in memory, used instead of the files
+    /// at their paths
+    memory_files: HashMap<PathBuf, String>,
+
+    /// Markdown pages added in memory, built as if they were at their paths
+    /// in the source dir
+    virtual_pages: BTreeMap<PathBuf, String>,
+
+    /// The preprocessors, postprocessors, and page rendered hooks
+    hooks: Hooks,
+
+    /// The warnings of the build
+    warnings: Warnings,
+
+    /// If the build was cancelled
+    cancellation: Cancellation,
+
+    /// How long each stage of each page took, if `generation.timings`
+    timings: Recorder,
+}
+
+impl Website
+{
+    pub fn new(
         config: Config,
         syntax_set: SyntaxSet,
-        assets: Arc<DashMap<PathBuf, String>>,
+        assets: Arc<AssetCache>,
         syntax_theme: highlighting::Theme,
     ) -> Self
     {
-        Self {
-            config,
+        let mut site = Self {
             emoji_replacer: Replacer::new(),
             syntax_set,
             syntax_theme,
+            syntax_themes: BTreeMap::new(),
             assets,
+            linked_favicons: DashMap::new(),
+            placeholders: Placeholders::builtin(),
+            page_titles: mdbook::page_titles(&config),
+            page_index: PageIndex::from_config(&config),
+            wiki_index: config.wiki_links().then(|| WikiIndex::from_config(&config)),
+            page_infos: PageInfos::new(),
+            page_headings: DashMap::new(),
+            memory_files: HashMap::new(),
+            virtual_pages: BTreeMap::new(),
+            hooks: Hooks::builtin(),
+            warnings: Warnings::default(),
+            cancellation: Cancellation::default(),
+            timings: Recorder::new(config.timings()),
+            config,
+        };
+        site.register_tokens();
+        site
+    }
+
+    /// Supply a placeholder for each of the configured `[tokens]`, and
+    /// replace them in markdown too if `generation.tokens_in_markdown` is set.
+    fn register_tokens(&mut self)
+    {
+        let tokens = self.config.tokens.clone().unwrap_or_default();
+        for (name, value) in &tokens {
+            let value = value.clone();
+            self.placeholders.register_fn(name, move |_| Some(value.clone()));
+        }
+
+        let in_markdown = self
+            .config
+            .generation
+            .as_ref()
+            .and_then(|generation| generation.tokens_in_markdown)
+            .unwrap_or(false);
+        if in_markdown && !tokens.is_empty() {
+            self.add_markdown_preprocessor(move |_, mut markdown| {
+                for (name, value) in &tokens {
+                    markdown = markdown.replace(&format!("{TOKEN_PREFIX}{name}{TOKEN_SUFFIX}"), value);
+                }
+                markdown
+            });
+        }
+    }
+
+    /// Construct a `Website` from `config`, loading its syntaxes and syntax
+    /// theme.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if:
+    ///
+    /// - Syntaxes or syntax themes cannot be loaded
+    /// - The configured syntax theme doesn't exist
+    /// - The `[analytics]` snippet cannot be read, or is misconfigured
+    pub fn from_config(config: Config) -> Result<Self> { Self::load(config, true) }
+
+    /// [`Self::from_config`], without keeping the syntaxes in the
+    /// [`syntax_cache`], for runs that write no files, like `raven check`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error like [`Self::from_config`].
+    pub(crate) fn from_config_read_only(config: Config) -> Result<Self> { Self::load(config, false) }
+
+    /// Construct a `Website` from `config`, caching the syntaxes if
+    /// `persist`.
+    fn load(config: Config, persist: bool) -> Result<Self>
+    {
+        let syntax_set = load_syntax_set(&config, persist)?;
+        let mut themes = syntax_themes(&config)?;
+        let theme = themes
+            .remove(&config.syntax_theme)
+            .ok_or_else(|| Error::MissingTheme(config.syntax_theme.clone()))?;
+
+        // The assets we've already loaded.
+        let assets = Arc::new(AssetCache::new());
+        let analytics = analytics::snippet(&config)?;
+        let mut site = Self::new(config, syntax_set, assets, theme);
+        site.syntax_themes = themes;
+        if let Some(snippet) = analytics {
+            site.add_html_postprocessor(move |_, html| analytics::inject(html, &snippet));
+        }
+        if og::enabled(&site.config) {
+            site.add_html_postprocessor(|context, html| og::link(context.config, context.source_file, html));
+        }
+        if site.config.feeds.is_some() {
+            site.add_html_postprocessor(|context, html| feeds::link(context.config, context.source_file, html));
+        }
+        if site.config.service_worker() {
+            site.add_html_postprocessor(|context, html| offline::register(context.config, context.source_file, html));
+        }
+        Ok(site)
+    }
+
+    pub fn config(&self) -> &Config { &self.config }
+
+    /// The cancellation of the build, for cancelling it while it's underway.
+    pub fn cancellation(&self) -> &Cancellation { &self.cancellation }
+
+    /// Share `cancellation` with the build, e.g. so that one Ctrl-C handler
+    /// cancels whichever build is underway.
+    pub fn set_cancellation(&mut self, cancellation: Cancellation) { self.cancellation = cancellation; }
+
+    /// Fail with [`Error::Cancelled`] if the build was cancelled, so nothing
+    /// more is written.
+    fn check_cancelled(&self) -> Result<()>
+    {
+        if self.cancellation.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// The warnings of the build, since they were last taken.
+    pub fn warnings(&self) -> &Warnings { &self.warnings }
+
+    /// Whether warnings are printed, for programs that read them from
+    /// [`Self::warnings`] instead.
+    pub fn set_print_warnings(&mut self, print: bool) { self.warnings = Warnings::new(print); }
+
+    /// The template tokens in `template` that wouldn't be replaced.
+    pub fn unknown_template_tokens(&self, template: &str) -> Vec<String> { self.placeholders.unknown_tokens(template) }
+
+    /// Supply the `[/rustic_<name>/]` placeholder with `provider`, replacing
+    /// any provider (including a built-in one) with the same name.
+    pub fn register_placeholder(&mut self, provider: impl PlaceholderProvider + 'static)
+    {
+        self.placeholders.register(provider);
+    }
+
+    /// Supply the `[/rustic_<name>/]` placeholder with the text `provide`
+    /// returns, replacing any provider (including a built-in one) with the
+    /// same name.
+    pub fn register_placeholder_fn(
+        &mut self,
+        name: &str,
+        provide: impl Fn(&PlaceholderContext) -> Option<String> + Send + Sync + 'static,
+    )
+    {
+        self.placeholders.register_fn(name, provide);
+    }
+
+    /// Supply the `[/rustic_<name>/]` placeholder with the HTML `provide`
+    /// returns, replacing any provider (including a built-in one) with the
+    /// same name.
+    pub fn register_placeholder_html_fn(
+        &mut self,
+        name: &str,
+        provide: impl Fn(&PlaceholderContext) -> Option<String> + Send + Sync + 'static,
+    )
+    {
+        self.placeholders.register_html_fn(name, provide);
+    }
+
+    /// Use `contents` as the template or stylesheet at `path`, instead of the
+    /// file there, if any.
+    pub fn add_file(&mut self, path: impl Into<PathBuf>, contents: impl Into<String>)
+    {
+        self.memory_files.insert(path.into(), contents.into());
+    }
+
+    /// Build `markdown` as the page at `path` in the source dir, without a
+    /// file there. It's rebuilt by every build, having no file to compare
+    /// with its dest file.
+    pub fn add_page(&mut self, path: impl Into<PathBuf>, markdown: impl Into<String>)
+    {
+        self.virtual_pages.insert(path.into(), markdown.into());
+    }
+
+    /// The files in the source dirs that are built or copied, and the pages
+    /// [added in memory](Self::add_page), without the pages that aren't for
+    /// the audience being built, or are dated in the future.
+    pub(crate) fn sources(&self) -> Vec<(PathBuf, String)>
+    {
+        let mut sources = source_files(&self.config, &self.warnings);
+        sources.extend(
+            self.virtual_pages
+                .iter()
+                .filter(|(path, markdown)| {
+                    let page_info = sections::scan_source_page_info(&self.config, path, markdown);
+                    is_included(&self.config, page_info.as_ref())
+                })
+                .map(|(path, _)| (path.clone(), String::from("md"))),
+        );
+        sources
+    }
+
+    /// The contents of the source at `source_file`, from memory if it was
+    /// [added there](Self::add_page).
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if `source_file` cannot be read into a string.
+    pub(crate) async fn read_source(&self, source_file: &Path) -> Result<String>
+    {
+        if let Some(markdown) = self.virtual_pages.get(source_file) {
+            return Ok(markdown.clone());
+        }
+        fs::read_to_string(source_file).await.map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: source_file.to_path_buf(),
+            }
+        })
+    }
+
+    /// The contents of the template or stylesheet at `path` if they don't
+    /// come from a file: added with [`Self::add_file`], or built in if
+    /// neither the project nor the theme has the file.
+    fn unresolved_file(&self, path: &Path) -> Option<String>
+    {
+        if let Some(contents) = self.memory_files.get(path) {
+            return Some(contents.clone());
+        }
+        if self.config.resolve(path).is_file() {
+            return None;
+        }
+        self.config.builtin_file(path).map(String::from)
+    }
+
+    /// Transform every page's markdown with `hook` before it's parsed, after
+    /// any preprocessors added before it.
+    pub fn add_markdown_preprocessor(&mut self, hook: impl Fn(&HookContext, String) -> String + Send + Sync + 'static)
+    {
+        self.hooks.add_markdown_preprocessor(hook);
+    }
+
+    /// Transform every HTML page with `hook` after it's rendered, after the
+    /// built-in `minify` postprocessor and any added before it.
+    pub fn add_html_postprocessor(&mut self, hook: impl Fn(&HookContext, String) -> String + Send + Sync + 'static)
+    {
+        self.hooks.add_html_postprocessor(hook);
+    }
+
+    /// Call `hook` with every page's dest path and HTML once it's written.
+    pub fn on_page_rendered(&mut self, hook: impl Fn(&HookContext, &Path, &str) + Send + Sync + 'static)
+    {
+        self.hooks.on_page_rendered(hook);
+    }
+
+    /// Run the `hooks.post_page` command, if any, for the page built from
+    /// `source` to `dest`.
+    async fn run_post_page_hook(&self, source: &Path, dest: &Path) -> Result<()>
+    {
+        let Some(line) = self.config.hooks.as_ref().and_then(|hooks| hooks.post_page.as_deref())
+        else {
+            return Ok(());
+        };
+        let env = vec![
+            ("RAVEN_PAGE_SOURCE", source.as_os_str().to_os_string()),
+            ("RAVEN_PAGE_DEST", dest.as_os_str().to_os_string()),
+        ];
+        hooks::run_command("post_page", line, &self.config, env).await
+    }
+
+    fn hook_context<'a>(&'a self, source_file: &'a Path) -> HookContext<'a>
+    {
+        HookContext {
+            config: &self.config,
+            source_file,
+            timings: &self.timings,
+        }
+    }
+
+    /// Run the page rendered hooks for `source_file`, written to `dest_file`.
+    pub(crate) fn page_rendered(&self, source_file: &Path, dest_file: &Path, html: &str)
+    {
+        self.hooks
+            .page_rendered(&self.hook_context(source_file), dest_file, html);
+    }
+
+    /// The page info in `source`, over the page info of its `section`, found
+    /// before the page is parsed for the settings that change how it's parsed.
+    pub(crate) fn scan_page_info(source: &str, section: &toml::Table) -> Option<PageInfo>
+    {
+        use pulldown_cmark::{Parser, Tag};
+
+        let mut in_page_info = false;
+        for event in Parser::new(source) {
+            match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
+                    in_page_info = lang.as_ref() == PageInfo::CODE_BLOCK_IDENTIFIER;
+                }
+                Event::Text(text) if in_page_info => {
+                    // Unparsable page info is reported when the page is parsed.
+                    return PageInfo::parse_in_section(&text, section).ok();
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The syntax theme `page_info` chooses, or the configured one.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the chosen theme doesn't exist.
+    fn page_syntax_theme(&self, page_info: Option<&PageInfo>, source_path: &Path) -> Result<&highlighting::Theme>
+    {
+        match page_info.and_then(|x| x.syntax_theme.clone()) {
+            Some(theme) if theme != self.config.syntax_theme => {
+                self.syntax_themes.get(&theme).ok_or_else(|| {
+                    Error::MissingPageTheme {
+                        theme,
+                        source_file: source_path.to_path_buf(),
+                    }
+                })
+            }
+            _ => Ok(&self.syntax_theme),
+        }
+    }
+
+    /// The variant of `template` for the output `format`, e.g.
+    /// `template.print.html` for `template.html`, if it exists, or `template`
+    /// itself.
+    pub fn format_template(&self, template: &Path, format: &str) -> PathBuf
+    {
+        let variant = format_variant(template, format);
+        if self.memory_files.contains_key(&variant) || self.config.resolve(&variant).is_file() {
+            variant
+        }
+        else {
+            template.to_path_buf()
         }
     }
 
@@ -266,19 +1353,73 @@ impl Website
     /// Will return an error if:
     ///
     /// - Syntax highligting fails
-    /// - `PageInfo` isn't parsable or is missing.
+    /// - `PageInfo` isn't parsable
+    /// - A shortcode is invalid
     pub fn parse_markdown(&self, source: &str, source_path: PathBuf) -> Result<(String, PageInfo)>
     {
-        use pulldown_cmark::{html, Options, Parser, Tag};
-
-        // Enable features that aren't part of the standard, but are widely
-        // used.
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_TASKLISTS);
-
-        let parser = Parser::new_ext(source, options);
+        use pulldown_cmark::{html, Parser, Tag};
+
+        // Enable the configured features that aren't part of the standard.
+        let options = self.config.markdown_options();
+
+        // Locked pages are compared as they're written.
+        let written_source = source;
+        let source = self
+            .hooks
+            .preprocess_markdown(&self.hook_context(&source_path), source.to_string());
+        let source = includes::expand(&source, &source_path)?;
+        let section = sections::page_info(&self.config, &source_path).map_err(|err| {
+            Error::ParsePageInfo {
+                err,
+                path: source_path.clone(),
+            }
+        })?;
+        let source = if self.config.variables_in_markdown() {
+            let page_info = Self::scan_page_info(&source, &section);
+            variables::substitute(&source, &source_path, &self.config, page_info.as_ref())?
+        }
+        else {
+            source
+        };
+        let stylesheet_href = if styles::linked(&self.config) {
+            let extention = source_path
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let dest_file = self.dest_path(&source_path, &extention)?;
+            Some(links::relative_url(
+                &dest_file,
+                &self.config.dest.join(styles::COMPONENTS_STYLESHEET),
+            ))
+        }
+        else {
+            None
+        };
+        let source = shortcodes::expand_with_styles(
+            &source,
+            &source_path,
+            &self.config.shortcode_dirs(),
+            stylesheet_href.as_deref(),
+            self.config.error_markers(),
+            &self.warnings,
+        )?;
+        let source = self.page_index.resolve_links(&source, &source_path, &self.config)?;
+        let source = match &self.wiki_index {
+            Some(wiki_index) => wiki_index.resolve_links(&source, &source_path, &self.config, &self.warnings)?,
+            None => source,
+        };
+        let source = if self.config.definition_lists() {
+            deflists::expand(&source, options).unwrap_or(source)
+        }
+        else {
+            source
+        };
+        let scanned_page_info = Self::scan_page_info(&source, &section);
+        let syntax_theme = self.page_syntax_theme(scanned_page_info.as_ref(), &source_path)?;
+        let emoji = scanned_page_info
+            .and_then(|x| x.emoji)
+            .unwrap_or_else(|| self.config.emoji());
+        let parser = Parser::new_ext(&source, options);
 
         let mut html_out = String::new();
         let mut current_language = None;
@@ -304,8 +1445,10 @@ impl Website
                 }
                 Event::Text(ref mut text) => {
                     // Insert emojis
-                    if let Cow::Owned(new_text) = self.emoji_replacer.replace_all(text) {
-                        *text = new_text.into();
+                    if emoji {
+                        if let Cow::Owned(new_text) = self.emoji_replacer.replace_all(text) {
+                            *text = new_text.into();
+                        }
                     }
 
                     if let Some(lang) = current_language.as_ref() {
@@ -314,12 +1457,10 @@ impl Website
                             continue 'next_event;
                         }
                         else if let Some(syntax) = self.syntax_set.find_syntax_by_token(lang) {
-                            let highlighted_html = match syntect::html::highlighted_html_for_string(
-                                text,
-                                &self.syntax_set,
-                                syntax,
-                                &self.syntax_theme,
-                            ) {
+                            let highlighted = self.timings.time(&source_path, Stage::Highlight, || {
+                                syntect::html::highlighted_html_for_string(text, &self.syntax_set, syntax, syntax_theme)
+                            });
+                            let highlighted_html = match highlighted {
                                 Ok(x) => x,
                                 Err(e) => return Err(Error::SyntaxHighlight(e.to_string())),
                             };
@@ -327,6 +1468,12 @@ impl Website
                             // Change the event to an html event
                             event = Event::Html(highlighted_html.into());
                         }
+                        else if !lang.is_empty() && !matches!(&*lang.to_lowercase(), "text" | "plain" | "plaintext") {
+                            self.warnings.warn(Warning::UnknownFenceLanguage {
+                                source_file: source_path.clone(),
+                                language:    lang.to_string(),
+                            });
+                        }
                     }
                 }
                 _ => {}
@@ -335,59 +1482,233 @@ impl Website
             markdown_html.push(event);
         }
 
+        let headings = markdown::resolve_heading_ids(&mut markdown_html, &source_path, &self.warnings);
+        if mdbook::book_of(&self.config, &source_path).is_some() {
+            mdbook::rewrite_chapter_links(&mut markdown_html);
+        }
+        if let Some(images) = &self.config.images {
+            markdown_html = markdown::process_images(markdown_html, images, &source_path, &self.warnings)?;
+        }
+
+        // What the page info leaves out is taken from the page.
+        let first_heading = headings.iter().find(|x| x.level == 1).map(|x| x.text.clone());
+        let first_paragraph = markdown::first_paragraph(&markdown_html);
+
         // Parse the markdown to HTML
         html::push_html(&mut html_out, markdown_html.into_iter());
 
-        let unparsed_page_info = unparsed_page_info.ok_or_else(|| Error::MissingPageInfo(source_path.clone()))?;
-        let page_info = toml::from_str::<PageInfo>(&unparsed_page_info).map_err(|e| {
-            Error::ParsePageInfo {
-                err:  e.to_string(),
-                path: source_path,
+        let has_page_info = unparsed_page_info.is_some();
+        let (mut page_info, omits) = match unparsed_page_info {
+            Some(unparsed_page_info) => {
+                let page_info = PageInfo::parse_in_section(&unparsed_page_info, &section).map_err(|err| {
+                    Error::ParsePageInfo {
+                        err,
+                        path: source_path.clone(),
+                    }
+                })?;
+                if page_info.locked == Some(true) {
+                    let hash = page_hash(written_source);
+                    if page_info.approved_hash.as_deref() != Some(hash.as_str()) {
+                        return Err(Error::LockedPageChanged {
+                            path: source_path,
+                            hash,
+                        });
+                    }
+                }
+                let omits = |key| PageInfo::omits(&unparsed_page_info, &section, key);
+                let omits = (omits("title"), omits("description"));
+                (page_info, omits)
             }
-        })?;
+            None => {
+                // Chapters of an mdBook are titled by its summary.
+                if let Some(title) = self.page_titles.get(&mdbook::normalize(&source_path)) {
+                    return Ok((html_out, PageInfo::new(title.clone(), String::new())));
+                }
+                if self.config.require_page_info() {
+                    return Err(Error::MissingPageInfo(source_path));
+                }
+                // Only the defaults and its sections give it page info.
+                let page_info = PageInfo::parse_in_section("", &section).map_err(|err| {
+                    Error::ParsePageInfo {
+                        err,
+                        path: source_path.clone(),
+                    }
+                })?;
+                let omits = |key| PageInfo::omits("", &section, key);
+                (page_info, (omits("title"), omits("description")))
+            }
+        };
+        // Pages without page info are only built when it isn't required, so
+        // what's taken from them isn't warned about.
+        if omits.0 {
+            page_info.title = first_heading.unwrap_or_else(|| {
+                source_path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            });
+            if has_page_info {
+                self.warnings.warn(Warning::DerivedPageInfo {
+                    source_file: source_path.clone(),
+                    field:       "title",
+                });
+            }
+        }
+        if let (true, Some(description)) = (omits.1, first_paragraph) {
+            page_info.description = description;
+            if has_page_info {
+                self.warnings.warn(Warning::DerivedPageInfo {
+                    source_file: source_path.clone(),
+                    field:       "description",
+                });
+            }
+        }
+        if page_info.description.trim().is_empty() {
+            self.warnings.warn(Warning::EmptyDescription(source_path));
+        }
+        if let Some(id) = &page_info.id {
+            let headings = headings.into_iter().map(|x| x.id).collect();
+            self.page_headings.insert(id.clone(), headings);
+        }
         Ok((html_out, page_info))
     }
 
-    async fn get_stylesheet(&self, stylesheet: PathBuf) -> Result<String>
+    pub(crate) async fn get_stylesheet(&self, stylesheet: PathBuf) -> Result<String>
     {
+        if let Some(contents) = self.unresolved_file(&stylesheet) {
+            return Ok(format!("<style>{}</style>", self.minify_stylesheet(&contents)));
+        }
+
         // Read the stylesheet and wrap it in html
+        let stylesheet = self.config.resolve(&stylesheet);
         let stylesheet_path = stylesheet.canonicalize().unwrap_or(stylesheet);
         let stylesheet = if let Some(contents) = self.assets.get(&stylesheet_path) {
-            contents.clone()
+            contents
         }
         else {
-            let stylesheet = format!(
-                "<style>{}</style>",
+            let css = if sass::is_sass(&stylesheet_path) {
+                let path = stylesheet_path.clone();
+                tokio::task::spawn_blocking(move || sass::compile(&path))
+                    .await
+                    .map_err(|_| Error::AysncJoin)??
+            }
+            else {
                 fs::read_to_string(&stylesheet_path).await.map_err(|e| {
                     Error::Io {
                         err:  e,
                         path: stylesheet_path.clone(),
                     }
                 })?
-            );
+            };
+            let stylesheet = format!("<style>{}</style>", self.minify_stylesheet(&css));
+            self.warn_if_large(&stylesheet_path, &stylesheet, self.config.stylesheet_inline_limit());
             self.assets.insert(stylesheet_path, stylesheet.clone());
             stylesheet
         };
         Ok(stylesheet)
     }
 
-    async fn get_favicon(&self, favicon: PathBuf) -> Result<String>
+    /// The HTML for the `[/rustic_head/]` of a page with `page_info`:
+    /// `default.head_extra`, then the page's `head_extra`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a file of either cannot be read.
+    pub(crate) async fn get_head(&self, page_info: Option<&PageInfo>) -> Result<String>
     {
-        let favicon_path = favicon.canonicalize().unwrap_or(favicon);
+        let mut head = String::new();
+        let extras = [
+            self.config.default.head_extra.as_ref(),
+            page_info.and_then(|x| x.head_extra.as_ref()),
+        ];
+        for extra in extras.into_iter().flatten() {
+            match extra {
+                HeadExtra::Html(html) => head.push_str(html),
+                HeadExtra::Files(files) => {
+                    for file in files {
+                        head.push_str(&self.get_head_file(file).await?);
+                    }
+                }
+            }
+        }
+        Ok(head)
+    }
+
+    /// The contents of a `head_extra` file, read once.
+    async fn get_head_file(&self, file: &Path) -> Result<String>
+    {
+        if let Some(contents) = self.unresolved_file(file) {
+            return Ok(contents);
+        }
+        let path = self.config.resolve(file);
+        let path = path.canonicalize().unwrap_or(path);
+        if let Some(contents) = self.assets.get(&path) {
+            return Ok(contents);
+        }
+        let contents = fs::read_to_string(&path).await.map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: path.clone(),
+            }
+        })?;
+        self.assets.insert(path, contents.clone());
+        Ok(contents)
+    }
+
+    /// Minify an inlined stylesheet if configured.
+    fn minify_stylesheet(&self, css: &str) -> String
+    {
+        if self.config.minify_css() {
+            minify::css(css)
+        }
+        else {
+            css.to_string()
+        }
+    }
+
+    /// The HTML for the favicon of the page written to `page_dest`: the
+    /// favicon embedded as a data URL, or a link to its copy in the dest dir
+    /// if `generation.embed_favicon` is `false`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the favicon cannot be read.
+    pub(crate) async fn get_favicon(&self, favicon: PathBuf, page_dest: &Path) -> Result<String>
+    {
+        let favicon = self.config.resolve(&favicon);
+        let favicon_path = favicon.canonicalize().unwrap_or_else(|_| favicon.clone());
+        if !self.config.embed_favicon() {
+            // If the favicon isn't found then one isn't inserted.
+            if !favicon_path.is_file() {
+                return Ok(String::new());
+            }
+            let dest = favicon_dest(&self.config, &favicon);
+            let href = links::relative_url(page_dest, &dest);
+            self.linked_favicons.insert(favicon_path.clone(), dest);
+            return Ok(format!(
+                "<link rel=\"icon\" type=\"{}\" href=\"{}\">",
+                favicon_type(&favicon_path),
+                htmlescape::encode_minimal(&href)
+            ));
+        }
+
         let favicon_encoded = if let Some(contents) = self.assets.get(&favicon_path) {
-            contents.clone()
+            contents
         }
         else {
             // If the favicon isn't found then one isn't inserted.
             let encoded = if favicon_path.is_file() {
                 let b64 = read_to_base64_string(favicon_path.clone()).await?;
                 // Base64 encode the favicon and wrap it in the icon HTML
-                format!("<link rel=\"icon\" type=\"image/x-icon\" href=\"data:image/x-icon;base64,{b64}\">",)
+                let mime = favicon_type(&favicon_path);
+                format!("<link rel=\"icon\" type=\"{mime}\" href=\"data:{mime};base64,{b64}\">",)
             }
             else {
                 String::new()
             };
 
+            self.warn_if_large(&favicon_path, &encoded, self.config.favicon_inline_limit());
             self.assets.insert(favicon_path, encoded.clone());
             encoded
         };
@@ -395,6 +1716,113 @@ impl Website
         Ok(favicon_encoded)
     }
 
+    /// The favicon of the page generated from `source_file`: the page's own,
+    /// then the one in the shared page info it's merged over, then the
+    /// default. A missing default favicon just isn't inserted, but one the
+    /// page asks for is likely a mistake, so it's warned about, or fails the
+    /// page with `generation.strict`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a requested favicon doesn't exist, in strict
+    /// mode.
+    fn favicon_fallback(&self, page_info: &PageInfo, source_file: &Path) -> Result<PathBuf>
+    {
+        let config = &self.config;
+        let shared = page_info.shared_favicon();
+        let requested = page_info.favicon.iter().chain(shared.iter());
+        for favicon in requested {
+            if config.resolve(favicon).is_file() {
+                return Ok(favicon.clone());
+            }
+            if config.strict() {
+                return Err(Error::MissingFavicon {
+                    source_file:           source_file.to_path_buf(),
+                    expected_favicon_file: favicon.clone(),
+                });
+            }
+            self.warnings.warn(Warning::MissingFavicon {
+                source_file: source_file.to_path_buf(),
+                favicon:     favicon.clone(),
+            });
+        }
+        Ok(config.default.favicon.clone())
+    }
+
+    /// Copy the favicons pages link to into the dest dir.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a favicon cannot be copied.
+    pub(crate) async fn copy_linked_favicons(&self) -> Result<()>
+    {
+        let favicons = self
+            .linked_favicons
+            .iter()
+            .map(|x| (x.key().clone(), x.value().clone()))
+            .collect::<Vec<_>>();
+        for (source, dest) in favicons {
+            let contents = fs::read(&source)
+                .await
+                .map_err(|e| Error::Io { err: e, path: source })?;
+            write_output(&dest, contents).await?;
+        }
+        Ok(())
+    }
+
+    /// Where the page generated from `source_file`, or the combined page of
+    /// the book whose section it is, is written.
+    fn page_dest(&self, source_file: &Path) -> Result<PathBuf>
+    {
+        let config = &self.config;
+        if let Some(book) = config
+            .books
+            .iter()
+            .flatten()
+            .find(|book| config.source.join(&book.section) == source_file)
+        {
+            return Ok(book::dest(config, book));
+        }
+        let extention = source_file
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        self.dest_path(source_file, &extention)
+    }
+
+    /// Where the output generated from `source_file` is written, using the
+    /// page info scanned when the build collected its source files.
+    pub(crate) fn dest_path(&self, source_file: &Path, extention: &str) -> Result<PathBuf>
+    {
+        dest_path_in(&self.config, &self.page_infos, source_file, extention)
+    }
+
+    /// Warn about the asset at `path` if the HTML inlining it is over
+    /// `limit` bytes.
+    fn warn_if_large(&self, path: &Path, inlined: &str, limit: usize)
+    {
+        if inlined.len() > limit {
+            self.warnings.warn(Warning::LargeInlineAsset {
+                asset: path.to_path_buf(),
+                size: inlined.len(),
+                limit,
+            });
+        }
+    }
+
+    /// [`write_output`] the page built from `source_file`, timing it, unless
+    /// the build was cancelled.
+    async fn write_page(&self, source_file: &Path, dest_file: &Path, contents: impl AsRef<[u8]>) -> Result<()>
+    {
+        self.check_cancelled()?;
+        let started = Instant::now();
+        write_output(dest_file, contents).await?;
+        self.timings.record(source_file, Stage::Write, started.elapsed());
+        Ok(())
+    }
+
+    /// Generate the dest file of `source_file`, and report what was done.
+    ///
     /// # Errors
     ///
     /// Will return an error if
@@ -407,120 +1835,289 @@ impl Website
     ///
     /// Will panic if:
     ///
-    /// - `source_file`'s file stem cannot be extracted.
+    /// - `source_file`'s file name cannot be extracted.
     pub async fn make_html_from_md(
         &self,
         source_file: (PathBuf, String),
         pb: indicatif::ProgressBar,
         rebuild_all: bool,
-    ) -> Result<()>
+    ) -> Result<PageReport>
     {
+        let started = Instant::now();
         let config = &self.config;
         let (source_file, source_file_extention) = source_file;
-        let source_file_name = source_file.file_stem().unwrap();
-        let here = PathBuf::from(".").canonicalize().map_err(|e| {
-            Error::Io {
-                err:  e,
-                path: PathBuf::from("."),
-            }
-        })?;
-        let source_path_stem = source_file
-            .iter()
-            .skip_while(|x| *x != here.file_name().unwrap())
-            .skip(2)
-            .collect::<PathBuf>();
-        let dest_dir = config.dest.join(source_path_stem.parent().unwrap_or(&source_path_stem));
+        let dest_file = self.dest_path(&source_file, &source_file_extention)?;
+        let report = |status| PageReport::new(source_file.clone(), dest_file.clone(), status, started.elapsed());
 
+        if self.is_linked(&source_file, &source_file_extention) {
+            self.check_cancelled()?;
+            write_link(&source_file, &dest_file).await?;
+            return Ok(report(PageStatus::Copied));
+        }
         match &*source_file_extention {
             "md" | "markdown" => (),
-            "css" | "html" | "htm" => {
-                let mut contents = fs::read_to_string(&source_file).await.map_err(|e| {
+            extensions::COPY => {
+                let contents = fs::read(&source_file).await.map_err(|e| {
                     Error::Io {
                         err:  e,
                         path: source_file.clone(),
                     }
                 })?;
-
-                // Perform final actions on html
-                if source_file_extention != "css" {
-                    if let Some(generation) = &config.generation {
-                        if generation.treat_source_as_template.unwrap_or(false) {
-                            let stylesheet = self.get_stylesheet(config.default.stylesheet.clone()).await?;
-                            let favicon = self.get_favicon(config.default.favicon.clone()).await?;
-                            self.apply_to_template(&mut contents, None, None, &favicon, &stylesheet);
-                        }
-                        if let Some(process_config) = &generation.process {
-                            if process_config.minify {
-                                contents = post_process_html(contents);
-                            }
-                        }
-                    }
+                self.write_page(&source_file, &dest_file, &contents).await?;
+                return Ok(report(PageStatus::Copied));
+            }
+            extensions::CONVERT => {
+                let html = self.render_converted(&source_file).await?;
+                self.write_page(&source_file, &dest_file, &html).await?;
+                self.page_rendered(&source_file, &dest_file, &html);
+                return Ok(report(PageStatus::Built));
+            }
+            "css" | "js" => {
+                let contents = self.copy_asset(&source_file, &source_file_extention).await?;
+                self.write_page(&source_file, &dest_file, &contents).await?;
+                return Ok(report(PageStatus::Copied));
+            }
+            "html" | "htm" => {
+                let contents = self.render_html(&source_file).await?;
+                self.write_page(&source_file, &dest_file, &contents).await?;
+                self.page_rendered(&source_file, &dest_file, &contents);
+
+                let treat_source_as_template = config
+                    .generation
+                    .as_ref()
+                    .and_then(|generation| generation.treat_source_as_template)
+                    .unwrap_or(false);
+                return Ok(report(if treat_source_as_template {
+                    PageStatus::Built
                 }
+                else {
+                    PageStatus::Copied
+                }));
+            }
+            _ => return Ok(report(PageStatus::Skipped)),
+        }
 
-                let dest_file = dest_dir.join(source_file.file_name().unwrap());
-                fs::write(&dest_file, contents).await.map_err(|e| {
-                    Error::Io {
-                        err:  e,
-                        path: dest_file,
-                    }
-                })?;
-
-                return Ok(());
+        // If the destination exists, and neither the source nor the files it
+        // uses, such as the shared page info, shortcodes, head_extra files, and
+        // a Sass stylesheet and its partials, are more recent'ly modified than
+        // the destination, then we skip generating this file. A page added in
+        // memory has no file to compare, so it's always generated.
+        if !rebuild_all && !self.virtual_pages.contains_key(&source_file) {
+            let source = self.read_source(&source_file).await?;
+            let dependencies = PageDependencies::from_markdown(&source, &source_file, config, false);
+            // A missing one is reported when the page is rendered.
+            let used_files = dependencies
+                .meta_from
+                .into_iter()
+                .chain(dependencies.shortcodes)
+                .chain(dependencies.head_extra)
+                .chain(dependencies.analytics)
+                .chain(dependencies.stylesheet.filter(|x| sass::is_sass(x)))
+                .chain(dependencies.stylesheet_partials)
+                .filter(|x| x.is_file())
+                .collect::<Vec<_>>();
+            let section_files = sections::files(config, &source_file);
+            // A missing one is reported when the page is rendered.
+            let included_files = includes::files(&source)
+                .into_iter()
+                .filter(|x| x.is_file())
+                .collect::<Vec<_>>();
+            // The summary of an mdBook is the page's navigation.
+            let summary = mdbook::book_of(config, &source_file).and_then(|book| mdbook::summary_path(config, book));
+            let inputs = std::iter::once(source_file.as_path())
+                .chain(used_files.iter().map(PathBuf::as_path))
+                .chain(section_files.iter().map(PathBuf::as_path))
+                .chain(included_files.iter().map(PathBuf::as_path))
+                .chain(summary.as_deref())
+                .collect::<Vec<_>>();
+            if !should_regenerate_file(&inputs, &dest_file)? {
+                return Ok(report(PageStatus::Skipped));
             }
-            _ => return Ok(()),
         }
 
-        let dest_file = dest_dir.join(format!("{}.html", source_file_name.to_string_lossy()));
+        let html = match self.render_markdown(&source_file).await {
+            Ok(x) => x,
+            Err(Error::MissingTemplate {
+                source_file,
+                expected_template_file,
+            }) => {
+                // Skip this file
+                self.warnings.warn(Warning::MissingTemplate {
+                    source_file,
+                    template: expected_template_file,
+                });
+                return Ok(report(PageStatus::Skipped));
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.write_page(&source_file, &dest_file, &html).await?;
+        self.page_rendered(&source_file, &dest_file, &html);
 
-        // If the destination exists, and the source is more recent'ly modified than the
-        // destination, then we skip generating this file.
-        if !rebuild_all && !should_regenerate_file(&source_file, &dest_file)? {
-            return Ok(());
+        pb.inc(1);
+        Ok(report(PageStatus::Built))
+    }
+
+    /// Render a source file to what's written to its dest path, or `None` if
+    /// it isn't a page.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the page cannot be rendered.
+    pub async fn render(&self, source_file: &Path, extention: &str) -> Result<Option<String>>
+    {
+        match extention {
+            "md" | "markdown" => self.render_markdown(source_file).await.map(Some),
+            "html" | "htm" => self.render_html(source_file).await.map(Some),
+            extensions::CONVERT => self.render_converted(source_file).await.map(Some),
+            _ => Ok(None),
         }
+    }
 
+    /// Render a markdown source into its template, minifying it if
+    /// configured.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if:
+    ///
+    /// - `source_file` cannot be read into a string
+    /// - The markdown or page info cannot be parsed
+    /// - The template, favicon, or stylesheet cannot be read
+    async fn render_markdown(&self, source_file: &Path) -> Result<String>
+    {
         // Parse the markdown into HTML
-        let source = fs::read_to_string(&source_file).await.map_err(|e| {
-            Error::Io {
-                err:  e,
-                path: source_file.clone(),
-            }
+        let source = self.read_source(source_file).await?;
+        Ok(self.render_markdown_source(&source, source_file).await?.1)
+    }
+
+    /// Render the markdown `source` of the page at `source_file` into its
+    /// template, minifying it if configured, without reading the page.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if:
+    ///
+    /// - The markdown or page info cannot be parsed
+    /// - The template, favicon, or stylesheet cannot be read
+    pub async fn render_markdown_source(&self, source: &str, source_file: &Path) -> Result<(PageInfo, String)>
+    {
+        let (html, page_info) = cpu_bound(|| {
+            self.timings.time(source_file, Stage::Parse, || {
+                self.parse_markdown(source, source_file.to_path_buf())
+            })
         })?;
-        let (mut html, page_info) = self.parse_markdown(&source, source_file.clone())?;
-        html = match self.integrate_html_into_template(page_info, source_file, html).await {
-            Ok(x) => x,
-            Err(_) => return Ok(()),
-        };
+        let started = Instant::now();
+        let html = self
+            .integrate_html_into_template(page_info.clone(), source_file.to_path_buf(), html)
+            .await?;
+        self.timings.record(source_file, Stage::Template, started.elapsed());
+        Ok((page_info, self.post_process(source_file, html)))
+    }
 
-        // Create the parent dir in the destination path
-        let dest_path_parent = dest_file.parent().unwrap_or(&dest_file);
-        if !dest_path_parent.exists() {
-            fs::create_dir_all(dest_path_parent).await.map_err(|e| {
-                Error::Io {
-                    err:  e,
-                    path: dest_path_parent.to_path_buf(),
-                }
-            })?;
-        }
+    /// Run the HTML postprocessors, e.g. `minify`, on the page generated from
+    /// `source_file`.
+    pub(crate) fn post_process(&self, source_file: &Path, html: String) -> String
+    {
+        cpu_bound(|| self.hooks.postprocess_html(&self.hook_context(source_file), html))
+    }
 
+    /// Read an HTML source, using it as a template and minifying it if
+    /// configured.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if:
+    ///
+    /// - `source_file` cannot be read into a string
+    /// - The favicon or stylesheet cannot be read
+    async fn render_html(&self, source_file: &Path) -> Result<String>
+    {
+        let config = &self.config;
+        let mut contents = fs::read_to_string(source_file).await.map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: source_file.to_path_buf(),
+            }
+        })?;
 
+        // Perform final actions on html
         if let Some(generation) = &config.generation {
-            if let Some(process_config) = &generation.process {
-                if process_config.minify {
-                    html = post_process_html(html);
-                }
+            if generation.treat_source_as_template.unwrap_or(false) {
+                let stylesheet = self.get_stylesheet(config.default.stylesheet.clone()).await?;
+                let favicon = self
+                    .get_favicon(config.default.favicon.clone(), &self.page_dest(source_file)?)
+                    .await?;
+                let head = self.get_head(None).await?;
+                contents = self.extend_template(source_file, contents).await?;
+                self.warn_unknown_tokens(&contents, source_file);
+                self.timings.time(source_file, Stage::Template, || {
+                    self.apply_to_template(&mut contents, source_file, None, None, &favicon, &stylesheet, &head);
+                });
             }
         }
+        Ok(self.post_process(source_file, contents))
+    }
+
+    /// Convert a source with its [converter](extensions::convert), putting its
+    /// HTML into the default template and minifying it if configured.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if:
+    ///
+    /// - The converter cannot be run or fails
+    /// - The template, favicon, or stylesheet cannot be read
+    async fn render_converted(&self, source_file: &Path) -> Result<String>
+    {
+        let started = Instant::now();
+        let html = extensions::convert(&self.config, source_file).await?;
+        self.timings.record(source_file, Stage::Parse, started.elapsed());
+        let page_info = PageInfo::new(extensions::title(&html, source_file), String::new());
+        let started = Instant::now();
+        let html = self
+            .integrate_html_into_template(page_info, source_file.to_path_buf(), html)
+            .await?;
+        self.timings.record(source_file, Stage::Template, started.elapsed());
+        Ok(self.post_process(source_file, html))
+    }
+
+    /// If the source file at `source_file` is a symlink that's written to the
+    /// dest dir as one, with `generation.symlinks = "link"`. Only files that
+    /// are copied as they are can be.
+    fn is_linked(&self, source_file: &Path, extention: &str) -> bool
+    {
+        let verbatim = match extention {
+            extensions::COPY => true,
+            "css" => !self.config.minify_css(),
+            "js" => !self.config.minify_js(),
+            _ => false,
+        };
+        verbatim && self.config.symlinks() == Symlinks::Link && source_file.is_symlink()
+    }
 
-        // Write out the file
-        fs::write(&dest_file, html).await.map_err(|e| {
+    /// Read a CSS or JavaScript source, minifying it if configured.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if `source_file` cannot be read into a string.
+    async fn copy_asset(&self, source_file: &Path, extention: &str) -> Result<String>
+    {
+        let contents = fs::read_to_string(source_file).await.map_err(|e| {
             Error::Io {
                 err:  e,
-                path: dest_file,
+                path: source_file.to_path_buf(),
             }
         })?;
-
-        pb.inc(1);
-        Ok(())
+        Ok(match extention {
+            "css" if self.config.minify_css() => {
+                cpu_bound(|| self.timings.time(source_file, Stage::Minify, || minify::css(&contents)))
+            }
+            "js" if self.config.minify_js() => {
+                cpu_bound(|| self.timings.time(source_file, Stage::Minify, || minify::js(&contents)))
+            }
+            _ => contents,
+        })
     }
 
     /// # Errors
@@ -542,125 +2139,162 @@ impl Website
             Some(x) => x,
             None => config.default.stylesheet.clone(),
         };
-        let template = match page_info.template.clone() {
-            Some(x) => x,
-            None => config.default.template.clone(),
-        };
-        // If the template file doesn't exist, skip this file
-        if !template.is_file() {
-            Error::MissingTemplate {
-                source_file,
-                expected_template_file: template,
+        let template = page_info
+            .template
+            .clone()
+            .unwrap_or_else(|| config.default.template.clone());
+        let mut in_memory = self.unresolved_file(&template);
+        let template = config.resolve(&template);
+        let mut html = html;
+        if in_memory.is_none() && !template.is_file() {
+            let e = Error::MissingTemplate {
+                source_file:            source_file.clone(),
+                expected_template_file: template.clone(),
+            };
+            if !config.error_markers() {
+                return Err(e);
             }
-            .report();
-            return Err(Error::IntegraionIntoTemplate);
+            // Show the page in the built-in template instead.
+            self.warnings.warn(Warning::ErrorMarker {
+                source_file: source_file.clone(),
+                err:         e.to_string(),
+            });
+            html = format!("<p>{}</p>{html}", report::error_marker(&e.to_string()));
+            in_memory = Some(defaults::DEFAULT_HTML_TEMPLATE_SRC.to_string());
         }
 
-        // Get the favicon file path
-        let favicon_path = page_info
-            .favicon
-            .clone()
-            .unwrap_or(PathBuf::from(&config.default.favicon));
-        let favicon_path = favicon_path.canonicalize().unwrap_or(favicon_path);
-        let favicon = self.get_favicon(favicon_path).await?;
+        let favicon_path = self.favicon_fallback(&page_info, &source_file)?;
+        let favicon = self.get_favicon(favicon_path, &self.page_dest(&source_file)?).await?;
         let stylesheet = self.get_stylesheet(stylesheet).await?;
+        let head = self.get_head(Some(&page_info)).await?;
 
         // Add the markdown html into the template html, then write it out.
-        let mut template = fs::read_to_string(&template).await.map_err(|e| {
+        let template_path = template;
+        let template = match in_memory {
+            Some(template) => template,
+            None => self.get_template(&template_path).await?,
+        };
+        let mut template = self.extend_template(&template_path, template).await?;
+        self.warn_unknown_tokens(&template, &template_path);
+
+        self.apply_to_template(
+            &mut template,
+            &source_file,
+            Some(&html),
+            Some(&page_info),
+            &favicon,
+            &stylesheet,
+            &head,
+        );
+        Ok(template)
+    }
+
+    /// The contents of the template file at `path`, read once for every page
+    /// that uses it, and again if it changes.
+    async fn get_template(&self, path: &Path) -> Result<String>
+    {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(contents) = self.assets.get(&path) {
+            return Ok(contents);
+        }
+        let contents = fs::read_to_string(&path).await.map_err(|e| {
             Error::Io {
                 err:  e,
-                path: template.clone(),
+                path: path.clone(),
             }
         })?;
-
-        self.apply_to_template(&mut template, Some(html), Some(page_info), &favicon, &stylesheet);
-        Ok(template)
+        self.assets.insert(path, contents.clone());
+        Ok(contents)
     }
 
-    fn apply_to_template(
-        &self,
-        template: &mut String,
-        html: Option<String>,
-        page_info: Option<PageInfo>,
-        favicon: &str,
-        stylesheet: &str,
-    )
+    /// The template at `path`, whose contents are `template`, merged with the
+    /// templates it [extends](inheritance).
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a template it extends is missing or cannot be
+    /// read, they extend each other, or a block isn't closed.
+    pub(crate) async fn extend_template(&self, path: &Path, template: String) -> Result<String>
     {
-        if let Some(html) = html {
-            *template = template.replace(TEMPLATE_NAME_BODY, html.as_ref());
-        }
-        if let Some(page_info) = page_info {
-            use htmlescape::encode_minimal;
-            let (site_name, authors) = match &page_info.meta {
-                Some(meta) => (meta.site_name.as_str(), meta.authors.join(", ")),
+        let mut paths = vec![path.to_path_buf()];
+        let mut templates = vec![template];
+        while let Some(base) = templates.last().and_then(|x| inheritance::extends(x)) {
+            let base = PathBuf::from(base);
+            let resolved = self.config.resolve(&base);
+            if paths.contains(&resolved) {
+                return Err(Error::TemplateInheritance {
+                    path: path.to_path_buf(),
+                    err:  format!("\"{}\" extends itself", resolved.display()),
+                });
+            }
+            let contents = match self.unresolved_file(&base) {
+                Some(contents) => contents,
+                None if resolved.is_file() => self.get_template(&resolved).await?,
                 None => {
-                    (
-                        self.config
-                            .default
-                            .meta
-                            .as_ref()
-                            .map_or("", |meta| meta.site_name.as_str()),
-                        self.config
-                            .default
-                            .meta
-                            .as_ref()
-                            .map_or_else(String::new, |meta| meta.authors.join(", ")),
-                    )
+                    return Err(Error::TemplateInheritance {
+                        path: paths.last().cloned().unwrap_or_default(),
+                        err:  format!("The base template \"{}\" doesn't exist", base.display()),
+                    });
                 }
             };
-
-            // HTML escape anything needed
-            let (site_name, authors) = (encode_minimal(site_name), encode_minimal(&authors));
-            let mut title = page_info.title;
-            if let Some(meta) = &self.config.meta {
-                if let Some(append_site_name_to_title) = &meta.append_site_name_to_title {
-                    match append_site_name_to_title {
-                        crate::MetaAppendSiteNameToTitle::Default(x) => {
-                            if *x {
-                                title.push_str(&format!(" — {site_name}"));
-                            }
-                        }
-                        crate::MetaAppendSiteNameToTitle::Custom(s) => title.push_str(&format!("{s}{site_name}")),
-                    }
-                }
+            paths.push(resolved);
+            templates.push(contents);
+        }
+        inheritance::merge(&templates).map_err(|err| {
+            Error::TemplateInheritance {
+                path: path.to_path_buf(),
+                err,
             }
+        })
+    }
 
-            *template = template
-                .replace(TEMPLATE_NAME_TITLE, &title)
-                .replace(TEMPLATE_NAME_DESC, &page_info.description)
-                .replace(TEMPLATE_NAME_SITENAME, &site_name)
-                .replace(TEMPLATE_NAME_AUTHORS, &authors);
+    /// Warn about the tokens in the template at `path` that wouldn't be
+    /// replaced.
+    fn warn_unknown_tokens(&self, template: &str, path: &Path)
+    {
+        for token in self.unknown_template_tokens(template) {
+            self.warnings.warn(Warning::UnknownTemplateToken {
+                template: path.to_path_buf(),
+                token,
+            });
         }
-
-        *template = template
-            .replace(TEMPLATE_NAME_FAVICON, favicon)
-            .replace(TEMPLATE_NAME_STYLESHEET, stylesheet);
     }
-}
 
-fn post_process_html(mut html: String) -> String
-{
-    // Only minify if config.generation.process.minify == true
-
-    // Create a byte vector containing the html. We feed this into an html minifier,
-    // then reconstruct a string from it.
-    let mut html_b: Vec<u8> = html.as_bytes().to_vec();
-    let mut cfg = minify_html::Cfg::new();
-    cfg.minify_css = true;
-    cfg.ensure_spec_compliant_unquoted_attribute_values = true;
-    html_b = minify_html::minify(&html_b, &cfg);
-    html = String::from_utf8_lossy(&html_b).to_string();
-    html
+    /// Replace the placeholders in `template` with the values their providers
+    /// supply for this page.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn apply_to_template(
+        &self,
+        template: &mut String,
+        source_file: &Path,
+        html: Option<&str>,
+        page_info: Option<&PageInfo>,
+        favicon: &str,
+        stylesheet: &str,
+        head: &str,
+    )
+    {
+        let context = PlaceholderContext {
+            config: &self.config,
+            source_file: Some(source_file),
+            page_info,
+            body: html,
+            favicon,
+            stylesheet,
+            head,
+            assets: &self.assets,
+        };
+        self.placeholders.apply(template, &context);
+    }
 }
 
 #[cfg(test)]
 mod tests
 {
-    use dashmap::DashMap;
+    use indicatif::ProgressBar;
 
     use super::*;
 
-
     #[test]
     /// Test that github-like emoji parsing works properly
     fn test_emoji_markdown_parsing()
@@ -670,7 +2304,7 @@ mod tests
             .themes
             .remove(&config.syntax_theme)
             .unwrap();
-        let assets: Arc<DashMap<PathBuf, String>> = Arc::new(DashMap::new());
+        let assets = Arc::new(AssetCache::new());
         let site = Website::new(config, SyntaxSet::load_defaults_newlines(), assets, theme);
         let markdown = r#"```pageinfo
 title = "hello world"
@@ -685,6 +2319,188 @@ template = "template.html"
         assert!(html.contains('😄'));
     }
 
+    #[test]
+    /// Test that emoji aren't replaced with `generation.emoji = false`
+    fn test_emoji_disabled()
+    {
+        let mut config = Config::default();
+        config.generation = Some(toml::from_str("emoji = false").unwrap());
+        let site = site_with(config);
+        let markdown = "```pageinfo\ntitle = \"A\"\ndescription = \"\"\n```\n\n# Hello World :smile:";
+        let (html, _) = site.parse_markdown(markdown, PathBuf::new()).unwrap();
+        assert!(html.contains(":smile:"));
+    }
+
+    #[test]
+    /// Test that a page with `emoji = false` doesn't have its emoji replaced
+    fn test_emoji_disabled_on_page()
+    {
+        let site = site_with(Config::default());
+        let markdown = "```pageinfo\ntitle = \"A\"\ndescription = \"\"\nemoji = false\n```\n\n# Hello World :smile:";
+        let (html, _) = site.parse_markdown(markdown, PathBuf::new()).unwrap();
+        assert!(html.contains(":smile:"));
+    }
+
+    #[test]
+    /// Test that a locked page only builds with the hash of its source, which
+    /// doesn't include the hash itself
+    fn test_locked_page()
+    {
+        let config = Config::default();
+        let theme = highlighting::ThemeSet::load_defaults()
+            .themes
+            .remove(&config.syntax_theme)
+            .unwrap();
+        let assets = Arc::new(AssetCache::new());
+        let site = Website::new(config, SyntaxSet::load_defaults_newlines(), assets, theme);
+        let markdown = "```pageinfo\ntitle = \"Terms\"\ndescription = \"Terms\"\nlocked = true\n```\n\nBe nice.\n";
+        let hash = match site.parse_markdown(markdown, PathBuf::new()) {
+            Err(Error::LockedPageChanged { hash, .. }) => hash,
+            x => panic!("{x:?}"),
+        };
+        assert_eq!(hash, page_hash(markdown));
+
+        let approved = markdown.replace(
+            "locked = true\n",
+            &format!("locked = true\napproved_hash = \"{hash}\"\n"),
+        );
+        assert_eq!(page_hash(&approved), hash);
+        assert!(site.parse_markdown(&approved, PathBuf::new()).is_ok());
+        let changed = approved.replace("nice", "kind");
+        assert!(site.parse_markdown(&changed, PathBuf::new()).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    /// Test that the other tasks on a worker thread run while it's busy with
+    /// CPU-bound work
+    async fn test_cpu_bound_frees_worker()
+    {
+        let received = tokio::spawn(async {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            tokio::spawn(async move { sender.send(()).unwrap() });
+            cpu_bound(|| receiver.recv_timeout(std::time::Duration::from_secs(10)))
+        });
+        assert!(received.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    /// Test that CPU-bound work is run in place on a current-thread runtime
+    async fn test_cpu_bound_current_thread()
+    {
+        assert_eq!(cpu_bound(|| 1 + 1), 2);
+    }
+
+    #[test]
+    /// Test that CPU-bound work is run in place outside of a runtime
+    fn test_cpu_bound_without_runtime()
+    {
+        assert_eq!(cpu_bound(|| 1 + 1), 2);
+    }
+
+    #[test]
+    /// Test that pages dated after now are scheduled, unless future pages are
+    /// built
+    fn test_scheduled_pages()
+    {
+        let now = DateTime::parse_from_rfc3339("2024-03-14T12:00:00+00:00").unwrap();
+        let date = |x: &str| x.parse::<Datetime>().unwrap();
+        let today = now.date_naive().to_string();
+        assert!(!is_future(&date(&today), now));
+        assert!(is_future(&date("2024-03-16"), now));
+        assert!(!is_future(&date("2024-03-14T11:00:00Z"), now));
+        assert!(is_future(&date("2024-03-14T13:00:00Z"), now));
+        assert!(!is_future(&date("12:00:00"), now));
+
+        let mut page_info = Website::scan_page_info(
+            "```pageinfo\ntitle = \"A\"\ndescription = \"A\"\n```\n",
+            &toml::Table::new(),
+        )
+        .unwrap();
+        page_info.date = Some(date("9999-01-01"));
+        let mut config = Config::default();
+        assert!(is_scheduled(&config, &page_info));
+
+        // Scanned once, the page info decides what's built and counted.
+        let page_infos = PageInfos::from([
+            (PathBuf::from("src/later.md"), Some(page_info.clone())),
+            (PathBuf::from("src/plain.md"), None),
+        ]);
+        assert!(!is_included(&config, Some(&page_info)));
+        assert!(is_included(&config, None));
+        assert_eq!(scheduled_count(&config, &page_infos), 1);
+        config.set_future(true);
+        assert!(!is_scheduled(&config, &page_info));
+        assert_eq!(scheduled_count(&config, &page_infos), 0);
+    }
+
+    #[tokio::test]
+    /// Test that the site's head extras come before the page's, from HTML or
+    /// files
+    async fn test_get_head()
+    {
+        let mut config = Config::default();
+        config.default.head_extra = Some(HeadExtra::Files(vec![PathBuf::from("head.html")]));
+        let theme = highlighting::ThemeSet::load_defaults()
+            .themes
+            .remove(&config.syntax_theme)
+            .unwrap();
+        let assets = Arc::new(AssetCache::new());
+        let mut site = Website::new(config, SyntaxSet::load_defaults_newlines(), assets, theme);
+        site.add_file("head.html", "<meta name=\"site\">");
+        let page_info = PageInfo {
+            head_extra: Some(HeadExtra::Html(String::from("<meta name=\"page\">"))),
+            ..PageInfo::new(String::new(), String::new())
+        };
+        assert_eq!(site.get_head(None).await.unwrap(), "<meta name=\"site\">");
+        assert_eq!(
+            site.get_head(Some(&page_info)).await.unwrap(),
+            "<meta name=\"site\"><meta name=\"page\">"
+        );
+    }
+
+    #[tokio::test]
+    /// Test that a template is read once, and again once it changes
+    async fn test_get_template()
+    {
+        let config = Config::default();
+        let theme = highlighting::ThemeSet::load_defaults()
+            .themes
+            .remove(&config.syntax_theme)
+            .unwrap();
+        let assets = Arc::new(AssetCache::new());
+        let site = Website::new(config, SyntaxSet::load_defaults_newlines(), assets.clone(), theme);
+        let path = std::env::temp_dir().join(format!("raven-template-{}.html", std::process::id()));
+        std::fs::write(&path, "[/rustic_body/]").unwrap();
+        assert_eq!(site.get_template(&path).await.unwrap(), "[/rustic_body/]");
+        assert!(assets.get(&path.canonicalize().unwrap()).is_some());
+
+        std::fs::write(&path, "<main>[/rustic_body/]</main>").unwrap();
+        assert_eq!(site.get_template(&path).await.unwrap(), "<main>[/rustic_body/]</main>");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    /// Test that a template's format variant is used only when it exists
+    fn test_format_template()
+    {
+        let config = Config::default();
+        let theme = highlighting::ThemeSet::load_defaults()
+            .themes
+            .remove(&config.syntax_theme)
+            .unwrap();
+        let assets = Arc::new(AssetCache::new());
+        let mut site = Website::new(config, SyntaxSet::load_defaults_newlines(), assets, theme);
+        site.add_file("layouts/book.print.html", "[/rustic_body/]");
+        assert_eq!(
+            site.format_template(Path::new("layouts/book.html"), "print"),
+            PathBuf::from("layouts/book.print.html")
+        );
+        assert_eq!(
+            site.format_template(Path::new("template.html"), "print"),
+            PathBuf::from("template.html")
+        );
+    }
+
     #[test]
     /// Test that syntax-highligting works properly
     fn test_syntax_highliting_markdown_parsing()
@@ -702,7 +2518,7 @@ template = "template.html"
             .themes
             .remove(&config.syntax_theme)
             .unwrap();
-        let assets: Arc<DashMap<PathBuf, String>> = Arc::new(DashMap::new());
+        let assets = Arc::new(AssetCache::new());
         let site = Website::new(config, SyntaxSet::load_defaults_newlines(), assets, theme);
         let markdown = r#"```pageinfo
 title = "hello world"
@@ -740,4 +2556,479 @@ Suscipit cum excepturi aliquam ut."#;
             .unwrap();
         assert_eq!(b64, TEST_FILE_B64);
     }
+
+    #[test]
+    /// Test that a favicon's type comes from its extension
+    fn test_favicon_type()
+    {
+        assert_eq!(super::favicon_type(Path::new("icon.PNG")), "image/png");
+        assert_eq!(super::favicon_type(Path::new("icon.svg")), "image/svg+xml");
+        assert_eq!(super::favicon_type(Path::new("favicon.ico")), "image/x-icon");
+    }
+
+    #[test]
+    /// Test that permalink patterns expand, need a date only when they use
+    /// one, and can't leave the dest dir
+    fn test_permalink()
+    {
+        let date = "2024-03-09".parse::<toml::value::Datetime>().ok().and_then(|x| x.date);
+        let pattern = "/:year/:month/:slug/";
+        assert_eq!(
+            super::expand_permalink(pattern, "hello", "posts", date).as_deref(),
+            Some("/2024/03/hello/")
+        );
+        assert_eq!(super::expand_permalink(pattern, "hello", "posts", None), None);
+        assert_eq!(
+            super::expand_permalink(":section/:slug", "hello", "", None).as_deref(),
+            Some("/hello")
+        );
+        assert_eq!(
+            super::permalink_file("/2024/03/hello/"),
+            Some(PathBuf::from("2024/03/hello/index.html"))
+        );
+        assert_eq!(super::permalink_file("../about"), Some(PathBuf::from("about.html")));
+        assert_eq!(super::permalink_file("/"), Some(PathBuf::from("index.html")));
+        assert_eq!(super::permalink_file(".."), None);
+
+        let config = Config {
+            source: PathBuf::from("src"),
+            dest: PathBuf::from("dest"),
+            ..Config::default()
+        };
+        let page_info = PageInfo {
+            slug: Some(String::from("hello")),
+            ..PageInfo::new(String::new(), String::new())
+        };
+        let page_infos = PageInfos::from([(PathBuf::from("src/blog/post.md"), Some(page_info))]);
+        assert_eq!(
+            dest_path_in(&config, &page_infos, Path::new("src/blog/post.md"), "md").unwrap(),
+            PathBuf::from("dest/blog/hello.html")
+        );
+    }
+
+    #[tokio::test]
+    /// Test that outputs replace the previous ones without leaving a
+    /// temporary file, and that a partial one is cleaned up
+    async fn test_write_output()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/write-output");
+        let _ = fs::remove_dir_all(&dir).await;
+        let dest_file = dir.join("docs/index.html");
+        super::write_output(&dest_file, "old").await.unwrap();
+        super::write_output(&dest_file, "new").await.unwrap();
+        assert_eq!(fs::read_to_string(&dest_file).await.unwrap(), "new");
+        assert_eq!(std::fs::read_dir(dir.join("docs")).unwrap().count(), 1);
+
+        fs::write(dir.join("docs/page.html.raven-tmp"), "<p>tru").await.unwrap();
+        super::remove_partial_outputs(&Config {
+            dest: dir.clone(),
+            ..Config::default()
+        });
+        assert_eq!(std::fs::read_dir(dir.join("docs")).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    /// Test that an output that can't be written leaves the previous one as
+    /// it was
+    async fn test_write_output_failed()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/write-output-failed");
+        let _ = fs::remove_dir_all(&dir).await;
+        let dest_file = dir.join("index.html");
+        super::write_output(&dest_file, "old").await.unwrap();
+        fs::create_dir_all(dir.join("index.html.raven-tmp")).await.unwrap();
+        assert!(super::write_output(&dest_file, "new").await.is_err());
+        assert_eq!(fs::read_to_string(&dest_file).await.unwrap(), "old");
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that an output that can't be renamed into place doesn't leave its
+    /// temporary file behind
+    async fn test_write_output_not_renamed()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/write-output-not-renamed");
+        let _ = fs::remove_dir_all(&dir).await;
+        let dest_file = dir.join("index.html");
+        fs::create_dir_all(dest_file.join("taken")).await.unwrap();
+        assert!(super::write_output(&dest_file, "new").await.is_err());
+        assert!(!dir.join("index.html.raven-tmp").exists());
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that a copied asset that can't be written leaves the previous copy
+    /// as it was
+    async fn test_copy_asset_failed()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/copy-asset-failed");
+        let _ = fs::remove_dir_all(&dir).await;
+        fs::create_dir_all(dir.join("src")).await.unwrap();
+        fs::write(dir.join("src/style.css"), "a { color: red }").await.unwrap();
+        fs::create_dir_all(dir.join("dest/style.css.raven-tmp")).await.unwrap();
+        fs::write(dir.join("dest/style.css"), "a{}").await.unwrap();
+
+        let page = (dir.join("src/style.css"), String::from("css"));
+        let report = build_page(&site_in(&dir, false), page, ProgressBar::hidden(), true)
+            .await
+            .unwrap();
+        assert_eq!(report.status, PageStatus::Failed);
+        assert_eq!(fs::read_to_string(dir.join("dest/style.css")).await.unwrap(), "a{}");
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    /// Test that a symlinked file is linked to its target, replacing a copy
+    async fn test_write_link()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/write-link");
+        let _ = fs::remove_dir_all(&dir).await;
+        fs::create_dir_all(dir.join("src")).await.unwrap();
+        fs::write(dir.join("logo.svg"), "<svg/>").await.unwrap();
+        fs::symlink(dir.join("logo.svg"), dir.join("src/logo.svg"))
+            .await
+            .unwrap();
+        let dest_file = dir.join("docs/logo.svg");
+        super::write_output(&dest_file, "<svg/>").await.unwrap();
+        super::write_link(&dir.join("src/logo.svg"), &dest_file).await.unwrap();
+        assert_eq!(
+            fs::read_link(&dest_file).await.unwrap(),
+            dir.join("logo.svg").canonicalize().unwrap()
+        );
+        assert_eq!(std::fs::read_dir(dir.join("docs")).unwrap().count(), 1);
+    }
+
+    /// A site building `dir/src` into `dir/dest`, failing fast if `fail_fast`.
+    fn site_in(dir: &Path, fail_fast: bool) -> Website
+    {
+        let mut config = Config {
+            source: dir.join("src"),
+            dest: dir.join("dest"),
+            ..Config::default()
+        };
+        config.set_fail_fast(fail_fast);
+        site_with(config)
+    }
+
+    fn site_with(config: Config) -> Website
+    {
+        let theme = highlighting::ThemeSet::load_defaults()
+            .themes
+            .remove(&config.syntax_theme)
+            .unwrap();
+        Website::new(
+            config,
+            SyntaxSet::load_defaults_newlines(),
+            Arc::new(AssetCache::new()),
+            theme,
+        )
+    }
+
+    #[tokio::test]
+    /// Test that a page that fails to build is reported as failed instead of
+    /// failing the build
+    async fn test_failed_page()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/failed-page");
+        let _ = fs::remove_dir_all(&dir).await;
+        fs::create_dir_all(dir.join("src")).await.unwrap();
+        let source_file = dir.join("src/index.md");
+        fs::write(
+            &source_file,
+            "```pageinfo\ntitle = \"Home\"\ndescription = \"\"\ntemplate = \"/nonexistent/template.html\"\n```\n",
+        )
+        .await
+        .unwrap();
+
+        let page = (source_file.clone(), String::from("md"));
+        let report = build_page(&site_in(&dir, false), page.clone(), ProgressBar::hidden(), true)
+            .await
+            .unwrap();
+        assert_eq!(report.status, PageStatus::Failed);
+        assert_eq!(report.dest, dir.join("dest/index.html"));
+        assert!(report.error.unwrap().contains("MissingTemplateError"));
+        assert!(!dir.join("dest/index.html").exists());
+
+        let result = build_page(&site_in(&dir, true), page, ProgressBar::hidden(), true).await;
+        assert!(matches!(result, Err(Error::MissingTemplate { .. })));
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that a page added in memory is built as if it was at its path,
+    /// without a file there
+    async fn test_virtual_page()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/virtual-page");
+        let _ = fs::remove_dir_all(&dir).await;
+        let mut site = site_in(&dir, false);
+        site.add_file(site.config.default.template.clone(), "<main>[/rustic_body/]</main>");
+        let source_file = dir.join("src/posts/hello.md");
+        site.add_page(
+            &source_file,
+            "```pageinfo\ntitle = \"Hello\"\ndescription = \"\"\n```\n\nHi",
+        );
+
+        let page = (source_file.clone(), String::from("md"));
+        let report = build_page(&site, page, ProgressBar::hidden(), false).await.unwrap();
+        assert_eq!(report.status, PageStatus::Built);
+        let html = fs::read_to_string(dir.join("dest/posts/hello.html")).await.unwrap();
+        assert!(html.contains("<main><p>Hi</p>"));
+        assert!(!source_file.exists());
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that an inlined stylesheet or favicon over its limit is warned
+    /// about once, and one under it isn't
+    async fn test_large_inline_assets()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/large-inline-assets");
+        fs::create_dir_all(&dir).await.unwrap();
+        fs::write(dir.join("large.css"), "body { color: red; }").await.unwrap();
+        fs::write(dir.join("small.css"), "a{}").await.unwrap();
+        fs::write(dir.join("favicon.png"), [0; 16]).await.unwrap();
+        let mut config = Config {
+            source: dir.join("src"),
+            dest: dir.join("dest"),
+            ..Config::default()
+        };
+        config.generation = Some(toml::from_str("[inline_limits]\nfavicon = 16\nstylesheet = 20").unwrap());
+        let site = site_with(config);
+
+        site.get_stylesheet(dir.join("large.css")).await.unwrap();
+        site.get_stylesheet(dir.join("large.css")).await.unwrap();
+        site.get_stylesheet(dir.join("small.css")).await.unwrap();
+        site.get_favicon(dir.join("favicon.png"), &dir.join("dest/index.html"))
+            .await
+            .unwrap();
+        let warnings = site.warnings().take();
+        let large = warnings
+            .iter()
+            .map(|x| {
+                match x {
+                    Warning::LargeInlineAsset { asset, limit, .. } => (asset.file_name().unwrap(), *limit),
+                    x => panic!("Unexpected warning: {x}"),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            large,
+            [
+                (std::ffi::OsStr::new("large.css"), 20),
+                (std::ffi::OsStr::new("favicon.png"), 16)
+            ]
+        );
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that a cancelled build doesn't write a page
+    async fn test_cancelled_write()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/cancelled-write");
+        let _ = fs::remove_dir_all(&dir).await;
+        let mut site = site_in(&dir, false);
+        let cancellation = Cancellation::default();
+        site.set_cancellation(cancellation.clone());
+        cancellation.cancel();
+
+        let dest_file = dir.join("dest/index.html");
+        let written = site
+            .write_page(&dir.join("src/index.md"), &dest_file, "<p>Hi</p>")
+            .await;
+        assert!(matches!(written, Err(Error::Cancelled)));
+        assert!(!dest_file.exists());
+    }
+
+    #[tokio::test]
+    /// Test that the built-in default stylesheet is used when neither the
+    /// project nor the theme has it
+    async fn test_builtin_stylesheet()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/builtin-stylesheet");
+        let mut config = Config::default();
+        config.default.stylesheet = dir.join("style.css");
+        let site = site_with(config);
+        assert_eq!(
+            site.get_stylesheet(dir.join("style.css")).await.unwrap(),
+            format!("<style>{}</style>", crate::defaults::DEFAULT_CSS_STYLESHEET_SRC)
+        );
+    }
+
+    #[tokio::test]
+    /// Test that the theme's default stylesheet is used over the built-in one
+    async fn test_theme_stylesheet()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/theme-stylesheet");
+        fs::create_dir_all(&dir).await.unwrap();
+        fs::write(dir.join("raven-theme-style.css"), "a{}").await.unwrap();
+        let mut config = Config {
+            theme: Some(dir.clone()),
+            ..Config::default()
+        };
+        config.default.stylesheet = PathBuf::from("raven-theme-style.css");
+        let site = site_with(config);
+        assert_eq!(
+            site.get_stylesheet(PathBuf::from("raven-theme-style.css"))
+                .await
+                .unwrap(),
+            "<style>a{}</style>"
+        );
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that with the dev profile, a page whose template is missing is
+    /// put into the built-in one with an error marker
+    async fn test_missing_template_marker()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/missing-template-marker");
+        let mut site = site_in(&dir, false);
+        site.config.profile = Some(String::from("dev"));
+        let page_info = PageInfo::parse("title = \"A\"\ndescription = \"\"\ntemplate = \"missing.html\"").unwrap();
+        let html = site
+            .integrate_html_into_template(page_info, dir.join("src/index.md"), String::from("<p>Hi</p>"))
+            .await
+            .unwrap();
+        assert!(html.contains("class=\"raven-error\""));
+        assert!(html.contains("<p>Hi</p>"));
+        assert!(site
+            .warnings()
+            .take()
+            .iter()
+            .any(|x| matches!(x, Warning::ErrorMarker { .. })));
+    }
+
+    #[test]
+    /// Test that a linked favicon in the source dir keeps its path in it
+    fn test_favicon_dest()
+    {
+        let config = Config {
+            source: PathBuf::from("src"),
+            dest: PathBuf::from("dest"),
+            ..Config::default()
+        };
+        assert_eq!(
+            favicon_dest(&config, Path::new("src/blog/favicon.png")),
+            Path::new("dest/blog/favicon.png")
+        );
+    }
+
+    #[test]
+    /// Test that a linked favicon outside the source dir is copied to the root
+    /// of the dest dir
+    fn test_favicon_dest_outside_source()
+    {
+        let config = Config {
+            source: PathBuf::from("src"),
+            dest: PathBuf::from("dest"),
+            ..Config::default()
+        };
+        assert_eq!(
+            favicon_dest(&config, Path::new("assets/favicon.png")),
+            Path::new("dest/favicon.png")
+        );
+    }
+
+    #[test]
+    /// Test that a page whose favicon is missing falls back to the one in its
+    /// shared page info, with a warning
+    fn test_favicon_fallback()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/favicon-fallback");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("shared.png"), [0; 4]).unwrap();
+        let meta_from = dir.join("shared.toml");
+        std::fs::write(
+            &meta_from,
+            format!(
+                "favicon = {}",
+                toml::Value::String(dir.join("shared.png").display().to_string())
+            ),
+        )
+        .unwrap();
+        let page_info = PageInfo::parse(&format!(
+            "title = \"A\"\ndescription = \"\"\nfavicon = \"missing.png\"\nmeta_from = {}",
+            toml::Value::String(meta_from.display().to_string())
+        ))
+        .unwrap();
+        let site = site_with(Config::default());
+        assert_eq!(
+            site.favicon_fallback(&page_info, Path::new("src/index.md")).unwrap(),
+            dir.join("shared.png")
+        );
+        assert!(matches!(
+            &site.warnings().take()[..],
+            [Warning::MissingFavicon { favicon, .. }] if favicon == Path::new("missing.png")
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that a page whose favicons are all missing falls back to the
+    /// default one
+    fn test_favicon_fallback_default()
+    {
+        let page_info = PageInfo::parse("title = \"A\"\ndescription = \"\"\nfavicon = \"missing.png\"").unwrap();
+        let site = site_with(Config::default());
+        assert_eq!(
+            site.favicon_fallback(&page_info, Path::new("src/index.md")).unwrap(),
+            site.config().default.favicon
+        );
+    }
+
+    #[test]
+    /// Test that a missing favicon a page asks for fails it in strict mode
+    fn test_favicon_fallback_strict()
+    {
+        let page_info = PageInfo::parse("title = \"A\"\ndescription = \"\"\nfavicon = \"missing.png\"").unwrap();
+        let mut config = Config::default();
+        config.set_strict(true);
+        let site = site_with(config);
+        assert!(matches!(
+            site.favicon_fallback(&page_info, Path::new("src/index.md")),
+            Err(Error::MissingFavicon { expected_favicon_file, .. }) if expected_favicon_file == Path::new("missing.png")
+        ));
+    }
+
+    /// Page info choosing `syntax_theme`.
+    fn page_info(syntax_theme: &str) -> PageInfo
+    {
+        PageInfo::parse(&format!(
+            "title = \"A\"\ndescription = \"\"\nsyntax_theme = \"{syntax_theme}\""
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    /// Test that a page is highlighted with the syntax theme it chooses, and
+    /// the configured one if it doesn't choose one
+    fn test_page_syntax_theme()
+    {
+        let mut site = site_with(Config::default());
+        site.syntax_themes = highlighting::ThemeSet::load_defaults().themes;
+        let path = Path::new("src/index.md");
+        let chosen = site
+            .page_syntax_theme(Some(&page_info("InspiredGitHub")), path)
+            .unwrap();
+        assert!(std::ptr::eq(chosen, &site.syntax_themes["InspiredGitHub"]));
+        assert!(std::ptr::eq(
+            site.page_syntax_theme(None, path).unwrap(),
+            &site.syntax_theme
+        ));
+    }
+
+    #[test]
+    /// Test that a page choosing a syntax theme that doesn't exist fails
+    fn test_missing_page_theme()
+    {
+        let site = site_with(Config::default());
+        let path = Path::new("src/index.md");
+        assert!(matches!(
+            site.page_syntax_theme(Some(&page_info("Nonexistent")), path),
+            Err(Error::MissingPageTheme { theme, .. }) if theme == "Nonexistent"
+        ));
+    }
 }
@@ -0,0 +1,249 @@
+//! Building a site in memory, for programs using `rustic_raven` as a
+//! library.
+//!
+//! ```no_run
+//! # async fn example() -> rustic_raven::Result<()> {
+//! use rustic_raven::WebsiteBuilder;
+//!
+//! let site = WebsiteBuilder::new()
+//!     .template(
+//!         "template.html",
+//!         "<title>[/rustic_title/]</title>[/rustic_body/]",
+//!     )
+//!     .source(
+//!         "src/index.md",
+//!         "```pageinfo\ntitle = \"Home\"\ndescription = \"\"\n```\n# Hi",
+//!     )
+//!     .build()?;
+//! for page in site.render().await {
+//!     let page = page?;
+//!     println!("{}: {}", page.dest.display(), page.html);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    build::{dest_path, Website},
+    report::Warning,
+    Config, Error, PageInfo, Path, PathBuf, Result,
+};
+
+/// Sets up a site from a configuration, sources, templates, and
+/// stylesheets given as strings.
+#[derive(Debug, Clone, Default)]
+pub struct WebsiteBuilder
+{
+    config:      Config,
+    sources:     Vec<(PathBuf, String)>,
+    files:       Vec<(PathBuf, String)>,
+    stylesheets: bool,
+}
+
+impl WebsiteBuilder
+{
+    /// A builder with the default configuration and nothing in it.
+    pub fn new() -> Self { Self::default() }
+
+    /// Use `config` instead of the default configuration.
+    pub fn config(mut self, config: Config) -> Self
+    {
+        self.config = config;
+        self
+    }
+
+    /// Add a markdown page at `path`, which should be in the configured
+    /// source directory.
+    pub fn source(mut self, path: impl Into<PathBuf>, contents: impl Into<String>) -> Self
+    {
+        self.sources.push((path.into(), contents.into()));
+        self
+    }
+
+    /// Add the template at `path`, e.g. the configured `default.template`.
+    pub fn template(mut self, path: impl Into<PathBuf>, contents: impl Into<String>) -> Self
+    {
+        self.files.push((path.into(), contents.into()));
+        self
+    }
+
+    /// Add the stylesheet at `path`. Without any, the configured default
+    /// stylesheet is empty.
+    pub fn stylesheet(mut self, path: impl Into<PathBuf>, css: impl Into<String>) -> Self
+    {
+        self.files.push((path.into(), css.into()));
+        self.stylesheets = true;
+        self
+    }
+
+    /// Set up the site. Its warnings aren't printed, they're returned with
+    /// each rendered page.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the configured syntaxes or syntax theme cannot
+    /// be loaded.
+    pub fn build(self) -> Result<MemorySite>
+    {
+        let mut website = Website::from_config(self.config)?;
+        if !self.stylesheets {
+            let stylesheet = website.config().default.stylesheet.clone();
+            website.add_file(stylesheet, "");
+        }
+        for (path, contents) in self.files {
+            website.add_file(path, contents);
+        }
+        website.set_print_warnings(false);
+        Ok(MemorySite {
+            website,
+            sources: self.sources,
+        })
+    }
+}
+
+/// A site whose pages are rendered from memory, without writing anything.
+pub struct MemorySite
+{
+    website: Website,
+    sources: Vec<(PathBuf, String)>,
+}
+
+/// A page rendered by a [`MemorySite`].
+#[derive(Debug, Clone)]
+pub struct RenderedPage
+{
+    pub source:    PathBuf,
+    /// Where a build would write the page.
+    pub dest:      PathBuf,
+    pub page_info: PageInfo,
+    pub html:      String,
+    pub warnings:  Vec<Warning>,
+}
+
+impl MemorySite
+{
+    /// The website, e.g. to register placeholders or hooks on.
+    pub fn website(&mut self) -> &mut Website { &mut self.website }
+
+    /// Render every page, in the order they were added.
+    pub async fn render(&self) -> Vec<Result<RenderedPage>>
+    {
+        let mut pages = Vec::with_capacity(self.sources.len());
+        for (source, contents) in &self.sources {
+            self.website.warnings().take();
+            pages.push(self.render_page(source, contents).await);
+        }
+        pages
+    }
+
+    async fn render_page(&self, source: &Path, contents: &str) -> Result<RenderedPage>
+    {
+        let extention = source
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if extention != "md" && extention != "markdown" {
+            return Err(Error::ReadSourceDir {
+                err:  String::from("Only markdown pages can be rendered from memory"),
+                path: source.to_path_buf(),
+            });
+        }
+        let (page_info, html) = self.website.render_markdown_source(contents, source).await?;
+        Ok(RenderedPage {
+            source: source.to_path_buf(),
+            dest: dest_path(self.website.config(), source, &extention)?,
+            page_info,
+            html,
+            warnings: self.website.warnings().take(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[tokio::test]
+    /// Test that pages are rendered from in-memory sources and templates
+    async fn test_website_builder()
+    {
+        let mut site = WebsiteBuilder::new()
+            .template("memory-template.html", "<h1>[/rustic_title/]</h1>[/rustic_body/]")
+            .source(
+                "src/memory/index.md",
+                "```pageinfo\ntitle = \"Home\"\ndescription = \"\"\ntemplate = \"memory-template.html\"\n```\nHi",
+            )
+            .build()
+            .unwrap();
+        site.website().register_placeholder_fn("unused", |_| None);
+        let pages = site.render().await;
+        let page = pages[0].as_ref().unwrap();
+        assert_eq!(page.html, "<h1>Home</h1><p>Hi</p>\n");
+        assert_eq!(page.dest, PathBuf::from("dest/memory/index.html"));
+        assert!(page
+            .warnings
+            .contains(&Warning::EmptyDescription(PathBuf::from("src/memory/index.md"))));
+    }
+
+    #[tokio::test]
+    /// Test that configured tokens are replaced in templates, and in
+    /// markdown only when asked to
+    async fn test_tokens()
+    {
+        let mut config = Config {
+            tokens: Some([(String::from("github"), String::from("https://github.com/me"))].into()),
+            ..Config::default()
+        };
+        let page =
+            "```pageinfo\ntitle = \"Home\"\ndescription = \"\"\ntemplate = \"tokens.html\"\n```\n[/rustic_github/]";
+        let render = |config: Config| {
+            async {
+                let site = WebsiteBuilder::new()
+                    .config(config)
+                    .template("tokens.html", "<a href=\"[/rustic_github/]\"></a>[/rustic_body/]")
+                    .source("src/index.md", page)
+                    .build()
+                    .unwrap();
+                site.render().await.remove(0).unwrap().html
+            }
+        };
+        assert_eq!(
+            render(config.clone()).await,
+            "<a href=\"https://github.com/me\"></a><p>[/rustic_github/]</p>\n"
+        );
+
+        config.set_jobs(1);
+        config.generation.as_mut().unwrap().tokens_in_markdown = Some(true);
+        assert_eq!(
+            render(config).await,
+            "<a href=\"https://github.com/me\"></a><p>https://github.com/me</p>\n"
+        );
+    }
+
+    #[tokio::test]
+    /// Test that pages without page info fail unless it isn't required, and
+    /// are then titled and described by their content
+    async fn test_require_page_info()
+    {
+        let render = |config: Config| {
+            async {
+                let site = WebsiteBuilder::new()
+                    .config(config)
+                    .template("bare.html", "<h1>[/rustic_title/]</h1>[/rustic_description/]")
+                    .source("src/bare.md", "# Imported\n\nFrom another tree.")
+                    .build()
+                    .unwrap();
+                site.render().await.remove(0)
+            }
+        };
+        let mut config = Config::default();
+        config.default.template = PathBuf::from("bare.html");
+        assert!(render(config.clone()).await.is_err());
+
+        config.set_require_page_info(false);
+        let page = render(config).await.unwrap();
+        assert!(page.html.starts_with("<h1>Imported</h1>From another tree."));
+        assert!(page.warnings.is_empty());
+    }
+}
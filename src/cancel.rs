@@ -0,0 +1,25 @@
+//! Cancelling a build that's underway.
+//!
+//! A fatal error, or [`Cancellation::cancel`], e.g. on Ctrl-C, while
+//! [`crate::build::build_with_report`] runs cancels the build: pages that are
+//! still waiting to start don't, and pages that are being rendered stop before
+//! they write anything. Each [`crate::build::Website`] has its own, so a
+//! cancelled build doesn't cancel the next.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// If a build was cancelled, shared by the build and whatever cancels it.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation
+{
+    /// Cancel the build, returning if it already was.
+    pub fn cancel(&self) -> bool { self.0.swap(true, Ordering::SeqCst) }
+
+    /// If the build was cancelled.
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::SeqCst) }
+}
@@ -0,0 +1,208 @@
+use std::collections::BTreeSet;
+
+use tokio::fs;
+
+use crate::{
+    book,
+    build::{dest_path, walk_sources, Website},
+    extensions,
+    graph::DependencyGraph,
+    links::PageIndex,
+    Config, Error, Path, Result,
+};
+
+/// Run every stage of a build except writing files, and collect every problem
+/// found instead of stopping at the first.
+///
+/// This parses the markdown and page info of every page, integrates it into
+/// its template, renders every book, looks for unknown tokens in every
+/// template used, and for page IDs more than one page has.
+pub async fn check(config: Config) -> Vec<Error>
+{
+    let graph = match DependencyGraph::from_config(&config) {
+        Ok(x) => x,
+        Err(e) => return vec![e],
+    };
+    let site = match Website::from_config_read_only(config) {
+        Ok(x) => x,
+        Err(e) => return vec![e],
+    };
+
+    let source_files = walk_sources(site.config(), site.warnings());
+    if source_files.is_empty() {
+        return vec![Error::MissingSourceFiles(site.config().source.clone())];
+    }
+
+    let mut index = PageIndex::default();
+    index.extend(&graph);
+    let mut problems = index.duplicates();
+    for (source_file, extention) in &source_files {
+        if let Err(e) = check_source_file(&site, source_file, extention).await {
+            problems.push(e);
+        }
+    }
+    for book in site.config().books.iter().flatten() {
+        if let Err(e) = book::render(&site, book).await {
+            problems.push(e);
+        }
+    }
+
+    problems.extend(check_templates(&site, &graph).await);
+    problems
+}
+
+/// Look for unknown tokens in every template the pages of `graph` use, found
+/// in the project or its theme.
+async fn check_templates(site: &Website, graph: &DependencyGraph) -> Vec<Error>
+{
+    let mut problems = Vec::new();
+    let templates = graph
+        .pages
+        .values()
+        .filter_map(|page| page.template.as_ref())
+        .map(|template| site.config().resolve(template))
+        .collect::<BTreeSet<_>>();
+    for template in templates.into_iter().filter(|x| x.is_file()) {
+        match fs::read_to_string(&template).await {
+            Ok(contents) => {
+                // A template that can't be merged is reported with its pages.
+                let Ok(contents) = site.extend_template(&template, contents).await
+                else {
+                    continue;
+                };
+                problems.extend(site.unknown_template_tokens(&contents).into_iter().map(|token| {
+                    Error::UnresolvedTemplateToken {
+                        token,
+                        source_file: template.clone(),
+                    }
+                }));
+            }
+            Err(e) => {
+                problems.push(Error::Io {
+                    err:  e,
+                    path: template,
+                })
+            }
+        }
+    }
+    problems
+}
+
+async fn check_source_file(site: &Website, source_file: &Path, extention: &str) -> Result<()>
+{
+    let config = site.config();
+    match extention {
+        extensions::COPY => return Ok(()),
+        extensions::CONVERT => return site.render(source_file, extention).await.map(|_| ()),
+        _ => (),
+    }
+    let contents = fs::read_to_string(source_file).await.map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: source_file.to_path_buf(),
+        }
+    })?;
+
+    match extention {
+        "md" | "markdown" => {
+            let (html, page_info) = site.parse_markdown(&contents, source_file.to_path_buf())?;
+            site.integrate_html_into_template(page_info, source_file.to_path_buf(), html)
+                .await?;
+        }
+        "html" | "htm" => {
+            let treat_source_as_template = config
+                .generation
+                .as_ref()
+                .and_then(|generation| generation.treat_source_as_template)
+                .unwrap_or(false);
+            if treat_source_as_template {
+                site.get_stylesheet(config.default.stylesheet.clone()).await?;
+                let dest_file = dest_path(config, source_file, extention)?;
+                site.get_favicon(config.default.favicon.clone(), &dest_file).await?;
+                if let Some(token) = site.unknown_template_tokens(&contents).into_iter().next() {
+                    return Err(Error::UnresolvedTemplateToken {
+                        token,
+                        source_file: source_file.to_path_buf(),
+                    });
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+
+    use syntect::{highlighting, parsing::SyntaxSet};
+
+    use super::*;
+    use crate::{asset_cache::AssetCache, graph::PageDependencies, PathBuf};
+
+    /// A site using `theme`, and a graph of one page using `template`.
+    fn site_with_template(theme: &Path, template: &str) -> (Website, DependencyGraph)
+    {
+        let config = Config {
+            theme: Some(theme.to_path_buf()),
+            ..Config::default()
+        };
+        let syntax_theme = highlighting::ThemeSet::load_defaults()
+            .themes
+            .remove(&config.syntax_theme)
+            .unwrap();
+        let site = Website::new(
+            config,
+            SyntaxSet::load_defaults_newlines(),
+            Arc::new(AssetCache::new()),
+            syntax_theme,
+        );
+        let mut graph = DependencyGraph::default();
+        graph.pages.insert(
+            PathBuf::from("src/index.md"),
+            PageDependencies {
+                template: Some(PathBuf::from(template)),
+                ..PageDependencies::default()
+            },
+        );
+        (site, graph)
+    }
+
+    #[tokio::test]
+    /// Test that the unknown tokens of a template found in the theme are
+    /// reported with the template's path
+    async fn test_theme_template_tokens()
+    {
+        let theme = std::env::temp_dir().join("rustic-raven-tests/check-theme");
+        std::fs::create_dir_all(&theme).unwrap();
+        std::fs::write(theme.join("check-page.html"), "[/rustic_body/][/rustic_nothing/]").unwrap();
+
+        let (site, graph) = site_with_template(&theme, "check-page.html");
+        let problems = check_templates(&site, &graph).await;
+        assert!(matches!(
+            problems.as_slice(),
+            [Error::UnresolvedTemplateToken { token, source_file }]
+                if token == "[/rustic_nothing/]" && *source_file == theme.join("check-page.html")
+        ));
+        std::fs::remove_dir_all(theme).unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that a template that can't be read is reported with its own path
+    async fn test_unreadable_template()
+    {
+        let theme = std::env::temp_dir().join("rustic-raven-tests/check-unreadable");
+        std::fs::create_dir_all(&theme).unwrap();
+        std::fs::write(theme.join("check-binary.html"), [0xFF, 0xFE, 0x00]).unwrap();
+
+        let (site, graph) = site_with_template(&theme, "check-binary.html");
+        let problems = check_templates(&site, &graph).await;
+        assert!(matches!(
+            problems.as_slice(),
+            [Error::Io { path, .. }] if *path == theme.join("check-binary.html")
+        ));
+        std::fs::remove_dir_all(theme).unwrap();
+    }
+}
@@ -0,0 +1,511 @@
+//! Pages pulled from a headless CMS, with `[cms]`.
+//!
+//! `raven build --sync` fetches the entries through the [fetch
+//! cache](crate::fetch) from the configured endpoint, which returns a JSON
+//! array of them (or an object with one, at `cms.entries`). Each entry
+//! becomes a markdown page, its page info and body taken from the fields
+//! `[cms.fields]` maps, and is kept in [`STATE_FILE`]. Builds read the pages
+//! from there without fetching anything, and build them as if they were in
+//! `cms.section` under the source dir, so nothing is written to it.
+//!
+//! The state is saved after each entry that changed, so a sync that fails
+//! partway keeps what it synced, and the entries that are gone are dropped
+//! once every entry is synced. A page whose path is taken by a file in the
+//! source dir, such as a hand-written one with an entry's slug, fails.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    build::write_output, fetch, markdown::slugify, Cms, CmsFields, Config, Error, PageInfo, Path, PathBuf, Result,
+};
+
+/// Where the synced pages are kept, relative to the project.
+pub const STATE_FILE: &str = ".raven-cms.json";
+
+/// The synced entries, by their ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SyncState
+{
+    pub entries: BTreeMap<String, SyncedEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SyncedEntry
+{
+    pub updated_at: String,
+    pub slug:       String,
+    pub markdown:   String,
+}
+
+/// An entry, as a markdown page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPage
+{
+    pub id:         String,
+    pub updated_at: String,
+    pub slug:       String,
+    pub markdown:   String,
+}
+
+/// How many pages a sync changed and dropped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncSummary
+{
+    pub written:   usize,
+    pub unchanged: usize,
+    pub removed:   usize,
+}
+
+/// Pull the configured CMS's entries into [`STATE_FILE`], if there's one.
+///
+/// # Errors
+///
+/// Will return an error if the entries cannot be fetched or read, or the
+/// state cannot be saved, or a page [cannot be built](buildable).
+pub async fn sync(config: &Config) -> Result<Option<SyncSummary>>
+{
+    let Some(cms) = &config.cms
+    else {
+        return Ok(None);
+    };
+    let state_file = Path::new(STATE_FILE);
+    let response = fetch::get(config, &url(cms, &load(state_file)), cms.token.as_deref()).await?;
+    sync_response(config, cms, state_file, &response).await.map(Some)
+}
+
+/// Sync the entries of the endpoint's `response` into the state at
+/// `state_file`.
+///
+/// # Errors
+///
+/// Will return an error if the response or an entry cannot be read, or the
+/// state cannot be saved, or a page [cannot be built](buildable).
+async fn sync_response(config: &Config, cms: &Cms, state_file: &Path, response: &str) -> Result<SyncSummary>
+{
+    let mut state = load(state_file);
+    let mut summary = SyncSummary::default();
+    let mut seen = BTreeSet::new();
+    for entry in entries(response, cms)? {
+        let page = entry_page(&entry, &cms.fields)?;
+        buildable(&page.id, &page.slug, &page_path(config, cms, &page.slug), &state, &seen)?;
+        seen.insert(page.id.clone());
+        let synced = SyncedEntry {
+            updated_at: page.updated_at,
+            slug:       page.slug,
+            markdown:   page.markdown,
+        };
+        if state.entries.get(&page.id) == Some(&synced) {
+            summary.unchanged += 1;
+            continue;
+        }
+        // An entry that had the slug before and hasn't been synced yet gives
+        // it up.
+        let before = state.entries.len();
+        state.entries.retain(|id, x| *id == page.id || x.slug != synced.slug);
+        summary.removed += before - state.entries.len();
+        state.entries.insert(page.id, synced);
+        save(state_file, &state).await?;
+        summary.written += 1;
+    }
+
+    // With `since_param` only the changed entries are returned, so the rest
+    // are kept, and none can be told apart as removed.
+    if cms.since_param.is_none() {
+        let before = state.entries.len();
+        state.entries.retain(|id, _| seen.contains(id));
+        if state.entries.len() < before {
+            summary.removed += before - state.entries.len();
+            save(state_file, &state).await?;
+        }
+    }
+    Ok(summary)
+}
+
+/// The synced pages, by the path in the source dir they're built as. There
+/// are none without `[cms]`, or before the first sync.
+///
+/// # Errors
+///
+/// Will return an error if a page's path is taken by a file in the source
+/// dir.
+pub fn pages(config: &Config) -> Result<Vec<(PathBuf, String)>>
+{
+    let Some(cms) = &config.cms
+    else {
+        return Ok(Vec::new());
+    };
+    state_pages(config, cms, Path::new(STATE_FILE))
+}
+
+/// The pages synced into the state at `state_file`.
+///
+/// # Errors
+///
+/// Will return an error if a page's path is taken by a file in the source
+/// dir.
+fn state_pages(config: &Config, cms: &Cms, state_file: &Path) -> Result<Vec<(PathBuf, String)>>
+{
+    load(state_file)
+        .entries
+        .into_iter()
+        .map(|(id, entry)| {
+            let path = page_path(config, cms, &entry.slug);
+            untaken(&id, &path)?;
+            Ok((path, entry.markdown))
+        })
+        .collect()
+}
+
+/// The path in the source dir the page with `slug` is built as.
+fn page_path(config: &Config, cms: &Cms, slug: &str) -> PathBuf
+{
+    config.source.join(&cms.section).join(format!("{slug}.md"))
+}
+
+/// The state saved at `state_file`, or none if there's none or it cannot be
+/// read.
+fn load(state_file: &Path) -> SyncState
+{
+    std::fs::read_to_string(state_file)
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default()
+}
+
+async fn save(state_file: &Path, state: &SyncState) -> Result<()>
+{
+    write_output(state_file, serde_json::to_string_pretty(state).unwrap()).await
+}
+
+/// Check that the page of the entry `id` can be built as `path`: there's no
+/// file there, and no other entry this sync has `seen` has the same `slug`
+/// in `state`.
+///
+/// # Errors
+///
+/// Will return an error if there's a file at `path`, such as a hand-written
+/// page with the entry's slug, or another entry of this sync has the same
+/// page.
+fn buildable(id: &str, slug: &str, path: &Path, state: &SyncState, seen: &BTreeSet<String>) -> Result<()>
+{
+    let same_page = state
+        .entries
+        .iter()
+        .find(|(x, entry)| *x != id && seen.contains(*x) && entry.slug == slug);
+    if let Some((other, _)) = same_page {
+        return Err(Error::CmsSync(format!(
+            "Entry \"{id}\" has the same page as entry \"{other}\", \"{}\"",
+            path.display()
+        )));
+    }
+    untaken(id, path)
+}
+
+/// Check that there's no file at `path`, the page of the entry `id`.
+///
+/// # Errors
+///
+/// Will return an error if there's a file at `path`.
+fn untaken(id: &str, path: &Path) -> Result<()>
+{
+    if path.exists() {
+        return Err(Error::CmsSync(format!(
+            "Entry \"{id}\"'s page is \"{}\", which is a file in the source dir",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// The URL entries are fetched from: the endpoint, with the latest
+/// `updated_at` synced so far in `since_param` if it's set.
+pub fn url(cms: &Cms, state: &SyncState) -> String
+{
+    let since = state.entries.values().map(|x| x.updated_at.as_str()).max();
+    match (&cms.since_param, since) {
+        (Some(param), Some(since)) => {
+            let separator = if cms.endpoint.contains('?') { '&' } else { '?' };
+            format!("{}{separator}{param}={}", cms.endpoint, percent_encode(since))
+        }
+        _ => cms.endpoint.clone(),
+    }
+}
+
+fn percent_encode(value: &str) -> String
+{
+    value
+        .bytes()
+        .map(|x| {
+            if x.is_ascii_alphanumeric() || b"-._~".contains(&x) {
+                char::from(x).to_string()
+            }
+            else {
+                format!("%{x:02X}")
+            }
+        })
+        .collect()
+}
+
+/// The entries in the endpoint's `response`.
+///
+/// # Errors
+///
+/// Will return an error if the response isn't JSON, or has no array of
+/// entries where it's expected.
+pub fn entries(response: &str, cms: &Cms) -> Result<Vec<Value>>
+{
+    let response: Value =
+        serde_json::from_str(response).map_err(|e| Error::CmsSync(format!("The response isn't JSON: {e}")))?;
+    let entries = match &cms.entries {
+        Some(key) => field(&response, key),
+        None => Some(&response),
+    };
+    match entries {
+        Some(Value::Array(entries)) => Ok(entries.clone()),
+        _ => {
+            Err(Error::CmsSync(format!(
+                "The response has no array of entries at \"{}\"",
+                cms.entries.as_deref().unwrap_or("")
+            )))
+        }
+    }
+}
+
+/// The value at the dotted `path` in `value`, e.g. `attributes.title`.
+fn field<'a>(value: &'a Value, path: &str) -> Option<&'a Value>
+{
+    path.split('.').filter(|x| !x.is_empty()).try_fold(value, |value, key| {
+        match value {
+            Value::Array(values) => values.get(key.parse::<usize>().ok()?),
+            _ => value.get(key),
+        }
+    })
+}
+
+/// The field at `path` of `entry`, as text.
+fn text(entry: &Value, path: &str) -> Option<String>
+{
+    match field(entry, path)? {
+        Value::String(x) => Some(x.clone()),
+        Value::Null => None,
+        x => Some(x.to_string()),
+    }
+}
+
+/// `entry` as a markdown page, with the fields `fields` maps.
+///
+/// # Errors
+///
+/// Will return an error if the entry has no ID, update time, or title.
+pub fn entry_page(entry: &Value, fields: &CmsFields) -> Result<EntryPage>
+{
+    let required = |name: &str, path: &str| {
+        text(entry, path).ok_or_else(|| Error::CmsSync(format!("An entry has no {name} at \"{path}\"")))
+    };
+    let id = required("ID", fields.id.as_deref().unwrap_or("id"))?;
+    let updated_at = required("update time", fields.updated_at.as_deref().unwrap_or("updated_at"))?;
+    let title = required("title", fields.title.as_deref().unwrap_or("title"))?;
+    let description = text(entry, fields.description.as_deref().unwrap_or("description")).unwrap_or_default();
+    let body = text(entry, fields.body.as_deref().unwrap_or("body")).unwrap_or_default();
+    let slug = text(entry, fields.slug.as_deref().unwrap_or("slug"))
+        .map(|x| slugify(&x))
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| slugify(&id));
+
+    let mut page_info = toml::Table::new();
+    page_info.insert(String::from("title"), toml::Value::String(title));
+    page_info.insert(String::from("description"), toml::Value::String(description));
+    page_info.insert(String::from("id"), toml::Value::String(format!("cms-{id}")));
+    let date = fields
+        .date
+        .as_deref()
+        .and_then(|path| text(entry, path))
+        .and_then(|x| x.get(..10).and_then(|x| x.parse().ok()));
+    if let Some(date) = date {
+        page_info.insert(String::from("date"), toml::Value::Datetime(date));
+    }
+    let page_info = toml::to_string(&page_info).map_err(|e| Error::CmsSync(e.to_string()))?;
+    Ok(EntryPage {
+        id,
+        updated_at,
+        slug,
+        markdown: format!(
+            "```{}\n{page_info}```\n\n{}\n",
+            PageInfo::CODE_BLOCK_IDENTIFIER,
+            body.trim_end()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::build::Website;
+
+    #[test]
+    /// Test that entries are found in the response, and their mapped fields
+    /// become the page info and body
+    fn test_entry_page()
+    {
+        let cms: Cms = toml::from_str(
+            "endpoint = \"https://cms.example.com/posts?x=1\"\nsection = \"posts\"\nentries = \"data\"\nsince_param = \
+             \"since\"\n[fields]\ntitle = \"attributes.title\"\nbody = \"attributes.content\"\ndate = \
+             \"attributes.published\"",
+        )
+        .unwrap();
+        let response = r#"{"data": [{"id": 7, "updated_at": "2024-03-14T10:00:00Z", "attributes":
+            {"title": "Hello \"CMS\"", "content": "Some *text*.", "published": "2024-03-14T09:00:00Z"}}]}"#;
+        let found = entries(response, &cms).unwrap();
+        let page = entry_page(&found[0], &cms.fields).unwrap();
+        assert_eq!((page.id.as_str(), page.slug.as_str()), ("7", "7"));
+        let page_info = Website::scan_page_info(&page.markdown, &toml::Table::new()).unwrap();
+        assert_eq!(page_info.title, "Hello \"CMS\"");
+        assert_eq!(page_info.id.as_deref(), Some("cms-7"));
+        assert_eq!(page_info.date.unwrap().to_string(), "2024-03-14");
+        assert!(page.markdown.ends_with("```\n\nSome *text*.\n"));
+
+        let state = SyncState {
+            entries: BTreeMap::from([(
+                page.id,
+                SyncedEntry {
+                    updated_at: page.updated_at,
+                    slug:       page.slug,
+                    markdown:   page.markdown,
+                },
+            )]),
+        };
+        assert_eq!(
+            url(&cms, &state),
+            "https://cms.example.com/posts?x=1&since=2024-03-14T10%3A00%3A00Z"
+        );
+        assert!(entries("[]", &cms).is_err());
+    }
+
+    /// A CMS whose pages are in `posts` under `source`.
+    fn cms(source: &Path, since_param: Option<&str>) -> (Config, Cms)
+    {
+        let mut cms: Cms = toml::from_str("endpoint = \"https://cms.example.com/posts\"\nsection = \"posts\"").unwrap();
+        cms.since_param = since_param.map(String::from);
+        let config = Config {
+            source: source.to_path_buf(),
+            cms: Some(cms.clone()),
+            ..Config::default()
+        };
+        (config, cms)
+    }
+
+    #[test]
+    /// Test that an entry's page can't be built over a file in the source
+    /// dir, or another entry's page
+    fn test_buildable()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/cms-buildable");
+        std::fs::create_dir_all(&dir).unwrap();
+        let hand_written = dir.join("about.md");
+        std::fs::write(&hand_written, "```pageinfo\ntitle = \"About\"\n```\n").unwrap();
+
+        let state = SyncState {
+            entries: BTreeMap::from([(
+                String::from("1"),
+                SyncedEntry {
+                    updated_at: String::from("2024-03-14T10:00:00Z"),
+                    slug:       String::from("hello"),
+                    markdown:   String::new(),
+                },
+            )]),
+        };
+        let seen = BTreeSet::from([String::from("1")]);
+        assert!(matches!(
+            buildable("2", "about", &hand_written, &state, &seen),
+            Err(Error::CmsSync(_))
+        ));
+        buildable("1", "hello", &dir.join("hello.md"), &state, &seen).unwrap();
+        // Two entries with the same slug.
+        assert!(buildable("3", "hello", &dir.join("hello.md"), &state, &seen).is_err());
+        // Unless the other one hasn't been synced yet.
+        buildable("3", "hello", &dir.join("hello.md"), &state, &BTreeSet::new()).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that the entries synced before one that fails are saved
+    async fn test_sync_partial()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/cms-partial");
+        let _ = std::fs::remove_dir_all(&dir);
+        let state_file = dir.join(STATE_FILE);
+        let (config, cms) = cms(&dir.join("src"), None);
+        let response = r#"[{"id": 1, "updated_at": "1", "title": "One"}, {"id": 2, "updated_at": "1"}]"#;
+        assert!(sync_response(&config, &cms, &state_file, response).await.is_err());
+        assert_eq!(load(&state_file).entries.keys().collect::<Vec<_>>(), ["1"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that the entries that are gone are dropped, and only the changed
+    /// ones are written
+    async fn test_sync_removed()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/cms-removed");
+        let _ = std::fs::remove_dir_all(&dir);
+        let state_file = dir.join(STATE_FILE);
+        let (config, cms) = cms(&dir.join("src"), None);
+        let response =
+            r#"[{"id": 1, "updated_at": "1", "title": "One"}, {"id": 2, "updated_at": "1", "title": "Two"}]"#;
+        sync_response(&config, &cms, &state_file, response).await.unwrap();
+        let response = r#"[{"id": 1, "updated_at": "1", "title": "One"}]"#;
+        let summary = sync_response(&config, &cms, &state_file, response).await.unwrap();
+        assert_eq!((summary.written, summary.unchanged, summary.removed), (0, 1, 1));
+        assert_eq!(load(&state_file).entries.keys().collect::<Vec<_>>(), ["1"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that with `since_param` the entries that aren't returned are kept
+    async fn test_sync_since()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/cms-since");
+        let _ = std::fs::remove_dir_all(&dir);
+        let state_file = dir.join(STATE_FILE);
+        let (config, cms) = cms(&dir.join("src"), Some("since"));
+        let response =
+            r#"[{"id": 1, "updated_at": "1", "title": "One"}, {"id": 2, "updated_at": "1", "title": "Two"}]"#;
+        sync_response(&config, &cms, &state_file, response).await.unwrap();
+        let response = r#"[{"id": 2, "updated_at": "2", "title": "Two again"}]"#;
+        let summary = sync_response(&config, &cms, &state_file, response).await.unwrap();
+        assert_eq!((summary.written, summary.removed), (1, 0));
+        let state = load(&state_file);
+        assert_eq!(state.entries.keys().collect::<Vec<_>>(), ["1", "2"]);
+        assert!(state.entries["2"].markdown.contains("Two again"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that the synced pages are built in the section, unless a file
+    /// there has the same path
+    async fn test_pages()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/cms-pages");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src/posts")).unwrap();
+        let state_file = dir.join(STATE_FILE);
+        let (config, cms) = cms(&dir.join("src"), None);
+        let response = r#"[{"id": 1, "updated_at": "1", "title": "One", "slug": "hello"}]"#;
+        sync_response(&config, &cms, &state_file, response).await.unwrap();
+        let pages = state_pages(&config, &cms, &state_file).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].0, dir.join("src/posts/hello.md"));
+        assert!(!pages[0].0.exists());
+
+        std::fs::write(dir.join("src/posts/hello.md"), "Hello").unwrap();
+        assert!(matches!(
+            state_pages(&config, &cms, &state_file),
+            Err(Error::CmsSync(_))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
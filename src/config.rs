@@ -1,7 +1,14 @@
-use std::{fs, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
+use chrono::{DateTime, FixedOffset, Local, Offset, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use structstruck::strike;
+use toml::{value::Datetime, Table};
 
 use crate::{Error, Result};
 
@@ -45,6 +52,17 @@ strike! {
             /// The default HTML template for webpages.
             pub template: PathBuf,
 
+            /// HTML put in every page's `[/rustic_head/]`, before the page's
+            /// own `head_extra`.
+            pub head_extra: Option<HeadExtra>,
+
+            /// The keywords of pages that don't have their own.
+            pub keywords: Option<Vec<String>>,
+
+            /// The language pages are written in, as a language tag, `en`
+            /// if omitted.
+            pub lang: Option<String>,
+
             /// The default self-describing data for webpages
             pub meta: Option<pub struct DefaultMeta
             {
@@ -60,16 +78,458 @@ strike! {
             /// If generated HTML should be processed (minimized, etc.)
             pub process: Option<pub struct ProcessHtml {
                 pub minify: bool,
+
+                /// Minify the stylesheets copied from the source dir or
+                /// inlined into pages.
+                pub minify_css: Option<bool>,
+
+                /// Minify the scripts copied from the source dir.
+                pub minify_js: Option<bool>,
             }>,
 
             /// Treat html found in the source directory as a template
             pub treat_source_as_template: Option<bool>,
+
+            /// How many pages are built at once. Defaults to the number of
+            /// CPUs.
+            pub jobs: Option<usize>,
+
+            /// Stop the build at the first page that fails, instead of building
+            /// the rest and reporting every failure at the end.
+            pub fail_fast: Option<bool>,
+
+            /// Make warnings, like an unknown code block language, fail the
+            /// page they're about.
+            pub strict: Option<bool>,
+
+            /// How big, in bytes, an inlined asset can be before it's warned
+            /// about, since it's copied into every page that uses it.
+            pub inline_limits: Option<pub struct InlineLimits {
+                /// Defaults to 16 KiB.
+                pub favicon: Option<usize>,
+
+                /// Defaults to 64 KiB.
+                pub stylesheet: Option<usize>,
+            }>,
+
+            /// Also replace the `[tokens]` in markdown pages, before they're
+            /// parsed.
+            pub tokens_in_markdown: Option<bool>,
+
+            /// Replace `{{ site.name }}`, `{{ page.title }}`, and the like in
+            /// markdown pages, before they're parsed. Defaults to `false`.
+            pub variables_in_markdown: Option<bool>,
+
+            /// How pages get the CSS of the built-in shortcodes they use.
+            pub component_styles: Option<ComponentStyles>,
+
+            /// Embed favicons into pages as data URLs. Otherwise they're
+            /// copied into the dest dir and linked to. Defaults to `true`.
+            pub embed_favicon: Option<bool>,
+
+            /// Replace `:shortcode:` emoji in markdown pages. Defaults to
+            /// `true`.
+            pub emoji: Option<bool>,
+
+            /// Write redirect stubs where pages with IDs used to be, and keep
+            /// the old IDs of their renamed headings. Defaults to `false`.
+            pub redirects: Option<bool>,
+
+            /// Fail the build before writing anything if its output is
+            /// estimated to be over this many bytes.
+            pub max_output_size: Option<u64>,
+
+            /// Render a visible marker in place of a template placeholder
+            /// without a value, or a shortcode or template that fails to
+            /// load, and go on with the page instead of failing or skipping
+            /// it. Defaults to `true` with the `dev` profile, and `false`
+            /// otherwise.
+            pub error_markers: Option<bool>,
+
+            /// Write every markdown page's metadata, URL, rendered body, and
+            /// text to `index.json` in the dest dir. Defaults to `false`.
+            pub json_index: Option<bool>,
+
+            /// Write a service worker that caches every file of the site, so
+            /// it works offline, and register it in every page. Defaults to
+            /// `false`.
+            pub service_worker: Option<bool>,
+
+            /// Also build the pages with a `date` in the future, which are
+            /// otherwise left out until then. Defaults to `false`.
+            pub future: Option<bool>,
+
+            /// Walk into symlinked directories in the source dir, which are
+            /// otherwise skipped. Defaults to `false`.
+            pub follow_symlinks: Option<bool>,
+
+            /// Time each stage of building every page. Defaults to `false`.
+            pub timings: Option<bool>,
+
+            /// Wait for another build of the project to finish, instead of
+            /// failing. Defaults to `false`.
+            pub wait: Option<bool>,
+
+            /// How symlinked source files that are copied as they are get
+            /// written to the dest dir. Defaults to `"copy"`.
+            pub symlinks: Option<Symlinks>,
+
+            /// Fail markdown pages without a `pageinfo` block, instead of
+            /// building them with the page info taken from the page. Defaults
+            /// to `true`.
+            pub require_page_info: Option<bool>,
+
+            /// Open links to other sites in a new tab, by giving them a
+            /// `target` and `rel`.
+            pub external_links: Option<pub struct ExternalLinks {
+                /// Defaults to `"_blank"`, `""` leaves it out.
+                pub target: Option<String>,
+
+                /// Defaults to `"noopener noreferrer"`, `""` leaves it out.
+                pub rel: Option<String>,
+
+                /// The hosts that are part of the site, e.g. `example.com`,
+                /// whose links are left as they are.
+                pub internal: Option<Vec<String>>,
+            }>,
         }>,
 
         pub meta: Option<pub struct Meta
         {
             pub append_site_name_to_title: Option<MetaAppendSiteNameToTitle>
-        }>
+        }>,
+
+        /// The markdown extensions pages are parsed with.
+        pub markdown: Option<pub struct MarkdownExtensions
+        {
+            /// Defaults to `true`.
+            pub tables: Option<bool>,
+
+            /// Task list items, `- [x]`. Defaults to `true`.
+            pub tasklists: Option<bool>,
+
+            /// `~~struck through~~` text. Defaults to `true`.
+            pub strikethrough: Option<bool>,
+
+            /// `[^note]` footnotes. Defaults to `false`.
+            pub footnotes: Option<bool>,
+
+            /// Curly quotes, en and em dashes, and ellipses. Defaults to
+            /// `false`.
+            pub smart_punctuation: Option<bool>,
+
+            /// `# Heading {#id .class}` attributes. Defaults to `false`.
+            pub heading_attributes: Option<bool>,
+
+            /// Term and `: definition` lists. Defaults to `false`.
+            pub definition_lists: Option<bool>,
+
+            /// Resolve `[[Page Name]]` links to the page with that title or
+            /// file name. Defaults to `false`.
+            pub wiki_links: Option<bool>,
+        }>,
+
+        pub images: Option<pub struct Images
+        {
+            /// What to do about images without alt text.
+            pub missing_alt: Option<MissingAltText>,
+
+            /// Render images with a title, alone in a paragraph, as numbered
+            /// figures ("Figure 3: title") that `{{ ref fig:id }}` can refer
+            /// to.
+            pub number_figures: Option<bool>,
+
+            /// Render images with a title, alone in a paragraph, as figures
+            /// captioned by the title, without numbers. Defaults to `false`.
+            pub figures: Option<bool>,
+        }>,
+
+        /// Where `raven deploy` publishes the dest dir.
+        pub deploy: Option<Deploy>,
+
+        /// Where the skeletons `raven page new` creates pages from are
+        /// stored. Defaults to `archetypes`.
+        pub archetypes: Option<PathBuf>,
+
+        /// Where `raven test` keeps the snapshots of rendered pages.
+        /// Defaults to `snapshots`.
+        pub snapshots: Option<PathBuf>,
+
+        /// A theme directory. Templates, stylesheets, favicons, archetypes,
+        /// and shortcodes that aren't in the project are looked up in it.
+        pub theme: Option<PathBuf>,
+
+        /// Sections whose pages are also built into a single page, one after
+        /// the other, like the chapters of a book.
+        pub books: Option<Vec<pub struct Book {
+            /// The directory under the source dir the chapters are in.
+            pub section: PathBuf,
+
+            /// The title of the combined page.
+            pub title: String,
+
+            /// The description of the combined page.
+            pub description: Option<String>,
+
+            /// An mdBook `SUMMARY.md`, relative to the section, that the
+            /// chapters, their order, and their titles are taken from.
+            pub summary: Option<PathBuf>,
+
+            /// The chapters' paths relative to the section, in order.
+            /// Defaults to the chapters in `summary`, or every markdown page
+            /// in the section, sorted by path.
+            pub chapters: Option<Vec<PathBuf>>,
+
+            /// Where the combined page is written, relative to the dest dir.
+            /// Defaults to `<section>/print.html`.
+            pub output: Option<PathBuf>,
+
+            /// The HTML template for the combined page. Defaults to
+            /// `default.template`.
+            pub template: Option<PathBuf>,
+
+            /// Where an EPUB of the chapters is written, relative to the dest
+            /// dir. No EPUB is written if it's unset.
+            pub epub: Option<PathBuf>,
+
+            /// The cover image of the EPUB, relative to the project.
+            pub cover: Option<PathBuf>,
+
+            /// The language the book is written in, for the EPUB (`en`).
+            pub language: Option<String>,
+        }>>,
+
+        /// Where markdown pages are written, relative to the dest dir, e.g.
+        /// `/:year/:month/:slug/`, instead of mirroring the source dir.
+        /// `:year`, `:month`, and `:day` are from the page's `date`,
+        /// `:slug` is its `slug` or file name, and `:section` is the
+        /// directory it's in under the source dir. A pattern ending in `/`
+        /// writes each page to the `index.html` in it.
+        pub permalink: Option<String>,
+
+        /// Settings for the site as a whole.
+        pub site: Option<pub struct Site {
+            /// The IANA time zone dates are in, e.g. `Europe/Berlin`, instead
+            /// of the machine's.
+            pub timezone: Option<Tz>,
+
+            /// Site-wide values for `{{ site.extra.<name> }}` in markdown
+            /// pages, with `generation.variables_in_markdown`.
+            pub extra: Option<Table>,
+        }>,
+
+        /// A headless CMS whose entries are synced with `raven build --sync`,
+        /// and built as pages.
+        pub cms: Option<pub struct Cms {
+            /// The URL the entries are fetched from, as JSON.
+            pub endpoint: String,
+
+            /// Sent as a bearer token, e.g. `"${CMS_TOKEN}"`.
+            pub token: Option<String>,
+
+            /// The directory under the source dir the pages are built in.
+            pub section: PathBuf,
+
+            /// Where the array of entries is in the response, e.g. `data`.
+            /// Defaults to the whole response.
+            pub entries: Option<String>,
+
+            /// A query parameter the latest `updated_at` synced is sent in,
+            /// for endpoints that can return only the entries changed since.
+            pub since_param: Option<String>,
+
+            /// Which of an entry's fields the page is made of.
+            #[serde(default)]
+            pub fields: CmsFields,
+        }>,
+
+        /// How what's fetched over the network, such as a headless CMS's
+        /// entries, is cached in `.raven-fetch-cache`.
+        pub fetch: Option<pub struct FetchCache {
+            /// How many seconds a response is used before it's fetched
+            /// again. Defaults to 5 minutes.
+            pub ttl: Option<u64>,
+
+            /// How many bytes of responses are kept before the least
+            /// recently fetched ones are removed. Defaults to 50 MiB.
+            pub max_size: Option<u64>,
+
+            /// Only use cached responses, however old, and fail for what
+            /// isn't cached, like `--offline`.
+            pub offline: Option<bool>,
+        }>,
+
+        /// Tracking code put in every page.
+        pub analytics: Option<pub struct Analytics {
+            /// Turns the tracking code on and off. Defaults to `true`.
+            pub enabled: Option<bool>,
+
+            /// Whose tracking code to use, for `site_id`.
+            pub provider: Option<AnalyticsProvider>,
+
+            /// The site's ID, or domain, with the provider.
+            pub site_id: Option<String>,
+
+            /// A file with the tracking code, relative to the project,
+            /// instead of a provider's.
+            pub snippet: Option<PathBuf>,
+
+            /// Also put the tracking code in pages built with the `dev`
+            /// profile. Defaults to `false`.
+            pub dev: Option<bool>,
+        }>,
+
+        /// An Open Graph image of each markdown page's title, for when it's
+        /// shared.
+        pub og_image: Option<pub struct OgImage {
+            /// Turns the images on and off. Defaults to `true`.
+            pub enabled: Option<bool>,
+
+            /// Defaults to `#1e293b`.
+            pub background: Option<String>,
+
+            /// The background fades into this color, towards the
+            /// bottom-right corner.
+            pub background_end: Option<String>,
+
+            /// The text's color. Defaults to `#ffffff`.
+            pub color: Option<String>,
+
+            /// The URL the dest dir is deployed to, e.g.
+            /// `https://example.com`, since sites an image is shared on need
+            /// its full URL. Otherwise pages link to it relatively.
+            pub base_url: Option<String>,
+        }>,
+
+        /// Atom, RSS, and JSON Feed feeds of the dated markdown pages, and a
+        /// sitemap of every markdown page.
+        pub feeds: Option<pub struct Feeds {
+            /// The URL the dest dir is deployed to, e.g.
+            /// `https://example.com`, since feeds and sitemaps list full URLs.
+            pub base_url: String,
+
+            /// Defaults to `default.meta.site_name`.
+            pub title: Option<String>,
+
+            /// Defaults to the title.
+            pub description: Option<String>,
+
+            /// Which feeds are written. Defaults to `["atom", "rss", "json"]`.
+            pub formats: Option<Vec<FeedFormat>>,
+
+            /// How many of the newest pages a feed lists. Defaults to `20`.
+            pub limit: Option<usize>,
+
+            /// Sections, directories under the source dir, that also get
+            /// feeds of their pages, in the same directory under the dest
+            /// dir.
+            pub sections: Option<Vec<PathBuf>>,
+
+            /// How many of the newest pages a section's feed lists. Defaults
+            /// to `limit`.
+            pub section_limit: Option<usize>,
+
+            /// Also write feeds of the pages with each tag, to
+            /// `tags/<tag>/`. Defaults to `false`.
+            pub tags: Option<bool>,
+
+            /// How many of the newest pages a tag's feed lists. Defaults to
+            /// `limit`.
+            pub tag_limit: Option<usize>,
+
+            /// Also write `sitemap.xml`. Defaults to `true`.
+            pub sitemap: Option<bool>,
+
+            /// Templates, relative to the project or theme, instead of the
+            /// built-in ones.
+            pub templates: Option<pub struct FeedTemplates {
+                pub atom: Option<PathBuf>,
+                pub rss: Option<PathBuf>,
+                pub json: Option<PathBuf>,
+                pub sitemap: Option<PathBuf>,
+            }>,
+        }>,
+
+        /// Shell commands run as part of `raven build`, e.g. to compile
+        /// stylesheets or optimize images.
+        pub hooks: Option<pub struct CommandHooks {
+            /// Run before the source dir is read.
+            pub pre_build: Option<String>,
+
+            /// Run after every page is built, if none failed.
+            pub post_build: Option<String>,
+
+            /// Run after each page is written. The page's source and dest
+            /// paths are in `$RAVEN_PAGE_SOURCE` and `$RAVEN_PAGE_DEST`.
+            pub post_page: Option<String>,
+        }>,
+
+        /// Build a copy of the site for each of these audiences, into
+        /// `<dest>/<audience>`, with only the pages whose `audience` has it,
+        /// or that have none.
+        pub audiences: Option<Vec<String>>,
+
+        /// What's done with source files, by their extension, besides the
+        /// built-in ones.
+        pub extensions: Option<pub struct SourceExtensions {
+            /// Also parsed as markdown, like `md` and `markdown`.
+            pub markdown: Option<Vec<String>>,
+
+            /// Copied to the dest dir as they are.
+            pub copy: Option<Vec<String>>,
+
+            /// Commands that get a source file on stdin and output its HTML,
+            /// which is put into the default template.
+            pub converters: Option<BTreeMap<String, String>>,
+        }>,
+
+        /// More source dirs, whose files are built along with `source`'s, e.g.
+        /// from `source = ["content", "docs"]`.
+        pub sources: Option<Vec<pub struct SourceDir {
+            pub path: PathBuf,
+
+            /// Where in the dest dir its files are written, the top of it by
+            /// default.
+            pub dest: Option<PathBuf>,
+        }>>,
+
+        /// Patterns of source files that are never built or copied, relative
+        /// to the source dir, along with those in `.ravenignore`.
+        pub ignore: Option<Vec<String>>,
+
+        /// Site-wide values, replacing `[/rustic_<name>/]` in templates.
+        /// A token with the name of a built-in placeholder replaces it.
+        pub tokens: Option<BTreeMap<String, String>>,
+
+        /// The profile the configuration was loaded with, if any.
+        #[serde(skip)]
+        pub profile: Option<String>,
+
+        /// The audience being built, one of `audiences`, if any.
+        #[serde(skip)]
+        pub audience: Option<String>,
+    }
+}
+
+/// Extra HTML for a page's `<head>`: the HTML itself, or files of it,
+/// relative to the project.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum HeadExtra
+{
+    Html(String),
+    Files(Vec<PathBuf>),
+}
+
+impl HeadExtra
+{
+    /// The files of HTML, if it's in files.
+    pub fn files(&self) -> &[PathBuf]
+    {
+        match self {
+            Self::Html(_) => &[],
+            Self::Files(files) => files,
+        }
     }
 }
 
@@ -81,23 +541,177 @@ pub enum MetaAppendSiteNameToTitle
     Custom(String),
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingAltText
+{
+    /// Images without alt text are fine.
+    #[default]
+    Allow,
+
+    /// Print a warning for each image without alt text.
+    Warn,
+
+    /// Fail the build on the first image without alt text.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Symlinks
+{
+    /// Write the contents of the file the link points to.
+    #[default]
+    Copy,
+
+    /// Write a link to the file the link points to.
+    Link,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat
+{
+    Atom,
+    Rss,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyticsProvider
+{
+    Plausible,
+    Fathom,
+    GoatCounter,
+    Google,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStyles
+{
+    /// A `<style>` on each page with the CSS of the components it uses.
+    #[default]
+    Inline,
+
+    /// A link on each page that uses a component to one stylesheet with the
+    /// CSS of every component, written to the dest dir.
+    Linked,
+}
+
+/// The fields of a CMS entry the page is made of, as dotted paths into the
+/// entry, e.g. `attributes.title`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CmsFields
+{
+    /// Defaults to `id`.
+    pub id: Option<String>,
+
+    /// When the entry was last changed. Defaults to `updated_at`.
+    pub updated_at: Option<String>,
+
+    /// Defaults to `title`.
+    pub title: Option<String>,
+
+    /// Defaults to `description`.
+    pub description: Option<String>,
+
+    /// The page's markdown. Defaults to `body`.
+    pub body: Option<String>,
+
+    /// The page's file name. Defaults to `slug`, or the ID.
+    pub slug: Option<String>,
+
+    /// The page's `date`, if it has one.
+    pub date: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "target", rename_all = "lowercase")]
+pub enum Deploy
+{
+    /// Copy the dest dir to `destination` (e.g. `user@host:/var/www`) with
+    /// rsync over ssh.
+    Rsync
+    {
+        destination: String,
+
+        /// Delete files from `destination` that aren't in the dest dir.
+        delete: Option<bool>,
+
+        /// Additional arguments passed to ssh (e.g. `"-p 2222"`).
+        ssh_args: Option<String>,
+    },
+
+    /// Run `command` with a shell. The dest dir is in `$RAVEN_DEST`.
+    Command
+    {
+        command: String
+    },
+
+    /// Sync the dest dir to an S3 (or S3-compatible) bucket with the `aws`
+    /// CLI, only uploading files whose content changed.
+    S3
+    {
+        bucket: String,
+
+        /// The key prefix objects are uploaded under.
+        prefix: Option<String>,
+
+        /// The endpoint of S3-compatible storage.
+        endpoint_url: Option<String>,
+
+        /// Delete objects under `prefix` that aren't in the dest dir.
+        delete: Option<bool>,
+
+        /// Invalidate the changed paths in this CloudFront distribution.
+        cloudfront_distribution: Option<String>,
+    },
+}
+
 impl Default for Config
 {
     fn default() -> Self
     {
         Self {
             meta:                 None,
+            profile:              None,
+            audience:             None,
+            audiences:            None,
+            ignore:               None,
+            extensions:           None,
+            sources:              None,
             dest:                 PathBuf::from(Self::DEFAULT_DEST_DIR),
             source:               PathBuf::from(Self::DEFAULT_SRC_DIR),
             syntaxes:             PathBuf::from(Self::DEFAULT_SYNTAXES_DIR),
             syntax_theme:         String::from(Self::DEFAULT_SYNTAX_THEME),
             custom_syntax_themes: PathBuf::from(Self::DEFAULT_CUSTOM_SYNTAX_THEMES_DIR),
             generation:           None,
+            markdown:             None,
+            images:               None,
+            deploy:               None,
+            archetypes:           None,
+            snapshots:            None,
+            theme:                None,
+            books:                None,
+            permalink:            None,
+            site:                 None,
+            hooks:                None,
+            analytics:            None,
+            og_image:             None,
+            feeds:                None,
+            cms:                  None,
+            fetch:                None,
+            tokens:               None,
             default:              Defaults {
                 meta:       None,
                 favicon:    PathBuf::from(Self::DEFAULT_FAVICON_FILE),
                 template:   PathBuf::from(Self::DEFAULT_TEMPLATE_FILE),
                 stylesheet: PathBuf::from(Self::DEFUALT_STYLE_FILE),
+                head_extra: None,
+                keywords:   None,
+                lang:       None,
             },
         }
     }
@@ -105,14 +719,22 @@ impl Default for Config
 
 impl Config
 {
+    pub const DEFAULT_ARCHETYPES_DIR: &str = "archetypes";
     pub const DEFAULT_CONFIG_FILE: &str = "raven.toml";
     const DEFAULT_CUSTOM_SYNTAX_THEMES_DIR: &str = "syntax-themes";
     const DEFAULT_DEST_DIR: &str = "dest";
     const DEFAULT_FAVICON_FILE: &str = "favicon.ico";
+    const DEFAULT_FAVICON_INLINE_LIMIT: usize = 16 * 1024;
+    const DEFAULT_FETCH_MAX_SIZE: u64 = 50 * 1024 * 1024;
+    const DEFAULT_FETCH_TTL: u64 = 5 * 60;
+    pub const DEFAULT_SHORTCODES_DIR: &str = "shortcodes";
+    pub const DEFAULT_SNAPSHOTS_DIR: &str = "snapshots";
     const DEFAULT_SRC_DIR: &str = "src";
+    const DEFAULT_STYLESHEET_INLINE_LIMIT: usize = 64 * 1024;
     const DEFAULT_SYNTAXES_DIR: &str = "syntaxes";
     const DEFAULT_SYNTAX_THEME: &str = "base16-eighties.dark";
     const DEFAULT_TEMPLATE_FILE: &str = "template.html";
+    pub const DEFAULT_THEMES_DIR: &str = "themes";
     const DEFUALT_STYLE_FILE: &str = "style.css";
 
     /// Constructs a `Config` from a TOML file provided (`path`).
@@ -123,24 +745,705 @@ impl Config
     ///
     /// - The `path` cannot be read into a string
     /// - The TOML read from `path` cannot be parsed into a `Config`
-    pub fn from_toml(path: &PathBuf) -> Result<Self>
-    {
-        let contents = match fs::read_to_string(path) {
-            Ok(x) => x,
-            Err(e) => {
-                return Err(Error::Io {
-                    err:  e,
-                    path: path.clone(),
-                })
+    pub fn from_toml(path: &PathBuf) -> Result<Self> { Self::from_toml_with_profile(path, None) }
+
+    /// Constructs a `Config` from a TOML file provided (`path`), with the
+    /// overlays of `profile` merged over it.
+    ///
+    /// A profile's overlay is the `[profiles.<profile>]` table in `path`,
+    /// and the `raven.<profile>.toml` file next to `path`. When both exist,
+    /// the file takes precedence.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if:
+    ///
+    /// - The `path` or the profile's overlay file cannot be read into a string
+    /// - The merged TOML cannot be parsed into a `Config`
+    /// - `profile` has neither an overlay table nor an overlay file
+    /// - A `${VAR}` reference names an unset environment variable
+    pub fn from_toml_with_profile(path: &PathBuf, profile: Option<&str>) -> Result<Self>
+    {
+        Self::from_toml_with_env(path, profile, &|var| std::env::var(var).ok())
+    }
+
+    /// [`Self::from_toml_with_profile`], looking up the variables `${VAR}`
+    /// references name with `env`.
+    fn from_toml_with_env(path: &Path, profile: Option<&str>, env: &dyn Fn(&str) -> Option<String>) -> Result<Self>
+    {
+        let mut table = read_toml_table(path)?;
+        let profiles = table.remove("profiles");
+        expand_env_vars_in_table(&mut table, path, env)?;
+
+        if let Some(profile) = profile {
+            let mut found = false;
+            if let Some(toml::Value::Table(mut profiles)) = profiles {
+                if let Some(toml::Value::Table(mut overlay)) = profiles.remove(profile) {
+                    expand_env_vars_in_table(&mut overlay, path, env)?;
+                    merge_toml_tables(&mut table, overlay);
+                    found = true;
+                }
+            }
+
+            let overlay_path = Self::profile_path(path, profile);
+            if overlay_path.is_file() {
+                let mut overlay = read_toml_table(&overlay_path)?;
+                expand_env_vars_in_table(&mut overlay, &overlay_path, env)?;
+                merge_toml_tables(&mut table, overlay);
+                found = true;
+            }
+
+            if !found {
+                return Err(Error::ConfigParse(format!(
+                    "Unknown profile \"{profile}\": {} has no [profiles.{profile}] table and \"{}\" doesn't exist",
+                    path.display(),
+                    overlay_path.display()
+                )));
+            }
+        }
+
+        split_source_list(&mut table)
+            .map_err(|e| Error::ConfigParse(format!("Couldn't parse {}: {e}", path.display())))?;
+
+        let mut parsed: Self = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| Error::ConfigParse(format!("Couldn't parse {}: {e}", path.display())))?;
+        parsed.profile = profile.map(String::from);
+
+        Ok(parsed)
+    }
+
+    /// Find `path` in the project, falling back to the same path in the
+    /// theme. Returns `path` as is if it's in neither, and the default
+    /// template and stylesheet fall back to [`Self::builtin_file`] then.
+    pub fn resolve(&self, path: &Path) -> PathBuf
+    {
+        if path.exists() {
+            return path.to_path_buf();
+        }
+        match &self.theme {
+            Some(theme) if theme.join(path).exists() => theme.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// The built-in contents of the default template or stylesheet, used when
+    /// `path` is one of them and neither the project nor the theme has it.
+    pub fn builtin_file(&self, path: &Path) -> Option<&'static str>
+    {
+        if path == self.default.template {
+            Some(crate::defaults::DEFAULT_HTML_TEMPLATE_SRC)
+        }
+        else if path == self.default.stylesheet {
+            Some(crate::defaults::DEFAULT_CSS_STYLESHEET_SRC)
+        }
+        else {
+            None
+        }
+    }
+
+    /// The directories custom shortcodes are looked up in, in order.
+    pub fn shortcode_dirs(&self) -> Vec<PathBuf>
+    {
+        let mut dirs = vec![PathBuf::from(Self::DEFAULT_SHORTCODES_DIR)];
+        if let Some(theme) = &self.theme {
+            dirs.push(theme.join(Self::DEFAULT_SHORTCODES_DIR));
+        }
+        dirs
+    }
+
+    /// The configuration of each copy of the site to build: one per audience
+    /// in `audiences`, into its own dest dir, or just this one.
+    pub fn audience_configs(&self) -> Vec<Self>
+    {
+        match self.audiences.as_deref() {
+            Some(audiences) if !audiences.is_empty() => {
+                audiences
+                    .iter()
+                    .map(|audience| {
+                        Self {
+                            dest: self.dest.join(audience),
+                            audience: Some(audience.clone()),
+                            ..self.clone()
+                        }
+                    })
+                    .collect()
+            }
+            _ => vec![self.clone()],
+        }
+    }
+
+    /// How many pages are built at once: `generation.jobs`, or the number of
+    /// CPUs if it's unset or 0.
+    pub fn jobs(&self) -> usize
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.jobs)
+            .filter(|&x| x > 0)
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get))
+    }
+
+    /// Set `generation.jobs`, keeping the rest of the generation settings.
+    pub fn set_jobs(&mut self, jobs: usize) { self.generation_mut().jobs = Some(jobs); }
+
+    /// If the build stops at the first page that fails.
+    pub fn fail_fast(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.fail_fast)
+            .unwrap_or(false)
+    }
+
+    /// Set `generation.fail_fast`, keeping the rest of the generation
+    /// settings.
+    pub fn set_fail_fast(&mut self, fail_fast: bool) { self.generation_mut().fail_fast = Some(fail_fast); }
+
+    /// How big an inlined favicon can be before it's warned about.
+    pub fn favicon_inline_limit(&self) -> usize
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.inline_limits.as_ref()?.favicon)
+            .unwrap_or(Self::DEFAULT_FAVICON_INLINE_LIMIT)
+    }
+
+    /// The options markdown pages are parsed with, from `[markdown]`.
+    pub fn markdown_options(&self) -> pulldown_cmark::Options
+    {
+        use pulldown_cmark::Options;
+
+        let markdown = self.markdown.as_ref();
+        let enabled = |extension: fn(&MarkdownExtensions) -> Option<bool>, default| {
+            markdown.and_then(extension).unwrap_or(default)
+        };
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_TABLES, enabled(|x| x.tables, true));
+        options.set(Options::ENABLE_TASKLISTS, enabled(|x| x.tasklists, true));
+        options.set(Options::ENABLE_STRIKETHROUGH, enabled(|x| x.strikethrough, true));
+        options.set(Options::ENABLE_FOOTNOTES, enabled(|x| x.footnotes, false));
+        options.set(
+            Options::ENABLE_SMART_PUNCTUATION,
+            enabled(|x| x.smart_punctuation, false),
+        );
+        options.set(
+            Options::ENABLE_HEADING_ATTRIBUTES,
+            enabled(|x| x.heading_attributes, false),
+        );
+        options
+    }
+
+    /// If definition lists are rendered, `markdown.definition_lists`.
+    pub fn definition_lists(&self) -> bool
+    {
+        self.markdown
+            .as_ref()
+            .and_then(|markdown| markdown.definition_lists)
+            .unwrap_or(false)
+    }
+
+    /// If `[[Page Name]]` links are resolved, `markdown.wiki_links`.
+    pub fn wiki_links(&self) -> bool
+    {
+        self.markdown
+            .as_ref()
+            .and_then(|markdown| markdown.wiki_links)
+            .unwrap_or(false)
+    }
+
+    /// If emoji are replaced in markdown pages, `generation.emoji`.
+    pub fn emoji(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.emoji)
+            .unwrap_or(true)
+    }
+
+    /// If favicons are embedded into pages, `generation.embed_favicon`.
+    pub fn embed_favicon(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.embed_favicon)
+            .unwrap_or(true)
+    }
+
+    /// How big an inlined stylesheet can be before it's warned about.
+    pub fn stylesheet_inline_limit(&self) -> usize
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.inline_limits.as_ref()?.stylesheet)
+            .unwrap_or(Self::DEFAULT_STYLESHEET_INLINE_LIMIT)
+    }
+
+    /// If warnings fail the build.
+    pub fn strict(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.strict)
+            .unwrap_or(false)
+    }
+
+    /// If errors in templates and shortcodes are marked in the page instead
+    /// of failing it, `generation.error_markers`.
+    pub fn error_markers(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.error_markers)
+            .unwrap_or(self.profile.as_deref() == Some("dev"))
+    }
+
+    /// If the pages are also written to `index.json`,
+    /// `generation.json_index`.
+    pub fn json_index(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.json_index)
+            .unwrap_or(false)
+    }
+
+    /// If `{{ site.name }}` and the like are replaced in markdown pages,
+    /// `generation.variables_in_markdown`.
+    pub fn variables_in_markdown(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.variables_in_markdown)
+            .unwrap_or(false)
+    }
+
+    /// If a service worker is written for the site,
+    /// `generation.service_worker`.
+    pub fn service_worker(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.service_worker)
+            .unwrap_or(false)
+    }
+
+    /// If pages dated in the future are built, `generation.future`.
+    pub fn future(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.future)
+            .unwrap_or(false)
+    }
+
+    /// Set `generation.future`, keeping the rest of the generation settings.
+    pub fn set_future(&mut self, future: bool) { self.generation_mut().future = Some(future); }
+
+    /// If the source walk goes into symlinked directories,
+    /// `generation.follow_symlinks`.
+    pub fn follow_symlinks(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.follow_symlinks)
+            .unwrap_or(false)
+    }
+
+    /// Set `generation.follow_symlinks`, keeping the rest of the generation
+    /// settings.
+    pub fn set_follow_symlinks(&mut self, follow_symlinks: bool)
+    {
+        self.generation_mut().follow_symlinks = Some(follow_symlinks);
+    }
+
+    /// If each stage of building every page is timed, `generation.timings`.
+    pub fn timings(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.timings)
+            .unwrap_or(false)
+    }
+
+    /// Set `generation.timings`, keeping the rest of the generation settings.
+    pub fn set_timings(&mut self, timings: bool) { self.generation_mut().timings = Some(timings); }
+
+    /// If a build waits for another build of the project to finish,
+    /// `generation.wait`.
+    pub fn wait(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.wait)
+            .unwrap_or(false)
+    }
+
+    /// Set `generation.wait`, keeping the rest of the generation settings.
+    pub fn set_wait(&mut self, wait: bool) { self.generation_mut().wait = Some(wait); }
+
+    /// How many seconds a fetched response is used before it's fetched again,
+    /// `fetch.ttl`.
+    pub fn fetch_ttl(&self) -> u64
+    {
+        self.fetch
+            .as_ref()
+            .and_then(|fetch| fetch.ttl)
+            .unwrap_or(Self::DEFAULT_FETCH_TTL)
+    }
+
+    /// How many bytes of fetched responses are cached, `fetch.max_size`.
+    pub fn fetch_max_size(&self) -> u64
+    {
+        self.fetch
+            .as_ref()
+            .and_then(|fetch| fetch.max_size)
+            .unwrap_or(Self::DEFAULT_FETCH_MAX_SIZE)
+    }
+
+    /// If only cached responses are used, `fetch.offline`.
+    pub fn offline(&self) -> bool { self.fetch.as_ref().and_then(|fetch| fetch.offline).unwrap_or(false) }
+
+    /// Set `fetch.offline`, keeping the rest of the fetch settings.
+    pub fn set_offline(&mut self, offline: bool)
+    {
+        self.fetch
+            .get_or_insert(FetchCache {
+                ttl:      None,
+                max_size: None,
+                offline:  None,
+            })
+            .offline = Some(offline);
+    }
+
+    /// How symlinked source files are copied, `generation.symlinks`.
+    pub fn symlinks(&self) -> Symlinks
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.symlinks)
+            .unwrap_or_default()
+    }
+
+    /// Set `generation.symlinks`, keeping the rest of the generation settings.
+    pub fn set_symlinks(&mut self, symlinks: Symlinks) { self.generation_mut().symlinks = Some(symlinks); }
+
+    /// If markdown pages must have a `pageinfo` block,
+    /// `generation.require_page_info`.
+    pub fn require_page_info(&self) -> bool
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.require_page_info)
+            .unwrap_or(true)
+    }
+
+    /// Set `generation.require_page_info`, keeping the rest of the generation
+    /// settings.
+    pub fn set_require_page_info(&mut self, require_page_info: bool)
+    {
+        self.generation_mut().require_page_info = Some(require_page_info);
+    }
+
+    /// Set `generation.strict`, keeping the rest of the generation settings.
+    pub fn set_strict(&mut self, strict: bool) { self.generation_mut().strict = Some(strict); }
+
+    /// If stylesheets are minified, `generation.process.minify_css`.
+    pub fn minify_css(&self) -> bool { self.process().and_then(|process| process.minify_css).unwrap_or(false) }
+
+    /// If scripts are minified, `generation.process.minify_js`.
+    pub fn minify_js(&self) -> bool { self.process().and_then(|process| process.minify_js).unwrap_or(false) }
+
+    fn process(&self) -> Option<&ProcessHtml>
+    {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.process.as_ref())
+    }
+
+    fn generation_mut(&mut self) -> &mut Generation
+    {
+        self.generation.get_or_insert(Generation {
+            process:                  None,
+            treat_source_as_template: None,
+            jobs:                     None,
+            fail_fast:                None,
+            strict:                   None,
+            inline_limits:            None,
+            tokens_in_markdown:       None,
+            variables_in_markdown:    None,
+            component_styles:         None,
+            embed_favicon:            None,
+            emoji:                    None,
+            redirects:                None,
+            max_output_size:          None,
+            error_markers:            None,
+            json_index:               None,
+            service_worker:           None,
+            future:                   None,
+            follow_symlinks:          None,
+            timings:                  None,
+            wait:                     None,
+            symlinks:                 None,
+            require_page_info:        None,
+            external_links:           None,
+        })
+    }
+
+    /// The date and time now, in `site.timezone` if it's set, or else the
+    /// machine's time zone.
+    pub fn now(&self) -> DateTime<FixedOffset>
+    {
+        let now = Utc::now();
+        match self.site.as_ref().and_then(|site| site.timezone) {
+            Some(timezone) => {
+                let now = now.with_timezone(&timezone);
+                now.with_timezone(&now.offset().fix())
             }
+            None => now.with_timezone(&Local).into(),
+        }
+    }
+
+    /// Make sure building into or cleaning the dest dir can't overwrite or
+    /// delete the project. Paths are relative to the current directory,
+    /// which is the project's root.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the dest dir is the project's root or one of
+    /// its parents, or if it's the source dir, inside it, or contains it.
+    pub fn check_dest(&self) -> Result<()>
+    {
+        let root = project_root();
+        let dest = absolute(&self.dest);
+        let overlapping = |dir: &Path| {
+            let dir = absolute(dir);
+            dest.starts_with(&dir) || dir.starts_with(&dest)
+        };
+        let other = if root.starts_with(&dest) {
+            PathBuf::from(".")
+        }
+        else if let Some((source, _)) = self.source_dirs().into_iter().find(|(x, _)| overlapping(x)) {
+            source.to_path_buf()
+        }
+        else {
+            return Ok(());
         };
+        Err(Error::DestOverlap {
+            dest: self.dest.clone(),
+            other,
+        })
+    }
 
-        let parsed = match toml::from_str(&contents) {
-            Ok(x) => x,
-            Err(e) => return Err(Error::ConfigParse(format!("Couldn't parse {}: {e}", path.display()))),
+    /// The source dirs, `source` then `sources`, with where in the dest dir
+    /// each one's files are written.
+    pub fn source_dirs(&self) -> Vec<(&Path, Option<&Path>)>
+    {
+        std::iter::once((self.source.as_path(), None))
+            .chain(
+                self.sources
+                    .iter()
+                    .flatten()
+                    .map(|x| (x.path.as_path(), x.dest.as_deref())),
+            )
+            .collect()
+    }
+
+    /// The path of `source_file` in the source dir it's in, with where in the
+    /// dest dir that dir's files are written. `None` if it's in none of them.
+    pub fn source_relative<'a>(&'a self, source_file: &'a Path) -> Option<(&'a Path, Option<&'a Path>)>
+    {
+        self.source_dirs()
+            .into_iter()
+            .filter_map(|(dir, dest)| Some((dir, source_file.strip_prefix(dir).ok()?, dest)))
+            // The innermost dir, if one is in another.
+            .max_by_key(|(dir, ..)| dir.components().count())
+            .map(|(_, relative, dest)| (relative, dest))
+    }
+
+    /// The path of `profile`'s overlay file, e.g. `raven.prod.toml` for
+    /// `raven.toml`.
+    fn profile_path(path: &Path, profile: &str) -> PathBuf
+    {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file_name = match path.extension() {
+            Some(extention) => format!("{stem}.{profile}.{}", extention.to_string_lossy()),
+            None => format!("{stem}.{profile}"),
         };
+        path.with_file_name(file_name)
+    }
+}
 
-        Ok(parsed)
+/// Turn a list of source dirs in `source` into `source`, the first of them,
+/// and `sources`, the rest. They're paths, or tables like `sources`', but the
+/// first can't have a `dest`.
+fn split_source_list(table: &mut toml::Table) -> std::result::Result<(), String>
+{
+    let Some(toml::Value::Array(dirs)) = table.get("source")
+    else {
+        return Ok(());
+    };
+    let mut dirs = dirs.clone().into_iter();
+    let first = match dirs.next() {
+        Some(toml::Value::String(path)) => path,
+        Some(toml::Value::Table(mut first)) if !first.contains_key("dest") => {
+            match first.remove("path") {
+                Some(toml::Value::String(path)) => path,
+                _ => return Err(String::from("The first source dir needs a `path`")),
+            }
+        }
+        Some(toml::Value::Table(_)) => {
+            return Err(String::from(
+                "The first source dir can't have a `dest`, its files are written to the top of the dest dir",
+            ))
+        }
+        _ => return Err(String::from("`source` needs at least one dir")),
+    };
+    let mut sources = dirs
+        .map(|dir| {
+            match dir {
+                toml::Value::String(path) => {
+                    Ok(toml::Value::Table(toml::Table::from_iter([(
+                        String::from("path"),
+                        toml::Value::String(path),
+                    )])))
+                }
+                dir @ toml::Value::Table(_) => Ok(dir),
+                _ => Err(String::from("A source dir is a path or a table with a `path`")),
+            }
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    if let Some(toml::Value::Array(existing)) = table.remove("sources") {
+        sources.extend(existing);
+    }
+    table.insert(String::from("source"), toml::Value::String(first));
+    table.insert(String::from("sources"), toml::Value::Array(sources));
+    Ok(())
+}
+
+fn read_toml_table(path: &Path) -> Result<toml::Table>
+{
+    let contents = match fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) => {
+            return Err(Error::Io {
+                err:  e,
+                path: path.to_path_buf(),
+            })
+        }
+    };
+
+    match toml::from_str(&contents) {
+        Ok(x) => Ok(x),
+        Err(e) => Err(Error::ConfigParse(format!("Couldn't parse {}: {e}", path.display()))),
+    }
+}
+
+/// The project's root, which the config's paths are relative to: the current
+/// directory, as an absolute path.
+pub(crate) fn project_root() -> PathBuf { absolute(Path::new(".")) }
+
+/// `path` made absolute, with `.` and `..` resolved. Symbolic links are
+/// resolved for the part of the path that exists.
+fn absolute(path: &Path) -> PathBuf
+{
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    let joined = std::env::current_dir().unwrap_or_default().join(path);
+    let mut absolute = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) if parent.exists() => {
+            parent
+                .canonicalize()
+                .map_or_else(|_| absolute.clone(), |parent| parent.join(name))
+        }
+        _ => absolute,
+    }
+}
+
+/// Expand `${VAR}` references in every string value of `table`, read from
+/// `path`, looking the variables up with `env`.
+fn expand_env_vars_in_table(table: &mut toml::Table, path: &Path, env: &dyn Fn(&str) -> Option<String>) -> Result<()>
+{
+    for (_, value) in table.iter_mut() {
+        expand_env_vars_in_value(value, path, env)?;
+    }
+    Ok(())
+}
+
+fn expand_env_vars_in_value(value: &mut toml::Value, path: &Path, env: &dyn Fn(&str) -> Option<String>) -> Result<()>
+{
+    match value {
+        toml::Value::String(s) => {
+            *s = expand_env_vars(s, env).map_err(|var| {
+                Error::MissingEnvironmentVariable {
+                    var,
+                    path: path.to_path_buf(),
+                }
+            })?;
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                expand_env_vars_in_value(value, path, env)?;
+            }
+        }
+        toml::Value::Table(table) => expand_env_vars_in_table(table, path, env)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replace every `${VAR}` in `s` with the value of the environment variable
+/// `VAR`, as looked up with `env`. `$${` is an escaped, literal `${`.
+///
+/// # Errors
+///
+/// Returns the name of the first referenced variable that isn't set.
+fn expand_env_vars(s: &str, env: &dyn Fn(&str) -> Option<String>) -> std::result::Result<String, String>
+{
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = escaped;
+        }
+        else if let Some((var, after)) = rest.strip_prefix("${").and_then(|x| x.split_once('}')) {
+            expanded.push_str(&env(var).ok_or_else(|| var.to_string())?);
+            rest = after;
+        }
+        else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Recursively merge `overlay` into `base`. Tables are merged key by key,
+/// everything else in `overlay` replaces what's in `base`.
+pub(crate) fn merge_toml_tables(base: &mut toml::Table, overlay: toml::Table)
+{
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge_toml_tables(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
     }
 }
 
@@ -148,22 +1451,84 @@ structstruck::strike! {
 #[strikethrough[derive(Debug, Deserialize, Clone, PartialEq)]]
 pub struct PageInfo
 {
-    /// The page title.
+    /// The page title. If omitted, the page's first `#` heading is used.
+    #[serde(default)]
     pub title: String,
 
-    /// The page's description.
+    /// The page's description. If omitted, the page's first paragraph is
+    /// used.
+    #[serde(default)]
     pub description: String,
 
+    /// A stable ID that `{{ link id="..." }}` refers to the page by, even
+    /// after its file is moved.
+    pub id: Option<String>,
+
     /// The CSS stylesheet to use.
     pub style: Option<PathBuf>,
 
     /// The path to the HTML template to use.
     pub template: Option<PathBuf>,
 
+    /// The syntax theme code blocks on the page are highlighted with,
+    /// instead of the site's `syntax_theme`.
+    pub syntax_theme: Option<String>,
+
+    /// Replace `:shortcode:` emoji on this page, instead of following
+    /// `generation.emoji`.
+    pub emoji: Option<bool>,
+
+    /// The audiences the page is built for, out of the configured
+    /// `audiences`. Every one, if omitted.
+    pub audience: Option<Vec<String>>,
+
     /// Use a different favicon for this page. If omitted the defualt one will
     /// be used.
     pub favicon: Option<PathBuf>,
 
+    /// When the page was written.
+    pub date: Option<Datetime>,
+
+    /// HTML put in the page's `[/rustic_head/]`, after `default.head_extra`,
+    /// e.g. verification tags, scripts, or preload hints.
+    pub head_extra: Option<HeadExtra>,
+
+    /// The page's keywords, instead of `default.keywords`.
+    pub keywords: Option<Vec<String>>,
+
+    /// The page's tags. With `feeds.tags`, each one gets its own feeds.
+    pub tags: Option<Vec<String>>,
+
+    /// The language the page is written in, instead of `default.lang`.
+    pub lang: Option<String>,
+
+    /// Give the page a short URL, `/s/<code>`, that redirects to it.
+    pub short: Option<bool>,
+
+    /// Fail the build if the page changes and `approved_hash` isn't updated
+    /// to its new hash.
+    pub locked: Option<bool>,
+
+    /// The hash of the approved version of a locked page.
+    pub approved_hash: Option<String>,
+
+    /// The page's file name in the dest dir, without `.html`, instead of
+    /// its source file's. Also what `:slug` is in the `permalink` pattern.
+    pub slug: Option<String>,
+
+    /// Where the page is written, relative to the dest dir, instead of where
+    /// its source file or the `permalink` pattern puts it. A path ending in
+    /// `/` is written to the `index.html` in it.
+    pub path: Option<String>,
+
+    /// A TOML file of page info shared between pages. It's merged under the
+    /// page's own values.
+    pub meta_from: Option<PathBuf>,
+
+    /// The page's own values for `{{ page.extra.<name> }}`, with
+    /// `generation.variables_in_markdown`.
+    pub extra: Option<Table>,
+
     pub meta: Option<pub struct PageInfoMeta {
         pub site_name: String,
         pub authors: Vec<String>,
@@ -173,4 +1538,365 @@ pub struct PageInfo
 impl PageInfo
 {
     pub const CODE_BLOCK_IDENTIFIER: &str = "pageinfo";
+
+    /// Page info with only a title and a description.
+    pub fn new(title: String, description: String) -> Self
+    {
+        Self {
+            title,
+            description,
+            id: None,
+            style: None,
+            template: None,
+            syntax_theme: None,
+            emoji: None,
+            audience: None,
+            head_extra: None,
+            keywords: None,
+            tags: None,
+            lang: None,
+            short: None,
+            locked: None,
+            approved_hash: None,
+            favicon: None,
+            date: None,
+            slug: None,
+            path: None,
+            meta_from: None,
+            extra: None,
+            meta: None,
+        }
+    }
+
+    /// Parse the contents of a `pageinfo` block. If it has a `meta_from`
+    /// path, the TOML file there is merged under the page's own values.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the page info, or the file
+    /// its `meta_from` refers to, cannot be read or parsed.
+    pub fn parse(page_info: &str) -> std::result::Result<Self, String>
+    {
+        Self::parse_in_section(page_info, &toml::Table::new())
+    }
+
+    /// Parse the contents of a `pageinfo` block over `section`, the page info
+    /// the sections of the page give it. A `meta_from` file is merged between
+    /// the two.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the page info, or the file
+    /// its `meta_from` refers to, cannot be read or parsed.
+    pub fn parse_in_section(page_info: &str, section: &toml::Table) -> std::result::Result<Self, String>
+    {
+        let table: toml::Table = toml::from_str(page_info).map_err(|e| e.to_string())?;
+        let meta_from = table.get("meta_from").or_else(|| section.get("meta_from"));
+        if meta_from.is_none() && section.is_empty() {
+            // Parsed directly for errors that point into the page info.
+            return toml::from_str(page_info).map_err(|e| e.to_string());
+        }
+        let mut merged = section.clone();
+        if let Some(meta_from) = meta_from {
+            let path = meta_from
+                .as_str()
+                .ok_or_else(|| String::from("`meta_from` must be a path"))?;
+            let contents = fs::read_to_string(path).map_err(|e| format!("Couldn't read \"{path}\": {e}"))?;
+            let mut shared: toml::Table =
+                toml::from_str(&contents).map_err(|e| format!("Couldn't parse \"{path}\": {e}"))?;
+            shared.remove("meta_from");
+            merge_toml_tables(&mut merged, shared);
+        }
+        merge_toml_tables(&mut merged, table);
+        toml::Value::Table(merged)
+            .try_into()
+            .map_err(|e: toml::de::Error| e.to_string())
+    }
+
+    /// If `key` is left out of a `pageinfo` block, the page info of its
+    /// `section`, and its `meta_from` file.
+    pub fn omits(page_info: &str, section: &toml::Table, key: &str) -> bool
+    {
+        let Ok(table) = toml::from_str::<toml::Table>(page_info)
+        else {
+            return false;
+        };
+        if table.contains_key(key) || section.contains_key(key) {
+            return false;
+        }
+        let shared = table
+            .get("meta_from")
+            .or_else(|| section.get("meta_from"))
+            .and_then(toml::Value::as_str)
+            .and_then(|x| fs::read_to_string(x).ok())
+            .and_then(|x| toml::from_str::<toml::Table>(&x).ok());
+        !shared.is_some_and(|x| x.contains_key(key))
+    }
+
+    /// The favicon of the shared page info this page info's `meta_from`
+    /// refers to, if it has one different from the page's own.
+    pub fn shared_favicon(&self) -> Option<PathBuf>
+    {
+        let contents = fs::read_to_string(self.meta_from.as_ref()?).ok()?;
+        let shared: toml::Table = toml::from_str(&contents).ok()?;
+        let favicon = PathBuf::from(shared.get("favicon")?.as_str()?);
+        (self.favicon.as_ref() != Some(&favicon)).then_some(favicon)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that `${VAR}` references are expanded and `$${` is left literal
+    fn test_expand_env_vars()
+    {
+        let env = |var: &str| (var == "RUSTIC_RAVEN_TEST_VAR").then(|| String::from("value"));
+        assert_eq!(
+            expand_env_vars("a/${RUSTIC_RAVEN_TEST_VAR}/b$c", &env).unwrap(),
+            "a/value/b$c"
+        );
+        assert_eq!(
+            expand_env_vars("$${RUSTIC_RAVEN_TEST_VAR}", &env).unwrap(),
+            "${RUSTIC_RAVEN_TEST_VAR}"
+        );
+        assert_eq!(
+            expand_env_vars("${RUSTIC_RAVEN_TEST_UNSET_VAR}", &env).unwrap_err(),
+            "RUSTIC_RAVEN_TEST_UNSET_VAR"
+        );
+    }
+
+    #[test]
+    /// Test that an unset variable in a profile's overlay file is reported
+    /// against the overlay, not the base configuration
+    fn test_env_var_in_overlay()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/env-overlay");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("raven.toml");
+        std::fs::write(&path, "source = \"${SOURCE}\"\ndest = \"dest\"\n").unwrap();
+        std::fs::write(dir.join("raven.prod.toml"), "dest = \"${DEST}\"\n").unwrap();
+        let env = |var: &str| (var == "SOURCE").then(|| String::from("src"));
+
+        let config = Config::from_toml_with_env(&path, None, &env).unwrap();
+        assert_eq!(config.source, PathBuf::from("src"));
+        match Config::from_toml_with_env(&path, Some("prod"), &env) {
+            Err(Error::MissingEnvironmentVariable { var, path }) => {
+                assert_eq!(var, "DEST");
+                assert_eq!(path, dir.join("raven.prod.toml"));
+            }
+            x => panic!("expected a missing variable, got {x:?}"),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that a dest dir overlapping the project or the source is refused
+    fn test_check_dest()
+    {
+        let with_dirs = |source: &str, dest: &str| {
+            Config {
+                source: PathBuf::from(source),
+                dest: PathBuf::from(dest),
+                ..Config::default()
+            }
+        };
+        assert!(with_dirs("src", "dest").check_dest().is_ok());
+        assert!(with_dirs("src", "./out/../dest").check_dest().is_ok());
+        assert!(with_dirs("src", ".").check_dest().is_err());
+        assert!(with_dirs("src", "..").check_dest().is_err());
+        assert!(with_dirs("src", "src").check_dest().is_err());
+        assert!(with_dirs("src", "src/out").check_dest().is_err());
+        assert!(with_dirs("site/src", "site").check_dest().is_err());
+    }
+
+    #[test]
+    /// Test that a list of source dirs is split into `source` and `sources`,
+    /// and files are found in the innermost one
+    fn test_source_list()
+    {
+        let mut table: toml::Table =
+            toml::from_str("source = [\"content\", { path = \"docs\", dest = \"docs\" }, \"content/notes\"]").unwrap();
+        split_source_list(&mut table).unwrap();
+        let config = Config {
+            source: PathBuf::from(table["source"].as_str().unwrap()),
+            sources: table["sources"].clone().try_into().unwrap(),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.source_relative(Path::new("docs/intro.md")),
+            Some((Path::new("intro.md"), Some(Path::new("docs"))))
+        );
+        assert_eq!(
+            config.source_relative(Path::new("content/notes/a.md")),
+            Some((Path::new("a.md"), None))
+        );
+        assert_eq!(config.source_relative(Path::new("other/a.md")), None);
+
+        let mut table: toml::Table = toml::from_str("source = [{ path = \"a\", dest = \"b\" }]").unwrap();
+        assert!(split_source_list(&mut table).is_err());
+    }
+
+    #[test]
+    /// Test that each audience is built into its own dest dir, and that
+    /// without audiences there's one build
+    fn test_audience_configs()
+    {
+        let config = Config::default();
+        assert_eq!(config.audience_configs().len(), 1);
+        assert_eq!(config.audience_configs()[0].audience, None);
+
+        let config = Config {
+            audiences: Some(vec![String::from("public"), String::from("internal")]),
+            ..Config::default()
+        };
+        let configs = config.audience_configs();
+        assert_eq!(
+            configs
+                .iter()
+                .map(|x| (x.dest.clone(), x.audience.clone().unwrap()))
+                .collect::<Vec<_>>(),
+            vec![
+                (config.dest.join("public"), String::from("public")),
+                (config.dest.join("internal"), String::from("internal")),
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that IANA time zones are parsed, unknown ones refused, and dates
+    /// are in the zone
+    fn test_timezone()
+    {
+        let site = toml::from_str::<Site>("timezone = \"Asia/Kolkata\"").unwrap();
+        assert_eq!(site.timezone, Some(Tz::Asia__Kolkata));
+        for unknown in ["Nowhere/Atlantis", "../../etc/passwd"] {
+            assert!(toml::from_str::<Site>(&format!("timezone = \"{unknown}\"")).is_err());
+        }
+
+        let config = Config {
+            site: Some(site),
+            ..Config::default()
+        };
+        assert_eq!(config.now().offset().local_minus_utc(), 5 * 3600 + 30 * 60);
+    }
+
+    #[test]
+    /// Test that a page's own values take precedence over its `meta_from`
+    fn test_page_info_meta_from()
+    {
+        let path = std::env::temp_dir().join("rustic_raven_test_meta_from.toml");
+        std::fs::write(
+            &path,
+            "description = \"Shared\"\ntitle = \"Shared\"\n[meta]\nsite_name = \"Book\"\nauthors = [\"A\"]\n",
+        )
+        .unwrap();
+        let page_info = PageInfo::parse(&format!(
+            "title = \"Chapter\"\nmeta_from = {}",
+            toml::Value::String(path.display().to_string())
+        ))
+        .unwrap();
+        assert_eq!(page_info.title, "Chapter");
+        assert_eq!(page_info.description, "Shared");
+        assert_eq!(page_info.meta.unwrap().site_name, "Book");
+        std::fs::remove_file(path).unwrap();
+        assert!(PageInfo::parse("title = \"A\"\nmeta_from = \"missing.toml\"").is_err());
+    }
+
+    #[test]
+    /// Test that markdown extensions default to the widely used ones, and can
+    /// be turned on and off
+    fn test_markdown_options()
+    {
+        use pulldown_cmark::Options;
+
+        let mut config = Config::default();
+        assert_eq!(
+            config.markdown_options(),
+            Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH
+        );
+        config.markdown = toml::from_str("tables = false\nfootnotes = true").ok();
+        assert_eq!(
+            config.markdown_options(),
+            Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_FOOTNOTES
+        );
+    }
+
+    #[test]
+    /// Test that `generation.jobs` sets how many pages are built at once, and
+    /// that unset or 0 means the number of CPUs
+    fn test_jobs()
+    {
+        let cpus = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+        let mut config = Config::default();
+        assert_eq!(config.jobs(), cpus);
+        config.set_jobs(3);
+        assert_eq!(config.jobs(), 3);
+        config.set_jobs(0);
+        assert_eq!(config.jobs(), cpus);
+    }
+
+    #[test]
+    /// Test that setting the jobs keeps the other generation settings
+    fn test_set_jobs()
+    {
+        let mut config = Config::default();
+        config.generation_mut().fail_fast = Some(true);
+        config.set_jobs(2);
+        assert_eq!(config.generation.as_ref().unwrap().fail_fast, Some(true));
+        assert_eq!(config.generation.as_ref().unwrap().jobs, Some(2));
+    }
+
+    #[test]
+    /// Test that the build only stops at the first failure when asked to
+    fn test_fail_fast()
+    {
+        let mut config = Config::default();
+        assert!(!config.fail_fast());
+        config.set_fail_fast(true);
+        assert!(config.fail_fast());
+    }
+
+    #[test]
+    /// Test that the inline limits have defaults, and each can be set alone
+    fn test_inline_limits()
+    {
+        let mut config = Config::default();
+        assert_eq!(config.favicon_inline_limit(), 16 * 1024);
+        assert_eq!(config.stylesheet_inline_limit(), 64 * 1024);
+        config.generation = Some(toml::from_str("[inline_limits]\nstylesheet = 1000").unwrap());
+        assert_eq!(config.favicon_inline_limit(), 16 * 1024);
+        assert_eq!(config.stylesheet_inline_limit(), 1000);
+    }
+
+    #[test]
+    /// Test that error markers are on with the dev profile, unless they're
+    /// turned off, and off otherwise
+    fn test_error_markers()
+    {
+        let mut config = Config::default();
+        assert!(!config.error_markers());
+        config.profile = Some(String::from("dev"));
+        assert!(config.error_markers());
+        config.generation = Some(toml::from_str("error_markers = false").unwrap());
+        assert!(!config.error_markers());
+    }
+
+    #[test]
+    /// Test that only the default template and stylesheet are built in
+    fn test_builtin_file()
+    {
+        let config = Config::default();
+        assert_eq!(
+            config.builtin_file(&config.default.template),
+            Some(crate::defaults::DEFAULT_HTML_TEMPLATE_SRC)
+        );
+        assert_eq!(
+            config.builtin_file(&config.default.stylesheet),
+            Some(crate::defaults::DEFAULT_CSS_STYLESHEET_SRC)
+        );
+        assert_eq!(config.builtin_file(Path::new("other.css")), None);
+    }
 }
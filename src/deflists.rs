@@ -0,0 +1,149 @@
+//! Definition lists, rendered as `<dl>` blocks when
+//! `markdown.definition_lists` is set.
+//!
+//! A paragraph of terms, one per line, directly followed by lines starting
+//! with `: ` is a list of the terms and their definitions. A definition goes
+//! on until a blank line, and a blank line followed by an indented line
+//! continues it with another paragraph. Terms and definitions that follow
+//! after a blank line belong to the same list. Lists in fenced code blocks
+//! are left alone.
+
+use pulldown_cmark::{html, Options, Parser};
+
+use crate::fences::Fences;
+
+const MARKER: &str = ": ";
+
+/// Expand every definition list in the markdown `source`, or `None` if it
+/// has none. Terms are rendered with `options`, definitions are left as
+/// markdown.
+pub(crate) fn expand(source: &str, options: Options) -> Option<String>
+{
+    if !source.contains(&format!("\n{MARKER}")) {
+        return None;
+    }
+
+    let lines = source.split_inclusive('\n').collect::<Vec<_>>();
+    let mut output = String::with_capacity(source.len());
+    let mut expanded = false;
+    let mut fences = Fences::default();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if fences.contains(line) {
+            output.push_str(line);
+            i += 1;
+            continue;
+        }
+
+        let paragraph_start = i == 0 || is_blank(lines[i - 1]);
+        let Some(mut first_definition) = paragraph_start.then(|| definitions_at(&lines, i)).flatten()
+        else {
+            output.push_str(line);
+            i += 1;
+            continue;
+        };
+
+        output.push_str("\n<dl>\n");
+        loop {
+            for term in &lines[i..first_definition] {
+                output.push_str(&format!("<dt>{}</dt>\n", render_inline(term.trim(), options)));
+            }
+            i = first_definition;
+            while let Some(first_line) = lines.get(i).and_then(|x| definition(x)) {
+                let mut body = first_line.to_string();
+                i += 1;
+                while let Some(line) = lines.get(i).filter(|x| !is_blank(x) && definition(x).is_none()) {
+                    body.push_str(line.trim_start());
+                    i += 1;
+                }
+                while lines.get(i).is_some_and(|x| is_blank(x)) && lines.get(i + 1).is_some_and(|x| is_indented(x)) {
+                    body.push('\n');
+                    i += 1;
+                    while let Some(line) = lines.get(i).filter(|x| !is_blank(x)) {
+                        body.push_str(line.trim_start());
+                        i += 1;
+                    }
+                }
+                output.push_str("<dd>\n\n");
+                output.push_str(body.trim_end());
+                output.push_str("\n\n</dd>\n");
+            }
+
+            let next = (i..lines.len()).find(|&j| !is_blank(lines[j])).unwrap_or(lines.len());
+            match definitions_at(&lines, next) {
+                Some(definition) if next > i => {
+                    i = next;
+                    first_definition = definition;
+                }
+                _ => break,
+            }
+        }
+        output.push_str("</dl>\n\n");
+        expanded = true;
+    }
+    expanded.then_some(output)
+}
+
+/// Where the definitions of the terms starting at line `start` begin, if
+/// they're a paragraph of terms followed by a definition.
+fn definitions_at(lines: &[&str], start: usize) -> Option<usize>
+{
+    let first = lines.get(start)?;
+    if is_blank(first) || definition(first).is_some() {
+        return None;
+    }
+    let end = (start..lines.len())
+        .find(|&i| is_blank(lines[i]) || definition(lines[i]).is_some())
+        .unwrap_or(lines.len());
+    lines.get(end).and_then(|x| definition(x)).map(|_| end)
+}
+
+/// A definition line without its `: `.
+fn definition(line: &str) -> Option<&str>
+{
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    (indent < 4).then(|| line.trim_start().strip_prefix(MARKER))?
+}
+
+fn is_blank(line: &str) -> bool { line.trim().is_empty() }
+
+fn is_indented(line: &str) -> bool { line.starts_with("  ") || line.starts_with('\t') }
+
+/// The inline HTML of the markdown `text`, without the paragraph around it.
+fn render_inline(text: &str, options: Options) -> String
+{
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, Parser::new_ext(text, options));
+    let rendered = rendered.trim_end();
+    rendered
+        .strip_prefix("<p>")
+        .and_then(|x| x.strip_suffix("</p>"))
+        .unwrap_or(rendered)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that terms and their definitions become a single list, and that
+    /// code blocks and lone colons are left alone
+    fn test_expand()
+    {
+        let options = Options::empty();
+        assert_eq!(expand("Ratio\n\n: 1\n", options), None);
+        assert_eq!(
+            expand(
+                "Intro\n\n*Raven*\nCorvid\n: A bird.\n  Black.\n: A town.\n\nSwan\n: White.\n\n  Big.\n",
+                options
+            )
+            .unwrap(),
+            "Intro\n\n\n<dl>\n<dt><em>Raven</em></dt>\n<dt>Corvid</dt>\n<dd>\n\nA bird.\nBlack.\n\n</dd>\n<dd>\n\nA \
+             town.\n\n</dd>\n<dt>Swan</dt>\n<dd>\n\nWhite.\n\nBig.\n\n</dd>\n</dl>\n\n"
+        );
+        assert_eq!(expand("```\nTerm\n: Not one\n```\n", options), None);
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    collections::{HashMap, HashSet},
+    process::Command,
+};
+
+use chrono::{DateTime, Utc};
+use walkdir::WalkDir;
+
+use crate::{hash, mime, Deploy, Error, Path, Result};
+
+/// Publish `dest` to the target described by `deploy`. The deploy commands
+/// are run on a blocking thread.
+///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - The deploy command cannot be started
+/// - The deploy command exits unsuccessfully
+pub async fn deploy(deploy: &Deploy, dest: &Path) -> Result<()>
+{
+    let deploy = deploy.clone();
+    let dest = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || deploy_blocking(&deploy, &dest))
+        .await
+        .map_err(|_| Error::AysncJoin)?
+}
+
+fn deploy_blocking(deploy: &Deploy, dest: &Path) -> Result<()>
+{
+    let mut command = match deploy {
+        Deploy::Rsync {
+            destination,
+            delete,
+            ssh_args,
+        } => rsync_command(dest, destination, delete.unwrap_or(false), ssh_args.as_deref()),
+        Deploy::Command { command: line } => shell_command(line),
+        Deploy::S3 {
+            bucket,
+            prefix,
+            endpoint_url,
+            delete,
+            cloudfront_distribution,
+        } => {
+            println!("Deploying \"{}\"", dest.display());
+            let s3 = S3 {
+                bucket,
+                prefix: prefix.as_deref().map(|x| x.trim_matches('/')).filter(|x| !x.is_empty()),
+                endpoint_url: endpoint_url.as_deref(),
+            };
+            s3.sync(dest, delete.unwrap_or(false), cloudfront_distribution.as_deref())?;
+            println!("Deployed \"{}\"", dest.display());
+            return Ok(());
+        }
+    };
+
+    command.env("RAVEN_DEST", dest);
+    println!("Deploying \"{}\"", dest.display());
+    run(&mut command)?;
+    println!("Deployed \"{}\"", dest.display());
+    Ok(())
+}
+
+/// The rsync command copying the contents of `dest` to `destination` over
+/// ssh.
+fn rsync_command(dest: &Path, destination: &str, delete: bool, ssh_args: Option<&str>) -> Command
+{
+    let mut command = Command::new("rsync");
+    command.arg("--archive").arg("--compress");
+    if delete {
+        command.arg("--delete");
+    }
+    match ssh_args {
+        Some(ssh_args) => command.arg("--rsh").arg(format!("ssh {ssh_args}")),
+        None => command.arg("--rsh").arg("ssh"),
+    };
+
+    // The trailing slash makes rsync copy the contents of the dest dir rather
+    // than the dir itself.
+    command.arg(format!("{}/", dest.display())).arg(destination);
+    command
+}
+
+fn run(command: &mut Command) -> Result<()>
+{
+    let status = command
+        .status()
+        .map_err(|e| Error::Deploy(format!("Couldn't run {command:?}: {e}")))?;
+    if !status.success() {
+        return Err(Error::Deploy(format!("{command:?} failed: {status}")));
+    }
+    Ok(())
+}
+
+fn run_capturing(command: &mut Command) -> Result<String>
+{
+    let output = command
+        .output()
+        .map_err(|e| Error::Deploy(format!("Couldn't run {command:?}: {e}")))?;
+    if !output.status.success() {
+        return Err(Error::Deploy(format!(
+            "{command:?} failed: {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// An object in the bucket, as it's listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Object
+{
+    etag:          String,
+    size:          u64,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+impl Object
+{
+    /// If the object has the same content as the file at `path`.
+    ///
+    /// An object uploaded in one part has its MD5 as its ETag. One uploaded
+    /// in parts, as the `aws` CLI does with big files, has `<md5>-<parts>`
+    /// instead, so it's the same if it's as big as the file and was uploaded
+    /// after the file was last modified.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the file cannot be read.
+    fn matches(&self, path: &Path) -> Result<bool>
+    {
+        let io_error = |e| {
+            Error::Io {
+                err:  e,
+                path: path.to_path_buf(),
+            }
+        };
+        if self.etag.contains('-') {
+            let metadata = std::fs::metadata(path).map_err(io_error)?;
+            let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+            return Ok(self.size == metadata.len()
+                && matches!((self.last_modified, modified), (Some(uploaded), Some(modified)) if modified <= uploaded));
+        }
+        let contents = std::fs::read(path).map_err(io_error)?;
+        Ok(self.etag == hash::md5_hex(&contents))
+    }
+}
+
+/// An S3 (or S3-compatible) bucket, accessed through the `aws` CLI.
+struct S3<'a>
+{
+    bucket:       &'a str,
+    prefix:       Option<&'a str>,
+    endpoint_url: Option<&'a str>,
+}
+
+impl<'a> S3<'a>
+{
+    /// Upload the files in `dest` whose content differs from the bucket's,
+    /// optionally deleting objects that aren't in `dest`, then invalidate the
+    /// changed paths in `cloudfront_distribution`.
+    fn sync(&self, dest: &Path, delete: bool, cloudfront_distribution: Option<&str>) -> Result<()>
+    {
+        let remote = self.list_objects()?;
+        let mut local_keys = HashSet::new();
+        let mut changed = Vec::new();
+
+        for entry in WalkDir::new(dest)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|x| x.file_type().is_file())
+        {
+            let relative = entry.path().strip_prefix(dest).unwrap_or(entry.path());
+            let relative = relative
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let key = self.key(&relative);
+            local_keys.insert(key.clone());
+            if let Some(object) = remote.get(&key) {
+                if object.matches(entry.path())? {
+                    continue;
+                }
+            }
+
+            run(self
+                .aws()
+                .args(["s3", "cp"])
+                .arg(entry.path())
+                .arg(format!("s3://{}/{key}", self.bucket))
+                .args(["--content-type", mime::from_path(entry.path())]))?;
+            changed.push(self.distribution_path(&key));
+        }
+
+        if delete {
+            for key in remote.keys().filter(|x| !local_keys.contains(*x)) {
+                run(self.aws().args(["s3", "rm"]).arg(format!("s3://{}/{key}", self.bucket)))?;
+                changed.push(self.distribution_path(key));
+            }
+        }
+        println!("{} changed object(s)", changed.len());
+
+        if let Some(distribution) = cloudfront_distribution {
+            if !changed.is_empty() {
+                // Past a handful of paths a wildcard is cheaper than listing
+                // each one.
+                if changed.len() > 15 {
+                    changed = vec![String::from("/*")];
+                }
+                run(self
+                    .aws()
+                    .args([
+                        "cloudfront",
+                        "create-invalidation",
+                        "--distribution-id",
+                        distribution,
+                        "--paths",
+                    ])
+                    .args(&changed))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn aws(&self) -> Command
+    {
+        let mut command = Command::new("aws");
+        if let Some(endpoint_url) = self.endpoint_url {
+            command.args(["--endpoint-url", endpoint_url]);
+        }
+        command
+    }
+
+    /// The key of the file at `relative`, its `/`-separated path in the dest
+    /// dir.
+    fn key(&self, relative: &str) -> String
+    {
+        match self.prefix {
+            Some(prefix) => format!("{prefix}/{relative}"),
+            None => relative.to_string(),
+        }
+    }
+
+    /// The path the object at `key` is invalidated by. The distribution
+    /// serves the prefix as its root, so the prefix isn't part of it.
+    fn distribution_path(&self, key: &str) -> String
+    {
+        let path = self
+            .prefix
+            .and_then(|prefix| key.strip_prefix(prefix)?.strip_prefix('/'))
+            .unwrap_or(key);
+        format!("/{path}")
+    }
+
+    /// Every object under the prefix, keyed by its key.
+    fn list_objects(&self) -> Result<HashMap<String, Object>>
+    {
+        let mut command = self.aws();
+        command.args(["s3api", "list-objects-v2", "--bucket", self.bucket, "--output", "json"]);
+        if let Some(prefix) = self.prefix {
+            command.arg("--prefix").arg(format!("{prefix}/"));
+        }
+
+        parse_listing(&run_capturing(&mut command)?)
+    }
+}
+
+/// The objects of the JSON output of `aws s3api list-objects-v2`, keyed by
+/// their keys.
+fn parse_listing(output: &str) -> Result<HashMap<String, Object>>
+{
+    if output.trim().is_empty() {
+        return Ok(HashMap::new());
+    }
+    let listing: serde_json::Value =
+        serde_json::from_str(output).map_err(|e| Error::Deploy(format!("Couldn't parse the bucket listing: {e}")))?;
+
+    Ok(listing["Contents"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|object| {
+            let key = object["Key"].as_str()?;
+            let last_modified = object["LastModified"]
+                .as_str()
+                .and_then(|x| DateTime::parse_from_rfc3339(x).ok())
+                .map(|x| x.with_timezone(&Utc));
+            Some((
+                key.to_string(),
+                Object {
+                    etag: object["ETag"].as_str()?.trim_matches('"').to_string(),
+                    size: object["Size"].as_u64().unwrap_or_default(),
+                    last_modified,
+                },
+            ))
+        })
+        .collect())
+}
+
+/// A command that runs `line` with the platform's shell.
+pub(crate) fn shell_command(line: &str) -> Command
+{
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    }
+    else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(line);
+    command
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that rsync copies the dest dir's contents, with `--delete` and
+    /// the ssh arguments when they're set
+    fn test_rsync_command()
+    {
+        let args = |command: &Command| {
+            command
+                .get_args()
+                .map(|x| x.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        let command = rsync_command(Path::new("dest"), "user@host:/var/www", false, None);
+        assert_eq!(command.get_program(), "rsync");
+        assert_eq!(
+            args(&command),
+            ["--archive", "--compress", "--rsh", "ssh", "dest/", "user@host:/var/www"]
+        );
+
+        let command = rsync_command(Path::new("dest"), "user@host:/var/www", true, Some("-p 2222"));
+        assert_eq!(
+            args(&command),
+            [
+                "--archive",
+                "--compress",
+                "--delete",
+                "--rsh",
+                "ssh -p 2222",
+                "dest/",
+                "user@host:/var/www"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    /// Test that a command target gets the dest dir in `$RAVEN_DEST`, and
+    /// that it failing fails the deploy
+    async fn test_command_target()
+    {
+        let command = |line: &str| {
+            Deploy::Command {
+                command: String::from(line),
+            }
+        };
+        let dest = Path::new("site/dest");
+        deploy(&command(r#"test "$RAVEN_DEST" = site/dest"#), dest)
+            .await
+            .unwrap();
+        assert!(matches!(deploy(&command("exit 3"), dest).await, Err(Error::Deploy(_))));
+    }
+
+    #[test]
+    /// Test that single part objects are compared by MD5, and multipart ones,
+    /// whose ETags never match, by size and upload time
+    fn test_s3_matches()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/deploy");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("page.html");
+        std::fs::write(&path, "<p>Hi</p>").unwrap();
+
+        let listing = format!(
+            r#"{{"Contents": [
+                {{"Key": "docs/page.html", "ETag": "\"{}\"", "Size": 9, "LastModified": "2000-01-01T00:00:00.000Z"}},
+                {{"Key": "docs/big.bin", "ETag": "\"0123456789abcdef0123456789abcdef-3\"", "Size": 9, "LastModified": "9999-01-01T00:00:00+00:00"}}
+            ]}}"#,
+            hash::md5_hex(b"<p>Hi</p>")
+        );
+        let objects = parse_listing(&listing).unwrap();
+        assert_eq!(objects.len(), 2);
+        assert!(objects["docs/page.html"].matches(&path).unwrap());
+        assert!(objects["docs/big.bin"].matches(&path).unwrap());
+
+        let changed = Object {
+            etag: hash::md5_hex(b"<p>Bye</p>"),
+            ..objects["docs/page.html"].clone()
+        };
+        assert!(!changed.matches(&path).unwrap());
+        let resized = Object {
+            size: 10,
+            ..objects["docs/big.bin"].clone()
+        };
+        assert!(!resized.matches(&path).unwrap());
+        // Uploaded before the file was last modified.
+        let stale = Object {
+            last_modified: objects["docs/page.html"].last_modified,
+            ..objects["docs/big.bin"].clone()
+        };
+        assert!(!stale.matches(&path).unwrap());
+        assert!(parse_listing("").unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that keys are under the prefix, and invalidation paths aren't
+    fn test_s3_paths()
+    {
+        let s3 = S3 {
+            bucket:       "bucket",
+            prefix:       Some("docs"),
+            endpoint_url: None,
+        };
+        assert_eq!(s3.key("blog/post.html"), "docs/blog/post.html");
+        assert_eq!(s3.distribution_path("docs/blog/post.html"), "/blog/post.html");
+        assert_eq!(s3.distribution_path("docsy/a.html"), "/docsy/a.html");
+
+        let s3 = S3 { prefix: None, ..s3 };
+        assert_eq!(s3.key("index.html"), "index.html");
+        assert_eq!(s3.distribution_path("index.html"), "/index.html");
+    }
+}
@@ -0,0 +1,229 @@
+//! Project diagnostics that suggest how to fix what they find.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    build::get_syntaxes,
+    graph::{DependencyGraph, UnusedReport},
+    Config, Path, Result, NAME,
+};
+
+/// A problem found in a project and how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnosis
+{
+    pub problem:    String,
+    pub suggestion: String,
+}
+
+impl Diagnosis
+{
+    fn new(problem: String, suggestion: impl Into<String>) -> Self
+    {
+        Self {
+            problem,
+            suggestion: suggestion.into(),
+        }
+    }
+}
+
+/// Inspect the project described by `config`.
+///
+/// This looks for missing templates, stylesheets, and favicons, an unknown
+/// syntax theme, pages without usable page info, templates no page uses, and
+/// files in the dest dir that no source generates.
+///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - A source file cannot be read
+/// - Syntaxes or syntax themes cannot be loaded
+pub fn diagnose(config: &Config) -> Result<Vec<Diagnosis>>
+{
+    let graph = DependencyGraph::from_config(config)?;
+    let mut diagnoses = Vec::new();
+
+    diagnoses.extend(missing_files(config, &graph));
+
+    let (_, themes) = get_syntaxes(config)?;
+    if !themes.contains_key(&config.syntax_theme) {
+        diagnoses.push(Diagnosis::new(
+            format!("The syntax theme \"{}\" doesn't exist", config.syntax_theme),
+            format!(
+                "Set `syntax_theme` to one of {}, or add a .tmTheme file to \"{}\"",
+                themes.keys().map(|x| format!("\"{x}\"")).collect::<Vec<_>>().join(", "),
+                config.custom_syntax_themes.display()
+            ),
+        ));
+    }
+
+    for (source_file, page) in &graph.pages {
+        if let Some(e) = &page.page_info_error {
+            diagnoses.push(Diagnosis::new(
+                format!("\"{}\" has no usable page info: {}", source_file.display(), e.trim()),
+                "Start the page with a ```pageinfo block containing at least `title` and `description`",
+            ));
+        }
+    }
+
+    for template in UnusedReport::new(config, &graph).templates {
+        diagnoses.push(Diagnosis::new(
+            format!("The template \"{}\" isn't used by any page", template.display()),
+            "Delete it, or set `template` in a page's page info to use it",
+        ));
+    }
+
+    let orphans = graph.orphaned_dest_files(config)?;
+    if !orphans.is_empty() {
+        diagnoses.push(Diagnosis::new(
+            format!(
+                "{} file(s) in \"{}\" aren't generated from any source: {}",
+                orphans.len(),
+                config.dest.display(),
+                orphans
+                    .iter()
+                    .map(|x| format!("\"{}\"", x.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            "Run `raven clean --orphans` to delete them",
+        ));
+    }
+
+    Ok(diagnoses)
+}
+
+/// Templates, stylesheets, and favicons that pages use but don't exist, and
+/// aren't built in.
+fn missing_files(config: &Config, graph: &DependencyGraph) -> Vec<Diagnosis>
+{
+    let mut missing: BTreeMap<(&str, &Path), Vec<&Path>> = BTreeMap::new();
+    for (source_file, page) in &graph.pages {
+        let files = [
+            ("template", &page.template),
+            ("stylesheet", &page.stylesheet),
+            ("favicon", &page.favicon),
+        ];
+        for (kind, file) in files {
+            let absent = |x: &&Path| !x.is_file() && config.builtin_file(x).is_none();
+            if let Some(file) = file.as_deref().filter(absent) {
+                missing.entry((kind, file)).or_default().push(source_file);
+            }
+        }
+    }
+
+    missing
+        .into_iter()
+        .map(|((kind, file), pages)| {
+            let problem = format!(
+                "The {kind} \"{}\" doesn't exist, it's used by {} page(s) including \"{}\"",
+                file.display(),
+                pages.len(),
+                pages[0].display()
+            );
+            let suggestion = match kind {
+                "favicon" => "Create it, or the pages are built without a favicon",
+                _ => "Create it, or point the `default` section of the config or the pages' page info at another file",
+            };
+            Diagnosis::new(problem, suggestion)
+        })
+        .collect()
+}
+
+/// Print `diagnoses` with their suggestions.
+pub fn print(diagnoses: &[Diagnosis])
+{
+    if diagnoses.is_empty() {
+        println!("[{NAME}] No problems found");
+        return;
+    }
+    println!("[{NAME}] Found {} problem(s):", diagnoses.len());
+    for diagnosis in diagnoses {
+        println!("\n  {}\n    help: {}", diagnosis.problem, diagnosis.suggestion);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{Error, PathBuf};
+
+    /// A project in the temp dir `name` with `page` as its only source.
+    fn project(name: &str, page: &str) -> (PathBuf, Config)
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests").join(name);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/index.md"), page).unwrap();
+        std::fs::write(dir.join("favicon.png"), "").unwrap();
+        let mut config = Config {
+            source: dir.join("src"),
+            dest: dir.join("dest"),
+            ..Config::default()
+        };
+        config.default.favicon = dir.join("favicon.png");
+        (dir, config)
+    }
+
+    #[test]
+    /// Test that a missing template is diagnosed with the page using it
+    fn test_missing_template()
+    {
+        let (dir, config) = project(
+            "doctor-template",
+            "```pageinfo\ntitle = \"Home\"\ndescription = \"\"\ntemplate = \"/nonexistent/page.html\"\n```\n",
+        );
+        let diagnoses = diagnose(&config).unwrap();
+        assert!(diagnoses.iter().any(|x| {
+            x.problem
+                == format!(
+                    "The template \"/nonexistent/page.html\" doesn't exist, it's used by 1 page(s) including \"{}\"",
+                    dir.join("src/index.md").display()
+                )
+        }));
+        assert!(!diagnoses.iter().any(|x| x.problem.starts_with("The favicon")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that a page without page info is diagnosed
+    fn test_missing_page_info()
+    {
+        let (dir, config) = project("doctor-page-info", "Hi\n");
+        let diagnoses = diagnose(&config).unwrap();
+        assert!(diagnoses.iter().any(|x| {
+            x.problem
+                == format!(
+                    "\"{}\" has no usable page info: There's no pageinfo block",
+                    dir.join("src/index.md").display()
+                )
+        }));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that files in the dest dir no source generates are diagnosed
+    fn test_orphans()
+    {
+        let (dir, config) = project(
+            "doctor-orphans",
+            "```pageinfo\ntitle = \"Home\"\ndescription = \"\"\n```\n",
+        );
+        std::fs::create_dir_all(dir.join("dest")).unwrap();
+        std::fs::write(dir.join("dest/old.html"), "").unwrap();
+        let diagnoses = diagnose(&config).unwrap();
+        assert!(diagnoses.iter().any(|x| {
+            x.problem.starts_with("1 file(s) in") && x.suggestion == "Run `raven clean --orphans` to delete them"
+        }));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that finding problems makes doctor fail like check does
+    fn test_exit_code()
+    {
+        assert_eq!(Error::DoctorFailed(1).exit_code(), Error::CheckFailed(1).exit_code());
+        assert_ne!(Error::DoctorFailed(1).exit_code(), 0);
+    }
+}
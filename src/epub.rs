@@ -0,0 +1,270 @@
+//! EPUB exports of books, for the books with an `epub` path.
+//!
+//! Each chapter is rendered with [`Website::parse_markdown`], like in the
+//! book's combined page, and written as an XHTML document of its own. The
+//! EPUB has a table of contents of the chapters and their `<h2>` headings,
+//! the project's default stylesheet, and the book's `cover` image if it has
+//! one. Links between chapters point at the chapter in the EPUB, other
+//! relative links and images aren't bundled.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    book::{chapter_slug, chapters, prefix_ids, section_headings},
+    build::Website,
+    hash, mime, Book, Error, PathBuf, Result,
+};
+
+/// Where the EPUB of `book` is written, if it has one.
+pub fn dest(config: &crate::Config, book: &Book) -> Option<PathBuf>
+{
+    book.epub.as_ref().map(|epub| config.dest.join(epub))
+}
+
+/// Render the book's chapters into an EPUB.
+///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - The book's summary, or a chapter, cannot be read or parsed
+/// - The default stylesheet or the book's cover cannot be read
+pub async fn render(site: &Website, book: &Book) -> Result<Vec<u8>>
+{
+    let config = site.config();
+    let section = config.source.join(&book.section);
+    let chapters = chapters(config, book)?;
+    let slugs = chapters
+        .iter()
+        .map(|path| (path.clone(), chapter_slug(&section, path)))
+        .collect::<BTreeMap<_, _>>();
+    let title = xml_escape(&book.title);
+    let language = book.language.as_deref().unwrap_or("en");
+
+    let mut epub = Zip::default();
+    epub.add("mimetype", b"application/epub+zip");
+    epub.add("META-INF/container.xml", CONTAINER.as_bytes());
+
+    let stylesheet = site.get_stylesheet(config.default.stylesheet.clone()).await?;
+    let stylesheet = stylesheet
+        .strip_prefix("<style>")
+        .and_then(|x| x.strip_suffix("</style>"))
+        .unwrap_or(&stylesheet);
+    epub.add("OEBPS/style.css", stylesheet.as_bytes());
+
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n<item \
+         id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    );
+    let mut spine = String::new();
+    if let Some(cover) = &book.cover {
+        let path = config.resolve(cover);
+        let image = tokio::fs::read(&path).await.map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: path.clone(),
+            }
+        })?;
+        let name = format!(
+            "cover.{}",
+            cover
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default()
+        );
+        manifest.push_str(&format!(
+            "<item id=\"cover-image\" href=\"{name}\" media-type=\"{}\" properties=\"cover-image\"/>\n<item \
+             id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+            mime::from_path(cover)
+        ));
+        spine.push_str("<itemref idref=\"cover\"/>\n");
+        epub.add(&format!("OEBPS/{name}"), &image);
+        let body = format!("<section epub:type=\"cover\"><img src=\"{name}\" alt=\"{title}\"/></section>");
+        epub.add("OEBPS/cover.xhtml", xhtml(&title, language, &body).as_bytes());
+    }
+
+    let mut toc = String::from("<nav epub:type=\"toc\" id=\"toc\"><h1>Contents</h1><ol>");
+    for path in &chapters {
+        let source = tokio::fs::read_to_string(path).await.map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: path.clone(),
+            }
+        })?;
+        let (html, page_info) = site.parse_markdown(&source, path.clone())?;
+        let slug = &slugs[path];
+        let html = chapter_links(&prefix_ids(&html, path, &slugs), &slugs);
+        let file = format!("{slug}.xhtml");
+        let chapter_title = xml_escape(&page_info.title);
+
+        toc.push_str(&format!("<li><a href=\"{file}\">{chapter_title}</a>"));
+        let headings = section_headings(&html);
+        if !headings.is_empty() {
+            toc.push_str("<ol>");
+            for (id, text) in headings {
+                toc.push_str(&format!("<li><a href=\"{file}#{id}\">{text}</a></li>"));
+            }
+            toc.push_str("</ol>");
+        }
+        toc.push_str("</li>");
+        manifest.push_str(&format!(
+            "<item id=\"chapter-{slug}\" href=\"{file}\" media-type=\"application/xhtml+xml\"/>\n"
+        ));
+        spine.push_str(&format!("<itemref idref=\"chapter-{slug}\"/>\n"));
+        let body = format!("<section id=\"{slug}\">{html}</section>");
+        epub.add(
+            &format!("OEBPS/{file}"),
+            xhtml(&chapter_title, language, &body).as_bytes(),
+        );
+    }
+    toc.push_str("</ol></nav>");
+    epub.add("OEBPS/nav.xhtml", xhtml(&title, language, &toc).as_bytes());
+
+    let identifier = hash::md5_hex(format!("{}\n{}", book.title, book.section.display()).as_bytes());
+    let modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    let description = book
+        .description
+        .as_deref()
+        .map(|x| format!("<dc:description>{}</dc:description>\n", xml_escape(x)))
+        .unwrap_or_default();
+    epub.add(
+        "OEBPS/content.opf",
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<package xmlns=\"http://www.idpf.org/2007/opf\" \
+             version=\"3.0\" unique-identifier=\"id\">\n<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+             <dc:identifier id=\"id\">urn:md5:{identifier}</dc:identifier>\n<dc:title>{title}</dc:title>\n\
+             <dc:language>{}</dc:language>\n{description}<meta property=\"dcterms:modified\">{modified}</meta>\n\
+             </metadata>\n<manifest>\n{manifest}</manifest>\n<spine>\n{spine}</spine>\n</package>\n",
+            xml_escape(language)
+        )
+        .as_bytes(),
+    );
+    Ok(epub.finish())
+}
+
+const CONTAINER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<container version=\"1.0\" \
+                         xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n<rootfiles>\n<rootfile \
+                         full-path=\"OEBPS/content.opf\" \
+                         media-type=\"application/oebps-package+xml\"/>\n</rootfiles>\n</container>\n";
+
+/// An XHTML document of `body`, with the EPUB's stylesheet.
+fn xhtml(title: &str, language: &str, body: &str) -> String
+{
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n<html xmlns=\"http://www.w3.org/1999/xhtml\" \
+         xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{language}\" \
+         lang=\"{language}\">\n<head>\n<title>{title}</title>\n<link rel=\"stylesheet\" type=\"text/css\" \
+         href=\"style.css\"/>\n</head>\n<body>\n{body}\n</body>\n</html>\n",
+        language = xml_escape(language)
+    )
+}
+
+/// Point the links into the combined page that [`prefix_ids`] made at the
+/// chapter's own document.
+fn chapter_links(html: &str, slugs: &BTreeMap<PathBuf, String>) -> String
+{
+    let mut html = html.to_string();
+    for slug in slugs.values() {
+        html = html
+            .replace(&format!("href=\"#{slug}\""), &format!("href=\"{slug}.xhtml\""))
+            .replace(&format!("href=\"#{slug}--"), &format!("href=\"{slug}.xhtml#{slug}--"));
+    }
+    html
+}
+
+fn xml_escape(text: &str) -> String { htmlescape::encode_minimal(text) }
+
+/// A ZIP archive of uncompressed files, in the order they're added, as EPUB
+/// readers need the `mimetype` file first and uncompressed.
+#[derive(Debug, Default)]
+struct Zip
+{
+    data:      Vec<u8>,
+    directory: Vec<u8>,
+    entries:   u16,
+}
+
+impl Zip
+{
+    fn add(&mut self, name: &str, contents: &[u8])
+    {
+        let crc = crc32fast::hash(contents);
+        let offset = self.data.len() as u32;
+        let size = contents.len() as u32;
+        let name_len = name.len() as u16;
+
+        // The local file header, version 2.0, no flags, stored, at the DOS epoch.
+        self.data.extend(0x0403_4B50_u32.to_le_bytes());
+        for x in [20_u16, 0, 0, 0, 0x21] {
+            self.data.extend(x.to_le_bytes());
+        }
+        for x in [crc, size, size] {
+            self.data.extend(x.to_le_bytes());
+        }
+        self.data.extend(name_len.to_le_bytes());
+        self.data.extend(0_u16.to_le_bytes());
+        self.data.extend(name.as_bytes());
+        self.data.extend(contents);
+
+        self.directory.extend(0x0201_4B50_u32.to_le_bytes());
+        for x in [20_u16, 20, 0, 0, 0, 0x21] {
+            self.directory.extend(x.to_le_bytes());
+        }
+        for x in [crc, size, size] {
+            self.directory.extend(x.to_le_bytes());
+        }
+        for x in [name_len, 0, 0, 0, 0] {
+            self.directory.extend(x.to_le_bytes());
+        }
+        for x in [0_u32, offset] {
+            self.directory.extend(x.to_le_bytes());
+        }
+        self.directory.extend(name.as_bytes());
+        self.entries += 1;
+    }
+
+    fn finish(mut self) -> Vec<u8>
+    {
+        let offset = self.data.len() as u32;
+        let size = self.directory.len() as u32;
+        self.data.append(&mut self.directory);
+        self.data.extend(0x0605_4B50_u32.to_le_bytes());
+        for x in [0_u16, 0, self.entries, self.entries] {
+            self.data.extend(x.to_le_bytes());
+        }
+        for x in [size, offset] {
+            self.data.extend(x.to_le_bytes());
+        }
+        self.data.extend(0_u16.to_le_bytes());
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that links into the combined page are pointed at the chapters'
+    /// documents, and that the archive starts with the uncompressed mimetype
+    fn test_epub_parts()
+    {
+        let slugs = BTreeMap::from([
+            (PathBuf::from("src/book/intro.md"), String::from("intro")),
+            (PathBuf::from("src/book/usage.md"), String::from("usage")),
+        ]);
+        assert_eq!(
+            chapter_links("<a href=\"#usage\">a</a><a href=\"#intro--why\">b</a>", &slugs),
+            "<a href=\"usage.xhtml\">a</a><a href=\"intro.xhtml#intro--why\">b</a>"
+        );
+
+        let mut zip = Zip::default();
+        zip.add("mimetype", b"application/epub+zip");
+        let zip = zip.finish();
+        assert_eq!(&zip[..4], b"PK\x03\x04");
+        assert_eq!(&zip[30..38], b"mimetype");
+        assert_eq!(&zip[38..58], b"application/epub+zip");
+        assert_eq!(&zip[zip.len() - 22..zip.len() - 18], b"PK\x05\x06");
+    }
+}
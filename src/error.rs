@@ -15,6 +15,54 @@ pub enum Error
     #[error("[{}] ConfigParseError: {0}", crate::NAME)]
     ConfigParse(String),
 
+    #[error(
+        "[{}] MissingEnvironmentVariableError: \"{path}\": References the environment variable \"{var}\", but it \
+         isn't set",
+        crate::NAME
+    )]
+    MissingEnvironmentVariable
+    {
+        var: String, path: PathBuf
+    },
+
+    #[error("[{}] CancelledError: The build was cancelled before it finished", crate::NAME)]
+    Cancelled,
+
+    #[error("[{}] PageTaskError: A page's build stopped unexpectedly: {0}", crate::NAME)]
+    PageTask(String),
+
+    #[error(
+        "[{}] InsufficientSpaceError: \"{dest}\": The build needs about {needed} bytes, but only {available} bytes \
+         are free",
+        crate::NAME
+    )]
+    InsufficientSpace
+    {
+        dest:      PathBuf,
+        needed:    u64,
+        available: u64,
+    },
+
+    #[error(
+        "[{}] OutputTooLargeError: The build would write about {estimated} bytes, over `generation.max_output_size` \
+         of {limit} bytes",
+        crate::NAME
+    )]
+    OutputTooLarge
+    {
+        estimated: u64, limit: u64
+    },
+
+    #[error(
+        "[{}] DestOverlapError: \"{dest}\": The dest dir overlaps \"{other}\", so building or cleaning could \
+         overwrite or delete it. Change `dest`, or pass --force",
+        crate::NAME
+    )]
+    DestOverlap
+    {
+        dest: PathBuf, other: PathBuf
+    },
+
     #[error("[{}] SyntaxHighlightError: {0}", crate::NAME)]
     SyntaxHighlight(String),
 
@@ -27,6 +75,39 @@ pub enum Error
         err: String, path: PathBuf
     },
 
+    #[error(
+        "[{}] MissingAltTextError: \"{path}\": Image \"{image}\" has no alt text",
+        crate::NAME
+    )]
+    MissingAltText
+    {
+        image: String, path: PathBuf
+    },
+
+    #[error(
+        "[{}] UnresolvedReferenceError: \"{path}\": Nothing with the ID \"{reference}\" to refer to",
+        crate::NAME
+    )]
+    UnresolvedReference
+    {
+        reference: String, path: PathBuf
+    },
+
+    #[error(
+        "[{}] DuplicatePageIdError: \"{id}\" is the ID of more than one page: {pages}",
+        crate::NAME
+    )]
+    DuplicatePageId
+    {
+        id: String, pages: String
+    },
+
+    #[error("[{}] ShortcodeError: \"{path}\": {err}", crate::NAME)]
+    Shortcode
+    {
+        err: String, path: PathBuf
+    },
+
     #[error("[{}] LoadSyntaxError: \"{path}\": {err}", crate::NAME)]
     LoadSyntax
     {
@@ -48,6 +129,12 @@ pub enum Error
     #[error("[{}] MissingSourceFilesError: \"{0}\": No source files found", crate::NAME)]
     MissingSourceFiles(PathBuf),
 
+    #[error(
+        "[{}] BuildLockedError: \"{0}\": Another build of the project is running, wait for it with `--wait`",
+        crate::NAME
+    )]
+    BuildLocked(PathBuf),
+
     #[error(
         "[{}] MissingFaviconError: \"{source_file}\": Requested favicon file \"{expected_favicon_file}\", but it \
          doesn't exist",
@@ -60,7 +147,7 @@ pub enum Error
     },
 
     #[error(
-        "[{}] MissingTemplateError: \"{source_file}\": Requested favicon file \"{expected_template_file}\", but it \
+        "[{}] MissingTemplateError: \"{source_file}\": Requested template file \"{expected_template_file}\", but it \
          doesn't exist",
         crate::NAME
     )]
@@ -70,15 +157,66 @@ pub enum Error
         expected_template_file: PathBuf,
     },
 
+    #[error("[{}] IncludeError: \"{path}\": {err}", crate::NAME)]
+    Include
+    {
+        path: PathBuf, err: String
+    },
+
+    #[error(
+        "[{}] UnknownVariableError: \"{path}\": \"{variable}\" isn't a site or page variable with a value",
+        crate::NAME
+    )]
+    UnknownVariable
+    {
+        path: PathBuf, variable: String
+    },
+
+    #[error("[{}] TemplateInheritanceError: \"{path}\": {err}", crate::NAME)]
+    TemplateInheritance
+    {
+        path: PathBuf, err: String
+    },
+
     #[error(
         "[{}] MissingThemeError: Requested theme \"{0}\" in configuration file, but it doesn't exist",
         crate::NAME
     )]
     MissingTheme(String),
 
+    #[error(
+        "[{}] MissingThemeError: \"{source_file}\": Requested theme \"{theme}\" in page info, but it doesn't exist",
+        crate::NAME
+    )]
+    MissingPageTheme
+    {
+        theme: String, source_file: PathBuf
+    },
+
     #[error("[{}] HtmlPostprocessError: There was an error generated HTML: \"{0}\"", crate::NAME)]
     HtmlPostprocess(String),
 
+    #[error(
+        "[{}] MissingDeployConfigError: There's no [deploy] table in the configuration file",
+        crate::NAME
+    )]
+    MissingDeployConfig,
+
+    #[error("[{}] DeployError: {0}", crate::NAME)]
+    Deploy(String),
+
+    #[error("[{}] ConvertError: \"{path}\": {err}", crate::NAME)]
+    Convert
+    {
+        path: PathBuf, err: String
+    },
+
+    #[error("[{}] HookError: The {hook} hook failed: {err}", crate::NAME)]
+    Hook
+    {
+        hook: &'static str, err: String
+    },
+
     #[error(
         "[{}] AsyncJoinError: There was an internal error during the build process.",
         crate::NAME
@@ -88,6 +226,90 @@ pub enum Error
     #[error("[{}] IntegraionIntoTemplateError", crate::NAME)]
     IntegraionIntoTemplate,
 
+    #[error(
+        "[{}] UnresolvedTemplateTokenError: \"{source_file}\": \"{token}\" isn't a known template token",
+        crate::NAME
+    )]
+    UnresolvedTemplateToken
+    {
+        token: String, source_file: PathBuf
+    },
+
+    #[error("[{}] CheckFailedError: Found {0} problem(s)", crate::NAME)]
+    CheckFailed(usize),
+
+    #[error("[{}] DoctorFailedError: Found {0} problem(s)", crate::NAME)]
+    DoctorFailed(usize),
+
+    #[error("[{}] SnapshotsFailedError: {0} snapshot(s) didn't match", crate::NAME)]
+    SnapshotsFailed(usize),
+
+    #[error("[{}] BuildFailedError: {0} page(s) failed to build", crate::NAME)]
+    BuildFailed(usize),
+
+    #[error("{0}")]
+    Warning(crate::report::Warning),
+
+    #[error(
+        "[{}] UnknownStarterError: \"{name}\" isn't a starter ({available}) or a directory",
+        crate::NAME
+    )]
+    UnknownStarter
+    {
+        name: String, available: String
+    },
+
+    #[error("[{}] ThemeInstallError: {0}", crate::NAME)]
+    ThemeInstall(String),
+
+    #[error("[{}] PageExistsError: \"{0}\": A page already exists at this path", crate::NAME)]
+    PageExists(PathBuf),
+
+    #[error("[{}] InvalidPatternError: \"{pattern}\": {err}", crate::NAME)]
+    InvalidPattern
+    {
+        pattern: String, err: String
+    },
+
+    #[error(
+        "[{}] LockedPageChangedError: \"{path}\": The page is locked, and changed since it was approved. Review it, \
+         then set `approved_hash = \"{hash}\"` in its page info",
+        crate::NAME
+    )]
+    LockedPageChanged
+    {
+        path: PathBuf, hash: String
+    },
+
+    #[error("[{}] AnalyticsConfigError: {0}", crate::NAME)]
+    AnalyticsConfig(String),
+
+    #[error("[{}] CmsSyncError: {0}", crate::NAME)]
+    CmsSync(String),
+
+    #[error("[{}] FetchError: \"{url}\": {err}", crate::NAME)]
+    Fetch
+    {
+        url: String, err: String
+    },
+
+    #[error("[{}] OgImageError: {0}", crate::NAME)]
+    OgImage(String),
+
+    #[error("[{}] InvalidFeedError: \"{path}\": At {pointer}: {err}", crate::NAME)]
+    InvalidFeed
+    {
+        pointer: String,
+        err:     String,
+        path:    PathBuf,
+    },
+
+    #[error("[{}] SassError: \"{path}\": {err}", crate::NAME)]
+    Sass
+    {
+        err: String, path: PathBuf
+    },
+
     #[error("[{}] ProgressBarInitializationError", crate::NAME)]
     ProgressBarInitialization,
 }
@@ -107,13 +329,24 @@ impl Error
     /// Prints the error and exits with the appropriate code
     pub fn report_and_exit(&self) -> !
     {
-        let code = match self {
-            Error::Io { .. } => 74,
-            Error::ConfigParse(_) => 78,
-            _ => 64,
-        };
         eprintln!("{self}");
-        std::process::exit(code);
+        std::process::exit(self.exit_code());
+    }
+
+    /// The code the program exits with when it fails with this error.
+    pub fn exit_code(&self) -> i32
+    {
+        match self {
+            Error::Io { .. } | Error::InsufficientSpace { .. } => 74,
+            Error::ConfigParse(_)
+            | Error::MissingEnvironmentVariable { .. }
+            | Error::MissingDeployConfig
+            | Error::DestOverlap { .. } => 78,
+            Error::CheckFailed(_) | Error::DoctorFailed(_) | Error::SnapshotsFailed(_) | Error::BuildFailed(_) => 65,
+            Error::BuildLocked(_) => 75,
+            Error::Cancelled => 130,
+            _ => 64,
+        }
     }
 
     pub fn report(&self)
@@ -0,0 +1,166 @@
+//! Which source files are built, by their extension, and the external
+//! converters that turn other formats into pages.
+//!
+//! Besides the built-in extensions, `extensions.markdown` are parsed as
+//! markdown, `extensions.copy` are copied as they are, and each of
+//! `extensions.converters` is piped through its command, whose HTML output is
+//! put into the default template like a markdown page's.
+
+use std::{process::Stdio, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::{deploy::shell_command, headless::plain_text, Config, Error, Path, Result};
+
+/// The kind of the source files copied as they are.
+pub(crate) const COPY: &str = "copy";
+
+/// The kind of the source files built with a converter.
+pub(crate) const CONVERT: &str = "convert";
+
+/// What's done with a source file with the lowercase `extention`: its own
+/// extension if it's built-in, `md` if it's markdown, [`COPY`], [`CONVERT`],
+/// or `None` if it's left out. The configured extensions come before the
+/// built-in ones.
+pub(crate) fn kind(config: &Config, extention: &str) -> Option<String>
+{
+    let configured = |list: Option<&Vec<String>>| {
+        list.into_iter()
+            .flatten()
+            .any(|x| x.trim_start_matches('.').eq_ignore_ascii_case(extention))
+    };
+    let extensions = config.extensions.as_ref();
+    if converter(config, extention).is_some() {
+        Some(String::from(CONVERT))
+    }
+    else if configured(extensions.and_then(|x| x.copy.as_ref())) {
+        Some(String::from(COPY))
+    }
+    else if configured(extensions.and_then(|x| x.markdown.as_ref())) {
+        Some(String::from("md"))
+    }
+    else if matches!(extention, "markdown" | "md" | "html" | "htm" | "css" | "js") {
+        Some(extention.to_string())
+    }
+    else {
+        None
+    }
+}
+
+/// The converter command of the files with `extention`.
+fn converter<'a>(config: &'a Config, extention: &str) -> Option<&'a str>
+{
+    config
+        .extensions
+        .as_ref()?
+        .converters
+        .as_ref()?
+        .iter()
+        .find(|(x, _)| x.trim_start_matches('.').eq_ignore_ascii_case(extention))
+        .map(|(_, command)| command.as_str())
+}
+
+/// The HTML of `source_file`, output by its converter with the file on its
+/// stdin, and its path in `$RAVEN_PAGE_SOURCE`.
+///
+/// # Errors
+///
+/// Will return an error if there's no converter for the file, or it cannot be
+/// run or fails.
+pub(crate) async fn convert(config: &Config, source_file: &Path) -> Result<String>
+{
+    let extention = source_file
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    let err = |err: String| {
+        Error::Convert {
+            path: source_file.to_path_buf(),
+            err,
+        }
+    };
+    let line = converter(config, &extention).ok_or_else(|| err(format!("No converter for \".{extention}\" files")))?;
+    let input = std::fs::File::open(source_file).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: source_file.to_path_buf(),
+        }
+    })?;
+    let mut command = shell_command(line);
+    command
+        .env("RAVEN_SOURCE", &config.source)
+        .env("RAVEN_DEST", &config.dest)
+        .env("RAVEN_PAGE_SOURCE", source_file)
+        .stdin(input)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+
+    let output = tokio::task::spawn_blocking(move || command.output())
+        .await
+        .map_err(|_| Error::AysncJoin)?
+        .map_err(|e| err(format!("Couldn't run \"{line}\": {e}")))?;
+    if !output.status.success() {
+        return Err(err(format!("\"{line}\" failed: {}", output.status)));
+    }
+    String::from_utf8(output.stdout).map_err(|_| err(format!("\"{line}\" didn't output UTF-8")))
+}
+
+/// The title of a converted page: the text of its first `<h1>`, or its file
+/// name without the extension.
+pub(crate) fn title(html: &str, source_file: &Path) -> String
+{
+    static HEADING: OnceLock<Regex> = OnceLock::new();
+    let heading = HEADING.get_or_init(|| Regex::new(r"(?s)<h1[^>]*>(.*?)</h1>").unwrap());
+    heading
+        .captures(html)
+        .map(|x| plain_text(&x[1]))
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| {
+            source_file
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::SourceExtensions;
+
+    #[test]
+    /// Test that configured extensions come before the built-in ones
+    fn test_kind()
+    {
+        let config = Config {
+            extensions: Some(SourceExtensions {
+                markdown:   Some(vec![String::from("mdx")]),
+                copy:       Some(vec![String::from(".txt"), String::from("html")]),
+                converters: Some(BTreeMap::from([(
+                    String::from("adoc"),
+                    String::from("asciidoctor -s -o - -"),
+                )])),
+            }),
+            ..Config::default()
+        };
+        assert_eq!(kind(&config, "mdx").as_deref(), Some("md"));
+        assert_eq!(kind(&config, "txt").as_deref(), Some(COPY));
+        assert_eq!(kind(&config, "html").as_deref(), Some(COPY));
+        assert_eq!(kind(&config, "adoc").as_deref(), Some(CONVERT));
+        assert_eq!(kind(&config, "markdown").as_deref(), Some("markdown"));
+        assert_eq!(kind(&config, "png"), None);
+        assert_eq!(
+            title(
+                "<div><h1 id=\"a\">An <em>Intro</em></h1></div>",
+                Path::new("src/intro.adoc")
+            ),
+            "An Intro"
+        );
+        assert_eq!(title("<p>Hi</p>", Path::new("src/intro.adoc")), "intro");
+    }
+}
@@ -0,0 +1,125 @@
+//! Links to other sites opened in a new tab, with `generation.external_links`.
+//!
+//! Every `<a>` in a rendered page whose `href` is an absolute `http(s)` URL,
+//! on a host that isn't one of the `internal` ones, gets the configured
+//! `target` and `rel`, unless it has its own.
+
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+use crate::{placeholders::escape_attribute, ExternalLinks};
+
+/// The `target` external links get, if none is configured.
+pub const DEFAULT_TARGET: &str = "_blank";
+
+/// The `rel` external links get, if none is configured.
+pub const DEFAULT_REL: &str = "noopener noreferrer";
+
+/// `html` with the configured attributes added to its external links.
+pub fn rewrite(html: &str, options: &ExternalLinks) -> String
+{
+    static ANCHOR: OnceLock<Regex> = OnceLock::new();
+    let anchor = ANCHOR.get_or_init(|| Regex::new(r"(?i)<a(\s[^>]*)>").unwrap());
+    let target = options.target.as_deref().unwrap_or(DEFAULT_TARGET);
+    let rel = options.rel.as_deref().unwrap_or(DEFAULT_REL);
+    anchor
+        .replace_all(html, |x: &Captures| {
+            let attributes = &x[1];
+            if !attribute(attributes, "href").is_some_and(|href| is_external(href, options)) {
+                return x[0].to_string();
+            }
+            let mut added = String::new();
+            for (name, value) in [("target", target), ("rel", rel)] {
+                if !value.is_empty() && attribute(attributes, name).is_none() {
+                    added.push_str(&format!(" {name}=\"{}\"", escape_attribute(value)));
+                }
+            }
+            format!("<a{added}{attributes}>")
+        })
+        .into_owned()
+}
+
+/// The value of the attribute `name` in a tag's `attributes`, if it has it.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str>
+{
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    let attribute = ATTRIBUTE.get_or_init(|| {
+        Regex::new(r#"(?i)(?:^|\s)([a-z][a-z0-9_:-]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+)))?"#).unwrap()
+    });
+    attribute
+        .captures_iter(attributes)
+        .find(|x| x[1].eq_ignore_ascii_case(name))
+        .map(|x| {
+            x.get(2)
+                .or_else(|| x.get(3))
+                .or_else(|| x.get(4))
+                .map_or("", |x| x.as_str())
+        })
+}
+
+/// If `href` links to another site.
+fn is_external(href: &str, options: &ExternalLinks) -> bool
+{
+    let lowercase = href.trim().to_lowercase();
+    let Some(rest) = ["https://", "http://", "//"]
+        .into_iter()
+        .find_map(|scheme| lowercase.strip_prefix(scheme))
+    else {
+        return false;
+    };
+    let host = rest
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .rsplit('@')
+        .next()
+        .unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    !options.internal.iter().flatten().any(|x| x.eq_ignore_ascii_case(host))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that only links to other hosts are changed, keeping their own
+    /// attributes
+    fn test_rewrite()
+    {
+        let options = ExternalLinks {
+            target:   None,
+            rel:      None,
+            internal: Some(vec![String::from("example.com")]),
+        };
+        assert_eq!(
+            rewrite("<a href=\"https://rust-lang.org/learn\">Rust</a>", &options),
+            "<a target=\"_blank\" rel=\"noopener noreferrer\" href=\"https://rust-lang.org/learn\">Rust</a>"
+        );
+        assert_eq!(
+            rewrite("<A HREF='//docs.rs' target=\"_self\">docs</A>", &options),
+            "<a rel=\"noopener noreferrer\" HREF='//docs.rs' target=\"_self\">docs</A>"
+        );
+        for internal in [
+            "<a href=\"https://Example.com:443/about\">",
+            "<a href=\"/about\">",
+            "<a href=\"#top\">",
+            "<a href=\"mailto:me@example.org\">",
+            "<abbr title=\"https://rust-lang.org\">",
+        ] {
+            assert_eq!(rewrite(internal, &options), internal);
+        }
+
+        let options = ExternalLinks {
+            target:   Some(String::new()),
+            rel:      Some(String::from("external")),
+            internal: None,
+        };
+        assert_eq!(
+            rewrite("<a class=\"x\" href=http://a.b>", &options),
+            "<a rel=\"external\" class=\"x\" href=http://a.b>"
+        );
+    }
+}
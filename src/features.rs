@@ -0,0 +1,247 @@
+//! A local report of which features a project uses, so a team can audit its
+//! setup. It's computed from the project's files only, nothing is sent
+//! anywhere.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+
+use crate::{
+    book, build::walk_sources, epub, headless, offline, og, report::Warnings, shortcodes, styles, Config, Error,
+    PageInfo, Result,
+};
+
+/// The features a project uses, and how many pages use each.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureReport
+{
+    /// The dotted names of the options set in the configuration.
+    pub config_options: Vec<String>,
+
+    /// The shortcodes (and `{{ link }}`, `{{ ref }}` references) used, and
+    /// by how many pages.
+    pub shortcodes: BTreeMap<String, usize>,
+
+    /// The page info fields set, and by how many pages.
+    pub page_info_fields: BTreeMap<String, usize>,
+
+    /// The code block languages used, and by how many pages.
+    pub languages: BTreeMap<String, usize>,
+
+    /// What the build writes.
+    pub outputs: Vec<String>,
+}
+
+impl FeatureReport
+{
+    /// Scan the configuration and the pages of the source directory.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a source file cannot be read.
+    pub fn from_config(config: &Config) -> Result<Self>
+    {
+        let mut report = Self {
+            config_options: toml::Value::try_from(config)
+                .map(|value| option_names(&value, ""))
+                .unwrap_or_default(),
+            ..Self::default()
+        };
+
+        let (mut pages, mut copied) = (0, 0);
+        for (source_file, extention) in walk_sources(config, &Warnings::default()) {
+            if extention != "md" && extention != "markdown" {
+                copied += 1;
+                continue;
+            }
+            pages += 1;
+            let source = std::fs::read_to_string(&source_file).map_err(|e| {
+                Error::Io {
+                    err:  e,
+                    path: source_file.clone(),
+                }
+            })?;
+
+            let mut used = shortcodes::names(&source).collect::<BTreeSet<_>>();
+            for reference in ["link", "ref"] {
+                if source.contains(&format!("{{{{ {reference} ")) {
+                    used.insert(reference);
+                }
+            }
+            count(&mut report.shortcodes, used);
+
+            let (fields, languages) = page_info_fields_and_languages(&source);
+            count(&mut report.page_info_fields, fields);
+            count(&mut report.languages, languages.iter().map(String::as_str));
+        }
+
+        report.outputs.push(format!("{pages} page(s)"));
+        report.outputs.push(format!("{copied} copied file(s)"));
+        for book in config.books.iter().flatten() {
+            report.outputs.push(format!(
+                "The book \"{}\" at \"{}\"",
+                book.title,
+                book::dest(config, book).display()
+            ));
+            if let Some(epub) = epub::dest(config, book) {
+                report
+                    .outputs
+                    .push(format!("The EPUB of \"{}\" at \"{}\"", book.title, epub.display()));
+            }
+        }
+        if config.json_index() {
+            report.outputs.push(format!(
+                "The JSON index of the pages at \"{}\"",
+                config.dest.join(headless::INDEX_FILE).display()
+            ));
+        }
+        if og::enabled(config) {
+            report.outputs.push(format!(
+                "Open Graph images in \"{}\"",
+                config.dest.join(og::OG_DIR).display()
+            ));
+        }
+        if config.service_worker() {
+            report.outputs.push(format!(
+                "The service worker at \"{}\"",
+                config.dest.join(offline::SERVICE_WORKER).display()
+            ));
+        }
+        if styles::linked(config) {
+            report.outputs.push(format!(
+                "The components stylesheet at \"{}\"",
+                config.dest.join(styles::COMPONENTS_STYLESHEET).display()
+            ));
+        }
+        Ok(report)
+    }
+
+    /// Prints each group of features.
+    pub fn print(&self)
+    {
+        println!("Configuration options:");
+        for option in &self.config_options {
+            println!("    {option}");
+        }
+        let groups = [
+            ("Shortcodes", &self.shortcodes),
+            ("Page info fields", &self.page_info_fields),
+            ("Code block languages", &self.languages),
+        ];
+        for (name, features) in groups {
+            if features.is_empty() {
+                continue;
+            }
+            println!("{name}:");
+            for (feature, pages) in features {
+                println!("    {feature} ({pages} page(s))");
+            }
+        }
+        println!("Outputs:");
+        for output in &self.outputs {
+            println!("    {output}");
+        }
+    }
+}
+
+fn count<'a>(counts: &mut BTreeMap<String, usize>, used: impl IntoIterator<Item = &'a str>)
+{
+    for feature in used {
+        *counts.entry(feature.to_string()).or_default() += 1;
+    }
+}
+
+/// The dotted names of the leaves of `value`. Arrays are named as a whole.
+fn option_names(value: &toml::Value, prefix: &str) -> Vec<String>
+{
+    match value {
+        toml::Value::Table(table) => {
+            table
+                .iter()
+                .flat_map(|(key, value)| {
+                    let name = if prefix.is_empty() {
+                        key.clone()
+                    }
+                    else {
+                        format!("{prefix}.{key}")
+                    };
+                    option_names(value, &name)
+                })
+                .collect()
+        }
+        _ => vec![prefix.to_string()],
+    }
+}
+
+/// The top-level page info fields set in `source`, and the languages of its
+/// code blocks.
+fn page_info_fields_and_languages(source: &str) -> (BTreeSet<&'static str>, BTreeSet<String>)
+{
+    let mut fields = BTreeSet::new();
+    let mut languages = BTreeSet::new();
+    let mut current_language = None;
+    for event in Parser::new(source) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => current_language = Some(lang),
+            Event::End(Tag::CodeBlock(_)) => current_language = None,
+            Event::Text(text) => {
+                match current_language.as_deref() {
+                    Some(PageInfo::CODE_BLOCK_IDENTIFIER) => {
+                        if let Ok(table) = toml::from_str::<toml::Table>(&text) {
+                            fields.extend(PAGE_INFO_FIELDS.iter().filter(|x| table.contains_key(**x)));
+                        }
+                    }
+                    Some(lang) if !lang.is_empty() => {
+                        languages.insert(lang.to_string());
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    (fields, languages)
+}
+
+/// The fields of [`PageInfo`].
+const PAGE_INFO_FIELDS: &[&str] = &[
+    "title",
+    "description",
+    "id",
+    "style",
+    "template",
+    "syntax_theme",
+    "emoji",
+    "audience",
+    "favicon",
+    "date",
+    "head_extra",
+    "short",
+    "locked",
+    "approved_hash",
+    "slug",
+    "path",
+    "meta_from",
+    "extra",
+    "meta",
+];
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that the options set in a configuration are named by their path
+    fn test_option_names()
+    {
+        let value = toml::from_str::<toml::Value>(
+            "source = \"src\"\n[generation.process]\nminify = true\n[[books]]\nsection = \"manual\"",
+        )
+        .unwrap();
+        assert_eq!(
+            option_names(&value, ""),
+            ["books", "generation.process.minify", "source"]
+        );
+    }
+}
@@ -0,0 +1,1115 @@
+//! Atom, RSS, and JSON Feed feeds of the markdown pages with a `date`, newest
+//! first, and a sitemap of every markdown page, written to the dest dir with
+//! `[feeds]`.
+//!
+//! Each is rendered from a built-in template, or the project's own set in
+//! `feeds.templates`, and checked before it's written: one that isn't
+//! well-formed XML or JSON, or is missing what its format requires, fails the
+//! build with where the problem is, e.g. `/rss/channel/item[2]/pubDate`.
+//!
+//! With `feeds.sections` each of those sections also gets feeds of its pages,
+//! in the same directory under the dest dir, and with `feeds.tags` each tag
+//! does, in `tags/<tag>/`. Pages link to the feeds they're in with
+//! `<link rel="alternate">` tags, so readers can discover them.
+//!
+//! In a template, `[/rustic_feed_title/]`, `[/rustic_feed_description/]`,
+//! `[/rustic_feed_url/]` (the feed's own URL), `[/rustic_site_url/]`,
+//! `[/rustic_feed_updated/]`, and `[/rustic_feed_items/]` are replaced with
+//! their values, escaped for XML, or as JSON strings in a JSON Feed.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, TimeZone};
+use roxmltree::{Document, Node};
+use serde_json::Value;
+use toml::value::Datetime;
+
+use crate::{
+    analytics::inject,
+    build::{source_files, write_output, Website},
+    headless::{self, IndexedPage},
+    markdown::slugify,
+    placeholders::{escape_attribute as escape, TOKEN_PREFIX, TOKEN_SUFFIX},
+    report::Warnings,
+    sections, Config, Error, FeedFormat, Feeds, Path, PathBuf, Result,
+};
+
+/// Where the sitemap is written, relative to the dest dir.
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+
+/// Where the tags' feeds are written, relative to the dest dir.
+pub const TAGS_DIR: &str = "tags";
+
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+const JSON_FEED_VERSION: &str = "https://jsonfeed.org/version/1.1";
+const SITEMAP_NAMESPACE: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+/// How many of the newest pages a feed lists by default.
+const DEFAULT_LIMIT: usize = 20;
+
+/// The most URLs a sitemap can list.
+const SITEMAP_URL_LIMIT: usize = 50_000;
+
+/// The longest URL a sitemap can list.
+const SITEMAP_URL_LENGTH: usize = 2048;
+
+const ATOM_TEMPLATE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>[/rustic_feed_title/]</title>
+<subtitle>[/rustic_feed_description/]</subtitle>
+<id>[/rustic_feed_url/]</id>
+<link rel="self" href="[/rustic_feed_url/]"/>
+<link rel="alternate" href="[/rustic_site_url/]"/>
+<updated>[/rustic_feed_updated/]</updated>
+<author><name>[/rustic_feed_title/]</name></author>
+[/rustic_feed_items/]
+</feed>
+"#;
+
+const RSS_TEMPLATE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>[/rustic_feed_title/]</title>
+<link>[/rustic_site_url/]</link>
+<description>[/rustic_feed_description/]</description>
+<atom:link href="[/rustic_feed_url/]" rel="self" type="application/rss+xml"/>
+<lastBuildDate>[/rustic_feed_updated/]</lastBuildDate>
+[/rustic_feed_items/]
+</channel>
+</rss>
+"#;
+
+const JSON_TEMPLATE: &str = r#"{
+"version": "https://jsonfeed.org/version/1.1",
+"title": [/rustic_feed_title/],
+"description": [/rustic_feed_description/],
+"home_page_url": [/rustic_site_url/],
+"feed_url": [/rustic_feed_url/],
+"items": [
+[/rustic_feed_items/]
+]
+}
+"#;
+
+const SITEMAP_TEMPLATE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+[/rustic_feed_items/]
+</urlset>
+"#;
+
+/// A page as it's listed in a feed or the sitemap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedItem
+{
+    /// The page's full URL.
+    pub url:         String,
+    pub title:       String,
+    pub description: String,
+    pub date:        Option<DateTime<FixedOffset>>,
+    pub authors:     Vec<String>,
+    pub tags:        Vec<String>,
+    pub source:      PathBuf,
+
+    /// The rendered body of the page, without its template.
+    pub html: String,
+}
+
+/// A feed of the site's pages, or of a section's or a tag's.
+#[derive(Debug, Clone)]
+struct Feed<'a>
+{
+    /// Where its files are written, relative to the dest dir.
+    dir:   PathBuf,
+    title: String,
+    limit: usize,
+    items: Vec<&'a FeedItem>,
+}
+
+/// Where in a feed a problem is, and what it is.
+type Problem = (String, String);
+
+impl FeedFormat
+{
+    /// Where the feed is written, relative to the dest dir.
+    pub fn file(self) -> &'static str
+    {
+        match self {
+            Self::Atom => "atom.xml",
+            Self::Rss => "rss.xml",
+            Self::Json => "feed.json",
+        }
+    }
+
+    /// The feed's template in `feeds.templates`, if it has one.
+    fn template(self, feeds: &Feeds) -> Option<&PathBuf>
+    {
+        let templates = feeds.templates.as_ref()?;
+        match self {
+            Self::Atom => templates.atom.as_ref(),
+            Self::Rss => templates.rss.as_ref(),
+            Self::Json => templates.json.as_ref(),
+        }
+    }
+
+    fn builtin_template(self) -> &'static str
+    {
+        match self {
+            Self::Atom => ATOM_TEMPLATE,
+            Self::Rss => RSS_TEMPLATE,
+            Self::Json => JSON_TEMPLATE,
+        }
+    }
+
+    /// The media type the format is linked to with.
+    fn media_type(self) -> &'static str
+    {
+        match self {
+            Self::Atom => "application/atom+xml",
+            Self::Rss => "application/rss+xml",
+            Self::Json => "application/feed+json",
+        }
+    }
+
+    /// A date as the format writes it.
+    fn date(self, date: &DateTime<FixedOffset>) -> String
+    {
+        match self {
+            Self::Atom | Self::Json => date.to_rfc3339(),
+            Self::Rss => date.to_rfc2822(),
+        }
+    }
+
+    /// `value` as it's put into the format's template.
+    fn escape(self, value: &str) -> String
+    {
+        match self {
+            Self::Atom | Self::Rss => escape(value),
+            Self::Json => serde_json::to_string(value).unwrap(),
+        }
+    }
+
+    /// What the format's items are kept apart by.
+    fn separator(self) -> &'static str
+    {
+        match self {
+            Self::Atom | Self::Rss => "",
+            Self::Json => ",\n",
+        }
+    }
+
+    /// Check that `feed` is a feed of this format.
+    fn validate(self, feed: &str) -> std::result::Result<(), Problem>
+    {
+        match self {
+            Self::Atom => validate(feed, validate_atom),
+            Self::Rss => validate(feed, validate_rss),
+            Self::Json => validate_json_feed(feed),
+        }
+    }
+}
+
+/// The feeds written, `atom`, `rss`, and `json` by default.
+fn formats(feeds: &Feeds) -> Vec<FeedFormat>
+{
+    feeds
+        .formats
+        .clone()
+        .unwrap_or_else(|| vec![FeedFormat::Atom, FeedFormat::Rss, FeedFormat::Json])
+}
+
+/// The files the feeds and sitemap are written to.
+pub fn outputs(config: &Config) -> Vec<PathBuf>
+{
+    let Some(feeds) = &config.feeds
+    else {
+        return Vec::new();
+    };
+    let mut dirs = vec![PathBuf::new()];
+    dirs.extend(feeds.sections.iter().flatten().cloned());
+    if feeds.tags.unwrap_or(false) {
+        // Unreadable entries are warned about when the source files are
+        // walked.
+        let mut tags = source_files(config, &Warnings::new(false))
+            .into_iter()
+            .filter(|(_, extention)| matches!(&**extention, "md" | "markdown"))
+            .filter_map(|(source_file, _)| sections::scan_page_info(config, &source_file))
+            .flat_map(|page_info| page_info.tags.unwrap_or_default())
+            .map(|tag| tag_dir(&tag))
+            .collect::<Vec<_>>();
+        tags.sort();
+        tags.dedup();
+        dirs.extend(tags);
+    }
+    let mut outputs = dirs
+        .iter()
+        .flat_map(|dir| {
+            formats(feeds)
+                .into_iter()
+                .map(|format| config.dest.join(dir).join(format.file()))
+        })
+        .collect::<Vec<_>>();
+    if feeds.sitemap.unwrap_or(true) {
+        outputs.push(config.dest.join(SITEMAP_FILE));
+    }
+    outputs
+}
+
+/// Write the feeds and the sitemap, checking each one first.
+///
+/// # Errors
+///
+/// Will return an error if a page cannot be rendered, a template cannot be
+/// read, a feed isn't valid, or it cannot be written.
+pub async fn write(site: &Website) -> Result<()>
+{
+    let config = site.config();
+    let Some(feeds) = &config.feeds
+    else {
+        return Ok(());
+    };
+    let items = items(config, feeds, headless::pages(site).await?);
+    for feed in site_feeds(config, feeds, &items) {
+        write_feed(config, feeds, &feed).await?;
+    }
+
+    if feeds.sitemap.unwrap_or(true) {
+        let template = read_template(
+            config,
+            feeds.templates.as_ref().and_then(|x| x.sitemap.as_ref()),
+            SITEMAP_TEMPLATE,
+        )?;
+        let urls = items.iter().map(render_sitemap_url).collect::<String>();
+        let xml = fill(&template, &[("feed_items", urls)]);
+        let dest_file = config.dest.join(SITEMAP_FILE);
+        validate(&xml, validate_sitemap).map_err(|(pointer, err)| invalid(&dest_file, pointer, err))?;
+        write_output(&dest_file, xml).await?;
+    }
+    Ok(())
+}
+
+/// The site's feed of `items`, and each section's and tag's.
+fn site_feeds<'a>(config: &Config, feeds: &Feeds, items: &'a [FeedItem]) -> Vec<Feed<'a>>
+{
+    let title = title(config, feeds);
+    let limit = feeds.limit.unwrap_or(DEFAULT_LIMIT);
+    let mut site_feeds = vec![Feed {
+        dir: PathBuf::new(),
+        title: title.clone(),
+        limit,
+        items: items.iter().collect(),
+    }];
+    for section in feeds.sections.iter().flatten() {
+        let dir = config.source.join(section);
+        site_feeds.push(Feed {
+            dir:   section.clone(),
+            title: format!("{title}: {}", section.display()),
+            limit: feeds.section_limit.unwrap_or(limit),
+            items: items.iter().filter(|x| x.source.starts_with(&dir)).collect(),
+        });
+    }
+    if feeds.tags.unwrap_or(false) {
+        // By the tag's dir, with the tag as it's first written.
+        let mut tags = BTreeMap::<PathBuf, (&str, Vec<&FeedItem>)>::new();
+        for item in items {
+            for tag in &item.tags {
+                tags.entry(tag_dir(tag))
+                    .or_insert((tag.as_str(), Vec::new()))
+                    .1
+                    .push(item);
+            }
+        }
+        site_feeds.extend(tags.into_iter().map(|(dir, (tag, items))| {
+            Feed {
+                dir,
+                title: format!("{title}: {tag}"),
+                limit: feeds.tag_limit.unwrap_or(limit),
+                items,
+            }
+        }));
+    }
+    site_feeds
+}
+
+/// Write each format of `feed`, its newest dated items first.
+///
+/// # Errors
+///
+/// Will return an error if a template cannot be read, or a feed isn't valid,
+/// or it cannot be written.
+async fn write_feed(config: &Config, feeds: &Feeds, feed: &Feed<'_>) -> Result<()>
+{
+    let mut dated = feed.items.iter().filter(|x| x.date.is_some()).collect::<Vec<_>>();
+    dated.sort_by(|a, b| b.date.cmp(&a.date));
+    dated.truncate(feed.limit);
+    let updated = dated.first().and_then(|x| x.date).unwrap_or_else(|| config.now());
+    for format in formats(feeds) {
+        let template = read_template(config, format.template(feeds), format.builtin_template())?;
+        let entries = dated
+            .iter()
+            .map(|item| render_item(format, item))
+            .collect::<Vec<_>>()
+            .join(format.separator());
+        let contents = fill(
+            &template,
+            &[
+                ("feed_title", format.escape(&feed.title)),
+                (
+                    "feed_description",
+                    format.escape(feeds.description.as_deref().unwrap_or(&feed.title)),
+                ),
+                ("feed_url", format.escape(&feed_url(feeds, &feed.dir, format))),
+                ("site_url", format.escape(&format!("{}/", base_url(feeds)))),
+                ("feed_updated", format.escape(&format.date(&updated))),
+                ("feed_items", entries),
+            ],
+        );
+        let dest_file = config.dest.join(&feed.dir).join(format.file());
+        format
+            .validate(&contents)
+            .map_err(|(pointer, err)| invalid(&dest_file, pointer, err))?;
+        write_output(&dest_file, contents).await?;
+    }
+    Ok(())
+}
+
+/// Link the page built from `source_file` to the feeds it's in: the site's,
+/// its sections', and its tags', in `html`'s head.
+pub fn link(config: &Config, source_file: &Path, html: String) -> String
+{
+    let Some(feeds) = &config.feeds
+    else {
+        return html;
+    };
+    let title = title(config, feeds);
+    let mut linked = vec![(PathBuf::new(), title.clone())];
+    for section in feeds.sections.iter().flatten() {
+        if source_file.starts_with(config.source.join(section)) {
+            linked.push((section.clone(), format!("{title}: {}", section.display())));
+        }
+    }
+    let is_markdown = source_file
+        .extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("md") || x.eq_ignore_ascii_case("markdown"));
+    if feeds.tags.unwrap_or(false) && is_markdown {
+        let tags = sections::scan_page_info(config, source_file)
+            .and_then(|x| x.tags)
+            .unwrap_or_default();
+        linked.extend(tags.iter().map(|tag| (tag_dir(tag), format!("{title}: {tag}"))));
+    }
+    let links = linked
+        .iter()
+        .flat_map(|(dir, title)| {
+            formats(feeds).into_iter().map(move |format| {
+                format!(
+                    "<link rel=\"alternate\" type=\"{}\" title=\"{}\" href=\"{}\">",
+                    format.media_type(),
+                    escape(title),
+                    escape(&feed_url(feeds, dir, format))
+                )
+            })
+        })
+        .collect::<String>();
+    inject(html, &links)
+}
+
+/// The feeds' title, `default.meta.site_name` if it isn't set.
+fn title(config: &Config, feeds: &Feeds) -> String
+{
+    feeds
+        .title
+        .clone()
+        .or_else(|| config.default.meta.as_ref().map(|meta| meta.site_name.clone()))
+        .unwrap_or_default()
+}
+
+/// Where the feeds of `tag` are written, relative to the dest dir.
+fn tag_dir(tag: &str) -> PathBuf { Path::new(TAGS_DIR).join(slugify(tag)) }
+
+/// The full URL of the `format` feed written to `dir`.
+fn feed_url(feeds: &Feeds, dir: &Path, format: FeedFormat) -> String
+{
+    let path = dir.join(format.file());
+    format!("{}/{}", base_url(feeds), path.to_string_lossy().replace('\\', "/"))
+}
+
+fn invalid(path: &Path, pointer: String, err: String) -> Error
+{
+    Error::InvalidFeed {
+        path: path.to_path_buf(),
+        pointer,
+        err,
+    }
+}
+
+/// The URL the dest dir is deployed to, without a trailing `/`.
+fn base_url(feeds: &Feeds) -> &str { feeds.base_url.trim_end_matches('/') }
+
+/// The contents of the template at `path`, found in the project or theme, or
+/// `builtin` without one.
+fn read_template(config: &Config, path: Option<&PathBuf>, builtin: &str) -> Result<String>
+{
+    let Some(path) = path
+    else {
+        return Ok(builtin.to_string());
+    };
+    let path = config.resolve(path);
+    std::fs::read_to_string(&path).map_err(|e| Error::Io { err: e, path })
+}
+
+/// Replace the tokens in `template` with `values`, by name, in one pass, so
+/// tokens in a value are left as they are. Other tokens are left too.
+fn fill(template: &str, values: &[(&str, String)]) -> String
+{
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(TOKEN_PREFIX) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest[TOKEN_PREFIX.len()..].find(TOKEN_SUFFIX).and_then(|end| {
+            let name = &rest[TOKEN_PREFIX.len()..TOKEN_PREFIX.len() + end];
+            let (_, value) = values.iter().find(|(x, _)| *x == name)?;
+            Some((value, TOKEN_PREFIX.len() + end + TOKEN_SUFFIX.len()))
+        });
+        match value {
+            Some((value, len)) => {
+                output.push_str(value);
+                rest = &rest[len..];
+            }
+            None => {
+                output.push_str(TOKEN_PREFIX);
+                rest = &rest[TOKEN_PREFIX.len()..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// The feed items of `pages`, in the order they were found.
+pub(crate) fn items(config: &Config, feeds: &Feeds, pages: Vec<IndexedPage>) -> Vec<FeedItem>
+{
+    pages
+        .into_iter()
+        .map(|page| {
+            FeedItem {
+                url:         format!("{}{}", base_url(feeds), page.url),
+                date:        page
+                    .date
+                    .and_then(|x| x.parse::<Datetime>().ok())
+                    .and_then(|x| date_time(config, &x)),
+                title:       page.title,
+                description: page.description,
+                authors:     page.authors,
+                tags:        page.tags,
+                source:      page.source,
+                html:        page.html,
+            }
+        })
+        .collect()
+}
+
+/// When `date` is. A date without a time is at its start, and a time without
+/// an offset is in [`Config::now`]'s time zone, like scheduled pages.
+pub(crate) fn date_time(config: &Config, date: &Datetime) -> Option<DateTime<FixedOffset>>
+{
+    let day = date
+        .date
+        .and_then(|x| NaiveDate::from_ymd_opt(i32::from(x.year), u32::from(x.month), u32::from(x.day)))?;
+    let time = match (date.time, date.offset) {
+        (Some(_), Some(_)) => return DateTime::parse_from_rfc3339(&date.to_string()).ok(),
+        (Some(time), None) => {
+            NaiveTime::from_hms_opt(u32::from(time.hour), u32::from(time.minute), u32::from(time.second))?
+        }
+        (None, _) => NaiveTime::MIN,
+    };
+    config.now().offset().from_local_datetime(&day.and_time(time)).single()
+}
+
+/// A dated item as an Atom entry, RSS item, or JSON Feed item.
+fn render_item(format: FeedFormat, item: &FeedItem) -> String
+{
+    let url = escape(&item.url);
+    let title = escape(&item.title);
+    let date = item.date.as_ref().map(|x| format.date(x)).unwrap_or_default();
+    let description = escape(&item.description);
+    match format {
+        FeedFormat::Atom => {
+            let authors = item
+                .authors
+                .iter()
+                .map(|x| format!("<author><name>{}</name></author>\n", escape(x)))
+                .collect::<String>();
+            let html = escape(&item.html);
+            [
+                String::from("<entry>"),
+                format!("<title>{title}</title>"),
+                format!("<id>{url}</id>"),
+                format!("<link rel=\"alternate\" href=\"{url}\"/>"),
+                format!("<updated>{date}</updated>"),
+                format!("{authors}<summary>{description}</summary>"),
+                format!("<content type=\"html\">{html}</content>"),
+                String::from("</entry>\n"),
+            ]
+            .join("\n")
+        }
+        FeedFormat::Rss => {
+            [
+                String::from("<item>"),
+                format!("<title>{title}</title>"),
+                format!("<link>{url}</link>"),
+                format!("<guid isPermaLink=\"true\">{url}</guid>"),
+                format!("<pubDate>{date}</pubDate>"),
+                format!("<description>{description}</description>"),
+                String::from("</item>\n"),
+            ]
+            .join("\n")
+        }
+        FeedFormat::Json => render_json_item(item),
+    }
+}
+
+/// A dated item as a JSON Feed item.
+fn render_json_item(item: &FeedItem) -> String
+{
+    let mut entry = serde_json::json!({
+        "id": item.url,
+        "url": item.url,
+        "title": item.title,
+        "summary": item.description,
+        "content_html": item.html,
+    });
+    if let Some(date) = &item.date {
+        entry["date_published"] = Value::String(date.to_rfc3339());
+    }
+    if !item.authors.is_empty() {
+        entry["authors"] = item.authors.iter().map(|x| serde_json::json!({ "name": x })).collect();
+    }
+    serde_json::to_string(&entry).unwrap()
+}
+
+/// An item as a sitemap URL.
+fn render_sitemap_url(item: &FeedItem) -> String
+{
+    let lastmod = item
+        .date
+        .map(|x| format!("<lastmod>{}</lastmod>", x.to_rfc3339()))
+        .unwrap_or_default();
+    format!("<url><loc>{}</loc>{lastmod}</url>\n", escape(&item.url))
+}
+
+/// Parse `xml` and check its root element with `check`.
+fn validate(xml: &str, check: impl Fn(Node) -> std::result::Result<(), Problem>) -> std::result::Result<(), Problem>
+{
+    let document = Document::parse(xml).map_err(|e| {
+        let pos = e.pos();
+        let err = e.to_string();
+        let err = err.rsplit_once(" at ").map_or(err.as_str(), |(x, _)| x);
+        (format!("line {}, column {}", pos.row, pos.col), err.to_string())
+    })?;
+    check(document.root_element())
+}
+
+/// Where `node` is in its document, e.g. `/feed/entry[2]/title`. Elements
+/// with siblings of the same name are numbered from 1.
+fn pointer(node: Node) -> String
+{
+    let mut parts = node
+        .ancestors()
+        .filter(Node::is_element)
+        .map(|x| {
+            let name = x.tag_name().name();
+            let same_name = |y: &Node| y.is_element() && y.tag_name().name() == name;
+            let siblings = x
+                .parent()
+                .map_or(1, |parent| parent.children().filter(same_name).count());
+            if siblings > 1 {
+                format!("{name}[{}]", x.prev_siblings().filter(same_name).count())
+            }
+            else {
+                name.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    parts.reverse();
+    format!("/{}", parts.join("/"))
+}
+
+/// The element children of `node` named `name`.
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>>
+{
+    node.children()
+        .filter(move |x| x.is_element() && x.tag_name().name() == name)
+}
+
+/// The child of `node` named `name`, which it must have.
+fn required<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> std::result::Result<Node<'a, 'input>, Problem>
+{
+    children(node, name).next().ok_or_else(|| {
+        (
+            pointer(node),
+            format!("<{}> is missing its <{name}>", node.tag_name().name()),
+        )
+    })
+}
+
+/// The trimmed text of `node`.
+fn text<'a>(node: Node<'a, '_>) -> &'a str { node.text().unwrap_or_default().trim() }
+
+/// Check that `node`'s text is a date that `parse` accepts.
+fn check_date(node: Node, kind: &str, parse: impl Fn(&str) -> bool) -> std::result::Result<(), Problem>
+{
+    if parse(text(node)) {
+        Ok(())
+    }
+    else {
+        Err((pointer(node), format!("\"{}\" isn't an {kind} date", text(node))))
+    }
+}
+
+/// Check that `node`'s text is a full URL.
+fn check_url(node: Node) -> std::result::Result<(), Problem>
+{
+    let url = text(node);
+    if url.starts_with("https://") || url.starts_with("http://") {
+        Ok(())
+    }
+    else {
+        Err((pointer(node), format!("\"{url}\" isn't a full URL")))
+    }
+}
+
+fn is_rfc3339(x: &str) -> bool { DateTime::parse_from_rfc3339(x).is_ok() }
+
+/// Check that `root` is an Atom feed whose entries have what Atom requires.
+fn validate_atom(root: Node) -> std::result::Result<(), Problem>
+{
+    if root.tag_name().name() != "feed" || root.tag_name().namespace() != Some(ATOM_NAMESPACE) {
+        return Err((
+            pointer(root),
+            format!("An Atom feed's root must be <feed xmlns=\"{ATOM_NAMESPACE}\">"),
+        ));
+    }
+    required(root, "id")?;
+    required(root, "title")?;
+    check_date(required(root, "updated")?, "RFC 3339", is_rfc3339)?;
+    let has_author = children(root, "author").next().is_some();
+    for entry in children(root, "entry") {
+        check_url(required(entry, "id")?)?;
+        required(entry, "title")?;
+        check_date(required(entry, "updated")?, "RFC 3339", is_rfc3339)?;
+        if !has_author {
+            required(entry, "author")?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that `root` is an RSS 2.0 feed whose items have what RSS requires.
+fn validate_rss(root: Node) -> std::result::Result<(), Problem>
+{
+    if root.tag_name().name() != "rss" || root.attribute("version") != Some("2.0") {
+        return Err((
+            pointer(root),
+            String::from("An RSS feed's root must be <rss version=\"2.0\">"),
+        ));
+    }
+    let channel = required(root, "channel")?;
+    required(channel, "title")?;
+    check_url(required(channel, "link")?)?;
+    required(channel, "description")?;
+    for item in children(channel, "item") {
+        if children(item, "title")
+            .chain(children(item, "description"))
+            .next()
+            .is_none()
+        {
+            return Err((pointer(item), String::from("<item> needs a <title> or a <description>")));
+        }
+        if let Some(link) = children(item, "link").next() {
+            check_url(link)?;
+        }
+        if let Some(date) = children(item, "pubDate").next() {
+            check_date(date, "RFC 2822", |x| DateTime::parse_from_rfc2822(x).is_ok())?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that `feed` is a JSON Feed whose items have what JSON Feed requires.
+/// Problems are pointed to with JSON pointers, e.g. `/items/1/id`.
+fn validate_json_feed(feed: &str) -> std::result::Result<(), Problem>
+{
+    let root = serde_json::from_str::<Value>(feed).map_err(|e| {
+        let err = e.to_string();
+        let err = err.rsplit_once(" at line ").map_or(err.as_str(), |(x, _)| x);
+        (format!("line {}, column {}", e.line(), e.column()), err.to_string())
+    })?;
+    let required = |value: &Value, pointer: &str, name: &str| {
+        value
+            .get(name)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| {
+                (
+                    format!("{pointer}/{name}"),
+                    format!("\"{name}\" is missing, or isn't a string"),
+                )
+            })
+    };
+    let version = required(&root, "", "version")?;
+    if !version.starts_with("https://jsonfeed.org/version/1") {
+        return Err((
+            String::from("/version"),
+            format!("\"{version}\" isn't a JSON Feed 1 version, e.g. \"{JSON_FEED_VERSION}\""),
+        ));
+    }
+    required(&root, "", "title")?;
+    let Some(items) = root.get("items").and_then(Value::as_array)
+    else {
+        return Err((
+            String::from("/items"),
+            String::from("\"items\" is missing, or isn't an array"),
+        ));
+    };
+    for (i, item) in items.iter().enumerate() {
+        let pointer = format!("/items/{i}");
+        required(item, &pointer, "id")?;
+        if let Some(url) = item.get("url") {
+            let url = url.as_str().unwrap_or_default();
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err((format!("{pointer}/url"), format!("\"{url}\" isn't a full URL")));
+            }
+        }
+        if let Some(date) = item.get("date_published") {
+            let date = date.as_str().unwrap_or_default();
+            if !is_rfc3339(date) {
+                return Err((
+                    format!("{pointer}/date_published"),
+                    format!("\"{date}\" isn't an RFC 3339 date"),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that `root` is a sitemap within the sitemap protocol's limits.
+fn validate_sitemap(root: Node) -> std::result::Result<(), Problem>
+{
+    if root.tag_name().name() != "urlset" || root.tag_name().namespace() != Some(SITEMAP_NAMESPACE) {
+        return Err((
+            pointer(root),
+            format!("A sitemap's root must be <urlset xmlns=\"{SITEMAP_NAMESPACE}\">"),
+        ));
+    }
+    let urls = children(root, "url").collect::<Vec<_>>();
+    if urls.len() > SITEMAP_URL_LIMIT {
+        return Err((
+            pointer(root),
+            format!("{} URLs is over the limit of {SITEMAP_URL_LIMIT}", urls.len()),
+        ));
+    }
+    for url in urls {
+        let loc = required(url, "loc")?;
+        check_url(loc)?;
+        if text(loc).len() > SITEMAP_URL_LENGTH {
+            return Err((
+                pointer(loc),
+                format!("URLs can't be over {SITEMAP_URL_LENGTH} characters"),
+            ));
+        }
+        if let Some(lastmod) = children(url, "lastmod").next() {
+            let is_w3c_date = |x: &str| is_rfc3339(x) || NaiveDate::parse_from_str(x, "%Y-%m-%d").is_ok();
+            check_date(lastmod, "W3C", is_w3c_date)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn item(url: &str, date: Option<&str>) -> FeedItem
+    {
+        FeedItem {
+            url:         url.to_string(),
+            title:       String::from("Fish & chips"),
+            description: String::from("<Hot>"),
+            date:        date.map(|x| DateTime::parse_from_rfc3339(x).unwrap()),
+            authors:     vec![String::from("A")],
+            tags:        Vec::new(),
+            source:      PathBuf::from("src/a.md"),
+            html:        String::from("<p>Hi</p>"),
+        }
+    }
+
+    #[test]
+    /// Test that items are escaped, and valid once they're in a feed
+    fn test_render_items()
+    {
+        let item = item("https://example.com/a.html?x=1&y=2", Some("2023-03-14T10:00:00+01:00"));
+        let atom = fill(
+            ATOM_TEMPLATE,
+            &[
+                ("feed_title", String::from("Site")),
+                ("feed_url", String::from("https://example.com/atom.xml")),
+                ("feed_updated", String::from("2023-03-14T10:00:00+01:00")),
+                ("feed_items", render_item(FeedFormat::Atom, &item)),
+            ],
+        );
+        assert!(atom.contains("<title>Fish &amp; chips</title>"));
+        assert!(atom.contains("<content type=\"html\">&lt;p&gt;Hi&lt;/p&gt;</content>"));
+        assert_eq!(validate(&atom, validate_atom), Ok(()));
+
+        let rss = fill(
+            RSS_TEMPLATE,
+            &[
+                ("feed_title", String::from("Site")),
+                ("site_url", String::from("https://example.com/")),
+                ("feed_items", render_item(FeedFormat::Rss, &item)),
+            ],
+        );
+        assert!(rss.contains("<pubDate>Tue, 14 Mar 2023 10:00:00 +0100</pubDate>"));
+        assert_eq!(validate(&rss, validate_rss), Ok(()));
+    }
+
+    #[test]
+    /// Test that a JSON Feed's items are JSON, and valid once they're in it
+    fn test_render_json_items()
+    {
+        let format = FeedFormat::Json;
+        let items = [
+            item("https://example.com/a.html", Some("2023-03-14T10:00:00+01:00")),
+            item("https://example.com/b.html", Some("2023-03-13T10:00:00Z")),
+        ];
+        let feed = fill(
+            JSON_TEMPLATE,
+            &[
+                ("feed_title", format.escape("Fish \"&\" chips")),
+                ("feed_description", format.escape("")),
+                ("feed_url", format.escape("https://example.com/feed.json")),
+                ("site_url", format.escape("https://example.com/")),
+                (
+                    "feed_items",
+                    items
+                        .iter()
+                        .map(|x| render_item(format, x))
+                        .collect::<Vec<_>>()
+                        .join(format.separator()),
+                ),
+            ],
+        );
+        assert_eq!(format.validate(&feed), Ok(()));
+        let feed = serde_json::from_str::<Value>(&feed).unwrap();
+        assert_eq!(feed["title"], "Fish \"&\" chips");
+        assert_eq!(feed["items"][0]["content_html"], "<p>Hi</p>");
+        assert_eq!(feed["items"][0]["date_published"], "2023-03-14T10:00:00+01:00");
+        assert_eq!(feed["items"][1]["authors"][0]["name"], "A");
+    }
+
+    #[test]
+    /// Test that a JSON Feed without what it requires is reported with a JSON
+    /// pointer
+    fn test_invalid_json_feed()
+    {
+        assert_eq!(
+            validate_json_feed("{\"version\": \"1\", \"title\": \"T\", \"items\": []}"),
+            Err((
+                String::from("/version"),
+                format!("\"1\" isn't a JSON Feed 1 version, e.g. \"{JSON_FEED_VERSION}\"")
+            ))
+        );
+        let feed = format!(
+            "{{\"version\": \"{JSON_FEED_VERSION}\", \"title\": \"T\", \"items\": [{{\"id\": \"a\"}}, {{\"id\": \
+             \"b\", \"date_published\": \"2023-03-14\"}}]}}"
+        );
+        assert_eq!(
+            validate_json_feed(&feed),
+            Err((
+                String::from("/items/1/date_published"),
+                String::from("\"2023-03-14\" isn't an RFC 3339 date")
+            ))
+        );
+        assert_eq!(
+            validate_json_feed("{\n\"title\": }").unwrap_err().0,
+            "line 2, column 10"
+        );
+    }
+
+    #[test]
+    /// Test that each section and tag gets a feed of its pages, with its own
+    /// limit
+    fn test_site_feeds()
+    {
+        let feeds = toml::from_str::<Feeds>(
+            "base_url = \"https://example.com\"\ntitle = \"Site\"\nsections = [\"blog\"]\ntags = true\ntag_limit = 1",
+        )
+        .unwrap();
+        let config = Config {
+            source: PathBuf::from("src"),
+            ..Config::default()
+        };
+        let mut post = item("https://example.com/blog/a.html", None);
+        post.source = PathBuf::from("src/blog/a.md");
+        post.tags = vec![String::from("Rust Lang"), String::from("web")];
+        let mut page = item("https://example.com/b.html", None);
+        page.tags = vec![String::from("rust lang")];
+        let items = [post, page];
+
+        let site_feeds = site_feeds(&config, &feeds, &items);
+        let feeds = site_feeds
+            .iter()
+            .map(|x| (x.dir.to_str().unwrap(), x.title.as_str(), x.limit, x.items.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            feeds,
+            [
+                ("", "Site", DEFAULT_LIMIT, 2),
+                ("blog", "Site: blog", DEFAULT_LIMIT, 1),
+                ("tags/rust-lang", "Site: Rust Lang", 1, 2),
+                ("tags/web", "Site: web", 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that a page links to the site's feeds, and its section's and
+    /// tags'
+    fn test_link()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/feeds-link");
+        std::fs::create_dir_all(dir.join("blog")).unwrap();
+        std::fs::write(
+            dir.join("blog/a.md"),
+            "```pageinfo\ntitle = \"A\"\ntags = [\"Rust\"]\n```\n",
+        )
+        .unwrap();
+        let config = Config {
+            source: dir.clone(),
+            feeds: Some(
+                toml::from_str(
+                    "base_url = \"https://example.com/\"\ntitle = \"Site\"\nformats = [\"atom\"]\nsections = \
+                     [\"blog\", \"docs\"]\ntags = true",
+                )
+                .unwrap(),
+            ),
+            ..Config::default()
+        };
+        let html = link(&config, &dir.join("blog/a.md"), String::from("<head></head>"));
+        assert_eq!(
+            html,
+            "<head><link rel=\"alternate\" type=\"application/atom+xml\" title=\"Site\" \
+             href=\"https://example.com/atom.xml\"><link rel=\"alternate\" type=\"application/atom+xml\" \
+             title=\"Site: blog\" href=\"https://example.com/blog/atom.xml\"><link rel=\"alternate\" \
+             type=\"application/atom+xml\" title=\"Site: Rust\" href=\"https://example.com/tags/rust/atom.xml\"></head>"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that tokens in a value aren't replaced, and unknown ones are left
+    fn test_fill()
+    {
+        let values = [
+            ("feed_title", String::from("[/rustic_feed_url/]")),
+            ("feed_url", String::from("https://example.com/atom.xml")),
+        ];
+        assert_eq!(
+            fill(
+                "[/rustic_feed_title/] [/rustic_feed_url/] [/rustic_x/] [/rustic_",
+                &values
+            ),
+            "[/rustic_feed_url/] https://example.com/atom.xml [/rustic_x/] [/rustic_"
+        );
+    }
+
+    #[test]
+    /// Test that XML that isn't well-formed is reported with its position
+    fn test_malformed()
+    {
+        let (pointer, err) = validate("<rss version=\"2.0\">\n<channel></rss>", validate_rss).unwrap_err();
+        assert_eq!(pointer, "line 2, column 10");
+        assert_eq!(err, "expected 'channel' tag, not 'rss'");
+    }
+
+    #[test]
+    /// Test that a missing element is reported with the path to its parent
+    fn test_missing_element()
+    {
+        let atom = format!(
+            "<feed xmlns=\"{ATOM_NAMESPACE}\"><id>x</id><title>T</title><updated>2023-03-14T10:00:00Z</updated><author/>\
+             <entry><id>https://example.com/a</id><title>A</title><updated>2023-03-14T10:00:00Z</updated></entry>\
+             <entry><id>https://example.com/b</id><title>B</title></entry></feed>"
+        );
+        assert_eq!(
+            validate(&atom, validate_atom),
+            Err((
+                String::from("/feed/entry[2]"),
+                String::from("<entry> is missing its <updated>")
+            ))
+        );
+    }
+
+    #[test]
+    /// Test that dates and URLs in the wrong format are reported
+    fn test_invalid_values()
+    {
+        let rss = "<rss version=\"2.0\"><channel><title>T</title><link>https://example.com/</link><description/>\
+                   <item><title>A</title><pubDate>2023-03-14</pubDate></item></channel></rss>";
+        assert_eq!(
+            validate(rss, validate_rss),
+            Err((
+                String::from("/rss/channel/item/pubDate"),
+                String::from("\"2023-03-14\" isn't an RFC 2822 date")
+            ))
+        );
+        let sitemap = format!("<urlset xmlns=\"{SITEMAP_NAMESPACE}\"><url><loc>/a.html</loc></url></urlset>");
+        assert_eq!(
+            validate(&sitemap, validate_sitemap),
+            Err((
+                String::from("/urlset/url/loc"),
+                String::from("\"/a.html\" isn't a full URL")
+            ))
+        );
+    }
+
+    #[test]
+    /// Test that the sitemap lists every item, with the dated ones' dates
+    fn test_sitemap()
+    {
+        let urls = [
+            item("https://example.com/a.html", Some("2023-03-14T10:00:00Z")),
+            item("https://example.com/b.html", None),
+        ]
+        .iter()
+        .map(render_sitemap_url)
+        .collect::<String>();
+        assert_eq!(
+            urls,
+            "<url><loc>https://example.com/a.html</loc><lastmod>2023-03-14T10:00:00+00:00</lastmod></url>\n\
+             <url><loc>https://example.com/b.html</loc></url>\n"
+        );
+        let sitemap = fill(SITEMAP_TEMPLATE, &[("feed_items", urls)]);
+        assert_eq!(validate(&sitemap, validate_sitemap), Ok(()));
+    }
+
+    #[test]
+    /// Test that dates without an offset are in the site's time zone
+    fn test_date_time()
+    {
+        let config = Config {
+            site: Some(toml::from_str("timezone = \"Asia/Kolkata\"").unwrap()),
+            ..Config::default()
+        };
+        let date = |x: &str| {
+            date_time(&config, &x.parse::<Datetime>().unwrap())
+                .unwrap()
+                .to_rfc3339()
+        };
+        assert_eq!(date("2023-03-14"), "2023-03-14T00:00:00+05:30");
+        assert_eq!(date("2023-03-14T10:00:00"), "2023-03-14T10:00:00+05:30");
+        assert_eq!(date("2023-03-14T10:00:00Z"), "2023-03-14T10:00:00+00:00");
+    }
+}
@@ -0,0 +1,70 @@
+//! Fenced code blocks found line by line, so the passes over a page's markdown
+//! before it's parsed leave what's in them alone.
+//!
+//! A block is opened by three or more `` ` `` or `~`, indented or not, and
+//! closed by a line of at least as many of the same character.
+
+/// The opening fence of a code block in `line`, and its info string, if it
+/// opens one.
+pub fn opening(line: &str) -> Option<(&str, &str)>
+{
+    let trimmed = line.trim_start();
+    let c = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let length = trimmed.len() - trimmed.trim_start_matches(c).len();
+    (length >= 3).then(|| trimmed.split_at(length))
+}
+
+/// If `line` closes the code block opened with `fence`.
+pub fn closes(line: &str, fence: &str) -> bool
+{
+    let trimmed = line.trim();
+    trimmed.len() >= fence.len() && trimmed.chars().all(|c| fence.starts_with(c))
+}
+
+/// Which of the lines of a source, given in order, are in fenced code blocks.
+#[derive(Debug, Default)]
+pub struct Fences<'a>
+{
+    open: Option<&'a str>,
+}
+
+impl<'a> Fences<'a>
+{
+    /// If `line`, the next line of the source, is a fence or in a fenced code
+    /// block.
+    pub fn contains(&mut self, line: &'a str) -> bool
+    {
+        if let Some(fence) = self.open {
+            if closes(line, fence) {
+                self.open = None;
+            }
+            return true;
+        }
+        if let Some((fence, _)) = opening(line) {
+            self.open = Some(fence);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that a block is only closed by a fence as long as its own, of the
+    /// same character, and that indented fences count
+    fn test_fences()
+    {
+        let source = "a\n````md\n```\nb\n````\n- item\n\n  ~~~\n  ```\n  ~~~~\nc\n";
+        let mut fences = Fences::default();
+        let outside = source.lines().filter(|&x| !fences.contains(x)).collect::<Vec<_>>();
+        assert_eq!(outside, ["a", "- item", "", "c"]);
+        assert_eq!(opening("  ```rust file=x"), Some(("```", "rust file=x")));
+        assert!(opening("``code``").is_none());
+        assert!(!closes("```", "````"));
+        assert!(!closes("```rust", "```"));
+    }
+}
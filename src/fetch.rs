@@ -0,0 +1,338 @@
+//! A cache of what's fetched over the network, such as a headless CMS's
+//! entries, kept in [`CACHE_DIR`], with `[fetch]`.
+//!
+//! A response is used as it is for `fetch.ttl` seconds. After that it's
+//! revalidated with its `ETag`, if it had one, so an unchanged response isn't
+//! downloaded again. The least recently fetched responses are removed once
+//! the cache is over `fetch.max_size` bytes. With `--offline` nothing is
+//! fetched: a cached response is used however old it is, and a URL without
+//! one fails.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{hash, Config, Error, Path, Result};
+
+/// Where responses are cached, relative to the project.
+pub const CACHE_DIR: &str = ".raven-fetch-cache";
+
+/// A response in the cache.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CachedResponse
+{
+    pub url:  String,
+    pub etag: Option<String>,
+
+    /// When it was fetched or last revalidated, in seconds since the Unix
+    /// epoch.
+    pub fetched_at: i64,
+    pub body:       String,
+}
+
+/// A response from the network.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Response
+{
+    /// The server answered that the cached response is still current.
+    pub not_modified: bool,
+    pub etag:         Option<String>,
+    pub body:         String,
+}
+
+/// The body at `url`, sending `token` as a bearer token, from the cache
+/// while it's fresh.
+///
+/// # Errors
+///
+/// Will return an error if `url` cannot be fetched, or isn't cached with
+/// `--offline`, or the cache cannot be written to.
+pub async fn get(config: &Config, url: &str, token: Option<&str>) -> Result<String>
+{
+    let (config, url, token) = (config.clone(), url.to_string(), token.map(String::from));
+    tokio::task::spawn_blocking(move || {
+        cached(&config, Path::new(CACHE_DIR), &url, |etag| {
+            curl(&url, token.as_deref(), etag)
+        })
+    })
+    .await
+    .map_err(|_| Error::AysncJoin)?
+}
+
+/// The body at `url`, from the cache in `dir` while it's fresh, or else from
+/// `fetch`, which is given the cached response's ETag to revalidate it with.
+fn cached(
+    config: &Config,
+    dir: &Path,
+    url: &str,
+    fetch: impl FnOnce(Option<&str>) -> Result<Response>,
+) -> Result<String>
+{
+    let path = dir.join(format!("{}.json", hash::md5_hex(url.as_bytes())));
+    let cached = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|x| serde_json::from_str::<CachedResponse>(&x).ok())
+        .filter(|x| x.url == url);
+    let now = chrono::Utc::now().timestamp();
+    match &cached {
+        Some(cached) if config.offline() => return Ok(cached.body.clone()),
+        Some(cached) if u64::try_from(now - cached.fetched_at).is_ok_and(|age| age < config.fetch_ttl()) => {
+            return Ok(cached.body.clone());
+        }
+        None if config.offline() => {
+            return Err(Error::Fetch {
+                url: url.to_string(),
+                err: String::from("It isn't cached, and nothing is fetched with --offline"),
+            });
+        }
+        _ => (),
+    }
+
+    let response = fetch(cached.as_ref().and_then(|x| x.etag.as_deref()))?;
+    let cached = match cached {
+        Some(cached) if response.not_modified => {
+            CachedResponse {
+                fetched_at: now,
+                ..cached
+            }
+        }
+        _ => {
+            CachedResponse {
+                url:        url.to_string(),
+                etag:       response.etag,
+                fetched_at: now,
+                body:       response.body,
+            }
+        }
+    };
+    std::fs::create_dir_all(dir).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: dir.to_path_buf(),
+        }
+    })?;
+    std::fs::write(&path, serde_json::to_string(&cached).unwrap()).map_err(|e| Error::Io { err: e, path })?;
+    evict(dir, config.fetch_max_size())?;
+    Ok(cached.body)
+}
+
+/// Remove the least recently fetched responses in `dir` until there are at
+/// most `max_size` bytes of them.
+fn evict(dir: &Path, max_size: u64) -> Result<()>
+{
+    let mut responses = std::fs::read_dir(dir)
+        .map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: dir.to_path_buf(),
+            }
+        })?
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect::<Vec<_>>();
+    responses.sort();
+    let mut size = responses.iter().map(|(_, len, _)| len).sum::<u64>();
+    for (_, len, path) in responses {
+        if size <= max_size {
+            break;
+        }
+        std::fs::remove_file(&path).map_err(|e| Error::Io { err: e, path })?;
+        size -= len;
+    }
+    Ok(())
+}
+
+/// Fetch `url` with `curl`, sending `token` as a bearer token, and `etag` to
+/// only get the body if it changed. The token is passed on stdin, so it
+/// isn't in the process list.
+fn curl(url: &str, token: Option<&str>, etag: Option<&str>) -> Result<Response>
+{
+    let error = |err: String| {
+        Error::Fetch {
+            url: url.to_string(),
+            err,
+        }
+    };
+    let mut command = Command::new("curl");
+    command.args(["--silent", "--show-error", "--fail", "--location", "--include"]);
+    if token.is_some() {
+        command.args(["--header", "@-"]);
+    }
+    if let Some(etag) = etag {
+        command.arg("--header").arg(format!("If-None-Match: {etag}"));
+    }
+    let mut child = command
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| error(format!("Couldn't run curl: {e}")))?;
+    if let (Some(token), Some(mut stdin)) = (token, child.stdin.take()) {
+        writeln!(stdin, "Authorization: Bearer {token}")
+            .map_err(|e| error(format!("Couldn't pass the token to curl: {e}")))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| error(format!("Couldn't run curl: {e}")))?;
+    if !output.status.success() {
+        return Err(error(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    let output = String::from_utf8(output.stdout).map_err(|e| error(format!("The response isn't UTF-8: {e}")))?;
+    Ok(response(&output))
+}
+
+/// A response as `curl --include` outputs it: the headers of each response,
+/// following redirects, then the last one's body.
+fn response(output: &str) -> Response
+{
+    let mut headers = "";
+    let mut body = output;
+    while body.starts_with("HTTP/") {
+        let (head, rest) = body
+            .split_once("\r\n\r\n")
+            .or_else(|| body.split_once("\n\n"))
+            .unwrap_or((body, ""));
+        headers = head;
+        body = rest;
+    }
+    let mut lines = headers.lines();
+    let status = lines.next().and_then(|x| x.split_whitespace().nth(1));
+    let etag = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("etag")
+            .then(|| value.trim().to_string())
+    });
+    Response {
+        not_modified: status == Some("304"),
+        etag,
+        body: body.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{FetchCache, PathBuf};
+
+    const URL: &str = "https://cms.example.com/posts";
+
+    fn config(ttl: u64, offline: bool) -> Config
+    {
+        Config {
+            fetch: Some(FetchCache {
+                ttl:      Some(ttl),
+                max_size: None,
+                offline:  Some(offline),
+            }),
+            ..Config::default()
+        }
+    }
+
+    fn fresh_dir(name: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn body(body: &str) -> Response
+    {
+        Response {
+            not_modified: false,
+            etag:         Some(String::from("\"v1\"")),
+            body:         body.to_string(),
+        }
+    }
+
+    #[test]
+    /// Test that a fresh response is used without fetching it again
+    fn test_fresh()
+    {
+        let dir = fresh_dir("fetch-fresh");
+        let config = config(60, false);
+        assert_eq!(cached(&config, &dir, URL, |_| Ok(body("[1]"))).unwrap(), "[1]");
+        let again = cached(&config, &dir, URL, |_| panic!("A fresh response was fetched again"));
+        assert_eq!(again.unwrap(), "[1]");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that a stale response is revalidated with its ETag, and kept if
+    /// it's unchanged
+    fn test_revalidate()
+    {
+        let dir = fresh_dir("fetch-revalidate");
+        let config = config(0, false);
+        cached(&config, &dir, URL, |_| Ok(body("[1]"))).unwrap();
+        let revalidated = cached(&config, &dir, URL, |etag| {
+            assert_eq!(etag, Some("\"v1\""));
+            Ok(Response {
+                not_modified: true,
+                ..Response::default()
+            })
+        });
+        assert_eq!(revalidated.unwrap(), "[1]");
+        assert_eq!(cached(&config, &dir, URL, |_| Ok(body("[2]"))).unwrap(), "[2]");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that offline, a stale response is used, and a URL that isn't
+    /// cached fails
+    fn test_offline()
+    {
+        let dir = fresh_dir("fetch-offline");
+        cached(&config(0, false), &dir, URL, |_| Ok(body("[1]"))).unwrap();
+        let offline = config(0, true);
+        let fetched = |_: Option<&str>| -> Result<Response> { panic!("Fetched while offline") };
+        assert_eq!(cached(&offline, &dir, URL, fetched).unwrap(), "[1]");
+        assert!(matches!(
+            cached(&offline, &dir, "https://cms.example.com/other", fetched),
+            Err(Error::Fetch { .. })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that the least recently fetched responses are removed once the
+    /// cache is too big
+    fn test_evict()
+    {
+        let dir = fresh_dir("fetch-evict");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("old.json"), "0123456789").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::write(dir.join("new.json"), "0123456789").unwrap();
+        evict(&dir, 15).unwrap();
+        assert!(!dir.join("old.json").exists());
+        assert!(dir.join("new.json").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that the last response's status, ETag, and body are read from
+    /// curl's output, after any redirects
+    fn test_response()
+    {
+        let output = "HTTP/1.1 301 Moved Permanently\r\nLocation: /posts\r\n\r\nHTTP/2 200\r\ncontent-type: \
+                      application/json\r\nETag: \"v1\"\r\n\r\n[1]";
+        assert_eq!(
+            response(output),
+            Response {
+                not_modified: false,
+                etag:         Some(String::from("\"v1\"")),
+                body:         String::from("[1]"),
+            }
+        );
+        assert!(response("HTTP/1.1 304 Not Modified\r\n\r\n").not_modified);
+    }
+}
@@ -0,0 +1,642 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Component,
+};
+
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use walkdir::WalkDir;
+
+use crate::{
+    analytics, book,
+    build::{dest_path, favicon_dest, format_variant, source_files},
+    feeds, includes, inheritance, markdown, mdbook, offline, og, redirects,
+    report::Warnings,
+    sass, sections, shortcodes, styles, Config, Error, PageInfo, Path, PathBuf, Result,
+};
+
+/// The files a single source page depends on.
+#[derive(Debug, Clone, Default)]
+pub struct PageDependencies
+{
+    /// The HTML template the page is embedded into.
+    pub template: Option<PathBuf>,
+
+    /// The templates its template extends.
+    pub base_templates: Vec<PathBuf>,
+
+    /// The CSS stylesheet inlined into the page.
+    pub stylesheet: Option<PathBuf>,
+
+    /// The partials its stylesheet loads, if it's a Sass one.
+    pub stylesheet_partials: Vec<PathBuf>,
+
+    /// The favicon embedded into the page.
+    pub favicon: Option<PathBuf>,
+
+    /// The files its code blocks include.
+    pub included: Vec<PathBuf>,
+
+    /// The local images it shows, figures included.
+    pub images: Vec<PathBuf>,
+
+    /// The files of the custom shortcodes it uses.
+    pub shortcodes: Vec<PathBuf>,
+
+    /// The `head_extra` files put in its head, the default ones first.
+    pub head_extra: Vec<PathBuf>,
+
+    /// The analytics snippet put in it.
+    pub analytics: Option<PathBuf>,
+
+    /// The languages of every fenced code block on the page.
+    pub languages: BTreeSet<String>,
+
+    /// Why a markdown page's page info can't be used, if it can't.
+    pub page_info_error: Option<String>,
+
+    /// The shared page info file merged under the page's own.
+    pub meta_from: Option<PathBuf>,
+
+    /// The page's stable ID.
+    pub id: Option<String>,
+
+    /// The page's title, from its page info or an mdBook summary.
+    pub title: Option<String>,
+}
+
+/// Which files each page of a project depends on.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph
+{
+    pub pages: BTreeMap<PathBuf, PageDependencies>,
+
+    /// The files books depend on rather than any one page: their templates,
+    /// print variants included, and their covers.
+    pub books: Vec<PathBuf>,
+}
+
+impl DependencyGraph
+{
+    /// Walk the configured source directory and record the dependencies of
+    /// every page found.
+    ///
+    /// Pages whose page info cannot be parsed fall back to the configured
+    /// defaults, the same way a build would.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a source file cannot be read.
+    pub fn from_config(config: &Config) -> Result<Self>
+    {
+        let mut graph = Self::default();
+        let titles = mdbook::page_titles(config);
+        for (source_file, extention) in source_files(config, &Warnings::default()) {
+            let dependencies = match &*extention {
+                "md" | "markdown" => {
+                    let source = std::fs::read_to_string(&source_file).map_err(|e| {
+                        Error::Io {
+                            err:  e,
+                            path: source_file.clone(),
+                        }
+                    })?;
+                    let title = titles.get(&mdbook::normalize(&source_file));
+                    let mut dependencies =
+                        PageDependencies::from_markdown(&source, &source_file, config, title.is_some());
+                    if dependencies.title.is_none() {
+                        dependencies.title = title.cloned();
+                    }
+                    dependencies
+                }
+                "html" | "htm" => {
+                    let treat_source_as_template = config
+                        .generation
+                        .as_ref()
+                        .and_then(|generation| generation.treat_source_as_template)
+                        .unwrap_or(false);
+                    let stylesheet = treat_source_as_template.then(|| config.resolve(&config.default.stylesheet));
+                    PageDependencies {
+                        stylesheet_partials: stylesheet.as_deref().map(sass::partials).unwrap_or_default(),
+                        stylesheet,
+                        favicon: treat_source_as_template.then(|| config.resolve(&config.default.favicon)),
+                        head_extra: if treat_source_as_template {
+                            default_head_extra(config)
+                        }
+                        else {
+                            Vec::new()
+                        },
+                        analytics: analytics::snippet_file(config),
+                        ..Default::default()
+                    }
+                }
+                _ => PageDependencies::default(),
+            };
+            graph.pages.insert(source_file, dependencies);
+        }
+        for book in config.books.iter().flatten() {
+            let template = book.template.as_ref().unwrap_or(&config.default.template);
+            let variant = config.resolve(&format_variant(template, "print"));
+            let template = if variant.is_file() {
+                variant
+            }
+            else {
+                config.resolve(template)
+            };
+            graph.books.extend(inheritance::bases(config, &template));
+            graph.books.push(template);
+            graph.books.extend(book.cover.as_ref().map(|x| config.resolve(x)));
+        }
+        Ok(graph)
+    }
+
+    /// Every file referenced by at least one page, canonicalized when possible.
+    pub fn referenced_files(&self) -> BTreeSet<PathBuf>
+    {
+        self.pages
+            .values()
+            .flat_map(|page| {
+                [&page.template, &page.stylesheet, &page.favicon, &page.meta_from]
+                    .into_iter()
+                    .flatten()
+                    .chain(&page.base_templates)
+                    .chain(&page.stylesheet_partials)
+                    .chain(&page.included)
+                    .chain(&page.images)
+                    .chain(&page.shortcodes)
+                    .chain(&page.head_extra)
+                    .chain(&page.analytics)
+            })
+            .chain(&self.books)
+            .map(|path| normalize(path))
+            .collect()
+    }
+
+    /// Files in the dest dir that aren't generated from any source, such as
+    /// the output of a page that was since renamed or deleted.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a source file's dest path cannot be worked
+    /// out.
+    pub fn orphaned_dest_files(&self, config: &Config) -> Result<Vec<PathBuf>>
+    {
+        if !config.dest.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut generated = BTreeSet::new();
+        for source_file in self.pages.keys() {
+            let extention = source_file
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            generated.insert(dest_path(config, source_file, &extention)?);
+            if og::enabled(config) && matches!(&*extention, "md" | "markdown") {
+                generated.insert(og::image_path(config, source_file, &extention)?);
+            }
+        }
+        for book in config.books.iter().flatten() {
+            generated.insert(book::dest(config, book));
+        }
+        if styles::linked(config) {
+            generated.insert(config.dest.join(styles::COMPONENTS_STYLESHEET));
+        }
+        if config.service_worker() {
+            generated.insert(config.dest.join(offline::SERVICE_WORKER));
+        }
+        generated.extend(feeds::outputs(config));
+        let manifest = redirects::Manifest::load();
+        generated.extend(manifest.redirects.keys().map(|x| config.dest.join(x)));
+        if !config.embed_favicon() {
+            generated.extend(
+                self.pages
+                    .values()
+                    .filter_map(|page| page.favicon.as_ref())
+                    .map(|favicon| favicon_dest(config, favicon)),
+            );
+        }
+
+        Ok(WalkDir::new(&config.dest)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|entry| entry.file_type().is_file() && !generated.contains(entry.path()))
+            .map(|entry| entry.path().to_path_buf())
+            .collect())
+    }
+
+    /// Every fence language used by at least one page.
+    pub fn languages(&self) -> BTreeSet<&str>
+    {
+        self.pages
+            .values()
+            .flat_map(|page| page.languages.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+impl PageDependencies
+{
+    /// Extract the dependencies of the markdown source of the page at
+    /// `source_file` without rendering it. A page `titled` by an mdBook
+    /// summary doesn't need page info.
+    pub fn from_markdown(source: &str, source_file: &Path, config: &Config, titled: bool) -> Self
+    {
+        let mut languages = BTreeSet::new();
+        let mut images = Vec::new();
+        let mut current_language = None;
+        let mut unparsed_page_info = None;
+        for event in Parser::new(source) {
+            match event {
+                Event::Start(Tag::Image(_, url, _)) => images.extend(image_file(config, source_file, &url)),
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => current_language = Some(lang),
+                Event::End(Tag::CodeBlock(_)) => current_language = None,
+                Event::Text(text) => {
+                    if let Some(lang) = current_language.as_ref() {
+                        if lang.as_ref() == PageInfo::CODE_BLOCK_IDENTIFIER {
+                            unparsed_page_info = Some(text.to_string());
+                        }
+                        else if !lang.is_empty() {
+                            languages.insert(lang.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Recorded even if the page info can't be parsed, e.g. because the
+        // shared file is missing.
+        let section = sections::page_info(config, source_file);
+        let meta_from = unparsed_page_info
+            .as_deref()
+            .and_then(|x| toml::from_str::<toml::Table>(x).ok())
+            .and_then(|x| x.get("meta_from")?.as_str().map(PathBuf::from))
+            .or_else(|| section.as_ref().ok()?.get("meta_from")?.as_str().map(PathBuf::from));
+        // A page without page info, when it isn't required, has its sections'.
+        let unparsed_page_info = match unparsed_page_info {
+            None if !titled && !config.require_page_info() => Some(String::new()),
+            x => x,
+        };
+        let parsed = unparsed_page_info
+            .as_deref()
+            .map(|x| PageInfo::parse_in_section(x, section.as_ref().map_err(String::clone)?));
+        let (page_info, page_info_error) = match parsed {
+            Some(Ok(page_info)) => (Some(page_info), None),
+            Some(Err(e)) => (None, Some(e)),
+            None if titled => (None, None),
+            None => (None, Some(String::from("There's no pageinfo block"))),
+        };
+        let mut head_extra = default_head_extra(config);
+        let (template, stylesheet, favicon, id, title) = match page_info {
+            Some(page_info) => {
+                head_extra.extend(
+                    page_info
+                        .head_extra
+                        .iter()
+                        .flat_map(|x| x.files())
+                        .map(|x| config.resolve(x)),
+                );
+                (
+                    page_info.template,
+                    page_info.style,
+                    page_info.favicon,
+                    page_info.id,
+                    Some(page_info.title).filter(|x| !x.is_empty()),
+                )
+            }
+            None => (None, None, None, None, None),
+        };
+        // Untitled pages are titled by their first `#` heading, unless an
+        // mdBook summary titles them.
+        let title = title.or_else(|| {
+            let events = Parser::new_ext(source, config.markdown_options()).collect::<Vec<_>>();
+            markdown::first_heading(&events).filter(|_| !titled)
+        });
+
+        let template = config.resolve(&template.unwrap_or_else(|| config.default.template.clone()));
+        let stylesheet = config.resolve(&stylesheet.unwrap_or_else(|| config.default.stylesheet.clone()));
+        Self {
+            base_templates: inheritance::bases(config, &template),
+            template: Some(template),
+            stylesheet_partials: sass::partials(&stylesheet),
+            stylesheet: Some(stylesheet),
+            favicon: Some(config.resolve(&favicon.unwrap_or_else(|| config.default.favicon.clone()))),
+            included: includes::files(source),
+            images,
+            shortcodes: shortcodes::files(source, &config.shortcode_dirs()),
+            head_extra,
+            analytics: analytics::snippet_file(config),
+            languages,
+            page_info_error,
+            meta_from,
+            id,
+            title,
+        }
+    }
+}
+
+/// Files in the project that no page references.
+#[derive(Debug, Clone, Default)]
+pub struct UnusedReport
+{
+    pub templates:     Vec<PathBuf>,
+    pub stylesheets:   Vec<PathBuf>,
+    pub syntaxes:      Vec<PathBuf>,
+    pub syntax_themes: Vec<PathBuf>,
+    pub assets:        Vec<PathBuf>,
+}
+
+impl UnusedReport
+{
+    const ASSET_EXTENTIONS: [&str; 7] = ["ico", "png", "jpg", "jpeg", "gif", "svg", "webp"];
+
+    /// Compare the files found in the project directory against `graph`.
+    ///
+    /// The source, destination, snapshots, and theme directories are not
+    /// searched for templates, stylesheets, or assets.
+    pub fn new(config: &Config, graph: &DependencyGraph) -> Self
+    {
+        let referenced = graph.referenced_files();
+        let languages = graph.languages();
+        let mut report = Self::default();
+
+        let snapshots = config
+            .snapshots
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(Config::DEFAULT_SNAPSHOTS_DIR));
+        let mut excluded = vec![normalize(&config.dest), normalize(&snapshots)];
+        excluded.extend(config.source_dirs().into_iter().map(|(dir, _)| normalize(dir)));
+        excluded.extend(config.theme.as_deref().map(normalize));
+        let project_files = WalkDir::new(".")
+            .into_iter()
+            .filter_entry(|entry| {
+                let name = entry.file_name().to_string_lossy();
+                entry.depth() == 0
+                    || !(name.starts_with('.') || name == "target" || excluded.contains(&normalize(entry.path())))
+            })
+            .filter_map(std::result::Result::ok)
+            .filter(|entry| entry.file_type().is_file());
+
+        for entry in project_files {
+            let path = entry.path().strip_prefix(".").unwrap_or(entry.path()).to_path_buf();
+            let extention = path
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if path.starts_with(&config.syntaxes) {
+                if extention == "sublime-syntax" && !syntax_is_used(&path, &languages) {
+                    report.syntaxes.push(path);
+                }
+                continue;
+            }
+            if path.starts_with(&config.custom_syntax_themes) {
+                if extention == "tmtheme" && !syntax_theme_is_used(&path, &config.syntax_theme) {
+                    report.syntax_themes.push(path);
+                }
+                continue;
+            }
+            if referenced.contains(&normalize(&path)) {
+                continue;
+            }
+            match &*extention {
+                "html" | "htm" => report.templates.push(path),
+                "css" | "scss" | "sass" => report.stylesheets.push(path),
+                x if Self::ASSET_EXTENTIONS.contains(&x) => report.assets.push(path),
+                _ => {}
+            }
+        }
+
+        report
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.templates.is_empty()
+            && self.stylesheets.is_empty()
+            && self.syntaxes.is_empty()
+            && self.syntax_themes.is_empty()
+            && self.assets.is_empty()
+    }
+
+    /// Prints every unused file grouped by kind.
+    pub fn print(&self)
+    {
+        let groups = [
+            ("Templates", &self.templates),
+            ("Stylesheets", &self.stylesheets),
+            ("Syntaxes", &self.syntaxes),
+            ("Syntax themes", &self.syntax_themes),
+            ("Assets", &self.assets),
+        ];
+        for (name, files) in groups {
+            if files.is_empty() {
+                continue;
+            }
+            println!("{name}:");
+            for file in files {
+                println!("    \"{}\"", file.display());
+            }
+        }
+    }
+}
+
+/// The `default.head_extra` files, put in every page's head.
+fn default_head_extra(config: &Config) -> Vec<PathBuf>
+{
+    config
+        .default
+        .head_extra
+        .iter()
+        .flat_map(|x| x.files())
+        .map(|x| config.resolve(x))
+        .collect()
+}
+
+fn normalize(path: &Path) -> PathBuf { path.canonicalize().unwrap_or_else(|_| path.to_path_buf()) }
+
+/// The file the image at `url` on the page at `source_file` is, if it's a
+/// local one.
+///
+/// Relative URLs are relative to the page, and ones starting with `/` to the
+/// source dir written to where they point.
+fn image_file(config: &Config, source_file: &Path, url: &str) -> Option<PathBuf>
+{
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    if url.is_empty() || url.starts_with("//") || url.contains(':') {
+        return None;
+    }
+    let path = match url.strip_prefix('/') {
+        Some(rooted) => {
+            let candidates = config
+                .source_dirs()
+                .into_iter()
+                .filter_map(|(dir, dest)| {
+                    let relative = match dest {
+                        Some(dest) => Path::new(rooted).strip_prefix(dest).ok()?,
+                        None => Path::new(rooted),
+                    };
+                    Some(dir.join(relative))
+                })
+                .collect::<Vec<_>>();
+            candidates.iter().find(|x| x.is_file()).or(candidates.first())?.clone()
+        }
+        None => source_file.parent().unwrap_or(Path::new("")).join(url),
+    };
+
+    // `..` is resolved here so files that don't exist yet still compare equal.
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir if matches!(resolved.components().next_back(), Some(Component::Normal(_))) => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            x => resolved.push(x),
+        }
+    }
+    Some(resolved)
+}
+
+/// A syntax is used if any fence language matches its name or one of its file
+/// extentions.
+fn syntax_is_used(path: &Path, languages: &BTreeSet<&str>) -> bool
+{
+    use syntect::parsing::SyntaxDefinition;
+    let Ok(contents) = std::fs::read_to_string(path)
+    else {
+        return true;
+    };
+    let Ok(syntax) = SyntaxDefinition::load_from_str(&contents, true, None)
+    else {
+        return true;
+    };
+    languages.iter().any(|lang| {
+        syntax.name.eq_ignore_ascii_case(lang)
+            || syntax.file_extensions.iter().any(|ext| ext.eq_ignore_ascii_case(lang))
+    })
+}
+
+/// Custom themes are keyed by their name, or their file stem if they're
+/// unnamed.
+fn syntax_theme_is_used(path: &Path, syntax_theme: &str) -> bool
+{
+    use syntect::highlighting::ThemeSet;
+    let stem = path.file_stem().map(|x| x.to_string_lossy().to_string());
+    match ThemeSet::get_theme(path) {
+        Ok(theme) => theme.name.or(stem).as_deref() == Some(syntax_theme),
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{Analytics, Feeds, HeadExtra};
+
+    #[test]
+    /// Test that the local images of a page, figures included, are recorded
+    /// as referenced, relative to the page or to its source dir
+    fn test_images()
+    {
+        let config = Config {
+            source: PathBuf::from("src"),
+            dest: PathBuf::from("dest"),
+            ..Config::default()
+        };
+        let source = "```pageinfo\ntitle = \"Post\"\n```\n\n![A chart](../../media/chart.png \"Figure\")\n\n![Logo](/img/logo.svg?v=2) \
+                      ![Remote](https://example.com/a.png) ![Inline](data:image/png;base64,AA==)\n\n```md\n![Code](code.png)\n```\n";
+        let dependencies = PageDependencies::from_markdown(source, Path::new("src/blog/post.md"), &config, false);
+        assert_eq!(
+            dependencies.images,
+            [PathBuf::from("media/chart.png"), PathBuf::from("src/img/logo.svg")]
+        );
+
+        let mut graph = DependencyGraph::default();
+        graph.pages.insert(PathBuf::from("src/blog/post.md"), dependencies);
+        assert!(graph.referenced_files().contains(Path::new("media/chart.png")));
+    }
+
+    #[test]
+    /// Test that a page's custom shortcodes, head_extra files, and analytics
+    /// snippet, found in the theme, are recorded as what it depends on
+    fn test_page_inputs()
+    {
+        let theme = std::env::temp_dir().join("rustic-raven-tests/graph-theme");
+        std::fs::create_dir_all(theme.join("shortcodes")).unwrap();
+        std::fs::write(theme.join("shortcodes/signup.html"), "<div></div>").unwrap();
+        std::fs::write(theme.join("site-head.html"), "<meta>").unwrap();
+        std::fs::write(theme.join("page-head.html"), "<meta>").unwrap();
+        std::fs::write(theme.join("analytics.html"), "<script></script>").unwrap();
+
+        let mut config = Config {
+            source: PathBuf::from("src"),
+            dest: PathBuf::from("dest"),
+            theme: Some(theme.clone()),
+            analytics: Some(Analytics {
+                enabled:  None,
+                provider: None,
+                site_id:  None,
+                snippet:  Some(PathBuf::from("analytics.html")),
+                dev:      None,
+            }),
+            ..Config::default()
+        };
+        config.default.head_extra = Some(HeadExtra::Files(vec![PathBuf::from("site-head.html")]));
+        let source = "```pageinfo\ntitle = \"Post\"\nhead_extra = [\"page-head.html\"]\n```\n\n{{% signup %}}\n";
+        let dependencies = PageDependencies::from_markdown(source, Path::new("src/post.md"), &config, false);
+        assert_eq!(dependencies.shortcodes, [theme.join("shortcodes/signup.html")]);
+        assert_eq!(
+            dependencies.head_extra,
+            [theme.join("site-head.html"), theme.join("page-head.html")]
+        );
+        assert_eq!(dependencies.analytics, Some(theme.join("analytics.html")));
+        std::fs::remove_dir_all(theme).unwrap();
+    }
+
+    #[test]
+    /// Test that dest files no source generates are orphans, and that
+    /// generated feeds aren't
+    fn test_orphaned_dest_files()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/graph-orphans");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("dest/old")).unwrap();
+        std::fs::write(dir.join("src/post.md"), "# Post\n").unwrap();
+        for file in [
+            "post.html",
+            "old/post.html",
+            "atom.xml",
+            "rss.xml",
+            "feed.json",
+            "sitemap.xml",
+        ] {
+            std::fs::write(dir.join("dest").join(file), "").unwrap();
+        }
+        let config = Config {
+            source: dir.join("src"),
+            dest: dir.join("dest"),
+            feeds: Some(toml::from_str::<Feeds>("base_url = \"https://example.com\"").unwrap()),
+            ..Config::default()
+        };
+        let graph = DependencyGraph::from_config(&config).unwrap();
+        assert_eq!(
+            graph.orphaned_dest_files(&config).unwrap(),
+            [dir.join("dest/old/post.html")]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that a dest dir that doesn't exist has no orphans
+    fn test_no_dest_dir()
+    {
+        let config = Config {
+            dest: std::env::temp_dir().join("rustic-raven-tests/graph-no-dest"),
+            ..Config::default()
+        };
+        assert!(DependencyGraph::default()
+            .orphaned_dest_files(&config)
+            .unwrap()
+            .is_empty());
+    }
+}
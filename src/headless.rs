@@ -0,0 +1,134 @@
+//! A JSON dump of every rendered markdown page, written to `index.json` in
+//! the dest dir with `generation.json_index`, for using the site as the
+//! content of a single-page app.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    build::{write_output, Website},
+    PathBuf, Result,
+};
+
+/// Where the index is written, relative to the dest dir.
+pub const INDEX_FILE: &str = "index.json";
+
+/// A markdown page, as it's listed in the index.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedPage
+{
+    /// The page's path from the root of the site, e.g. `/blog/post.html`.
+    pub url:         String,
+    pub source:      PathBuf,
+    pub title:       String,
+    pub description: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    /// The page's `date`, as it's written in its page info.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// The rendered body of the page, without its template.
+    pub html: String,
+
+    /// The body's text, without its markup.
+    pub text: String,
+}
+
+/// Render every markdown page of `site` into its index entry, in the order
+/// they're found.
+///
+/// # Errors
+///
+/// Will return an error if a page cannot be read or rendered.
+pub async fn pages(site: &Website) -> Result<Vec<IndexedPage>>
+{
+    let config = site.config();
+    let mut pages = Vec::new();
+    for (source_file, extention) in site.sources() {
+        if !matches!(&*extention, "md" | "markdown") {
+            continue;
+        }
+        let source = site.read_source(&source_file).await?;
+        let (html, page_info) = site.parse_markdown(&source, source_file.clone())?;
+        let dest = site.dest_path(&source_file, &extention)?;
+        let dest = dest.strip_prefix(&config.dest).unwrap_or(&dest);
+        let url = format!("/{}", dest.to_string_lossy().replace('\\', "/"));
+        pages.push(IndexedPage {
+            url,
+            source: source_file,
+            title: page_info.title,
+            description: page_info.description,
+            id: page_info.id,
+            date: page_info.date.map(|x| x.to_string()),
+            authors: page_info.meta.map(|x| x.authors).unwrap_or_default(),
+            tags: page_info.tags.unwrap_or_default(),
+            text: plain_text(&html),
+            html,
+        });
+    }
+    Ok(pages)
+}
+
+/// Write the index of every markdown page to [`INDEX_FILE`] in the dest dir.
+///
+/// # Errors
+///
+/// Will return an error if a page cannot be rendered, or the index cannot be
+/// written.
+pub async fn write_index(site: &Website) -> Result<()>
+{
+    let pages = pages(site).await?;
+    let json = serde_json::to_string_pretty(&pages).unwrap();
+    write_output(&site.config().dest.join(INDEX_FILE), json + "\n").await
+}
+
+/// The text of `html`, with its tags, styles, and scripts removed, entities
+/// decoded, and whitespace collapsed. Blocks are kept apart by a space.
+pub(crate) fn plain_text(html: &str) -> String
+{
+    static SCRIPT: OnceLock<Regex> = OnceLock::new();
+    static BLOCK: OnceLock<Regex> = OnceLock::new();
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let block = BLOCK.get_or_init(|| {
+        Regex::new(r"(?i)</?(p|h[1-6]|li|ul|ol|dl|dt|dd|div|section|blockquote|pre|table|tr|td|th|br|hr|figure|figcaption)\b[^>]*>")
+            .unwrap()
+    });
+    let tag = TAG.get_or_init(|| Regex::new(r"<[^>]*>").unwrap());
+    let script = SCRIPT.get_or_init(|| Regex::new(r"(?is)<style\b.*?</style>|<script\b.*?</script>").unwrap());
+    let text = script.replace_all(html, "");
+    let text = block.replace_all(&text, " ");
+    let text = tag.replace_all(&text, "");
+    let text = htmlescape::decode_html(&text).unwrap_or_else(|_| text.to_string());
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that markup is removed, entities are decoded, and whitespace is
+    /// collapsed
+    fn test_plain_text()
+    {
+        assert_eq!(
+            plain_text(
+                "<h1 id=\"a\">Fish &amp; chips</h1>\n<p>Served <em>hot</em>,\n  with <code>x &lt; \
+                 3</code>.</p><style>p{}</style>"
+            ),
+            "Fish & chips Served hot, with x < 3."
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Hooks that transform pages at defined stages of a build.
+//!
+//! Markdown preprocessors run on a page's source before it's parsed, HTML
+//! postprocessors on every HTML page after it's rendered, and page rendered
+//! hooks once a page has been written. Each kind runs in registration order,
+//! after the built-in ones. Library users (and plugins built on the library)
+//! can register their own with
+//! [`crate::build::Website::add_markdown_preprocessor`],
+//! [`crate::build::Website::add_html_postprocessor`], and
+//! [`crate::build::Website::on_page_rendered`].
+//!
+//! The `[hooks]` table of the configuration adds shell commands that run
+//! before the build, after each page, and after the build.
+
+use std::{ffi::OsString, sync::Arc};
+
+use crate::{
+    deploy::shell_command,
+    external_links, minify,
+    timings::{Recorder, Stage},
+    Config, Error, Path, Result,
+};
+
+/// What a hook knows about the page it's called for.
+pub struct HookContext<'a>
+{
+    pub config: &'a Config,
+
+    /// The page's source file. For a book's combined page, the book's
+    /// section.
+    pub source_file: &'a Path,
+
+    /// Where the build records how long the page's stages take.
+    pub timings: &'a Recorder,
+}
+
+type Transform = Arc<dyn Fn(&HookContext, String) -> String + Send + Sync>;
+type PageRendered = Arc<dyn Fn(&HookContext, &Path, &str) + Send + Sync>;
+
+/// The hooks run while building, in registration order.
+#[derive(Clone, Default)]
+pub struct Hooks
+{
+    markdown_preprocessors: Vec<Transform>,
+    html_postprocessors:    Vec<Transform>,
+    page_rendered:          Vec<PageRendered>,
+}
+
+impl Hooks
+{
+    /// A registry of the built-in hooks: the `external_links` postprocessor,
+    /// which rewrites links to other sites if `generation.external_links` is
+    /// set, and the `minify` postprocessor, which minifies pages if
+    /// `generation.process.minify` is set.
+    pub fn builtin() -> Self
+    {
+        let mut hooks = Self::default();
+        hooks.add_html_postprocessor(|context, html| {
+            let external_links = context
+                .config
+                .generation
+                .as_ref()
+                .and_then(|generation| generation.external_links.as_ref());
+            match external_links {
+                Some(options) => external_links::rewrite(&html, options),
+                None => html,
+            }
+        });
+        hooks.add_html_postprocessor(|context, html| {
+            let minify = context
+                .config
+                .generation
+                .as_ref()
+                .and_then(|generation| generation.process.as_ref())
+                .is_some_and(|process| process.minify);
+            if minify {
+                context
+                    .timings
+                    .time(context.source_file, Stage::Minify, || minify::html(&html))
+            }
+            else {
+                html
+            }
+        });
+        hooks
+    }
+
+    /// Add a hook that transforms a page's markdown before it's parsed.
+    pub fn add_markdown_preprocessor(&mut self, hook: impl Fn(&HookContext, String) -> String + Send + Sync + 'static)
+    {
+        self.markdown_preprocessors.push(Arc::new(hook));
+    }
+
+    /// Add a hook that transforms a page's HTML after it's rendered.
+    pub fn add_html_postprocessor(&mut self, hook: impl Fn(&HookContext, String) -> String + Send + Sync + 'static)
+    {
+        self.html_postprocessors.push(Arc::new(hook));
+    }
+
+    /// Add a hook that's called with a page's dest path and HTML once it's
+    /// written.
+    pub fn on_page_rendered(&mut self, hook: impl Fn(&HookContext, &Path, &str) + Send + Sync + 'static)
+    {
+        self.page_rendered.push(Arc::new(hook));
+    }
+
+    /// Run the markdown preprocessors on `markdown`.
+    pub fn preprocess_markdown(&self, context: &HookContext, markdown: String) -> String
+    {
+        self.markdown_preprocessors
+            .iter()
+            .fold(markdown, |markdown, hook| hook(context, markdown))
+    }
+
+    /// Run the HTML postprocessors on `html`.
+    pub fn postprocess_html(&self, context: &HookContext, html: String) -> String
+    {
+        self.html_postprocessors
+            .iter()
+            .fold(html, |html, hook| hook(context, html))
+    }
+
+    /// Run the page rendered hooks for the page written to `dest`.
+    pub fn page_rendered(&self, context: &HookContext, dest: &Path, html: &str)
+    {
+        for hook in &self.page_rendered {
+            hook(context, dest, html);
+        }
+    }
+}
+
+/// Run the `hook` command `line` with the platform's shell, with the source
+/// and dest dirs (and the profile, if any) in `$RAVEN_SOURCE`, `$RAVEN_DEST`,
+/// and `$RAVEN_PROFILE`, and `env` added to its environment.
+///
+/// # Errors
+///
+/// Will return an error if the command cannot be started or exits
+/// unsuccessfully.
+pub(crate) async fn run_command(
+    hook: &'static str,
+    line: &str,
+    config: &Config,
+    env: Vec<(&str, OsString)>,
+) -> Result<()>
+{
+    let mut command = shell_command(line);
+    command
+        .env("RAVEN_SOURCE", &config.source)
+        .env("RAVEN_DEST", &config.dest);
+    if let Some(profile) = &config.profile {
+        command.env("RAVEN_PROFILE", profile);
+    }
+    command.envs(env);
+
+    let status = tokio::task::spawn_blocking(move || command.status())
+        .await
+        .map_err(|_| Error::AysncJoin)?
+        .map_err(|e| {
+            Error::Hook {
+                hook,
+                err: format!("Couldn't run \"{line}\": {e}"),
+            }
+        })?;
+    if !status.success() {
+        return Err(Error::Hook {
+            hook,
+            err: format!("\"{line}\" failed: {status}"),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that hooks run in registration order, after minify
+    fn test_hooks()
+    {
+        let mut config = Config::default();
+        config.set_jobs(1);
+        config.generation.as_mut().unwrap().process = Some(crate::ProcessHtml {
+            minify:     true,
+            minify_css: None,
+            minify_js:  None,
+        });
+        let context = HookContext {
+            config:      &config,
+            source_file: Path::new("src/index.md"),
+            timings:     &Recorder::default(),
+        };
+
+        let mut hooks = Hooks::builtin();
+        hooks.add_markdown_preprocessor(|_, markdown| markdown.replace("TODO", "Done"));
+        hooks.add_html_postprocessor(|context, html| format!("{html}<!-- {} -->", context.source_file.display()));
+        assert_eq!(hooks.preprocess_markdown(&context, String::from("TODO")), "Done");
+        assert_eq!(
+            hooks.postprocess_html(&context, String::from("<p>  a  </p>")),
+            "<p>a<!-- src/index.md -->"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    /// Test that command hooks get their environment, and fail with the
+    /// command
+    async fn test_run_command()
+    {
+        let config = Config::default();
+        let env = || vec![("RAVEN_PAGE_SOURCE", OsString::from("src/index.md"))];
+        let line = "test \"$RAVEN_DEST:$RAVEN_PAGE_SOURCE\" = dest:src/index.md";
+        run_command("post_page", line, &config, env()).await.unwrap();
+        assert!(matches!(
+            run_command("post_page", "exit 3", &config, env()).await,
+            Err(Error::Hook { hook: "post_page", .. })
+        ));
+    }
+}
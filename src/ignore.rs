@@ -0,0 +1,120 @@
+//! Source files that are never built or copied, e.g. editor temp files and
+//! private notes, matched by the patterns in `ignore` and [`IGNORE_FILE`].
+//!
+//! Patterns are relative to the source dir, one per line in the file, which
+//! can have blank lines and `#` comments. `*` matches anything but `/`, `?`
+//! one character, and `**` any number of directories. A pattern without a
+//! `/` (other than a trailing one) matches the file or directory name at any
+//! depth, e.g. `*.bak`, and everything in an ignored directory is ignored.
+
+use regex::Regex;
+
+use crate::{Config, Path};
+
+/// The patterns to ignore, in the project.
+pub const IGNORE_FILE: &str = ".ravenignore";
+
+/// The ignore patterns of a project.
+#[derive(Debug, Clone, Default)]
+pub struct Ignore
+{
+    /// Matched against the path under the source dir.
+    paths: Vec<Regex>,
+
+    /// Matched against the file or directory name.
+    names: Vec<Regex>,
+}
+
+impl Ignore
+{
+    /// The patterns in `ignore` and the [`IGNORE_FILE`], if there is one.
+    pub fn from_config(config: &Config) -> Self
+    {
+        let file = std::fs::read_to_string(IGNORE_FILE).unwrap_or_default();
+        let patterns = config
+            .ignore
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .chain(file.lines())
+            .map(str::trim)
+            .filter(|x| !x.is_empty() && !x.starts_with('#'));
+        Self::new(patterns)
+    }
+
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self
+    {
+        let mut ignore = Self::default();
+        for pattern in patterns {
+            let pattern = pattern.trim_end_matches('/');
+            match pattern.strip_prefix('/') {
+                Some(path) => ignore.paths.push(glob_regex(path)),
+                None if pattern.contains('/') => ignore.paths.push(glob_regex(pattern)),
+                None => ignore.names.push(glob_regex(pattern)),
+            }
+        }
+        ignore
+    }
+
+    /// If the file or directory at `relative`, under the source dir, is
+    /// ignored. Its parent directories aren't checked.
+    pub fn is_ignored(&self, relative: &Path) -> bool
+    {
+        let name = relative.file_name().unwrap_or_default().to_string_lossy();
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        self.names.iter().any(|x| x.is_match(&name)) || self.paths.iter().any(|x| x.is_match(&relative))
+    }
+}
+
+/// A regex matching the paths `glob` does.
+fn glob_regex(glob: &str) -> Regex
+{
+    let mut regex = String::from("^");
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        let (matched, len) = if rest.starts_with("**/") {
+            ("(?:.*/)?", 3)
+        }
+        else if rest.starts_with("**") {
+            (".*", 2)
+        }
+        else {
+            match c {
+                '*' => ("[^/]*", 1),
+                '?' => ("[^/]", 1),
+                _ => {
+                    regex.push_str(&regex::escape(&rest[..c.len_utf8()]));
+                    rest = &rest[c.len_utf8()..];
+                    continue;
+                }
+            }
+        };
+        regex.push_str(matched);
+        rest = &rest[len..];
+    }
+    regex.push('$');
+    // Everything but the wildcards is escaped, so the regex is valid.
+    Regex::new(&regex).unwrap()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that names match at any depth, and paths from the source dir
+    fn test_is_ignored()
+    {
+        let ignore = Ignore::new(["drafts/**", "*.bak", ".#*", "/notes/", "a?c.md"]);
+        assert!(ignore.is_ignored(Path::new("drafts/post.md")));
+        assert!(ignore.is_ignored(Path::new("drafts/2024/post.md")));
+        assert!(!ignore.is_ignored(Path::new("blog/drafts.md")));
+        assert!(ignore.is_ignored(Path::new("blog/index.md.bak")));
+        assert!(ignore.is_ignored(Path::new("blog/.#index.md")));
+        assert!(ignore.is_ignored(Path::new("notes")));
+        assert!(!ignore.is_ignored(Path::new("blog/notes")));
+        assert!(ignore.is_ignored(Path::new("abc.md")));
+        assert!(!ignore.is_ignored(Path::new("blog/abc.md.html")));
+    }
+}
@@ -0,0 +1,293 @@
+//! Markdown and code included from other files when a page is built, so
+//! content shared by pages, and examples that are compiled and tested, live in
+//! one file.
+//!
+//! A line that's only `{{ include "partials/warning.md" }}` is replaced with
+//! the file, relative to the project dir, and what it includes, before the
+//! page is parsed. A file that includes itself, directly or through others, is
+//! an error.
+//!
+//! A fence with `file="path"` in its info string, e.g. ```` ```rust
+//! file="examples/demo.rs" lines=10-30 ````, has the contents of the file,
+//! relative to the project dir, instead of its own. `lines` limits them to a
+//! range of lines, counted from 1: `10-30`, `10-`, `-30`, or `12`. Pages are
+//! rebuilt when a file they include changes.
+
+use std::{fs, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::{
+    fences::{closes, opening, Fences},
+    shortcodes::Arguments,
+    Error, Path, PathBuf, Result,
+};
+
+/// The file an include directive, `line`, includes, if it's one.
+fn directive(line: &str) -> Option<PathBuf>
+{
+    let directive = line.trim().strip_prefix("{{")?.strip_suffix("}}")?.trim();
+    let arguments = directive.strip_prefix("include")?;
+    if !arguments.starts_with(|c: char| c.is_whitespace() || c == '"') {
+        return None;
+    }
+    let arguments = Arguments::parse(arguments);
+    arguments
+        .positional
+        .first()
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+}
+
+/// `source`, of the page at `source_file`, with the files it includes in it.
+///
+/// # Errors
+///
+/// Will return an error if an included file cannot be read, includes itself,
+/// or doesn't have the lines given.
+pub fn expand(source: &str, source_file: &Path) -> Result<String>
+{
+    let source = transclude(source, &mut vec![source_file.to_path_buf()]).map_err(|err| {
+        Error::Include {
+            path: source_file.to_path_buf(),
+            err,
+        }
+    })?;
+    include_code(&source, source_file)
+}
+
+/// `source` with its include directives replaced with the files they
+/// include. `stack` is the file `source` is from, and the files that include
+/// it.
+fn transclude(source: &str, stack: &mut Vec<PathBuf>) -> std::result::Result<String, String>
+{
+    let mut output = String::with_capacity(source.len());
+    let mut fences = Fences::default();
+    for line in source.split_inclusive('\n') {
+        if fences.contains(line) {
+            output.push_str(line);
+            continue;
+        }
+        let Some(file) = directive(line)
+        else {
+            output.push_str(line);
+            continue;
+        };
+
+        if let Some(start) = stack.iter().position(|x| normalize(x) == normalize(&file)) {
+            let chain = stack[start..]
+                .iter()
+                .chain([&file])
+                .map(|x| format!("\"{}\"", x.display()))
+                .collect::<Vec<_>>();
+            return Err(format!("Includes itself: {}", chain.join(" includes ")));
+        }
+        let contents =
+            fs::read_to_string(&file).map_err(|e| format!("Couldn't include \"{}\": {e}", file.display()))?;
+        stack.push(file);
+        let contents = transclude(&contents, stack)?;
+        stack.pop();
+        // Included in a list item, it's indented like the directive.
+        let indent = &line[..line.len() - line.trim_start().len()];
+        for line in contents.lines() {
+            output.push_str(&format!("{indent}{line}\n"));
+        }
+    }
+    Ok(output)
+}
+
+/// `source` with the code blocks that include a file given its contents.
+fn include_code(source: &str, source_file: &Path) -> Result<String>
+{
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    let attribute = ATTRIBUTE.get_or_init(|| Regex::new(r#"\s*\b(?:file|lines)=(?:"[^"]*"|\S*)"#).unwrap());
+
+    let mut output = String::with_capacity(source.len());
+    let mut lines = source.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let Some((fence, info)) = opening(line)
+        else {
+            output.push_str(line);
+            continue;
+        };
+        let arguments = Arguments::parse(info);
+        let Some(file) = arguments.named.get("file")
+        else {
+            // Nothing in an ordinary code block is an opening fence.
+            output.push_str(line);
+            for line in lines.by_ref() {
+                output.push_str(line);
+                if closes(line, fence) {
+                    break;
+                }
+            }
+            continue;
+        };
+
+        let contents = include(Path::new(file), arguments.named.get("lines").map(String::as_str)).map_err(|err| {
+            Error::Include {
+                path: source_file.to_path_buf(),
+                err,
+            }
+        })?;
+        // The block's own contents are replaced.
+        lines.by_ref().find(|x| closes(x, fence));
+
+        // The fence must be longer than any run of its character in the file.
+        let c = &fence[..1];
+        let longest = contents
+            .split(|x| !c.starts_with(x))
+            .map(str::len)
+            .max()
+            .unwrap_or_default();
+        let fence = c.repeat(fence.len().max(longest + 1));
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let info = attribute.replace_all(info.trim_end(), "");
+        output.push_str(&format!("{indent}{fence}{info}\n"));
+        for line in contents.lines() {
+            output.push_str(&format!("{indent}{line}\n"));
+        }
+        output.push_str(&format!("{indent}{fence}\n"));
+    }
+    Ok(output)
+}
+
+/// The files `source` includes, and the files they include.
+pub fn files(source: &str) -> Vec<PathBuf>
+{
+    let mut files = Vec::new();
+    included_files(source, &mut files);
+    files
+}
+
+fn included_files(source: &str, files: &mut Vec<PathBuf>)
+{
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        if let Some((fence, info)) = opening(line) {
+            files.extend(Arguments::parse(info).named.remove("file").map(PathBuf::from));
+            lines.by_ref().find(|x| closes(x, fence));
+        }
+        else if let Some(file) = directive(line).filter(|x| !files.contains(x)) {
+            let contents = fs::read_to_string(&file).unwrap_or_default();
+            files.push(file);
+            included_files(&contents, files);
+        }
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf { path.canonicalize().unwrap_or_else(|_| path.to_path_buf()) }
+
+/// The contents of `file`, or the `lines` of it.
+fn include(file: &Path, lines: Option<&str>) -> std::result::Result<String, String>
+{
+    let contents = fs::read_to_string(file).map_err(|e| format!("Couldn't include \"{}\": {e}", file.display()))?;
+    let Some(range) = lines
+    else {
+        return Ok(contents);
+    };
+
+    let count = contents.lines().count();
+    let parse = |x: &str, default| {
+        match x.trim() {
+            "" => Ok(default),
+            x => {
+                x.parse::<usize>()
+                    .map_err(|_| format!("\"{range}\" isn't a range of lines"))
+            }
+        }
+    };
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse(start, 1)?, parse(end, count)?),
+        None => {
+            let line = parse(range, 0)?;
+            (line, line)
+        }
+    };
+    if start == 0 || start > end {
+        return Err(format!("\"{range}\" isn't a range of lines"));
+    }
+    if end > count {
+        return Err(format!(
+            "\"{}\" has {count} lines, so it doesn't have lines {range}",
+            file.display()
+        ));
+    }
+    Ok(contents
+        .lines()
+        .skip(start - 1)
+        .take(end - start + 1)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that included code replaces the block's own, keeping its
+    /// language and indent
+    fn test_expand()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/includes");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("demo.rs");
+        fs::write(&file, "// ```\nfn main()\n{\n    demo();\n}\n").unwrap();
+
+        let source = format!(
+            "- Step\n\n  ```rust file=\"{}\" lines=2-3\n  old\n  ```\n\n```\n```rust file=x\n```\n",
+            file.display()
+        );
+        assert_eq!(
+            expand(&source, Path::new("page.md")).unwrap(),
+            "- Step\n\n  ```rust\n  fn main()\n  {\n  ```\n\n```\n```rust file=x\n```\n"
+        );
+        assert_eq!(files(&source), vec![file.clone()]);
+
+        let source = format!("```rust file=\"{}\" lines=-2\n```\n", file.display());
+        assert!(expand(&source, Path::new("page.md"))
+            .unwrap()
+            .ends_with("```\nfn main()\n````\n"));
+        for lines in ["5-6", "3-2", "0", "a"] {
+            let source = format!("```rust file=\"{}\" lines={lines}\n```\n", file.display());
+            assert!(expand(&source, Path::new("page.md")).is_err());
+        }
+        assert!(expand("```rust file=\"missing.rs\"\n```\n", Path::new("page.md")).is_err());
+    }
+
+    #[test]
+    /// Test that included markdown is expanded, nested and indented, and
+    /// that cycles are errors
+    fn test_transclude()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/transclude");
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+        fs::write(dir.join("notice.md"), "> **Note**\n> {{ include \"x\" }}\n").unwrap();
+        fs::write(
+            dir.join("install.md"),
+            format!("Run:\n{{{{ include \"{}\" }}}}\n", path("notice.md")),
+        )
+        .unwrap();
+
+        let source = format!(
+            "# Setup\n\n- Step\n\n  {{{{include \"{}\"}}}}\n\n```\n{{{{ include \"x\" }}}}\n```\n",
+            path("install.md")
+        );
+        assert_eq!(
+            expand(&source, Path::new("page.md")).unwrap(),
+            "# Setup\n\n- Step\n\n  Run:\n  > **Note**\n  > {{ include \"x\" }}\n\n```\n{{ include \"x\" }}\n```\n"
+        );
+        assert_eq!(files(&source), vec![dir.join("install.md"), dir.join("notice.md")]);
+
+        fs::write(dir.join("a.md"), format!("{{{{ include \"{}\" }}}}\n", path("b.md"))).unwrap();
+        fs::write(dir.join("b.md"), format!("{{{{ include \"{}\" }}}}\n", path("a.md"))).unwrap();
+        let source = format!("{{{{ include \"{}\" }}}}\n", path("a.md"));
+        assert!(expand(&source, Path::new("page.md")).is_err());
+        assert_eq!(files(&source).len(), 2);
+        assert!(expand("{{ include \"missing.md\" }}\n", Path::new("page.md")).is_err());
+        assert!(directive("{{ includes \"missing.md\" }}").is_none());
+    }
+}
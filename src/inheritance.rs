@@ -0,0 +1,148 @@
+//! Templates that extend a base template, so the chrome shared by every
+//! template lives in one file.
+//!
+//! A base template marks what others can replace with
+//! `[/rustic_block name/]...[/rustic_endblock/]`, the contents being used
+//! unless a template extending it has its own block of that name. A template
+//! that starts with `[/rustic_extends base.html/]` only has blocks, and
+//! anything outside them is left out. In a block, `[/rustic_super/]` is
+//! replaced with the block it replaces. A base template can extend another.
+
+use std::collections::BTreeMap;
+
+use crate::{placeholders::TOKEN_SUFFIX, Config, Path, PathBuf};
+
+const EXTENDS: &str = "[/rustic_extends ";
+const BLOCK: &str = "[/rustic_block ";
+const END_BLOCK: &str = "[/rustic_endblock/]";
+const SUPER: &str = "[/rustic_super/]";
+
+/// The path of the template `template` extends, if it does.
+pub fn extends(template: &str) -> Option<&str>
+{
+    let rest = template.trim_start().strip_prefix(EXTENDS)?;
+    let end = rest.find(TOKEN_SUFFIX)?;
+    Some(rest[..end].trim())
+}
+
+/// The templates the template at `path` extends, nearest first. The chain
+/// stops at a template that can't be read.
+pub fn bases(config: &Config, path: &Path) -> Vec<PathBuf>
+{
+    let mut bases: Vec<PathBuf> = Vec::new();
+    let mut contents = std::fs::read_to_string(path).unwrap_or_default();
+    while let Some(base) = extends(&contents) {
+        let base = config.resolve(Path::new(base));
+        if base == path || bases.contains(&base) {
+            break;
+        }
+        contents = std::fs::read_to_string(&base).unwrap_or_default();
+        bases.push(base);
+    }
+    bases
+}
+
+/// A template and the templates it extends, nearest first, as one template.
+///
+/// # Errors
+///
+/// Returns a description of the problem if a block isn't closed, or is in
+/// another.
+pub fn merge(templates: &[String]) -> Result<String, String>
+{
+    let Some((base, extending)) = templates.split_last()
+    else {
+        return Ok(String::new());
+    };
+    let mut overrides: BTreeMap<String, String> = BTreeMap::new();
+    for template in extending {
+        for (name, contents) in blocks(template)? {
+            match overrides.get_mut(&name) {
+                Some(nearer) => *nearer = nearer.replace(SUPER, &contents),
+                None => {
+                    overrides.insert(name, contents);
+                }
+            }
+        }
+    }
+
+    let mut merged = String::with_capacity(base.len());
+    let mut rest = base.as_str();
+    while let Some((before, name, contents, after)) = next_block(rest)? {
+        merged.push_str(before);
+        match overrides.get(name) {
+            Some(nearer) => merged.push_str(&nearer.replace(SUPER, contents)),
+            None => merged.push_str(contents),
+        }
+        rest = after;
+    }
+    merged.push_str(rest);
+    Ok(merged)
+}
+
+/// The blocks of `template`, by their name.
+fn blocks(template: &str) -> Result<BTreeMap<String, String>, String>
+{
+    let mut blocks = BTreeMap::new();
+    let mut rest = template;
+    while let Some((_, name, contents, after)) = next_block(rest)? {
+        blocks.insert(name.to_string(), contents.to_string());
+        rest = after;
+    }
+    Ok(blocks)
+}
+
+/// What's before the first block in `template`, its name and contents, and
+/// what's after it.
+fn next_block(template: &str) -> Result<Option<(&str, &str, &str, &str)>, String>
+{
+    let Some(start) = template.find(BLOCK)
+    else {
+        return Ok(None);
+    };
+    let (before, rest) = template.split_at(start);
+    let rest = &rest[BLOCK.len()..];
+    let name_end = rest
+        .find(TOKEN_SUFFIX)
+        .ok_or_else(|| String::from("A block's name isn't closed"))?;
+    let name = rest[..name_end].trim();
+    let rest = &rest[name_end + TOKEN_SUFFIX.len()..];
+    let end = rest
+        .find(END_BLOCK)
+        .ok_or_else(|| format!("The block \"{name}\" has no {END_BLOCK}"))?;
+    let contents = &rest[..end];
+    if contents.contains(BLOCK) {
+        return Err(format!("The block \"{name}\" has a block in it"));
+    }
+    Ok(Some((before, name, contents, &rest[end + END_BLOCK.len()..])))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that the nearest template's blocks win, and `super` is the block
+    /// they replace
+    fn test_merge()
+    {
+        let base = "<title>[/rustic_block title/][/rustic_title/][/rustic_endblock/]</title><main>[/rustic_block \
+                    main/][/rustic_body/][/rustic_endblock/]</main>";
+        let section = "[/rustic_extends base.html/][/rustic_block title/]Blog: [/rustic_super/][/rustic_endblock/]";
+        let post = "[/rustic_extends blog.html/]ignored[/rustic_block title/]Post | \
+                    [/rustic_super/][/rustic_endblock/][/rustic_block \
+                    main/]<article>[/rustic_body/]</article>[/rustic_endblock/]";
+        assert_eq!(extends(post), Some("blog.html"));
+        assert_eq!(extends(base), None);
+        assert_eq!(
+            merge(&[post.to_string(), section.to_string(), base.to_string()]).unwrap(),
+            "<title>Post | Blog: [/rustic_title/]</title><main><article>[/rustic_body/]</article></main>"
+        );
+        assert_eq!(
+            merge(&[base.to_string()]).unwrap(),
+            "<title>[/rustic_title/]</title><main>[/rustic_body/]</main>"
+        );
+        assert!(merge(&["[/rustic_block a/]".to_string()]).is_err());
+    }
+}
@@ -1,17 +1,85 @@
 use std::path::{Path, PathBuf};
 
+pub(crate) mod admonitions;
+pub mod analytics;
+pub mod asset_cache;
+pub mod book;
 pub mod build;
+pub mod builder;
+pub(crate) mod cancel;
+pub mod check;
+pub mod cms;
 pub mod config;
 pub mod defaults;
+pub(crate) mod deflists;
+pub mod deploy;
+pub mod doctor;
+pub mod epub;
 pub mod error;
+pub(crate) mod extensions;
+pub mod external_links;
+pub mod features;
+pub mod feeds;
+pub(crate) mod fences;
+pub mod fetch;
+pub mod graph;
+pub(crate) mod hash;
+pub mod headless;
+pub mod hooks;
+pub(crate) mod ignore;
+pub mod includes;
+pub mod inheritance;
+pub mod links;
+pub(crate) mod lock;
+pub mod markdown;
+pub mod mdbook;
+pub mod migrate;
+pub(crate) mod mime;
+pub mod minify;
+pub mod offline;
+pub mod og;
+pub mod page;
+pub mod placeholders;
+pub mod redirects;
+pub mod report;
+pub(crate) mod sass;
+pub mod sections;
+pub mod shortcodes;
+pub mod shorturls;
+pub mod snapshot;
+pub(crate) mod space;
+pub mod starters;
+pub mod stats;
+pub(crate) mod styles;
+pub(crate) mod syntax_cache;
+pub mod theme;
+pub mod timings;
+pub(crate) mod variables;
+pub mod watch;
+pub mod wikilinks;
+pub use builder::WebsiteBuilder;
+pub use cancel::Cancellation;
 pub use config::*;
 pub use error::*;
+use starters::StarterSource;
 
 pub const NAME: &str = "RusticRaven";
 pub const DESC: &str = "A static html generator";
 
 /// Initialize a directiory with the defualt doodads
 ///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - A configuration file cannot be written to.
+/// - A directory or file cannot be made or written to.
+pub async fn init(config: Config) -> Result<()> { init_from_starter(config, &StarterSource::Bundled("default")).await }
+
+/// Initialize a directiory with the files of `starter`, and `config`
+/// (usually derived from `starter.config()`) unless the starter has its own
+/// configuration file.
+///
 /// # Panics
 ///
 /// Will panic if:
@@ -24,7 +92,7 @@ pub const DESC: &str = "A static html generator";
 ///
 /// - A configuration file cannot be written to.
 /// - A directory or file cannot be made or written to.
-pub async fn init(config: Config) -> Result<()>
+pub async fn init_from_starter(config: Config, starter: &StarterSource) -> Result<()>
 {
     use std::io::Write;
 
@@ -35,83 +103,41 @@ pub async fn init(config: Config) -> Result<()>
         return Ok(());
     }
 
-    // Open a new conf file.
-    let f = fs::File::create(&configuration_file_path).await.map_err(|e| {
-        Error::Io {
-            err:  e,
-            path: configuration_file_path.clone(),
-        }
-    })?;
-    println!("Created: \"{}\"", configuration_file_path.display());
-
-    // Serialize the defualt values, then write it to the new config file;
-    let toml = toml::to_string_pretty(&config).unwrap();
-    f.into_std().await.write_all(toml.as_bytes()).map_err(|e| {
-        Error::Io {
-            err:  e,
-            path: configuration_file_path,
-        }
-    })?;
-
-    // create dirs
-    let source = config.source;
-    let dest = config.dest;
-    let syntaxes = config.syntaxes;
-    let custom_syntax_themes = config.custom_syntax_themes;
-    fs::create_dir(&source).await.map_err(|e| {
-        Error::Io {
-            err:  e,
-            path: source.clone(),
-        }
-    })?;
-    println!("Created: \"{}\"", source.display());
-    fs::create_dir(&dest).await.map_err(|e| {
-        Error::Io {
-            err:  e,
-            path: dest.clone(),
-        }
-    })?;
-    println!("Created: \"{}\"", dest.display());
-    fs::create_dir(&syntaxes).await.map_err(|e| {
-        Error::Io {
-            err:  e,
-            path: syntaxes.clone(),
-        }
-    })?;
-    println!("Created: \"{}\"", syntaxes.display());
-    fs::create_dir(&custom_syntax_themes).await.map_err(|e| {
-        Error::Io {
-            err:  e,
-            path: custom_syntax_themes.clone(),
-        }
-    })?;
-    println!("Created: \"{}\"", custom_syntax_themes.display());
-    fs::write("template.html", defaults::DEFAULT_HTML_TEMPLATE_SRC)
-        .await
-        .map_err(|e| {
+    // A starter with its own configuration file is copied as is.
+    if starter.config_file().is_none() {
+        // Open a new conf file.
+        let f = fs::File::create(&configuration_file_path).await.map_err(|e| {
             Error::Io {
                 err:  e,
-                path: PathBuf::from("template.html"),
+                path: configuration_file_path.clone(),
             }
         })?;
-    println!("Created: \"template.html\"");
-    fs::write("style.css", defaults::DEFAULT_CSS_STYLESHEET_SRC)
-        .await
-        .map_err(|e| {
+        println!("Created: \"{}\"", configuration_file_path.display());
+
+        // Serialize the defualt values, then write it to the new config file;
+        let toml = toml::to_string_pretty(&config).unwrap();
+        f.into_std().await.write_all(toml.as_bytes()).map_err(|e| {
             Error::Io {
                 err:  e,
-                path: PathBuf::from("style.css"),
+                path: configuration_file_path,
             }
         })?;
-    println!("Created: \"style.css\"");
-    fs::write("src/index.md", defaults::DEFAULT_MD_STARTER_SRC)
-        .await
-        .map_err(|e| {
+    }
+
+    // create dirs
+    let source = &config.source;
+    let dest = &config.dest;
+    let syntaxes = &config.syntaxes;
+    let custom_syntax_themes = &config.custom_syntax_themes;
+    for dir in [source, dest, syntaxes, custom_syntax_themes] {
+        fs::create_dir_all(dir).await.map_err(|e| {
             Error::Io {
                 err:  e,
-                path: PathBuf::from("src/index.md"),
+                path: dir.clone(),
             }
         })?;
-    println!("Created: \"src/index.md\"");
-    Ok(())
+        println!("Created: \"{}\"", dir.display());
+    }
+
+    starter.write(&config)
 }
@@ -0,0 +1,206 @@
+//! Stable page IDs, and the `{{ link id="..." }}` references that resolve to
+//! the page with an ID wherever its file is moved.
+//!
+//! A page gets an ID with `id = "getting-started"` in its page info. A
+//! reference outside of fenced code blocks is replaced with the page's URL,
+//! relative to the page it's on, before the markdown is parsed, so it can be
+//! used as a link destination: `[Start here]({{ link id="getting-started" }})`.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    build::dest_path, fences::Fences, graph::DependencyGraph, shortcodes::Arguments, Config, Error, Path, PathBuf,
+    Result,
+};
+
+const OPEN: &str = "{{ link ";
+const CLOSE: &str = "}}";
+
+/// The pages of a project that have an ID, keyed by it.
+#[derive(Debug, Clone, Default)]
+pub struct PageIndex
+{
+    ids: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl PageIndex
+{
+    /// Index the pages of the configured source directory. Pages that cannot
+    /// be read are left out, their build fails on its own.
+    pub fn from_config(config: &Config) -> Self
+    {
+        let mut index = Self::default();
+        if let Ok(graph) = DependencyGraph::from_config(config) {
+            index.extend(&graph);
+        }
+        index
+    }
+
+    /// Add the pages of `graph` that have an ID.
+    pub fn extend(&mut self, graph: &DependencyGraph)
+    {
+        for (source_file, page) in &graph.pages {
+            if let Some(id) = &page.id {
+                self.ids.entry(id.clone()).or_default().push(source_file.clone());
+            }
+        }
+    }
+
+    /// The source file of the page with the ID `id`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if no page, or more than one, has the ID.
+    pub fn get(&self, id: &str, source_path: &Path) -> Result<&Path>
+    {
+        match self.ids.get(id).map(Vec::as_slice) {
+            Some([page]) => Ok(page),
+            Some(pages) => Err(duplicate(id, pages)),
+            None => {
+                Err(Error::UnresolvedReference {
+                    reference: id.to_string(),
+                    path:      source_path.to_path_buf(),
+                })
+            }
+        }
+    }
+
+    /// Each ID and the source file of the one page that has it.
+    pub fn pages(&self) -> impl Iterator<Item = (&str, &Path)>
+    {
+        self.ids.iter().filter_map(|(id, pages)| {
+            match pages.as_slice() {
+                [page] => Some((id.as_str(), page.as_path())),
+                _ => None,
+            }
+        })
+    }
+
+    /// An error for each ID that more than one page has.
+    pub fn duplicates(&self) -> Vec<Error>
+    {
+        self.ids
+            .iter()
+            .filter(|(_, pages)| pages.len() > 1)
+            .map(|(id, pages)| duplicate(id, pages))
+            .collect()
+    }
+
+    /// Replace every `{{ link id="..." }}` in the markdown `source` of the
+    /// page at `source_path` with the URL of the page with that ID.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a reference has no ID, or its ID isn't the
+    /// ID of exactly one page.
+    pub fn resolve_links(&self, source: &str, source_path: &Path, config: &Config) -> Result<String>
+    {
+        if !source.contains(OPEN) {
+            return Ok(source.to_string());
+        }
+
+        let dest_of = |source_file: &Path| {
+            let extention = source_file
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            dest_path(config, source_file, &extention)
+        };
+        let from = dest_of(source_path)?;
+        let mut output = String::with_capacity(source.len());
+        let mut fences = Fences::default();
+        for line in source.split_inclusive('\n') {
+            if fences.contains(line) {
+                output.push_str(line);
+                continue;
+            }
+
+            let mut rest = line;
+            while let Some(start) = rest.find(OPEN) {
+                let Some(len) = rest[start..].find(CLOSE)
+                else {
+                    break;
+                };
+                let arguments = Arguments::parse(&rest[start + OPEN.len()..start + len]);
+                let id = arguments.first_or("id").ok_or_else(|| {
+                    Error::Shortcode {
+                        err:  String::from("\"link\" needs the ID of a page"),
+                        path: source_path.to_path_buf(),
+                    }
+                })?;
+                let to = dest_of(self.get(id, source_path)?)?;
+                output.push_str(&rest[..start]);
+                output.push_str(&relative_url(&from, &to));
+                rest = &rest[start + len + CLOSE.len()..];
+            }
+            output.push_str(rest);
+        }
+        Ok(output)
+    }
+}
+
+fn duplicate(id: &str, pages: &[PathBuf]) -> Error
+{
+    Error::DuplicatePageId {
+        id:    id.to_string(),
+        pages: pages
+            .iter()
+            .map(|x| format!("\"{}\"", x.display()))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// The URL of the file at `to` from the page at `from`, both in the dest
+/// dir.
+pub(crate) fn relative_url(from: &Path, to: &Path) -> String
+{
+    let from = from.parent().unwrap_or(Path::new("")).components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut url = "../".repeat(from.len() - common);
+    url.push_str(
+        &to[common..]
+            .iter()
+            .map(|x| x.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    );
+    url
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that references resolve to the page with the ID, relative to the
+    /// page they're on, and that unknown and shared IDs are errors
+    fn test_resolve_links()
+    {
+        let mut index = PageIndex::default();
+        index
+            .ids
+            .insert(String::from("start"), vec![PathBuf::from("src/docs/start.md")]);
+        index.ids.insert(
+            String::from("twice"),
+            vec![PathBuf::from("src/a.md"), PathBuf::from("src/b.md")],
+        );
+        let config = Config::default();
+        let source = "[Start]({{ link id=\"start\" }})\n```\n{{ link id=\"start\" }}\n```\n";
+        assert_eq!(
+            index
+                .resolve_links(source, Path::new("src/blog/post.md"), &config)
+                .unwrap(),
+            "[Start](../docs/start.html)\n```\n{{ link id=\"start\" }}\n```\n"
+        );
+        assert!(index
+            .resolve_links("{{ link missing }}", Path::new("src/index.md"), &config)
+            .is_err());
+        assert!(index
+            .resolve_links("{{ link twice }}", Path::new("src/index.md"), &config)
+            .is_err());
+        assert_eq!(index.duplicates().len(), 1);
+    }
+}
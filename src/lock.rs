@@ -0,0 +1,142 @@
+//! The lock a build holds, so two builds of a project don't race on its dest
+//! dir.
+//!
+//! [`acquire`] takes an OS lock on [`LOCK_FILE`] in the project, which the
+//! [`BuildLock`] it returns releases when the build is done. The OS releases
+//! it when the process exits however it does, e.g. after a second Ctrl-C, so
+//! a crashed build never leaves the project locked.
+
+use std::{fs::File, time::Duration};
+
+use fs2::FileExt;
+
+use crate::{cancel::Cancellation, Error, Path, Result};
+
+/// Where the lock is kept, relative to the project.
+pub const LOCK_FILE: &str = ".raven.lock";
+
+/// How often a waiting build checks if the lock was released.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Held while a build runs, releasing the lock when dropped.
+#[derive(Debug)]
+pub(crate) struct BuildLock
+{
+    file: File,
+}
+
+impl Drop for BuildLock
+{
+    fn drop(&mut self) { let _ = self.file.unlock(); }
+}
+
+/// Take the lock at `path`, waiting for another build to release it if `wait`
+/// is set, until the build is cancelled.
+///
+/// The file is left in place when the lock is released: a build that opened
+/// it before it was deleted could lock it while another locks a new one.
+///
+/// # Errors
+///
+/// Will return an error if another build holds the lock and builds don't
+/// wait, the build is cancelled while waiting, or the lock cannot be opened.
+pub(crate) async fn acquire(path: &Path, wait: bool, cancellation: &Cancellation) -> Result<BuildLock>
+{
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: path.to_path_buf(),
+            }
+        })?;
+    loop {
+        match file.try_lock_exclusive() {
+            Ok(()) => return Ok(BuildLock { file }),
+            Err(e) if e.kind() == fs2::lock_contended_error().kind() => (),
+            Err(e) => {
+                return Err(Error::Io {
+                    err:  e,
+                    path: path.to_path_buf(),
+                })
+            }
+        }
+        if !wait {
+            return Err(Error::BuildLocked(path.to_path_buf()));
+        }
+        if cancellation.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        tokio::task::spawn_blocking(|| std::thread::sleep(POLL_INTERVAL))
+            .await
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn lock_path(name: &str) -> std::path::PathBuf
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(LOCK_FILE)
+    }
+
+    #[tokio::test]
+    /// Test that a build doesn't take the lock while another holds it
+    async fn test_locked()
+    {
+        let path = lock_path("lock-locked");
+        let cancellation = Cancellation::default();
+        let _lock = acquire(&path, false, &cancellation).await.unwrap();
+        assert!(matches!(
+            acquire(&path, false, &cancellation).await,
+            Err(Error::BuildLocked(_))
+        ));
+    }
+
+    #[tokio::test]
+    /// Test that the lock can be taken again once it's released
+    async fn test_released()
+    {
+        let path = lock_path("lock-released");
+        let cancellation = Cancellation::default();
+        drop(acquire(&path, false, &cancellation).await.unwrap());
+        assert!(acquire(&path, false, &cancellation).await.is_ok());
+    }
+
+    #[tokio::test]
+    /// Test that a waiting build takes the lock once it's released
+    async fn test_wait()
+    {
+        let path = lock_path("lock-wait");
+        let cancellation = Cancellation::default();
+        let lock = acquire(&path, false, &cancellation).await.unwrap();
+        std::thread::spawn(move || {
+            std::thread::sleep(POLL_INTERVAL * 2);
+            drop(lock);
+        });
+        assert!(acquire(&path, true, &cancellation).await.is_ok());
+    }
+
+    #[tokio::test]
+    /// Test that a waiting build stops waiting once it's cancelled
+    async fn test_wait_cancelled()
+    {
+        let path = lock_path("lock-wait-cancelled");
+        let cancellation = Cancellation::default();
+        let _lock = acquire(&path, false, &cancellation).await.unwrap();
+        cancellation.cancel();
+        assert!(matches!(
+            acquire(&path, true, &cancellation).await,
+            Err(Error::Cancelled)
+        ));
+    }
+}
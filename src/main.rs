@@ -1,13 +1,19 @@
-use std::{path::PathBuf, sync::Arc};
+use std::path::{Path, PathBuf};
 
-use build::{build, get_syntaxes, Website};
-use dashmap::DashMap;
+use build::{build, build_with_report, Website};
+use features::FeatureReport;
+use graph::{DependencyGraph, UnusedReport};
 use indicatif::{ProgressIterator, ProgressStyle};
+use report::BuildReport;
 pub use rustic_raven::*;
+use starters::StarterSource;
+use stats::SiteStats;
 use structopt::StructOpt;
 use tokio::fs;
-use walkdir::{DirEntry, WalkDir};
+use walkdir::WalkDir;
 
+/// How many of the slowest pages `raven build --timings` prints.
+const TIMINGS_TABLE_PAGES: usize = 20;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -37,6 +43,15 @@ enum Options
         /// The name of the custom syntax themes directory
         #[structopt(short = "t", long = "syntax_themes")]
         syntax_themes: Option<String>,
+
+        /// The starter to initialize the project from: default, blog, docs,
+        /// portfolio, multilingual, or the path of a directory
+        #[structopt(long = "template", default_value = "default")]
+        template: String,
+
+        /// Clone a theme from this git URL into the project and use it
+        #[structopt(long = "from")]
+        from: Option<String>,
     },
 
     /// Initialize a new project
@@ -45,6 +60,16 @@ enum Options
         /// The project directory
         #[structopt(default_value = ".")]
         directory: PathBuf,
+
+        /// The starter to initialize the project from: default, blog, docs,
+        /// portfolio, multilingual, mdbook, or the path of a directory
+        #[structopt(long = "template", default_value = "default")]
+        template: String,
+
+        /// Set up the existing mdBook project in the directory, configured
+        /// from its book.toml
+        #[structopt(long = "mdbook", conflicts_with = "template")]
+        mdbook: bool,
     },
 
     /// Build static HTML from an existing project
@@ -58,9 +83,69 @@ enum Options
         #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
         config_path: PathBuf,
 
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
         /// Rebuild all file regardless of if the sources have been modified
         #[structopt(long = "rebuild_all", short = "a")]
         rebuild_all: bool,
+
+        /// How many pages to build at once, the number of CPUs by default
+        #[structopt(long = "jobs", short = "j")]
+        jobs: Option<usize>,
+
+        /// Stop at the first page that fails to build, instead of building
+        /// the rest and reporting every failure
+        #[structopt(long = "fail-fast")]
+        fail_fast: bool,
+
+        /// Fail the pages that have warnings, e.g. for CI
+        #[structopt(long = "strict")]
+        strict: bool,
+
+        /// Also build the pages dated in the future
+        #[structopt(long = "future")]
+        future: bool,
+
+        /// Time each stage of building every page, and print the slowest
+        /// pages. They're also in the `--report`
+        #[structopt(long = "timings")]
+        timings: bool,
+
+        /// Walk into symlinked directories in the source dir
+        #[structopt(long = "follow-symlinks")]
+        follow_symlinks: bool,
+
+        /// How symlinked files that are copied as they are get written
+        #[structopt(long = "symlinks", possible_values = &["copy", "link"])]
+        symlinks: Option<String>,
+
+        /// Wait for another build of the project to finish, instead of
+        /// failing
+        #[structopt(long = "wait")]
+        wait: bool,
+
+        /// Use only what's already in the fetch cache, however old, and fail
+        /// for anything that isn't, instead of fetching over the network
+        #[structopt(long = "offline")]
+        offline: bool,
+
+        /// Sync the pages of the configured headless CMS before building
+        #[structopt(long = "sync")]
+        sync: bool,
+
+        /// Write a report of the build in this format
+        #[structopt(long = "report", possible_values = &["json"])]
+        report: Option<String>,
+
+        /// Where to write the report, stdout by default
+        #[structopt(long = "report_file")]
+        report_file: Option<PathBuf>,
+
+        /// Run even if the dest dir overlaps the source dir or the project
+        #[structopt(long = "force")]
+        force: bool,
     },
 
     /// Clean the dest dir of generated files and directories
@@ -73,6 +158,266 @@ enum Options
         /// Provide an alternate config file path
         #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
         config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
+        /// Print what would be deleted without deleting it
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+
+        /// Only delete files that no source generates anymore
+        #[structopt(long = "orphans")]
+        orphans: bool,
+
+        /// Run even if the dest dir overlaps the source dir or the project
+        #[structopt(long = "force")]
+        force: bool,
+    },
+
+    /// Build a project, then publish the dest dir to the configured deploy
+    /// target
+    Deploy
+    {
+        /// The project directory
+        #[structopt(default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
+        /// Publish the dest dir as it is, without building first
+        #[structopt(long = "skip_build")]
+        skip_build: bool,
+
+        /// Run even if the dest dir overlaps the source dir or the project
+        #[structopt(long = "force")]
+        force: bool,
+    },
+
+    /// Build a project, then rebuild it whenever a file it's built from
+    /// changes, with notices about the edited pages
+    Watch
+    {
+        /// The project directory
+        #[structopt(default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
+        /// Print a word diff of the text of each edited page after it's
+        /// rebuilt
+        #[structopt(long = "diff")]
+        diff: bool,
+    },
+
+    /// Check a project for problems without building it
+    Check
+    {
+        /// The project directory
+        #[structopt(default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
+        /// Report templates, stylesheets, syntaxes, and assets that no page
+        /// references
+        #[structopt(long = "unused")]
+        unused: bool,
+    },
+
+    /// Render a project without writing it and compare every page against
+    /// its snapshot
+    Test
+    {
+        /// The project directory
+        #[structopt(default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
+        /// Write the rendered pages as the new snapshots instead of comparing
+        #[structopt(long = "update")]
+        update: bool,
+    },
+
+    /// Inspect a project and suggest fixes for the problems found
+    Doctor
+    {
+        /// The project directory
+        #[structopt(default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+    },
+
+    /// Report on a project without building it
+    Report
+    {
+        /// The project directory
+        #[structopt(default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
+        /// List the configuration options, shortcodes, page info fields,
+        /// code block languages, and outputs the project uses. It's computed
+        /// from the project's files only.
+        #[structopt(long = "features")]
+        features: bool,
+    },
+
+    /// Print statistics of a project: its pages per section, words, largest
+    /// pages, and output size with and without minification
+    Stats
+    {
+        /// The project directory
+        #[structopt(default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
+        /// Print them as JSON, e.g. to keep track of them over time
+        #[structopt(long = "json")]
+        json: bool,
+    },
+
+    /// Find and replace text across every markdown source, backing up the
+    /// sources it changes
+    MigrateContent
+    {
+        /// The project directory
+        #[structopt(default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+
+        /// Merge the named profile's overlay over the configuration
+        #[structopt(long = "profile")]
+        profile: Option<String>,
+
+        /// The text to find and what to replace it with
+        #[structopt(long = "replace", number_of_values = 2, value_names = &["old", "new"], required = true)]
+        replace: Vec<String>,
+
+        /// Find a regular expression instead of literal text. The
+        /// replacement can use its groups, e.g. `$1`
+        #[structopt(long = "regex")]
+        regex: bool,
+
+        /// Print a diff of each change without writing it
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Manage the pages of a project
+    Page(PageCommand),
+
+    /// Manage the themes of a project
+    Theme(ThemeCommand),
+}
+
+#[derive(Debug, StructOpt)]
+enum PageCommand
+{
+    /// Create a new markdown page from an archetype
+    New
+    {
+        /// The title of the page, its file name is made from it
+        title: String,
+
+        /// The directory under the source dir to create the page in
+        #[structopt(long = "section", short = "s")]
+        section: Option<String>,
+
+        /// The project directory
+        #[structopt(long = "directory", short = "C", default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum ThemeCommand
+{
+    /// Clone a theme from a git repository into the project and use it
+    Install
+    {
+        /// The URL of the theme's git repository
+        url: String,
+
+        /// The name of the theme's directory under `themes/`, made from the
+        /// URL by default
+        #[structopt(long = "name")]
+        name: Option<String>,
+
+        /// The project directory
+        #[structopt(long = "directory", short = "C", default_value = ".")]
+        directory: PathBuf,
+
+        /// Provide an alternate config file path
+        #[structopt(long = "config", default_value = Config::DEFAULT_CONFIG_FILE)]
+        config_path: PathBuf,
+    },
+
+    /// Build the sample pages with a theme, to check its layouts against
+    /// every kind of content
+    Dev
+    {
+        /// The theme's directory
+        #[structopt(default_value = ".")]
+        theme: PathBuf,
+
+        /// Where to write the built pages
+        #[structopt(long = "dest", default_value = "theme-preview")]
+        dest: PathBuf,
     },
 }
 
@@ -89,35 +434,348 @@ async fn main() -> error::Result<()>
     }));
 
     match &options {
-        Options::Init { directory } => {
+        Options::Init {
+            directory,
+            mdbook: true,
+            ..
+        } => {
+            std::env::set_current_dir(directory).unwrap();
+            Error::unwrap_gracefully(mdbook::init());
+        }
+        Options::Init {
+            directory, template, ..
+        } => {
+            let starter = Error::unwrap_gracefully(StarterSource::parse(template));
             // Change directories into the specified directory.
             std::env::set_current_dir(directory).unwrap();
-            Error::unwrap_gracefully(init(Config::default()).await);
+            let config = Error::unwrap_gracefully(starter.config());
+            Error::unwrap_gracefully(init_from_starter(config, &starter).await);
         }
         Options::Build {
             config_path,
             directory,
             rebuild_all,
+            profile,
+            force,
+            jobs,
+            fail_fast,
+            strict,
+            future,
+            timings,
+            follow_symlinks,
+            symlinks,
+            wait,
+            offline,
+            sync,
+            report,
+            report_file,
+        } => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let mut config = load_config(config_path, profile.as_deref());
+            if let Some(jobs) = jobs {
+                config.set_jobs(*jobs);
+            }
+            if *fail_fast {
+                config.set_fail_fast(true);
+            }
+            if *strict {
+                config.set_strict(true);
+            }
+            if *future {
+                config.set_future(true);
+            }
+            if *follow_symlinks {
+                config.set_follow_symlinks(true);
+            }
+            if *timings {
+                config.set_timings(true);
+            }
+            if *wait {
+                config.set_wait(true);
+            }
+            if *offline {
+                config.set_offline(true);
+            }
+            match symlinks.as_deref() {
+                Some("copy") => config.set_symlinks(Symlinks::Copy),
+                Some("link") => config.set_symlinks(Symlinks::Link),
+                _ => (),
+            }
+            if !force {
+                Error::unwrap_gracefully(config.check_dest());
+            }
+            if *sync {
+                if let Some(summary) = Error::unwrap_gracefully(cms::sync(&config).await) {
+                    eprintln!(
+                        "[{NAME}] Synced the CMS: {} page(s) written, {} unchanged, {} removed",
+                        summary.written, summary.unchanged, summary.removed
+                    );
+                }
+            }
+            let cancellation = cancel_on_ctrl_c();
+            let mut build_report = BuildReport::default();
+            for config in config.audience_configs() {
+                let mut site = Error::unwrap_gracefully(Website::from_config(config));
+                site.set_cancellation(cancellation.clone());
+                build_report.merge(Error::unwrap_gracefully(build_with_report(site, *rebuild_all).await));
+            }
+            if let Some(timings) = &build_report.timings {
+                eprint!("{}", timings.table(TIMINGS_TABLE_PAGES));
+            }
+            if report.is_some() {
+                Error::unwrap_gracefully(build_report.write_json(report_file.as_deref()));
+            }
+            if build_report.failed > 0 {
+                Error::BuildFailed(build_report.failed).report_and_exit();
+            }
+        }
+        Options::Deploy {
+            directory,
+            config_path,
+            profile,
+            skip_build,
+            force,
+        } => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let config = load_config(config_path, profile.as_deref());
+            let deploy_config = Error::unwrap_gracefully(config.deploy.clone().ok_or(Error::MissingDeployConfig));
+            if !skip_build {
+                if !force {
+                    Error::unwrap_gracefully(config.check_dest());
+                }
+                let cancellation = cancel_on_ctrl_c();
+                for config in config.audience_configs() {
+                    let mut site = Error::unwrap_gracefully(Website::from_config(config));
+                    site.set_cancellation(cancellation.clone());
+                    Error::unwrap_gracefully(build(site, false).await);
+                }
+            }
+            Error::unwrap_gracefully(deploy::deploy(&deploy_config, &config.dest).await);
+        }
+        Options::Clean {
+            directory,
+            config_path,
+            profile,
+            dry_run,
+            orphans,
+            force,
+        } => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let config = load_config(config_path, profile.as_deref());
+            if !force {
+                Error::unwrap_gracefully(config.check_dest());
+            }
+            Error::unwrap_gracefully(clean(config, *dry_run, *orphans).await);
+        }
+        Options::Watch {
+            directory,
+            config_path,
+            profile,
+            diff,
+        } => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let config = load_config(config_path, profile.as_deref());
+            Error::unwrap_gracefully(config.check_dest());
+            Error::unwrap_gracefully(watch::watch(config, cancel_on_ctrl_c(), diff).await);
+        }
+        Options::Check {
+            directory,
+            config_path,
+            profile,
+            unused,
         } => {
             // Change directories into the specified directory.
             std::env::set_current_dir(directory).unwrap();
-            let config = Error::unwrap_gracefully(Config::from_toml(config_path));
-            let (syntax_set_builder, mut themes) = Error::unwrap_gracefully(get_syntaxes(&config));
-            let theme = match themes.remove(&config.syntax_theme) {
-                None => Err(Error::MissingTheme(config.syntax_theme.clone())),
-                Some(x) => Ok(x),
-            }?;
-            // The assets we've already loaded.
-            // We use an Arc<DashMap> over an Arc<Mutex<Hashmap>> for finer-grained locking.
-            // The changes are syncronized.
-            let open_assets: Arc<DashMap<PathBuf, String>> = Arc::new(DashMap::new());
-            let site = Website::new(config, syntax_set_builder.build(), open_assets, theme);
-            Error::unwrap_gracefully(build(site, *rebuild_all).await);
-        }
-        Options::Clean { directory, config_path } => {
+            let config = load_config(config_path, profile.as_deref());
+            let problems = check::check(config.clone()).await;
+            for problem in &problems {
+                problem.report();
+            }
+            if *unused {
+                let graph = Error::unwrap_gracefully(DependencyGraph::from_config(&config));
+                let report = UnusedReport::new(&config, &graph);
+                if report.is_empty() {
+                    println!("No unused files found");
+                }
+                else {
+                    report.print();
+                }
+            }
+            if !problems.is_empty() {
+                Error::CheckFailed(problems.len()).report_and_exit();
+            }
+            println!("No problems found");
+        }
+        Options::Test {
+            directory,
+            config_path,
+            profile,
+            update,
+        } => {
             // Change directories into the specified directory.
             std::env::set_current_dir(directory).unwrap();
-            Error::unwrap_gracefully(clean(Error::unwrap_gracefully(Config::from_toml(config_path))).await);
+            let config = load_config(config_path, profile.as_deref());
+            let site = Error::unwrap_gracefully(Website::from_config(config));
+            let results = Error::unwrap_gracefully(snapshot::run(&site, *update).await);
+            let failed = snapshot::print(&results);
+            if failed > 0 {
+                Error::SnapshotsFailed(failed).report_and_exit();
+            }
+        }
+        Options::Doctor {
+            directory,
+            config_path,
+            profile,
+        } => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let config = load_config(config_path, profile.as_deref());
+            let diagnoses = Error::unwrap_gracefully(doctor::diagnose(&config));
+            doctor::print(&diagnoses);
+            if !diagnoses.is_empty() {
+                Error::DoctorFailed(diagnoses.len()).report_and_exit();
+            }
+        }
+        Options::Report {
+            directory,
+            config_path,
+            profile,
+            features,
+        } => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let config = load_config(config_path, profile.as_deref());
+            if !features {
+                eprintln!("[{NAME}] Nothing to report, pass --features to list the features the project uses");
+                std::process::exit(64);
+            }
+            Error::unwrap_gracefully(FeatureReport::from_config(&config)).print();
+        }
+        Options::Stats {
+            directory,
+            config_path,
+            profile,
+            json,
+        } => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let config = load_config(config_path, profile.as_deref());
+            let stats = Error::unwrap_gracefully(SiteStats::from_config(&config).await);
+            if *json {
+                println!("{}", stats.to_json());
+            }
+            else {
+                stats.print();
+            }
+        }
+        Options::MigrateContent {
+            directory,
+            config_path,
+            profile,
+            replace,
+            regex,
+            dry_run,
+        } => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let config = load_config(config_path, profile.as_deref());
+            let replacement = Error::unwrap_gracefully(migrate::Replacement::new(&replace[0], &replace[1], *regex));
+            let migrations = Error::unwrap_gracefully(migrate::plan(&config, &replacement));
+            if migrations.is_empty() {
+                println!("Nothing matches in \"{}\"", config.source.display());
+                return Ok(());
+            }
+            for migration in &migrations {
+                if *dry_run {
+                    println!(
+                        "Would change: \"{}\" ({} match(es))",
+                        migration.path.display(),
+                        migration.count
+                    );
+                    println!("{}", snapshot::diff(&migration.old, &migration.new));
+                }
+                else {
+                    println!(
+                        "Changing: \"{}\" ({} match(es))",
+                        migration.path.display(),
+                        migration.count
+                    );
+                }
+            }
+            if !dry_run {
+                let backup_dir = Error::unwrap_gracefully(migrate::backup_dir());
+                Error::unwrap_gracefully(migrate::apply(&migrations, &backup_dir).await);
+                println!("The originals are backed up in \"{}\"", backup_dir.display());
+            }
+        }
+        Options::Page(PageCommand::New {
+            title,
+            section,
+            directory,
+            config_path,
+        }) => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            let config = load_config(config_path, None);
+            let path = Error::unwrap_gracefully(page::new_page(&config, title, section.as_deref()));
+            println!("Created: \"{}\"", path.display());
+        }
+        Options::Theme(ThemeCommand::Install {
+            url,
+            name,
+            directory,
+            config_path,
+        }) => {
+            // Change directories into the specified directory.
+            std::env::set_current_dir(directory).unwrap();
+            if !config_path.is_file() {
+                Error::Io {
+                    err:  std::io::ErrorKind::NotFound.into(),
+                    path: config_path.clone(),
+                }
+                .report_and_exit()
+            }
+            let theme = Error::unwrap_gracefully(theme::install(url, name.as_deref()));
+            Error::unwrap_gracefully(theme::set_theme(config_path, &theme));
+            println!("Installed: \"{}\"", theme.display());
+        }
+        Options::Theme(ThemeCommand::Dev { theme, dest }) => {
+            let theme = Error::unwrap_gracefully(theme.canonicalize().map_err(|e| {
+                Error::Io {
+                    err:  e,
+                    path: theme.clone(),
+                }
+            }));
+            let dest = initial_directory.join(dest);
+            // The sample project is written somewhere it can't clash with
+            // the theme's files.
+            let project = std::env::temp_dir().join(format!("rustic-raven-theme-dev-{}", std::process::id()));
+            Error::unwrap_gracefully(fs::create_dir_all(&project).await.map_err(|e| {
+                Error::Io {
+                    err:  e,
+                    path: project.clone(),
+                }
+            }));
+            std::env::set_current_dir(&project).unwrap();
+            let mut config = Error::unwrap_gracefully(theme::write_sample(Some(&theme)));
+            config.dest = dest;
+            let mut site = Error::unwrap_gracefully(Website::from_config(config.clone()));
+            site.set_cancellation(cancel_on_ctrl_c());
+            let build_report = build_with_report(site, true).await;
+            let _ = fs::remove_dir_all(&project).await;
+            let build_report = Error::unwrap_gracefully(build_report);
+            if build_report.failed > 0 {
+                Error::BuildFailed(build_report.failed).report_and_exit();
+            }
+            println!(
+                "Built the sample pages with \"{}\" into \"{}\"",
+                theme.display(),
+                config.dest.display()
+            );
         }
         Options::New {
             name,
@@ -125,8 +783,11 @@ async fn main() -> error::Result<()>
             dest,
             syntaxes,
             syntax_themes,
+            template,
+            from,
         } => {
-            let mut config = Config::default();
+            let starter = Error::unwrap_gracefully(StarterSource::parse(template));
+            let mut config = Error::unwrap_gracefully(starter.config());
             // Create the name dir
             if let Err(e) = fs::create_dir_all(name).await {
                 Error::Io {
@@ -154,7 +815,18 @@ async fn main() -> error::Result<()>
             }
             // Change directories into the specified directory.
             std::env::set_current_dir(name).unwrap();
-            Error::unwrap_gracefully(init(config).await);
+            let theme = from
+                .as_deref()
+                .map(|url| Error::unwrap_gracefully(theme::install(url, None)));
+            config.theme = theme.clone();
+            Error::unwrap_gracefully(init_from_starter(config, &starter).await);
+            if let Some(theme) = theme {
+                // A directory starter's own configuration doesn't know about the
+                // theme.
+                let config_path = PathBuf::from(Config::DEFAULT_CONFIG_FILE);
+                Error::unwrap_gracefully(theme::set_theme(&config_path, &theme));
+                println!("Installed: \"{}\"", theme.display());
+            }
         }
     };
 
@@ -164,7 +836,32 @@ async fn main() -> error::Result<()>
     Ok(())
 }
 
-async fn clean(config: Config) -> Result<()>
+/// A cancellation for the builds that follow, cancelled on Ctrl-C. A second
+/// Ctrl-C exits at once.
+fn cancel_on_ctrl_c() -> Cancellation
+{
+    let cancellation = Cancellation::default();
+    let cancel = cancellation.clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if cancel.cancel() {
+                std::process::exit(130);
+            }
+        }
+    });
+    cancellation
+}
+
+/// Load the configuration at `path`, merging `profile` over it. Exits if it
+/// can't be loaded.
+fn load_config(path: &PathBuf, profile: Option<&str>) -> Config
+{
+    Error::unwrap_gracefully(Config::from_toml_with_profile(path, profile))
+}
+
+/// Delete the contents of the dest dir, or with `orphans` only the files no
+/// source generates. With `dry_run`, print what would be deleted instead.
+async fn clean(config: Config, dry_run: bool, orphans: bool) -> Result<()>
 {
     let pbs = ProgressStyle::default_bar()
         .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}")
@@ -172,46 +869,38 @@ async fn clean(config: Config) -> Result<()>
         .progress_chars("#>-");
 
     let dest_dir = &config.dest;
-    if dest_dir.is_dir()
-        && dest_dir
-            .read_dir()
-            .map_err(|e| {
-                Error::Io {
-                    err:  e,
-                    path: dest_dir.clone(),
-                }
-            })?
-            .next()
-            .is_none()
-    {
+    let paths: Vec<PathBuf> = if orphans {
+        DependencyGraph::from_config(&config)?.orphaned_dest_files(&config)?
+    }
+    else {
+        WalkDir::new(dest_dir)
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .map(|x| x.path().to_path_buf())
+            .collect()
+    };
+
+    if paths.is_empty() {
+        println!("Nothing to clean in \"{}\"", dest_dir.display());
+        return Ok(());
+    }
+    if dry_run {
+        for path in &paths {
+            println!("Would delete: \"{}\"", path.display());
+        }
         return Ok(());
     }
-    let dest_dir_contents: Vec<DirEntry> = WalkDir::new(dest_dir)
-        .into_iter()
-        .filter_map(|x| {
-            if let Ok(x) = x {
-                if x.path() == dest_dir {
-                    Some(x)
-                }
-                else {
-                    None
-                }
-            }
-            else {
-                None
-            }
-        })
-        .collect();
 
     // We delete all the files inside the dest dir and create a progress bar to
     // track the progress.
-    for path in dest_dir_contents.iter().progress_with_style(pbs) {
-        let path = path.path();
+    for path in paths.iter().progress_with_style(pbs) {
         if path.is_file() {
             fs::remove_file(path).await.map_err(|e| {
                 Error::Io {
                     err:  e,
-                    path: path.to_path_buf(),
+                    path: path.clone(),
                 }
             })?;
         }
@@ -219,10 +908,101 @@ async fn clean(config: Config) -> Result<()>
             fs::remove_dir_all(path).await.map_err(|e| {
                 Error::Io {
                     err:  e,
-                    path: path.to_path_buf(),
+                    path: path.clone(),
                 }
             })?;
         }
     }
+
+    if orphans {
+        for path in &paths {
+            remove_empty_parents(dest_dir, path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the directories above `path` that deleting it left empty, up to
+/// but not including `dest_dir`.
+async fn remove_empty_parents(dest_dir: &Path, path: &Path) -> Result<()>
+{
+    for directory in path.ancestors().skip(1) {
+        let is_empty = directory.read_dir().is_ok_and(|mut x| x.next().is_none());
+        if directory == dest_dir || !directory.starts_with(dest_dir) || !is_empty {
+            break;
+        }
+        fs::remove_dir(directory).await.map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: directory.to_path_buf(),
+            }
+        })?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// A project in `rustic-raven-tests/<name>` with a page, its output, and
+    /// the orphans `old/post.html` and `a/b/post.html`.
+    fn project(name: &str) -> Config
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("dest/old")).unwrap();
+        std::fs::create_dir_all(dir.join("dest/a/b")).unwrap();
+        std::fs::create_dir_all(dir.join("dest/empty")).unwrap();
+        std::fs::write(dir.join("src/post.md"), "# Post\n").unwrap();
+        for file in ["post.html", "old/post.html", "a/b/post.html"] {
+            std::fs::write(dir.join("dest").join(file), "").unwrap();
+        }
+        Config {
+            source: dir.join("src"),
+            dest: dir.join("dest"),
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    /// Test that cleaning orphans removes them and the directories they
+    /// leave empty, but not other empty directories or generated files
+    async fn test_clean_orphans()
+    {
+        let config = project("clean-orphans");
+        let dest = config.dest.clone();
+        clean(config, false, true).await.unwrap();
+        assert!(dest.join("post.html").is_file());
+        assert!(!dest.join("old").exists());
+        assert!(!dest.join("a").exists());
+        assert!(dest.join("empty").is_dir());
+        std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that cleaning removes everything in the dest dir, but not the
+    /// dest dir itself
+    async fn test_clean()
+    {
+        let config = project("clean-all");
+        let dest = config.dest.clone();
+        clean(config, false, false).await.unwrap();
+        assert!(dest.read_dir().unwrap().next().is_none());
+        std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    /// Test that a dry run deletes nothing
+    async fn test_clean_dry_run()
+    {
+        let config = project("clean-dry-run");
+        let dest = config.dest.clone();
+        clean(config, true, true).await.unwrap();
+        assert!(dest.join("old/post.html").is_file());
+        assert!(dest.join("a/b/post.html").is_file());
+        std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::{HashMap, HashSet};
+
+use pulldown_cmark::{CowStr, Event, HeadingLevel, Tag};
+
+use crate::{
+    config::Images,
+    report::{Warning, Warnings},
+    Error, MissingAltText, Path, Result,
+};
+
+/// A heading on a page and the ID it was resolved to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading
+{
+    pub level: u32,
+    pub id:    String,
+    pub text:  String,
+}
+
+/// Turn `text` into a URL fragment: lowercase alphanumerics separated by
+/// single dashes.
+pub fn slugify(text: &str) -> String
+{
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        }
+        else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches('-').to_string()
+}
+
+/// The text of the first `#` heading in `events`.
+pub fn first_heading(events: &[Event]) -> Option<String>
+{
+    let start = events
+        .iter()
+        .position(|x| matches!(x, Event::Start(Tag::Heading(HeadingLevel::H1, ..))))?;
+    let mut text = String::new();
+    for event in &events[start + 1..] {
+        match event {
+            Event::End(Tag::Heading(..)) => break,
+            Event::Text(x) | Event::Code(x) => text.push_str(x),
+            _ => {}
+        }
+    }
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// The text of the first paragraph in `events`, with its whitespace
+/// collapsed.
+pub fn first_paragraph(events: &[Event]) -> Option<String>
+{
+    let start = events.iter().position(|x| matches!(x, Event::Start(Tag::Paragraph)))?;
+    let mut text = String::new();
+    for event in &events[start + 1..] {
+        match event {
+            Event::End(Tag::Paragraph) => break,
+            Event::Text(x) | Event::Code(x) => text.push_str(x),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Give every heading in `events` an `id` attribute.
+///
+/// Headings without an explicit ID get one slugified from their text. When
+/// two headings resolve to the same ID the later ones get deterministic `-1`,
+/// `-2`, ... suffixes and a warning naming `source_path` is added to
+/// `warnings`. Anything that links to headings must use the returned IDs.
+pub fn resolve_heading_ids(events: &mut [Event], source_path: &Path, warnings: &Warnings) -> Vec<Heading>
+{
+    let mut headings = Vec::new();
+    let mut used_ids = HashSet::new();
+    let mut i = 0;
+    while i < events.len() {
+        let Event::Start(Tag::Heading(level, explicit_id, classes)) = &events[i]
+        else {
+            i += 1;
+            continue;
+        };
+        let (level, explicit_id, classes) = (*level as u32, explicit_id.map(String::from), classes.join(" "));
+
+        let mut text = String::new();
+        let mut end = i + 1;
+        while end < events.len() {
+            match &events[end] {
+                Event::End(Tag::Heading(..)) => break,
+                Event::Text(x) | Event::Code(x) => text.push_str(x),
+                _ => {}
+            }
+            end += 1;
+        }
+
+        let base_id = explicit_id.unwrap_or_else(|| slugify(&text));
+        let mut id = base_id.clone();
+        let mut suffix = 0;
+        while !used_ids.insert(id.clone()) {
+            suffix += 1;
+            id = format!("{base_id}-{suffix}");
+        }
+        if suffix > 0 {
+            warnings.warn(Warning::DuplicateHeading {
+                source_file: source_path.to_path_buf(),
+                text:        text.clone(),
+                id:          id.clone(),
+            });
+        }
+
+        let id_attribute = htmlescape::encode_minimal(&id);
+        let start = if classes.is_empty() {
+            format!("<h{level} id=\"{id_attribute}\">")
+        }
+        else {
+            format!(
+                "<h{level} id=\"{id_attribute}\" class=\"{}\">",
+                htmlescape::encode_minimal(&classes)
+            )
+        };
+        events[i] = Event::Html(start.into());
+        if end < events.len() {
+            events[end] = Event::Html(format!("</h{level}>\n").into());
+        }
+
+        headings.push(Heading { level, id, text });
+        i = end + 1;
+    }
+    headings
+}
+
+/// Check images for alt text and render numbered figures, as configured by
+/// `images`. Images without alt text are added to `warnings`, unless they're
+/// allowed.
+///
+/// A figure is an image with a title that's alone in its paragraph, the
+/// title becomes its caption. It may be followed by `{#fig:id}` so that
+/// `{{ ref fig:id }}` elsewhere on the page links to it as "Figure N".
+///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - An image has no alt text and `images.missing_alt` is `"error"`
+/// - A `{{ ref ... }}` names a figure that doesn't exist
+pub fn process_images<'a>(
+    events: Vec<Event<'a>>,
+    images: &Images,
+    source_path: &Path,
+    warnings: &Warnings,
+) -> Result<Vec<Event<'a>>>
+{
+    let missing_alt = images.missing_alt.unwrap_or_default();
+    if missing_alt != MissingAltText::Allow {
+        for (i, event) in events.iter().enumerate() {
+            let Event::Start(Tag::Image(_, url, _)) = event
+            else {
+                continue;
+            };
+            let has_alt = events[i + 1..]
+                .iter()
+                .take_while(|x| !matches!(x, Event::End(Tag::Image(..))))
+                .any(|x| matches!(x, Event::Text(text) | Event::Code(text) if !text.trim().is_empty()));
+            if has_alt {
+                continue;
+            }
+            let e = Error::MissingAltText {
+                image: url.to_string(),
+                path:  source_path.to_path_buf(),
+            };
+            if missing_alt == MissingAltText::Error {
+                return Err(e);
+            }
+            warnings.warn(Warning::MissingAltText {
+                source_file: source_path.to_path_buf(),
+                image:       url.to_string(),
+            });
+        }
+    }
+
+    let numbered = images.number_figures.unwrap_or(false);
+    if !numbered && !images.figures.unwrap_or(false) {
+        return Ok(events);
+    }
+
+    let mut output = Vec::with_capacity(events.len());
+    let mut figures = HashMap::new();
+    let mut figure_count = 0;
+    let mut i = 0;
+    while i < events.len() {
+        let Some((image_end, id, paragraph_end)) = find_figure(&events, i)
+        else {
+            output.push(events[i].clone());
+            i += 1;
+            continue;
+        };
+        let Event::Start(Tag::Image(_, _, title)) = &events[i + 1]
+        else {
+            unreachable!()
+        };
+
+        let caption = if numbered {
+            figure_count += 1;
+            format!("Figure {figure_count}: {}", htmlescape::encode_minimal(title))
+        }
+        else {
+            htmlescape::encode_minimal(title)
+        };
+        match &id {
+            Some(id) => {
+                if numbered {
+                    figures.insert(id.clone(), figure_count);
+                }
+                output.push(Event::Html(
+                    format!("<figure id=\"{}\">", htmlescape::encode_minimal(id)).into(),
+                ));
+            }
+            None => output.push(Event::Html("<figure>".into())),
+        }
+        output.extend_from_slice(&events[i + 1..=image_end]);
+        output.push(Event::Html(
+            format!("<figcaption>{caption}</figcaption></figure>\n").into(),
+        ));
+        i = paragraph_end + 1;
+    }
+
+    if numbered {
+        resolve_figure_references(&mut output, &figures, source_path)?;
+    }
+    Ok(output)
+}
+
+/// If a figure's paragraph starts at `start`, returns the index of the image's
+/// end, the figure's ID, and the index of the paragraph's end.
+fn find_figure(events: &[Event], start: usize) -> Option<(usize, Option<String>, usize)>
+{
+    if !matches!(events.get(start), Some(Event::Start(Tag::Paragraph))) {
+        return None;
+    }
+    match events.get(start + 1) {
+        Some(Event::Start(Tag::Image(_, _, title))) if !title.is_empty() => {}
+        _ => return None,
+    }
+    let image_end = start
+        + 1
+        + events[start + 1..]
+            .iter()
+            .position(|x| matches!(x, Event::End(Tag::Image(..))))?;
+
+    match events.get(image_end + 1)? {
+        Event::End(Tag::Paragraph) => Some((image_end, None, image_end + 1)),
+        Event::Text(text) => {
+            let id = text.trim().strip_prefix("{#")?.strip_suffix('}')?;
+            matches!(events.get(image_end + 2)?, Event::End(Tag::Paragraph))
+                .then(|| (image_end, Some(id.to_string()), image_end + 2))
+        }
+        _ => None,
+    }
+}
+
+/// Replace every `{{ ref id }}` outside of code blocks with a link to the
+/// figure `id`.
+fn resolve_figure_references(events: &mut [Event], figures: &HashMap<String, usize>, source_path: &Path) -> Result<()>
+{
+    const OPEN: &str = "{{ ref ";
+    const CLOSE: &str = "}}";
+
+    let mut in_code_block = false;
+    for event in events.iter_mut() {
+        let text = match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                continue;
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                continue;
+            }
+            Event::Text(text) if !in_code_block && text.contains(OPEN) => text,
+            _ => continue,
+        };
+
+        let mut html = String::with_capacity(text.len());
+        let mut rest: &str = text;
+        while let Some(start) = rest.find(OPEN) {
+            let Some(len) = rest[start..].find(CLOSE)
+            else {
+                break;
+            };
+            let reference = rest[start + OPEN.len()..start + len].trim();
+            let number = figures.get(reference).ok_or_else(|| {
+                Error::UnresolvedReference {
+                    reference: reference.to_string(),
+                    path:      source_path.to_path_buf(),
+                }
+            })?;
+            html.push_str(&htmlescape::encode_minimal(&rest[..start]));
+            html.push_str(&format!(
+                "<a href=\"#{}\">Figure {number}</a>",
+                htmlescape::encode_minimal(reference)
+            ));
+            rest = &rest[start + len + CLOSE.len()..];
+        }
+        html.push_str(&htmlescape::encode_minimal(rest));
+        *event = Event::Html(CowStr::from(html));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use pulldown_cmark::{html, Parser};
+
+    use super::*;
+
+    #[test]
+    /// Test that headings sharing a slug get deterministic suffixes
+    fn test_duplicate_heading_ids()
+    {
+        let markdown = "# Intro\n\n## Intro\n\n### Intro!\n\n# Intro 1";
+        let mut events = Parser::new(markdown).collect::<Vec<_>>();
+        let headings = resolve_heading_ids(&mut events, Path::new(""), &Warnings::default());
+        let ids = headings.iter().map(|x| x.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["intro", "intro-1", "intro-2", "intro-1-1"]);
+
+        let mut html_out = String::new();
+        html::push_html(&mut html_out, events.into_iter());
+        assert!(html_out.contains("<h2 id=\"intro-1\">Intro</h2>"));
+    }
+
+    #[test]
+    /// Test that figures are numbered in order and references resolve to them
+    fn test_figure_numbering()
+    {
+        let markdown = "See {{ ref fig:b }}.\n\n![A](a.png \"First\")\n\n![B](b.png \"Second\") {#fig:b}";
+        let images = Images {
+            missing_alt:    None,
+            number_figures: Some(true),
+            figures:        None,
+        };
+        let events = process_images(
+            Parser::new(markdown).collect(),
+            &images,
+            Path::new(""),
+            &Warnings::default(),
+        )
+        .unwrap();
+        let mut html_out = String::new();
+        html::push_html(&mut html_out, events.into_iter());
+        assert!(html_out.contains("See <a href=\"#fig:b\">Figure 2</a>."));
+        assert!(html_out.contains("<figcaption>Figure 1: First</figcaption>"));
+        assert!(html_out.contains("<figure id=\"fig:b\">"));
+    }
+
+    /// Render `markdown` with `images.figures` set to `figures`.
+    fn render_figures(markdown: &str, figures: Option<bool>) -> String
+    {
+        let images = Images {
+            missing_alt: None,
+            number_figures: None,
+            figures,
+        };
+        let events = process_images(
+            Parser::new(markdown).collect(),
+            &images,
+            Path::new(""),
+            &Warnings::default(),
+        )
+        .unwrap();
+        let mut html_out = String::new();
+        html::push_html(&mut html_out, events.into_iter());
+        html_out
+    }
+
+    #[test]
+    /// Test that an image with a title, alone in its paragraph, is a figure
+    /// captioned by its title
+    fn test_titled_image_figure()
+    {
+        assert_eq!(
+            render_figures("![A](a.png \"Results\")", Some(true)),
+            "<figure><img src=\"a.png\" alt=\"A\" title=\"Results\" /><figcaption>Results</figcaption></figure>\n"
+        );
+    }
+
+    #[test]
+    /// Test that a figure's caption is escaped
+    fn test_figure_caption_escaped()
+    {
+        let html_out = render_figures("![A](a.png \"Fish & <chips>\")", Some(true));
+        assert!(html_out.contains("<figcaption>Fish &amp; &lt;chips&gt;</figcaption>"));
+    }
+
+    #[test]
+    /// Test that an unnumbered figure keeps its ID
+    fn test_figure_id()
+    {
+        let html_out = render_figures("![A](a.png \"Results\") {#fig:a}", Some(true));
+        assert!(html_out.starts_with("<figure id=\"fig:a\"><img"));
+        assert!(html_out.contains("<figcaption>Results</figcaption>"));
+    }
+
+    #[test]
+    /// Test that an image with a title in a paragraph's text isn't a figure
+    fn test_inline_image_not_figure()
+    {
+        let html_out = render_figures("Inline ![B](b.png \"Not a figure\")", Some(true));
+        assert!(!html_out.contains("<figure"));
+    }
+
+    #[test]
+    /// Test that an image without a title isn't a figure
+    fn test_untitled_image_not_figure()
+    {
+        let html_out = render_figures("![A](a.png)", Some(true));
+        assert_eq!(html_out, "<p><img src=\"a.png\" alt=\"A\" /></p>\n");
+    }
+
+    #[test]
+    /// Test that images aren't figures without `images.figures`
+    fn test_figures_disabled()
+    {
+        let html_out = render_figures("![A](a.png \"Results\")", None);
+        assert!(!html_out.contains("<figure"));
+    }
+
+    #[test]
+    /// Test that the first `#` heading and paragraph are found as plain text
+    fn test_first_heading_and_paragraph()
+    {
+        let source = "## Intro\n\n# The `raven` *book*\n\n> A\n> quote\n\nLater";
+        let events = Parser::new(source).collect::<Vec<_>>();
+        assert_eq!(first_heading(&events).as_deref(), Some("The raven book"));
+        assert_eq!(first_paragraph(&events).as_deref(), Some("A quote"));
+        assert_eq!(first_heading(&Parser::new("Text").collect::<Vec<_>>()), None);
+    }
+}
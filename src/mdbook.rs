@@ -0,0 +1,440 @@
+//! Compatibility with mdBook projects.
+//!
+//! A book with a `summary` takes its chapters, their order, and their titles
+//! from an mdBook `SUMMARY.md`, so its pages don't need `pageinfo` blocks.
+//! The summary is also rendered as the navigation of the book's pages by the
+//! `[/rustic_nav/]` placeholder.
+
+use std::{collections::BTreeMap, path::Component};
+
+use pulldown_cmark::{Event, Tag};
+use serde::Deserialize;
+
+use crate::{
+    build::dest_path,
+    starters::{write_file, STARTERS},
+    Book, Config, DefaultMeta, Error, Path, PathBuf, Result,
+};
+
+/// A line of a `SUMMARY.md`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SummaryItem
+{
+    /// A link to a chapter, or a draft chapter without a path.
+    Chapter
+    {
+        title: String, path: Option<PathBuf>
+    },
+
+    /// A `# Part title` heading between chapters.
+    Part(String),
+
+    /// A `---` separator.
+    Separator,
+}
+
+/// A parsed `SUMMARY.md`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary
+{
+    /// The summary's own title, `# Summary` by convention.
+    pub title: Option<String>,
+
+    /// Every item and how deeply it's nested.
+    pub items: Vec<(usize, SummaryItem)>,
+}
+
+impl Summary
+{
+    /// Parse the contents of a `SUMMARY.md`.
+    ///
+    /// Lines that aren't chapter links, list items with chapter links, part
+    /// headings, or separators are ignored.
+    pub fn parse(summary: &str) -> Self
+    {
+        let mut parsed = Self::default();
+        // The indentation of each enclosing list item.
+        let mut indents: Vec<usize> = Vec::new();
+        for line in summary.lines() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if let Some(heading) = trimmed.strip_prefix("# ") {
+                indents.clear();
+                if parsed.title.is_none() && parsed.items.is_empty() {
+                    parsed.title = Some(heading.trim().to_string());
+                }
+                else {
+                    parsed.items.push((0, SummaryItem::Part(heading.trim().to_string())));
+                }
+                continue;
+            }
+            if matches!(trimmed.trim_end(), "---" | "***" | "___") {
+                indents.clear();
+                parsed.items.push((0, SummaryItem::Separator));
+                continue;
+            }
+
+            let list_item = trimmed.strip_prefix("- ").or_else(|| trimmed.strip_prefix("* "));
+            let Some((title, path)) = parse_link(list_item.unwrap_or(trimmed))
+            else {
+                continue;
+            };
+            let depth = if list_item.is_some() {
+                let indent = line.len() - trimmed.len();
+                while indents.last().is_some_and(|&x| indent <= x) {
+                    indents.pop();
+                }
+                indents.push(indent);
+                indents.len() - 1
+            }
+            else {
+                indents.clear();
+                0
+            };
+            parsed.items.push((depth, SummaryItem::Chapter { title, path }));
+        }
+        parsed
+    }
+
+    /// The chapters with a path, in order.
+    pub fn chapters(&self) -> impl Iterator<Item = (&str, &Path)>
+    {
+        self.items.iter().filter_map(|(_, item)| {
+            match item {
+                SummaryItem::Chapter {
+                    title,
+                    path: Some(path),
+                } => Some((title.as_str(), path.as_path())),
+                _ => None,
+            }
+        })
+    }
+
+    /// Read and parse the summary of `book`, if it has one.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the summary cannot be read.
+    pub fn of_book(config: &Config, book: &Book) -> Result<Option<Self>>
+    {
+        let Some(path) = summary_path(config, book)
+        else {
+            return Ok(None);
+        };
+        let contents = std::fs::read_to_string(&path).map_err(|e| Error::Io { err: e, path })?;
+        Ok(Some(Self::parse(&contents)))
+    }
+
+    /// The summary as nested lists of links, relative to the dest file of the
+    /// page `source_file`, which is marked as active.
+    pub fn to_html(&self, config: &Config, book: &Book, source_file: &Path) -> String
+    {
+        let section = config.source.join(&book.section);
+        let current = page_dest(config, source_file);
+        let depth_of_current = current.parent().map_or(0, |x| x.components().count());
+        let root = "../".repeat(depth_of_current);
+
+        let mut html = String::from("<nav class=\"raven-nav\"><ol>");
+        let mut current_depth = 0;
+        for (i, (depth, item)) in self.items.iter().enumerate() {
+            if i > 0 {
+                if *depth > current_depth {
+                    html.push_str(&"<ol>".repeat(depth - current_depth));
+                }
+                else {
+                    html.push_str("</li>");
+                    html.push_str(&"</ol></li>".repeat(current_depth - depth));
+                }
+            }
+            current_depth = *depth;
+
+            match item {
+                SummaryItem::Chapter {
+                    title,
+                    path: Some(path),
+                } => {
+                    let source = normalize(&section.join(path));
+                    let dest = page_dest(config, &source);
+                    let class = if source == normalize(source_file) {
+                        " class=\"active\""
+                    }
+                    else {
+                        ""
+                    };
+                    html.push_str(&format!(
+                        "<li><a href=\"{root}{}\"{class}>{}</a>",
+                        htmlescape::encode_minimal(&dest.to_string_lossy().replace('\\', "/")),
+                        htmlescape::encode_minimal(title)
+                    ));
+                }
+                SummaryItem::Chapter { title, path: None } => {
+                    html.push_str(&format!(
+                        "<li class=\"raven-nav-draft\">{}",
+                        htmlescape::encode_minimal(title)
+                    ));
+                }
+                SummaryItem::Part(title) => {
+                    html.push_str(&format!(
+                        "<li class=\"raven-nav-part\">{}",
+                        htmlescape::encode_minimal(title)
+                    ));
+                }
+                SummaryItem::Separator => html.push_str("<li class=\"raven-nav-separator\">"),
+            }
+        }
+        if !self.items.is_empty() {
+            html.push_str("</li>");
+            html.push_str(&"</ol></li>".repeat(current_depth));
+        }
+        html.push_str("</ol></nav>");
+        html
+    }
+}
+
+/// Parse `[title](path)`. An empty path is a draft chapter.
+fn parse_link(text: &str) -> Option<(String, Option<PathBuf>)>
+{
+    let (title, rest) = text.strip_prefix('[')?.split_once("](")?;
+    let path = rest.trim_end().strip_suffix(')')?.trim();
+    Some((title.to_string(), (!path.is_empty()).then(|| PathBuf::from(path))))
+}
+
+/// Where the summary of `book` is, if it has one.
+pub fn summary_path(config: &Config, book: &Book) -> Option<PathBuf>
+{
+    book.summary
+        .as_ref()
+        .map(|summary| normalize(&config.source.join(&book.section).join(summary)))
+}
+
+/// The book with a summary that `source_file` is a chapter of.
+pub fn book_of<'a>(config: &'a Config, source_file: &Path) -> Option<&'a Book>
+{
+    let source_file = normalize(source_file);
+    config
+        .books
+        .iter()
+        .flatten()
+        .filter(|book| book.summary.is_some())
+        .find(|book| source_file.starts_with(normalize(&config.source.join(&book.section))))
+}
+
+/// The titles the summaries give their chapters, keyed by source path. A
+/// summary's own page is titled by its heading.
+///
+/// Summaries that cannot be read are left out.
+pub fn page_titles(config: &Config) -> BTreeMap<PathBuf, String>
+{
+    let mut titles = BTreeMap::new();
+    for book in config.books.iter().flatten() {
+        let (Some(path), Ok(Some(summary))) = (summary_path(config, book), Summary::of_book(config, book))
+        else {
+            continue;
+        };
+        let section = config.source.join(&book.section);
+        for (title, chapter) in summary.chapters() {
+            titles.insert(normalize(&section.join(chapter)), title.to_string());
+        }
+        titles.insert(path, summary.title.unwrap_or_else(|| String::from("Summary")));
+    }
+    titles
+}
+
+/// Point relative links to markdown files at the pages they're built into,
+/// as mdBook does: `install.md#linux` becomes `install.html#linux`.
+pub fn rewrite_chapter_links(events: &mut [Event])
+{
+    for event in events {
+        let (Event::Start(Tag::Link(_, url, _)) | Event::End(Tag::Link(_, url, _))) = event
+        else {
+            continue;
+        };
+        if url.contains(':') || url.starts_with('/') {
+            continue;
+        }
+        let (target, fragment) = url.split_once('#').map_or((&**url, None), |(x, y)| (x, Some(y)));
+        let Some(stem) = target.strip_suffix(".md").or_else(|| target.strip_suffix(".markdown"))
+        else {
+            continue;
+        };
+        let rewritten = match fragment {
+            Some(fragment) => format!("{stem}.html#{fragment}"),
+            None => format!("{stem}.html"),
+        };
+        *url = rewritten.into();
+    }
+}
+
+/// `path` without `.` components, and with `..` components resolved
+/// lexically, the way source files are found.
+pub(crate) fn normalize(path: &Path) -> PathBuf
+{
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The dest path of a markdown page, relative to the dest dir.
+fn page_dest(config: &Config, source_file: &Path) -> PathBuf
+{
+    dest_path(config, source_file, "md")
+        .ok()
+        .and_then(|x| x.strip_prefix(&config.dest).ok().map(Path::to_path_buf))
+        .unwrap_or_default()
+}
+
+/// The parts of mdBook's `book.toml` that carry over.
+#[derive(Debug, Deserialize)]
+struct BookToml
+{
+    book:  BookTable,
+    build: Option<BuildTable>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BookTable
+{
+    title:       Option<String>,
+    description: Option<String>,
+    authors:     Option<Vec<String>>,
+    src:         Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct BuildTable
+{
+    build_dir: Option<PathBuf>,
+}
+
+/// A configuration for the mdBook project described by `book_toml`: its
+/// `src` directory as a book with its `SUMMARY.md`, built into its build
+/// directory.
+///
+/// # Errors
+///
+/// Will return an error if `book_toml` cannot be read or parsed.
+pub fn config_from_book_toml(book_toml: &Path) -> Result<Config>
+{
+    let contents = std::fs::read_to_string(book_toml).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: book_toml.to_path_buf(),
+        }
+    })?;
+    let parsed: BookToml = toml::from_str(&contents)
+        .map_err(|e| Error::ConfigParse(format!("Couldn't parse {}: {e}", book_toml.display())))?;
+
+    let title = parsed.book.title.unwrap_or_else(|| String::from("Book"));
+    let mut config = Config {
+        source: parsed.book.src.unwrap_or_else(|| PathBuf::from("src")),
+        dest: parsed
+            .build
+            .and_then(|x| x.build_dir)
+            .unwrap_or_else(|| PathBuf::from("book")),
+        books: Some(vec![Book {
+            section:     PathBuf::from("."),
+            title:       title.clone(),
+            description: parsed.book.description,
+            summary:     Some(PathBuf::from("SUMMARY.md")),
+            chapters:    None,
+            output:      Some(PathBuf::from("print.html")),
+            template:    None,
+            epub:        None,
+            cover:       None,
+            language:    None,
+        }]),
+        ..Config::default()
+    };
+    config.default.meta = Some(DefaultMeta {
+        site_name: title,
+        authors:   parsed.book.authors.unwrap_or_default(),
+    });
+    Ok(config)
+}
+
+/// Set up the mdBook project in the current directory to be built with
+/// raven: write a `raven.toml` made from its `book.toml`, and the `mdbook`
+/// starter's template and stylesheet. Its sources aren't touched.
+///
+/// # Panics
+///
+/// Will panic if TOML cannot be serialized from `Config`
+///
+/// # Errors
+///
+/// Will return an error if `book.toml` cannot be read or parsed, or a file
+/// cannot be written.
+pub fn init() -> Result<()>
+{
+    let config_path = PathBuf::from(Config::DEFAULT_CONFIG_FILE);
+    if config_path.exists() {
+        return Ok(());
+    }
+    let config = config_from_book_toml(Path::new("book.toml"))?;
+    write_file(&config_path, toml::to_string_pretty(&config).unwrap().as_bytes())?;
+
+    let starter = STARTERS.iter().find(|x| x.name == "mdbook").unwrap();
+    for (path, contents) in starter.files {
+        let path = Path::new(path);
+        if path.starts_with("src") || path == Path::new("book.toml") || path.exists() {
+            continue;
+        }
+        write_file(path, contents.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that prefix chapters, nested chapters, parts, drafts, and
+    /// separators are parsed and rendered as nested lists
+    fn test_summary()
+    {
+        let summary = Summary::parse(
+            "# Summary\n\n[Introduction](README.md)\n\n# Guide\n\n- [Install](install.md)\n  - \
+             [Linux](install/linux.md)\n- [Usage]()\n\n---\n\n[Contributors](contributors.md)\n",
+        );
+        assert_eq!(summary.title.as_deref(), Some("Summary"));
+        assert_eq!(
+            summary.chapters().map(|(title, _)| title).collect::<Vec<_>>(),
+            ["Introduction", "Install", "Linux", "Contributors"]
+        );
+
+        let book = Book {
+            section:     PathBuf::from("."),
+            title:       String::new(),
+            description: None,
+            summary:     Some(PathBuf::from("SUMMARY.md")),
+            chapters:    None,
+            output:      None,
+            template:    None,
+            epub:        None,
+            cover:       None,
+            language:    None,
+        };
+        let html = summary.to_html(&Config::default(), &book, Path::new("src/install/linux.md"));
+        assert_eq!(
+            html,
+            "<nav class=\"raven-nav\"><ol><li><a href=\"../README.html\">Introduction</a></li><li \
+             class=\"raven-nav-part\">Guide</li><li><a href=\"../install.html\">Install</a><ol><li><a \
+             href=\"../install/linux.html\" class=\"active\">Linux</a></li></ol></li><li \
+             class=\"raven-nav-draft\">Usage</li><li class=\"raven-nav-separator\"></li><li><a \
+             href=\"../contributors.html\">Contributors</a></li></ol></nav>"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Find and replace across every markdown source, for `raven
+//! migrate-content`, e.g. when the site's domain changes.
+//!
+//! Matches are literal text unless `--regex` is given, in which case the
+//! replacement can use `$1`, `$name`, ... for the pattern's groups. Before a
+//! source is rewritten its original is copied under [`BACKUP_DIR`], so a
+//! migration can be undone by copying the backups back.
+
+use std::path::Component;
+
+use regex::{NoExpand, Regex};
+
+use crate::{
+    build::{walk_sources, write_output},
+    report::Warnings,
+    Config, Error, Path, PathBuf, Result,
+};
+
+/// Where the originals of rewritten sources are copied, in a directory named
+/// after when the migration ran, relative to the project.
+pub const BACKUP_DIR: &str = ".raven-backup";
+
+/// What to find and what to replace it with.
+#[derive(Debug, Clone)]
+pub struct Replacement
+{
+    pattern:     Regex,
+    replacement: String,
+    regex:       bool,
+}
+
+impl Replacement
+{
+    /// Replace `find` with `replacement`, as a regular expression if `regex`
+    /// is set, or literally otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if `find` is empty, or isn't a valid regular
+    /// expression.
+    pub fn new(find: &str, replacement: &str, regex: bool) -> Result<Self>
+    {
+        if find.is_empty() {
+            return Err(Error::InvalidPattern {
+                pattern: find.to_string(),
+                err:     String::from("It's empty"),
+            });
+        }
+        let pattern = if regex {
+            Regex::new(find)
+        }
+        else {
+            Regex::new(&regex::escape(find))
+        };
+        let pattern = pattern.map_err(|e| {
+            Error::InvalidPattern {
+                pattern: find.to_string(),
+                err:     e.to_string(),
+            }
+        })?;
+        Ok(Self {
+            pattern,
+            replacement: replacement.to_string(),
+            regex,
+        })
+    }
+
+    /// `source` with every match replaced, and how many there were.
+    pub fn apply(&self, source: &str) -> (String, usize)
+    {
+        let count = self.pattern.find_iter(source).count();
+        if count == 0 {
+            return (source.to_string(), 0);
+        }
+        let replaced = if self.regex {
+            self.pattern.replace_all(source, self.replacement.as_str())
+        }
+        else {
+            self.pattern.replace_all(source, NoExpand(&self.replacement))
+        };
+        (replaced.into_owned(), count)
+    }
+}
+
+/// A markdown source and what it's rewritten to.
+#[derive(Debug, Clone)]
+pub struct Migration
+{
+    pub path:  PathBuf,
+    pub old:   String,
+    pub new:   String,
+    pub count: usize,
+}
+
+/// The markdown sources of the project that `replacement` changes, by path.
+///
+/// # Errors
+///
+/// Will return an error if a source cannot be read.
+pub fn plan(config: &Config, replacement: &Replacement) -> Result<Vec<Migration>>
+{
+    let mut migrations = Vec::new();
+    for (path, extention) in walk_sources(config, &Warnings::default()) {
+        if !matches!(&*extention, "md" | "markdown") {
+            continue;
+        }
+        let old = std::fs::read_to_string(&path).map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: path.clone(),
+            }
+        })?;
+        let (new, count) = replacement.apply(&old);
+        if count > 0 {
+            migrations.push(Migration { path, old, new, count });
+        }
+    }
+    Ok(migrations)
+}
+
+/// Copy the original of each of `migrations` under `backup_dir`, keeping its
+/// path relative to the project, then rewrite it.
+///
+/// # Errors
+///
+/// Will return an error if a backup or a source cannot be written. Sources
+/// are only rewritten once every backup is written.
+pub async fn apply(migrations: &[Migration], backup_dir: &Path) -> Result<()>
+{
+    for migration in migrations {
+        write_output(&backup_path(backup_dir, &migration.path), &migration.old).await?;
+    }
+    for migration in migrations {
+        write_output(&migration.path, &migration.new).await?;
+    }
+    Ok(())
+}
+
+/// Where the original of the source at `path` is copied under `backup_dir`.
+///
+/// Absolute paths are made relative to the project, or failing that have
+/// their root dropped, so the backup never lands on the original.
+fn backup_path(backup_dir: &Path, path: &Path) -> PathBuf
+{
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let relative = path.strip_prefix(&cwd).unwrap_or(path);
+    backup_dir.join(
+        relative
+            .components()
+            .filter(|x| matches!(x, Component::Normal(_)))
+            .collect::<PathBuf>(),
+    )
+}
+
+/// Create a new backup directory for a migration running now.
+///
+/// # Errors
+///
+/// Will return an error if the directory cannot be created.
+pub fn backup_dir() -> Result<PathBuf>
+{
+    let name = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f").to_string();
+    std::fs::create_dir_all(BACKUP_DIR).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: PathBuf::from(BACKUP_DIR),
+        }
+    })?;
+    // Migrations in the same millisecond each get a directory of their own.
+    for i in 0.. {
+        let dir = match i {
+            0 => PathBuf::from(BACKUP_DIR).join(&name),
+            _ => PathBuf::from(BACKUP_DIR).join(format!("{name}-{i}")),
+        };
+        match std::fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(Error::Io { err: e, path: dir }),
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that literal replacements aren't read as patterns, and that
+    /// regex ones can use their groups
+    fn test_replacement()
+    {
+        let literal = Replacement::new("old.example.com", "$new.example.com", false).unwrap();
+        assert_eq!(
+            literal.apply("https://old.example.com/a oldXexample.com"),
+            (String::from("https://$new.example.com/a oldXexample.com"), 1)
+        );
+
+        let regex = Replacement::new(r"https?://old\.example\.com/(\w+)", "/$1/", true).unwrap();
+        assert_eq!(
+            regex.apply("[a](http://old.example.com/blog) [b](https://old.example.com/docs)"),
+            (String::from("[a](/blog/) [b](/docs/)"), 2)
+        );
+        assert!(Replacement::new("(", "", true).is_err());
+        assert!(Replacement::new("", "x", false).is_err());
+    }
+
+    #[test]
+    /// Test that absolute sources are backed up under the backup directory
+    /// instead of onto themselves
+    fn test_backup_path()
+    {
+        let backup_dir = Path::new(".raven-backup/20240101-000000.000");
+        assert_eq!(
+            backup_path(backup_dir, Path::new("src/blog/a.md")),
+            backup_dir.join("src/blog/a.md")
+        );
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            backup_path(backup_dir, &cwd.join("src/blog/a.md")),
+            backup_dir.join("src/blog/a.md")
+        );
+        let outside = std::env::temp_dir().join("rustic-raven-tests/migrate/a.md");
+        let path = backup_path(backup_dir, &outside);
+        assert!(path.starts_with(backup_dir));
+        assert!(path.ends_with("rustic-raven-tests/migrate/a.md"));
+        assert_ne!(path, outside);
+    }
+}
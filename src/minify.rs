@@ -0,0 +1,97 @@
+//! Minifying generated HTML, and the CSS and JavaScript copied from the
+//! source dir or inlined into pages, all with [minify_html].
+//!
+//! A stylesheet or script on its own is minified as the only element of a
+//! document. One that cannot be parsed is left as it is, trimmed.
+
+/// Minify an HTML document, including the CSS in it.
+pub fn html(html: &str) -> String
+{
+    let mut cfg = minify_html::Cfg::new();
+    cfg.minify_css = true;
+    cfg.ensure_spec_compliant_unquoted_attribute_values = true;
+    String::from_utf8_lossy(&minify_html::minify(html.as_bytes(), &cfg)).to_string()
+}
+
+/// Minify a stylesheet.
+pub fn css(css: &str) -> String
+{
+    let mut cfg = minify_html::Cfg::new();
+    cfg.minify_css = true;
+    element("style", css, &cfg)
+}
+
+/// Minify a classic (not module) script.
+pub fn js(js: &str) -> String
+{
+    let mut cfg = minify_html::Cfg::new();
+    cfg.minify_js = true;
+    element("script", js, &cfg)
+}
+
+/// Minify `code` as the contents of a `tag` element. Code that would end the
+/// element early is left as it is.
+fn element(tag: &str, code: &str, cfg: &minify_html::Cfg) -> String
+{
+    let (start, end) = (format!("<{tag}>"), format!("</{tag}>"));
+    if code.to_ascii_lowercase().contains(&end[..end.len() - 1]) {
+        return code.to_string();
+    }
+    let minified = minify_html::minify(format!("{start}{code}{end}").as_bytes(), cfg);
+    let minified = String::from_utf8_lossy(&minified);
+    minified
+        .strip_prefix(&start)
+        .and_then(|x| x.strip_suffix(&end))
+        .map_or_else(|| code.to_string(), String::from)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that a stylesheet is minified
+    fn test_minify_css()
+    {
+        assert_eq!(
+            css("a {\n  color: red;\n}\n\nb { margin: 0px; }\n"),
+            "a{color:red}b{margin:0}"
+        );
+    }
+
+    #[test]
+    /// Test that a script is minified
+    fn test_minify_js()
+    {
+        assert_eq!(js("const main = () => { let x = 1; };"), "const main=()=>{let a=1}");
+    }
+
+    #[test]
+    /// Test that code that cannot be parsed is left as it is, trimmed
+    fn test_minify_invalid()
+    {
+        assert_eq!(js("  let = ;\n"), "let = ;");
+    }
+
+    #[test]
+    /// Test that code that would end its element early is left as it is
+    fn test_minify_closing_tag()
+    {
+        let script = "document.write(\"</SCRIPT>\");  ";
+        assert_eq!(js(script), script);
+    }
+
+    #[test]
+    /// Test that a document and the CSS in it are minified
+    fn test_minify_html()
+    {
+        assert_eq!(
+            html(
+                "<!DOCTYPE html><html><head><style>a { color: red; }</style></head><body><p class=\"x\">Hi  \
+                 there</p></body></html>"
+            ),
+            "<!doctypehtml><style>a{color:red}</style><body><p class=x>Hi there"
+        );
+    }
+}
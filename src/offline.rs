@@ -0,0 +1,198 @@
+//! A precaching service worker, with `generation.service_worker`, so the site
+//! works offline once it's been visited.
+//!
+//! After a build, every file in the dest dir is listed in [`SERVICE_WORKER`]
+//! with the MD5 hash of its content. The worker caches them all when it's
+//! installed, and answers requests from the cache before the network. The
+//! cache is named after the hashes, so a build that changes any file makes a
+//! new worker, which replaces the old cache. Every HTML page gets a script
+//! that registers the worker.
+
+use walkdir::WalkDir;
+
+use crate::{
+    analytics::inject,
+    book,
+    build::{dest_path, write_output},
+    hash,
+    links::relative_url,
+    Config, Error, Path, Result,
+};
+
+/// Where the service worker is written, relative to the dest dir.
+pub const SERVICE_WORKER: &str = "sw.js";
+
+/// The prefix of the caches the service worker makes.
+const CACHE_PREFIX: &str = "raven-";
+
+/// The files in `dest`, other than the service worker, as URLs relative to
+/// it, with the hashes of their content. They're in sorted order.
+///
+/// # Errors
+///
+/// Will return an error if a file cannot be read.
+pub fn precache(dest: &Path) -> Result<Vec<(String, String)>>
+{
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dest)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|x| x.file_type().is_file())
+    {
+        let relative = entry.path().strip_prefix(dest).unwrap_or(entry.path());
+        let url = relative
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if url == SERVICE_WORKER {
+            continue;
+        }
+        let contents = std::fs::read(entry.path()).map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: entry.path().to_path_buf(),
+            }
+        })?;
+        files.push((url, hash::md5_hex(&contents)));
+    }
+    Ok(files)
+}
+
+/// The service worker that precaches `files`.
+pub fn script(files: &[(String, String)]) -> String
+{
+    let version = hash::md5_hex(
+        files
+            .iter()
+            .map(|(url, hash)| format!("{url} {hash}\n"))
+            .collect::<String>()
+            .as_bytes(),
+    );
+    let files = files
+        .iter()
+        .map(|(url, hash)| format!("  [{}, \"{hash}\"],\n", serde_json::to_string(url).unwrap()))
+        .collect::<String>();
+    format!(
+        "// Generated by {name}. Every file of the site, with the hash of its content.
+const FILES = [
+{files}];
+const CACHE = \"{CACHE_PREFIX}{version}\";
+
+self.addEventListener(\"install\", (event) => {{
+  event.waitUntil(
+    caches.open(CACHE)
+      .then((cache) => cache.addAll(FILES.map(([url]) => url)))
+      .then(() => self.skipWaiting()),
+  );
+}});
+
+self.addEventListener(\"activate\", (event) => {{
+  event.waitUntil(
+    caches.keys()
+      .then((keys) => Promise.all(
+        keys.filter((key) => key.startsWith(\"{CACHE_PREFIX}\") && key !== CACHE).map((key) => caches.delete(key)),
+      ))
+      .then(() => self.clients.claim()),
+  );
+}});
+
+self.addEventListener(\"fetch\", (event) => {{
+  const request = event.request;
+  if (request.method !== \"GET\" || new URL(request.url).origin !== self.location.origin) {{
+    return;
+  }}
+  const index = request.url.replace(/[?#].*$/, \"\").endsWith(\"/\")
+    ? new URL(\"index.html\", request.url).href
+    : null;
+  event.respondWith(
+    caches.open(CACHE)
+      .then((cache) => cache.match(request, {{ ignoreSearch: true }})
+        .then((response) => response || (index && cache.match(index))))
+      .then((response) => response || fetch(request)),
+  );
+}});
+",
+        name = crate::NAME
+    )
+}
+
+/// Write the service worker for what's in the dest dir.
+///
+/// # Errors
+///
+/// Will return an error if a file in the dest dir cannot be read, or the
+/// service worker cannot be written.
+pub async fn write(config: &Config) -> Result<()>
+{
+    let files = precache(&config.dest)?;
+    write_output(&config.dest.join(SERVICE_WORKER), script(&files)).await
+}
+
+/// The script that registers the service worker, for the page built from
+/// `source_file`.
+pub fn registration(config: &Config, source_file: &Path) -> String
+{
+    let dest_file = if source_file.is_dir() {
+        // A book's combined page.
+        config
+            .books
+            .iter()
+            .flatten()
+            .find(|x| config.source.join(&x.section) == source_file)
+            .map(|x| book::dest(config, x))
+    }
+    else {
+        let extention = source_file
+            .extension()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+        dest_path(config, source_file, &extention).ok()
+    };
+    let url = dest_file
+        .map(|x| relative_url(&x, &config.dest.join(SERVICE_WORKER)))
+        .unwrap_or_else(|| format!("/{SERVICE_WORKER}"));
+    format!(
+        "<script>if (\"serviceWorker\" in navigator) {{ navigator.serviceWorker.register({}); }}</script>",
+        serde_json::to_string(&url).unwrap()
+    )
+}
+
+/// Put the script that registers the service worker in `html`, the page
+/// built from `source_file`.
+pub fn register(config: &Config, source_file: &Path, html: String) -> String
+{
+    inject(html, &registration(config, source_file))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::PathBuf;
+
+    #[test]
+    /// Test that the worker lists every file with its hash, and pages register
+    /// it relative to where they are
+    fn test_service_worker()
+    {
+        let files = vec![
+            (String::from("index.html"), hash::md5_hex(b"a")),
+            (String::from("blog/post.html"), hash::md5_hex(b"b")),
+        ];
+        let worker = script(&files);
+        assert!(worker.contains(&format!("  [\"blog/post.html\", \"{}\"],\n", hash::md5_hex(b"b"))));
+        let changed = script(&[(String::from("index.html"), hash::md5_hex(b"c"))]);
+        let cache = |x: &str| x.lines().find(|x| x.starts_with("const CACHE")).unwrap().to_string();
+        assert_ne!(cache(&worker), cache(&changed));
+
+        let config = Config {
+            source: PathBuf::from("src"),
+            dest: PathBuf::from("dest"),
+            ..Config::default()
+        };
+        assert!(registration(&config, Path::new("src/blog/post.md")).contains("register(\"../sw.js\")"));
+        assert!(registration(&config, Path::new("src/index.md")).contains("register(\"sw.js\")"));
+    }
+}
@@ -0,0 +1,449 @@
+//! Open Graph images, with `[og_image]`: a 1200×630 PNG of each markdown
+//! page's title and site name over the configured background, written to
+//! `og/<slug>.png` in the dest dir and linked from the page's `og:image` and
+//! `twitter:card` meta tags.
+//!
+//! The text is drawn with DejaVu Sans Bold, pre-rendered into the glyph atlas
+//! in `assets/fonts`, which covers Latin-1. Other characters are drawn as `?`.
+
+use std::{collections::HashMap, io::Read, sync::OnceLock};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{
+    analytics::inject,
+    build::{dest_path, source_files, write_output},
+    markdown::slugify,
+    placeholders::{escape_attribute, site_name},
+    report::Warnings,
+    sections, Config, Error, OgImage, Path, PathBuf, Result,
+};
+
+/// The directory under the dest dir the images are in.
+pub const OG_DIR: &str = "og";
+
+pub const WIDTH: usize = 1200;
+pub const HEIGHT: usize = 630;
+
+const PADDING: f32 = 80.0;
+const TITLE_SIZES: [f32; 3] = [72.0, 60.0, 48.0];
+const MAX_TITLE_LINES: usize = 4;
+const SITE_NAME_SIZE: f32 = 36.0;
+
+static ATLAS: &[u8] = include_bytes!("../assets/fonts/dejavu-sans-bold.atlas");
+
+/// A glyph of the atlas, in pixels at the atlas's size.
+struct Glyph
+{
+    advance: f32,
+    left:    i32,
+    top:     i32,
+    width:   usize,
+    height:  usize,
+    bitmap:  Vec<u8>,
+}
+
+struct Font
+{
+    size:   f32,
+    ascent: f32,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl Font
+{
+    fn glyph(&self, c: char) -> &Glyph { self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?')).unwrap() }
+
+    /// How wide `text` is at `size`.
+    fn width(&self, text: &str, size: f32) -> f32
+    {
+        text.chars().map(|c| self.glyph(c).advance).sum::<f32>() * size / self.size
+    }
+}
+
+/// The bundled font, read from the atlas the first time it's needed.
+fn font() -> &'static Font
+{
+    static FONT: OnceLock<Font> = OnceLock::new();
+    FONT.get_or_init(|| {
+        let mut data = Vec::new();
+        ZlibDecoder::new(ATLAS).read_to_end(&mut data).unwrap();
+        let u16_at = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        let i16_at = |at: usize| i16::from_le_bytes([data[at], data[at + 1]]);
+        assert_eq!(&data[..4], b"RVNF");
+        let mut font = Font {
+            size:   f32::from(u16_at(5)),
+            ascent: f32::from(i16_at(7)),
+            glyphs: HashMap::new(),
+        };
+        let mut at = 13;
+        for _ in 0..u16_at(11) {
+            let c = u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+            let width = usize::from(u16_at(at + 10));
+            let height = usize::from(u16_at(at + 12));
+            let glyph = Glyph {
+                advance: f32::from(u16_at(at + 4)) / 64.0,
+                left: i32::from(i16_at(at + 6)),
+                top: i32::from(i16_at(at + 8)),
+                width,
+                height,
+                bitmap: data[at + 14..at + 14 + width * height].to_vec(),
+            };
+            at += 14 + width * height;
+            if let Some(c) = char::from_u32(c) {
+                font.glyphs.insert(c, glyph);
+            }
+        }
+        font
+    })
+}
+
+/// An RGB color, from `#rrggbb` or `#rgb`.
+fn color(value: &str) -> Result<[u8; 3]>
+{
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let digits = match hex.len() {
+        3 => hex.chars().flat_map(|x| [x, x]).collect::<String>(),
+        6 => hex.to_string(),
+        _ => String::new(),
+    };
+    let channel = |i: usize| {
+        digits
+            .get(i * 2..i * 2 + 2)
+            .and_then(|x| u8::from_str_radix(x, 16).ok())
+    };
+    match (channel(0), channel(1), channel(2)) {
+        (Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(Error::OgImage(format!("\"{value}\" isn't a color like \"#1e293b\""))),
+    }
+}
+
+/// An RGB image being drawn.
+struct Canvas
+{
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Canvas
+{
+    /// A canvas filled with `from`, fading diagonally into `to`.
+    fn gradient(from: [u8; 3], to: [u8; 3]) -> Self
+    {
+        let span = (WIDTH + HEIGHT - 2) as f32;
+        let pixels = (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x + y) as f32 / span))
+            .map(|t| [0, 1, 2].map(|i| blend(from[i], to[i], t)))
+            .collect();
+        Self { pixels }
+    }
+
+    /// Draw `text` at `size` with its baseline starting at `x`, `baseline`.
+    fn text(&mut self, font: &Font, text: &str, x: f32, baseline: f32, size: f32, color: [u8; 3])
+    {
+        let scale = size / font.size;
+        let mut pen = x;
+        for c in text.chars() {
+            let glyph = font.glyph(c);
+            self.glyph(
+                glyph,
+                pen + glyph.left as f32 * scale,
+                baseline - glyph.top as f32 * scale,
+                scale,
+                color,
+            );
+            pen += glyph.advance * scale;
+        }
+    }
+
+    /// Draw `glyph` scaled by `scale` with its top-left corner at `x`, `y`,
+    /// averaging the atlas pixels each canvas pixel covers.
+    fn glyph(&mut self, glyph: &Glyph, x: f32, y: f32, scale: f32, color: [u8; 3])
+    {
+        let right = (x + glyph.width as f32 * scale).ceil() as i64;
+        let bottom = (y + glyph.height as f32 * scale).ceil() as i64;
+        for dy in (y.floor() as i64).max(0)..bottom.min(HEIGHT as i64) {
+            for dx in (x.floor() as i64).max(0)..right.min(WIDTH as i64) {
+                let coverage = area(glyph, (dx as f32 - x) / scale, (dy as f32 - y) / scale, 1.0 / scale);
+                if coverage > 0.0 {
+                    let pixel = &mut self.pixels[dy as usize * WIDTH + dx as usize];
+                    *pixel = [0, 1, 2].map(|i| blend(pixel[i], color[i], coverage));
+                }
+            }
+        }
+    }
+
+    fn png(&self) -> Vec<u8>
+    {
+        let mut rows = Vec::with_capacity(HEIGHT * (WIDTH * 3 + 1));
+        for row in self.pixels.chunks(WIDTH) {
+            // No filter.
+            rows.push(0);
+            rows.extend(row.iter().flatten());
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        std::io::Write::write_all(&mut encoder, &rows).unwrap();
+        let data = encoder.finish().unwrap();
+
+        let mut header = Vec::with_capacity(13);
+        header.extend((WIDTH as u32).to_be_bytes());
+        header.extend((HEIGHT as u32).to_be_bytes());
+        // 8 bit RGB, not interlaced.
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, chunk) in [(b"IHDR", &header), (b"IDAT", &data), (b"IEND", &Vec::new())] {
+            png.extend((chunk.len() as u32).to_be_bytes());
+            png.extend(kind);
+            png.extend(chunk);
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(kind);
+            crc.update(chunk);
+            png.extend(crc.finalize().to_be_bytes());
+        }
+        png
+    }
+}
+
+fn blend(from: u8, to: u8, t: f32) -> u8 { (f32::from(from) + (f32::from(to) - f32::from(from)) * t).round() as u8 }
+
+/// The average coverage of the `size` × `size` square of `glyph` at `x`,
+/// `y`.
+fn area(glyph: &Glyph, x: f32, y: f32, size: f32) -> f32
+{
+    let mut total = 0.0;
+    for sy in (y.floor().max(0.0) as usize)..((y + size).ceil().max(0.0) as usize).min(glyph.height) {
+        let height = (y + size).min(sy as f32 + 1.0) - y.max(sy as f32);
+        for sx in (x.floor().max(0.0) as usize)..((x + size).ceil().max(0.0) as usize).min(glyph.width) {
+            let width = (x + size).min(sx as f32 + 1.0) - x.max(sx as f32);
+            total += f32::from(glyph.bitmap[sy * glyph.width + sx]) / 255.0 * width * height;
+        }
+    }
+    total / (size * size)
+}
+
+/// Break `text` into lines no wider than `width` at `size`, or `None` if it
+/// takes more than `max_lines`.
+fn wrap(font: &Font, text: &str, size: f32, width: f32, max_lines: usize) -> Option<Vec<String>>
+{
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if font.width(&format!("{line} {word}"), size) <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+        if lines.len() > max_lines || font.width(lines.last().unwrap(), size) > width {
+            return None;
+        }
+    }
+    Some(lines)
+}
+
+/// The title's lines and size: the biggest size it fits at, or else cut off
+/// with `…` at the smallest.
+fn title_lines(font: &Font, title: &str) -> (Vec<String>, f32)
+{
+    let width = WIDTH as f32 - 2.0 * PADDING;
+    for size in TITLE_SIZES {
+        if let Some(lines) = wrap(font, title, size, width, MAX_TITLE_LINES) {
+            return (lines, size);
+        }
+    }
+    let size = TITLE_SIZES[TITLE_SIZES.len() - 1];
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for c in title.chars().map(|c| {
+        if c.is_whitespace() {
+            ' '
+        }
+        else {
+            c
+        }
+    }) {
+        if font.width(&format!("{line}{c}…"), size) > width {
+            if lines.len() + 1 == MAX_TITLE_LINES {
+                line.push('…');
+                break;
+            }
+            lines.push(std::mem::take(&mut line).trim().to_string());
+        }
+        line.push(c);
+    }
+    lines.push(line.trim().to_string());
+    (lines, size)
+}
+
+/// The image of a page titled `title`, on the site `site_name`, as a PNG.
+///
+/// # Errors
+///
+/// Will return an error if a configured color isn't one.
+pub fn render(og_image: &OgImage, title: &str, site_name: &str) -> Result<Vec<u8>>
+{
+    let background = color(og_image.background.as_deref().unwrap_or("#1e293b"))?;
+    let background_end = match &og_image.background_end {
+        Some(x) => color(x)?,
+        None => background,
+    };
+    let text_color = color(og_image.color.as_deref().unwrap_or("#ffffff"))?;
+    let font = font();
+    let mut canvas = Canvas::gradient(background, background_end);
+
+    let (lines, size) = title_lines(font, title);
+    let line_height = size * 1.2;
+    let mut baseline = PADDING + font.ascent * size / font.size;
+    for line in &lines {
+        canvas.text(font, line, PADDING, baseline, size, text_color);
+        baseline += line_height;
+    }
+    if !site_name.is_empty() {
+        let site_name = site_name.split_whitespace().collect::<Vec<_>>().join(" ");
+        canvas.text(
+            font,
+            &site_name,
+            PADDING,
+            HEIGHT as f32 - PADDING,
+            SITE_NAME_SIZE,
+            text_color,
+        );
+    }
+    Ok(canvas.png())
+}
+
+/// Where the image of the page built from `source_file` is written: its dest
+/// path under [`OG_DIR`], with its directories joined by `-`.
+///
+/// # Errors
+///
+/// Will return an error if the page's dest path cannot be worked out.
+pub fn image_path(config: &Config, source_file: &Path, extention: &str) -> Result<PathBuf>
+{
+    let dest = dest_path(config, source_file, extention)?;
+    let relative = dest.strip_prefix(&config.dest).unwrap_or(&dest).with_extension("");
+    let slug = relative
+        .components()
+        .map(|x| slugify(&x.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("-");
+    Ok(config.dest.join(OG_DIR).join(format!("{slug}.png")))
+}
+
+/// If images are made, `[og_image]` without `enabled = false`.
+pub fn enabled(config: &Config) -> bool { config.og_image.as_ref().is_some_and(|x| x.enabled.unwrap_or(true)) }
+
+/// Write the image of every markdown page.
+///
+/// # Errors
+///
+/// Will return an error if a configured color isn't one, or an image cannot
+/// be written.
+pub async fn write(config: &Config) -> Result<()>
+{
+    let Some(og_image) = config.og_image.as_ref().filter(|_| enabled(config))
+    else {
+        return Ok(());
+    };
+    // Unreadable entries are warned about when the source files are walked.
+    for (source_file, extention) in source_files(config, &Warnings::new(false)) {
+        if !matches!(&*extention, "md" | "markdown") {
+            continue;
+        }
+        let Some(page_info) = sections::scan_page_info(config, &source_file)
+        else {
+            continue;
+        };
+        let png = render(og_image, &page_info.title, &site_name(config, &page_info))?;
+        write_output(&image_path(config, &source_file, &extention)?, png).await?;
+    }
+    Ok(())
+}
+
+/// Put the meta tags of the image of the page built from `source_file` in
+/// `html`, unless it's not a markdown page, or already has an `og:image`.
+pub fn link(config: &Config, source_file: &Path, html: String) -> String
+{
+    let extention = source_file
+        .extension()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !matches!(&*extention, "md" | "markdown") || html.contains("og:image\"") {
+        return html;
+    }
+    let (Ok(dest), Ok(image)) = (
+        dest_path(config, source_file, &extention),
+        image_path(config, source_file, &extention),
+    )
+    else {
+        return html;
+    };
+    let base_url = config.og_image.as_ref().and_then(|x| x.base_url.as_deref());
+    let url = match base_url {
+        Some(base_url) => {
+            let image = image.strip_prefix(&config.dest).unwrap_or(&image);
+            format!(
+                "{}/{}",
+                base_url.trim_end_matches('/'),
+                image.to_string_lossy().replace('\\', "/")
+            )
+        }
+        None => crate::links::relative_url(&dest, &image),
+    };
+    let tags = format!(
+        "<meta content=\"{}\" property=\"og:image\"><meta content=\"{WIDTH}\" property=\"og:image:width\"><meta \
+         content=\"{HEIGHT}\" property=\"og:image:height\"><meta content=\"summary_large_image\" \
+         name=\"twitter:card\">",
+        escape_attribute(&url)
+    );
+    inject(html, &tags)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that a long title is wrapped and shrunk, the image is a PNG of the
+    /// right size, and pages link to it
+    fn test_og_image()
+    {
+        let font = font();
+        assert!(font.glyphs.contains_key(&'é') && font.glyphs.contains_key(&'…'));
+        assert_eq!(title_lines(font, "Hello").0, vec!["Hello"]);
+        let (lines, size) = title_lines(font, &"A rather long title ".repeat(6));
+        assert!(lines.len() <= MAX_TITLE_LINES && size < TITLE_SIZES[0]);
+        let (lines, _) = title_lines(font, &"Much too long ".repeat(40));
+        assert_eq!(lines.len(), MAX_TITLE_LINES);
+        assert!(lines[MAX_TITLE_LINES - 1].ends_with('…'));
+
+        let og_image = OgImage {
+            enabled:        None,
+            background:     Some(String::from("#123")),
+            background_end: None,
+            color:          None,
+            base_url:       None,
+        };
+        let png = render(&og_image, "Hello", "Site").unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&png[16..24], &[0, 0, 4, 176, 0, 0, 2, 118]);
+        assert!(color("#12345").is_err());
+
+        let config = Config {
+            source: PathBuf::from("src"),
+            dest: PathBuf::from("dest"),
+            og_image: Some(OgImage {
+                base_url: Some(String::from("https://example.com/")),
+                ..og_image
+            }),
+            ..Config::default()
+        };
+        let html = link(&config, Path::new("src/blog/Post.md"), String::from("<head></head>"));
+        assert!(html.contains("<meta content=\"https://example.com/og/blog-post.png\" property=\"og:image\">"));
+        assert_eq!(
+            link(&config, Path::new("src/blog/post.html"), String::from("<head></head>")),
+            "<head></head>"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! Scaffolding new pages from archetypes.
+
+use crate::{defaults, markdown::slugify, Config, Error, PathBuf, Result};
+
+const ARCHETYPE_TITLE: &str = "[/rustic_title/]";
+const ARCHETYPE_DATE: &str = "[/rustic_date/]";
+const ARCHETYPE_SLUG: &str = "[/rustic_slug/]";
+const ARCHETYPE_SECTION: &str = "[/rustic_section/]";
+
+/// Create a markdown source for a page titled `title` in `section` (a
+/// directory under the source dir), and return its path.
+///
+/// The page is created from `<archetypes>/<section>.md`, falling back to
+/// `<archetypes>/default.md` and then to a built-in archetype. Archetypes
+/// that aren't in the project are looked up in the theme.
+///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - A page already exists at the new page's path
+/// - The archetype cannot be read
+/// - The page or its section's directory cannot be written to
+pub fn new_page(config: &Config, title: &str, section: Option<&str>) -> Result<PathBuf>
+{
+    let slug = slugify(title);
+    let directory = match section {
+        Some(section) => config.source.join(section),
+        None => config.source.clone(),
+    };
+    let path = directory.join(format!("{slug}.md"));
+    if path.exists() {
+        return Err(Error::PageExists(path));
+    }
+
+    let archetypes = config
+        .archetypes
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(Config::DEFAULT_ARCHETYPES_DIR));
+    let archetype_path = section
+        .map(|section| config.resolve(&archetypes.join(format!("{section}.md"))))
+        .filter(|x| x.is_file())
+        .unwrap_or_else(|| config.resolve(&archetypes.join("default.md")));
+    let archetype = if archetype_path.is_file() {
+        std::fs::read_to_string(&archetype_path).map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: archetype_path,
+            }
+        })?
+    }
+    else {
+        defaults::DEFAULT_MD_ARCHETYPE_SRC.to_string()
+    };
+
+    let contents = render_archetype(
+        &archetype,
+        title,
+        &slug,
+        section.unwrap_or_default(),
+        &config.now().format("%Y-%m-%d").to_string(),
+    );
+    std::fs::create_dir_all(&directory).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: directory.clone(),
+        }
+    })?;
+    std::fs::write(&path, contents).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: path.clone(),
+        }
+    })?;
+    Ok(path)
+}
+
+/// Fill in an archetype's tokens.
+///
+/// The title is escaped for a TOML string, which also reads correctly in
+/// markdown since a backslash before punctuation is a markdown escape too.
+fn render_archetype(archetype: &str, title: &str, slug: &str, section: &str, date: &str) -> String
+{
+    let title = title.replace('\\', "\\\\").replace('"', "\\\"");
+    archetype
+        .replace(ARCHETYPE_TITLE, &title)
+        .replace(ARCHETYPE_DATE, date)
+        .replace(ARCHETYPE_SLUG, slug)
+        .replace(ARCHETYPE_SECTION, section)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::PageInfo;
+
+    #[test]
+    /// Test that the default archetype renders to valid page info
+    fn test_render_default_archetype()
+    {
+        let title = "Say \"Hi\"";
+        let rendered = render_archetype(
+            defaults::DEFAULT_MD_ARCHETYPE_SRC,
+            title,
+            &slugify(title),
+            "blog",
+            "2023-03-14",
+        );
+        let page_info = rendered
+            .split("```")
+            .nth(1)
+            .and_then(|x| x.strip_prefix(PageInfo::CODE_BLOCK_IDENTIFIER))
+            .unwrap();
+        let page_info = toml::from_str::<PageInfo>(page_info).unwrap();
+        assert_eq!(page_info.title, title);
+        assert_eq!(page_info.date.unwrap().to_string(), "2023-03-14");
+        assert!(rendered.contains("# Say \\\"Hi\\\""));
+    }
+}
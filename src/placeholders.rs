@@ -0,0 +1,553 @@
+//! The `[/rustic_.../]` placeholders replaced in templates.
+//!
+//! Every placeholder, including the built-in ones, is supplied by a
+//! [`PlaceholderProvider`] registered on a [`Placeholders`] registry.
+//! Library users (and plugins built on the library) can register their own
+//! with [`crate::build::Website::register_placeholder`].
+//!
+//! Values are text unless their provider says they're HTML. Text is escaped
+//! for where its placeholder is: in an element's content, or in an
+//! attribute's value.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    asset_cache::AssetCache,
+    mdbook::{self, Summary},
+    report, Config, MetaAppendSiteNameToTitle, PageInfo, Path,
+};
+
+/// The start of every placeholder token.
+pub const TOKEN_PREFIX: &str = "[/rustic_";
+
+/// The end of every placeholder token.
+pub const TOKEN_SUFFIX: &str = "/]";
+
+/// What a provider knows about the page being rendered.
+pub struct PlaceholderContext<'a>
+{
+    pub config: &'a Config,
+
+    /// The source file of the page, if it has one.
+    pub source_file: Option<&'a Path>,
+
+    /// The page's page info. HTML sources used as templates have none.
+    pub page_info: Option<&'a PageInfo>,
+
+    /// The page's content, rendered to HTML.
+    pub body: Option<&'a str>,
+
+    /// The favicon, as HTML.
+    pub favicon: &'a str,
+
+    /// The stylesheet, as HTML.
+    pub stylesheet: &'a str,
+
+    /// The page's `default.head_extra` and `head_extra`, as HTML.
+    pub head: &'a str,
+
+    /// The text-based assets loaded into memory, keyed by their canonical
+    /// path.
+    pub assets: &'a AssetCache,
+}
+
+/// Supplies the value of the `[/rustic_<name>/]` placeholder.
+pub trait PlaceholderProvider: Send + Sync
+{
+    /// The name between `[/rustic_` and `/]`.
+    fn name(&self) -> &str;
+
+    /// The value to replace the placeholder with, or `None` to leave it in
+    /// place.
+    fn provide(&self, context: &PlaceholderContext) -> Option<String>;
+
+    /// If the value is HTML, inserted as is, rather than text to escape.
+    fn is_html(&self) -> bool { false }
+}
+
+/// A provider made from a name and a closure.
+struct FnProvider<F>
+{
+    name:    String,
+    html:    bool,
+    provide: F,
+}
+
+impl<F> PlaceholderProvider for FnProvider<F>
+where
+    F: Fn(&PlaceholderContext) -> Option<String> + Send + Sync,
+{
+    fn name(&self) -> &str { &self.name }
+
+    fn provide(&self, context: &PlaceholderContext) -> Option<String> { (self.provide)(context) }
+
+    fn is_html(&self) -> bool { self.html }
+}
+
+/// Where in a template a placeholder is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position
+{
+    /// An element's content.
+    Text,
+
+    /// Inside a tag, but not in a quoted attribute value.
+    Tag,
+
+    /// An attribute value quoted with the given character.
+    Attribute(char),
+}
+
+impl Position
+{
+    /// The position after `c`, which is followed by `rest`.
+    fn next(self, c: char, rest: &str) -> Self
+    {
+        match (self, c) {
+            (Self::Text, '<') if rest.starts_with(|x: char| x.is_ascii_alphabetic() || "/!?".contains(x)) => Self::Tag,
+            (Self::Tag, '"' | '\'') => Self::Attribute(c),
+            (Self::Tag, '>') => Self::Text,
+            (Self::Attribute(quote), _) if c == quote => Self::Tag,
+            (position, _) => position,
+        }
+    }
+}
+
+/// The providers placeholders are replaced with, in registration order.
+#[derive(Clone, Default)]
+pub struct Placeholders
+{
+    providers: Vec<Arc<dyn PlaceholderProvider>>,
+}
+
+impl Placeholders
+{
+    /// A registry of the built-in placeholders: `body`, `title`,
+    /// `description`, `name`, `authors`, `keywords`, `lang`, `favicon`,
+    /// `stylesheet`, `head`, and `nav`.
+    pub fn builtin() -> Self
+    {
+        let mut placeholders = Self::default();
+        placeholders.register_html_fn("body", |context| context.body.map(String::from));
+        placeholders.register_fn("title", |context| context.page_info.map(|x| title(context.config, x)));
+        placeholders.register_fn("description", |context| {
+            context.page_info.map(|x| x.description.clone())
+        });
+        placeholders.register_fn("name", |context| {
+            context.page_info.map(|x| site_name(context.config, x))
+        });
+        placeholders.register_fn("authors", |context| {
+            context.page_info.map(|x| authors(context.config, x))
+        });
+        placeholders.register_fn("keywords", |context| Some(keywords(context.config, context.page_info)));
+        placeholders.register_fn("lang", |context| Some(lang(context.config, context.page_info)));
+        placeholders.register_html_fn("favicon", |context| Some(context.favicon.to_string()));
+        placeholders.register_html_fn("stylesheet", |context| Some(context.stylesheet.to_string()));
+        placeholders.register_html_fn("head", |context| Some(context.head.to_string()));
+        placeholders.register_html_fn("nav", |context| Some(nav(context)));
+        placeholders
+    }
+
+    /// Add `provider`, replacing any provider with the same name.
+    pub fn register(&mut self, provider: impl PlaceholderProvider + 'static)
+    {
+        let provider: Arc<dyn PlaceholderProvider> = Arc::new(provider);
+        match self.providers.iter_mut().find(|x| x.name() == provider.name()) {
+            Some(existing) => *existing = provider,
+            None => self.providers.push(provider),
+        }
+    }
+
+    /// Add a provider named `name` whose text is supplied by `provide`,
+    /// replacing any provider with the same name.
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        provide: impl Fn(&PlaceholderContext) -> Option<String> + Send + Sync + 'static,
+    )
+    {
+        self.register(FnProvider {
+            name: name.to_string(),
+            html: false,
+            provide,
+        });
+    }
+
+    /// Add a provider named `name` whose HTML is supplied by `provide`,
+    /// replacing any provider with the same name.
+    pub fn register_html_fn(
+        &mut self,
+        name: &str,
+        provide: impl Fn(&PlaceholderContext) -> Option<String> + Send + Sync + 'static,
+    )
+    {
+        self.register(FnProvider {
+            name: name.to_string(),
+            html: true,
+            provide,
+        });
+    }
+
+    /// Whether `token` (e.g. `[/rustic_title/]`) has a provider.
+    pub fn is_known(&self, token: &str) -> bool
+    {
+        token
+            .strip_prefix(TOKEN_PREFIX)
+            .and_then(|x| x.strip_suffix(TOKEN_SUFFIX))
+            .is_some_and(|name| self.providers.iter().any(|x| x.name() == name))
+    }
+
+    /// The tokens in `template` that don't have a provider.
+    pub fn unknown_tokens(&self, template: &str) -> Vec<String>
+    {
+        let mut unknown = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(TOKEN_PREFIX) {
+            rest = &rest[start..];
+            let Some(end) = rest.find(TOKEN_SUFFIX)
+            else {
+                break;
+            };
+            let token = &rest[..end + TOKEN_SUFFIX.len()];
+            if !self.is_known(token) && !unknown.iter().any(|x| x == token) {
+                unknown.push(token.to_string());
+            }
+            rest = &rest[end + TOKEN_SUFFIX.len()..];
+        }
+        unknown
+    }
+
+    /// Replace every placeholder in `template` that its provider supplies a
+    /// value for, escaping text for where the placeholder is. With
+    /// `generation.error_markers`, placeholders in an element's content
+    /// without a value are replaced with an error marker.
+    ///
+    /// Values are inserted once, placeholders inside them aren't replaced.
+    pub fn apply(&self, template: &mut String, context: &PlaceholderContext)
+    {
+        let mut values: HashMap<&str, Option<(String, bool)>> = HashMap::new();
+        let mut output = String::with_capacity(template.len());
+        let mut position = Position::Text;
+        let mut rest = template.as_str();
+        while let Some(c) = rest.chars().next() {
+            if let Some((name, len)) = rest
+                .strip_prefix(TOKEN_PREFIX)
+                .and_then(|x| x.find(TOKEN_SUFFIX).map(|end| (&x[..end], end)))
+            {
+                let value = values.entry(name).or_insert_with(|| {
+                    let provider = self.providers.iter().find(|x| x.name() == name)?;
+                    Some((provider.provide(context)?, provider.is_html()))
+                });
+                if let Some((value, is_html)) = value {
+                    match position {
+                        _ if *is_html => output.push_str(value),
+                        Position::Text => output.push_str(&htmlescape::encode_minimal(value)),
+                        Position::Attribute(_) => output.push_str(&escape_attribute(value)),
+                        Position::Tag => output.push_str(&format!("\"{}\"", escape_attribute(value))),
+                    }
+                    rest = &rest[TOKEN_PREFIX.len() + len + TOKEN_SUFFIX.len()..];
+                    continue;
+                }
+                if position == Position::Text && context.config.error_markers() {
+                    output.push_str(&report::error_marker(&format!(
+                        "No value for {TOKEN_PREFIX}{name}{TOKEN_SUFFIX}"
+                    )));
+                    rest = &rest[TOKEN_PREFIX.len() + len + TOKEN_SUFFIX.len()..];
+                    continue;
+                }
+            }
+            rest = &rest[c.len_utf8()..];
+            position = position.next(c, rest);
+            output.push(c);
+        }
+        *template = output;
+    }
+}
+
+/// Escape `value` for use in a quoted attribute value.
+///
+/// Unlike `htmlescape::encode_minimal`, which is for text, this also encodes
+/// whitespace that would otherwise be normalized when the attribute is read.
+/// Everything else, including non-ASCII characters, is left as is.
+pub fn escape_attribute(value: &str) -> String
+{
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' => escaped.push_str("&#9;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn site_name(config: &Config, page_info: &PageInfo) -> String
+{
+    match &page_info.meta {
+        Some(meta) => meta.site_name.clone(),
+        None => {
+            config
+                .default
+                .meta
+                .as_ref()
+                .map_or_else(String::new, |meta| meta.site_name.clone())
+        }
+    }
+}
+
+fn authors(config: &Config, page_info: &PageInfo) -> String
+{
+    match &page_info.meta {
+        Some(meta) => meta.authors.join(", "),
+        None => {
+            config
+                .default
+                .meta
+                .as_ref()
+                .map_or_else(String::new, |meta| meta.authors.join(", "))
+        }
+    }
+}
+
+/// The page's keywords, or the default ones, separated by commas.
+fn keywords(config: &Config, page_info: Option<&PageInfo>) -> String
+{
+    page_info
+        .and_then(|x| x.keywords.as_ref())
+        .or(config.default.keywords.as_ref())
+        .map(|x| x.join(", "))
+        .unwrap_or_default()
+}
+
+/// The page's language, or the default one.
+fn lang(config: &Config, page_info: Option<&PageInfo>) -> String
+{
+    page_info
+        .and_then(|x| x.lang.as_deref())
+        .or(config.default.lang.as_deref())
+        .unwrap_or("en")
+        .to_string()
+}
+
+/// The page's title, with the site name appended if configured.
+fn title(config: &Config, page_info: &PageInfo) -> String
+{
+    let site_name = site_name(config, page_info);
+    let mut title = page_info.title.clone();
+    if let Some(meta) = &config.meta {
+        match &meta.append_site_name_to_title {
+            Some(MetaAppendSiteNameToTitle::Default(true)) => title.push_str(&format!(" — {site_name}")),
+            Some(MetaAppendSiteNameToTitle::Custom(s)) => title.push_str(&format!("{s}{site_name}")),
+            _ => {}
+        }
+    }
+    title
+}
+
+/// The summary of the mdBook the page is in, as its navigation. Empty for
+/// pages that aren't in one, combined book pages, and pages whose summary
+/// cannot be read.
+fn nav(context: &PlaceholderContext) -> String
+{
+    let Some(source_file) = context.source_file.filter(|x| x.is_file())
+    else {
+        return String::new();
+    };
+    let Some((book, path)) = mdbook::book_of(context.config, source_file)
+        .and_then(|book| Some((book, mdbook::summary_path(context.config, book)?)))
+    else {
+        return String::new();
+    };
+    let summary = match context.assets.get(&path) {
+        Some(summary) => summary,
+        None => {
+            let Ok(summary) = std::fs::read_to_string(&path)
+            else {
+                return String::new();
+            };
+            context.assets.insert(path, summary.clone());
+            summary
+        }
+    };
+    Summary::parse(&summary).to_html(context.config, book, source_file)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that registered providers are applied and override built-ins
+    fn test_register_placeholder()
+    {
+        let config = Config::default();
+        let assets = AssetCache::new();
+        let context = PlaceholderContext {
+            config:      &config,
+            source_file: None,
+            page_info:   None,
+            body:        Some("<p>Hi</p>"),
+            favicon:     "",
+            stylesheet:  "",
+            head:        "",
+            assets:      &assets,
+        };
+
+        let mut placeholders = Placeholders::builtin();
+        placeholders.register_fn("year", |_| Some(String::from("2023")));
+        placeholders.register_html_fn("body", |context| context.body.map(|x| format!("<main>{x}</main>")));
+        let mut template = String::from("[/rustic_body/] [/rustic_year/] [/rustic_title/] [/rustic_nope/]");
+        assert_eq!(placeholders.unknown_tokens(&template), ["[/rustic_nope/]"]);
+        placeholders.apply(&mut template, &context);
+        assert_eq!(template, "<main><p>Hi</p></main> 2023 [/rustic_title/] [/rustic_nope/]");
+    }
+
+    #[test]
+    /// Test that a placeholder in a substituted value isn't replaced
+    fn test_value_not_substituted()
+    {
+        let config = Config::default();
+        let assets = AssetCache::new();
+        let context = PlaceholderContext {
+            config:      &config,
+            source_file: None,
+            page_info:   None,
+            body:        Some("<p>[/rustic_year/]</p>"),
+            favicon:     "",
+            stylesheet:  "",
+            head:        "",
+            assets:      &assets,
+        };
+
+        let mut placeholders = Placeholders::builtin();
+        placeholders.register_fn("year", |_| Some(String::from("2023")));
+        let mut template = String::from("[/rustic_body/] [/rustic_year/]");
+        placeholders.apply(&mut template, &context);
+        assert_eq!(template, "<p>[/rustic_year/]</p> 2023");
+    }
+
+    #[test]
+    /// Test that a placeholder's provider is asked for its value once, however
+    /// many times it's in the template
+    fn test_value_provided_once()
+    {
+        let config = Config::default();
+        let assets = AssetCache::new();
+        let context = PlaceholderContext {
+            config:      &config,
+            source_file: None,
+            page_info:   None,
+            body:        None,
+            favicon:     "",
+            stylesheet:  "",
+            head:        "",
+            assets:      &assets,
+        };
+
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut placeholders = Placeholders::builtin();
+        let counter = calls.clone();
+        placeholders.register_fn("count", move |_| {
+            let count = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            Some(count.to_string())
+        });
+        let mut template = String::from("[/rustic_count/] [/rustic_count/] [/rustic_count/]");
+        placeholders.apply(&mut template, &context);
+        assert_eq!(template, "1 1 1");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    /// Test that with the dev profile, a placeholder without a value in an
+    /// element's content is replaced with an error marker, and one in an
+    /// attribute is left as it is
+    fn test_error_marker_placeholder()
+    {
+        let config = Config {
+            profile: Some(String::from("dev")),
+            ..Config::default()
+        };
+        let assets = AssetCache::new();
+        let context = PlaceholderContext {
+            config:      &config,
+            source_file: None,
+            page_info:   None,
+            body:        None,
+            favicon:     "",
+            stylesheet:  "",
+            head:        "",
+            assets:      &assets,
+        };
+        let placeholders = Placeholders::builtin();
+        let mut template = String::from("<a title=\"[/rustic_nope/]\">[/rustic_nope/]</a>");
+        placeholders.apply(&mut template, &context);
+        assert_eq!(
+            template,
+            format!(
+                "<a title=\"[/rustic_nope/]\">{}</a>",
+                report::error_marker("No value for [/rustic_nope/]")
+            )
+        );
+    }
+
+    #[test]
+    /// Test that text is escaped for the element content or attribute it's in
+    fn test_escape_by_position()
+    {
+        let config = Config::default();
+        let assets = AssetCache::new();
+        let context = PlaceholderContext {
+            config:      &config,
+            source_file: None,
+            page_info:   None,
+            body:        Some("<p>\"Hi\"</p>"),
+            favicon:     "",
+            stylesheet:  "",
+            head:        "",
+            assets:      &assets,
+        };
+
+        let mut placeholders = Placeholders::builtin();
+        placeholders.register_fn("text", |_| Some(String::from("Tom's \"<b>\" &\nJérôme ✓")));
+        let mut template = String::from(
+            "<meta content=\"[/rustic_text/]\" name='[/rustic_text/]' \
+             alt=[/rustic_text/]><title>[/rustic_text/]</title> [/rustic_body/] a < b [/rustic_text/]",
+        );
+        placeholders.apply(&mut template, &context);
+        let attribute = "Tom&#39;s &quot;&lt;b&gt;&quot; &amp;&#10;Jérôme ✓";
+        let text = htmlescape::encode_minimal("Tom's \"<b>\" &\nJérôme ✓");
+        assert_eq!(
+            template,
+            format!(
+                "<meta content=\"{attribute}\" name='{attribute}' alt=\"{attribute}\"><title>{text}</title> \
+                 <p>\"Hi\"</p> a < b {text}"
+            )
+        );
+    }
+
+    #[test]
+    /// Test that a page's keywords and language come before the defaults
+    fn test_keywords_and_lang()
+    {
+        let mut config = Config::default();
+        config.default.keywords = Some(vec![String::from("rust"), String::from("ssg")]);
+        let mut page_info = PageInfo::new(String::from("A"), String::from("A"));
+        assert_eq!(keywords(&config, Some(&page_info)), "rust, ssg");
+        assert_eq!(lang(&config, Some(&page_info)), "en");
+
+        page_info.keywords = Some(vec![String::from("ravens")]);
+        page_info.lang = Some(String::from("fr-CA"));
+        config.default.lang = Some(String::from("de"));
+        assert_eq!(keywords(&config, Some(&page_info)), "ravens");
+        assert_eq!(lang(&config, Some(&page_info)), "fr-CA");
+        assert_eq!(lang(&config, None), "de");
+    }
+}
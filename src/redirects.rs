@@ -0,0 +1,375 @@
+//! Keeping deep links to pages with [stable IDs](crate::links) working when
+//! their URLs or heading IDs change.
+//!
+//! After every build the dest path and heading IDs of each page with an ID
+//! are recorded in a manifest, [`MANIFEST_FILE`]. The next build compares
+//! against it and lists what moved in its summary. With
+//! `generation.redirects` it also writes a redirect stub where a moved page
+//! used to be, and gives a page an empty element with each renamed heading's
+//! old ID, so `#old-id` links still land on the heading.
+
+use std::{collections::BTreeMap, fmt, fs};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    build::{dest_path, write_output},
+    links::{relative_url, PageIndex},
+    Config, Error, PathBuf, Result,
+};
+
+/// Where the manifest of the last build is kept, relative to the project.
+pub const MANIFEST_FILE: &str = ".raven-manifest.json";
+
+/// What the last build produced for each page with an ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Manifest
+{
+    /// Each page's dest path, relative to the dest dir, and heading IDs.
+    pub pages: BTreeMap<String, ManifestPage>,
+
+    /// The redirect stubs written so far, by their dest path, relative to
+    /// the dest dir, and the ID of the page they redirect to.
+    #[serde(default)]
+    pub redirects: BTreeMap<PathBuf, String>,
+
+    /// The old heading IDs of each page that still lead to the new ones.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ManifestPage
+{
+    pub dest:     PathBuf,
+    pub headings: Vec<String>,
+}
+
+/// A page or heading whose URL changed since the last build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change
+{
+    Moved
+    {
+        id: String, from: PathBuf, to: PathBuf
+    },
+    HeadingRenamed
+    {
+        id: String, from: String, to: String
+    },
+    HeadingRemoved
+    {
+        id: String, heading: String
+    },
+}
+
+impl fmt::Display for Change
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            Self::Moved { id, from, to } => {
+                write!(
+                    f,
+                    "Page \"{id}\" moved from \"{}\" to \"{}\"",
+                    from.display(),
+                    to.display()
+                )
+            }
+            Self::HeadingRenamed { id, from, to } => write!(f, "Heading \"#{from}\" of page \"{id}\" is now \"#{to}\""),
+            Self::HeadingRemoved { id, heading } => write!(f, "Heading \"#{heading}\" of page \"{id}\" was removed"),
+        }
+    }
+}
+
+impl Manifest
+{
+    /// The manifest of the last build, or an empty one if there wasn't one.
+    pub fn load() -> Self
+    {
+        fs::read_to_string(MANIFEST_FILE)
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save the manifest for the next build.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the manifest cannot be written.
+    pub fn save(&self) -> Result<()>
+    {
+        fs::write(MANIFEST_FILE, serde_json::to_string_pretty(self).unwrap()).map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: PathBuf::from(MANIFEST_FILE),
+            }
+        })
+    }
+
+    /// The manifest of this build: the pages of `index`, with the heading IDs
+    /// in `headings` for the pages that were rendered, and the previous ones
+    /// for the pages that weren't.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a page's dest path cannot be worked out.
+    pub fn current(&self, config: &Config, index: &PageIndex, headings: &BTreeMap<String, Vec<String>>)
+        -> Result<Self>
+    {
+        let mut pages = BTreeMap::new();
+        for (id, source_file) in index.pages() {
+            let extention = source_file
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let dest = dest_path(config, source_file, &extention)?;
+            let dest = dest.strip_prefix(&config.dest).unwrap_or(&dest).to_path_buf();
+            let headings = headings
+                .get(id)
+                .or_else(|| self.pages.get(id).map(|x| &x.headings))
+                .cloned()
+                .unwrap_or_default();
+            pages.insert(id.to_string(), ManifestPage { dest, headings });
+        }
+        Ok(Self {
+            pages,
+            redirects: self.redirects.clone(),
+            aliases: self.aliases.clone(),
+        })
+    }
+
+    /// What changed from the `previous` build to this one.
+    pub fn changes(&self, previous: &Self) -> Vec<Change>
+    {
+        let mut changes = Vec::new();
+        for (id, page) in &self.pages {
+            let Some(old) = previous.pages.get(id)
+            else {
+                continue;
+            };
+            if old.dest != page.dest {
+                changes.push(Change::Moved {
+                    id:   id.clone(),
+                    from: old.dest.clone(),
+                    to:   page.dest.clone(),
+                });
+            }
+
+            let removed = old.headings.iter().filter(|x| !page.headings.contains(x));
+            for heading in removed {
+                // A heading renamed in place is at the same position, if
+                // there are as many headings as before.
+                let position = old.headings.iter().position(|x| x == heading);
+                let renamed = position
+                    .filter(|_| old.headings.len() == page.headings.len())
+                    .map(|i| &page.headings[i])
+                    .filter(|x| !old.headings.contains(x));
+                changes.push(match renamed {
+                    Some(to) => {
+                        Change::HeadingRenamed {
+                            id:   id.clone(),
+                            from: heading.clone(),
+                            to:   to.clone(),
+                        }
+                    }
+                    None => {
+                        Change::HeadingRemoved {
+                            id:      id.clone(),
+                            heading: heading.clone(),
+                        }
+                    }
+                });
+            }
+        }
+        changes
+    }
+
+    /// Record the redirects and heading aliases for `changes`, and write
+    /// every redirect stub and alias into the dest dir. Stubs where a page
+    /// now is are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a stub or a page cannot be written.
+    pub async fn apply(&mut self, config: &Config, changes: &[Change]) -> Result<()>
+    {
+        for change in changes {
+            match change {
+                Change::Moved { id, from, .. } => {
+                    self.redirects.insert(from.clone(), id.clone());
+                }
+                Change::HeadingRenamed { id, from, to } => {
+                    let aliases = self.aliases.entry(id.clone()).or_default();
+                    // Older aliases of the renamed heading follow it.
+                    for target in aliases.values_mut().filter(|x| *x == from) {
+                        target.clone_from(to);
+                    }
+                    aliases.insert(from.clone(), to.clone());
+                }
+                Change::HeadingRemoved { .. } => {}
+            }
+        }
+        let generated = self.pages.values().map(|x| x.dest.clone()).collect::<Vec<_>>();
+        self.redirects.retain(|from, _| !generated.contains(from));
+
+        for (from, id) in &self.redirects {
+            let Some(page) = self.pages.get(id)
+            else {
+                continue;
+            };
+            let from = config.dest.join(from);
+            let url = relative_url(&from, &config.dest.join(&page.dest));
+            write_output(&from, stub(&url)).await?;
+        }
+        for (id, aliases) in &self.aliases {
+            let Some(page) = self.pages.get(id)
+            else {
+                continue;
+            };
+            let dest = config.dest.join(&page.dest);
+            let Ok(html) = fs::read_to_string(&dest)
+            else {
+                continue;
+            };
+            let aliased = add_aliases(&html, aliases);
+            if aliased != html {
+                write_output(&dest, aliased).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// If redirect stubs and heading aliases are written,
+/// `generation.redirects`.
+pub fn enabled(config: &Config) -> bool
+{
+    config
+        .generation
+        .as_ref()
+        .and_then(|generation| generation.redirects)
+        .unwrap_or(false)
+}
+
+/// A page that redirects to `url`.
+pub(crate) fn stub(url: &str) -> String
+{
+    let url = htmlescape::encode_minimal(url);
+    format!(
+        "<!DOCTYPE html><html lang=\"en\"><meta charset=\"UTF-8\"><title>Redirecting</title><meta \
+         http-equiv=\"refresh\" content=\"0; url={url}\"><link rel=\"canonical\" href=\"{url}\"><p>This page has \
+         moved to <a href=\"{url}\">{url}</a>.</p></html>"
+    )
+}
+
+/// Put an empty element with each old heading ID in `aliases` before the
+/// heading with the new ID, unless `html` already has one.
+fn add_aliases(html: &str, aliases: &BTreeMap<String, String>) -> String
+{
+    let mut html = html.to_string();
+    for (from, to) in aliases {
+        let from_attribute = format!(" id=\"{}\"", htmlescape::encode_minimal(from));
+        if html.contains(&from_attribute) {
+            continue;
+        }
+        let heading = Regex::new(&format!(
+            r#"<h[1-6][^>]* id="{}""#,
+            regex::escape(&htmlescape::encode_minimal(to))
+        ))
+        .unwrap();
+        if let Some(found) = heading.find(&html) {
+            html.insert_str(found.start(), &format!("<span{from_attribute}></span>"));
+        }
+    }
+    html
+}
+
+/// Compare this build's pages with the last build's, and save them for the
+/// next one. With `generation.redirects`, write the redirect stubs and
+/// heading aliases for what changed too.
+///
+/// # Errors
+///
+/// Will return an error if the manifest, a stub, or a page cannot be
+/// written.
+pub(crate) async fn update(
+    config: &Config,
+    index: &PageIndex,
+    headings: &BTreeMap<String, Vec<String>>,
+) -> Result<Vec<Change>>
+{
+    let previous = Manifest::load();
+    let mut current = previous.current(config, index, headings)?;
+    let changes = current.changes(&previous);
+    if enabled(config) {
+        current.apply(config, &changes).await?;
+    }
+    if current != previous {
+        current.save()?;
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that moved pages and renamed or removed headings are found, and
+    /// that aliases land before the renamed heading
+    fn test_changes()
+    {
+        let page = |dest: &str, headings: &[&str]| {
+            ManifestPage {
+                dest:     PathBuf::from(dest),
+                headings: headings.iter().map(ToString::to_string).collect(),
+            }
+        };
+        let previous = Manifest {
+            pages: BTreeMap::from([
+                (String::from("start"), page("start.html", &["install", "usage"])),
+                (String::from("faq"), page("faq.html", &["why", "how"])),
+            ]),
+            ..Manifest::default()
+        };
+        let current = Manifest {
+            pages: BTreeMap::from([
+                (String::from("start"), page("docs/start.html", &["installing", "usage"])),
+                (String::from("faq"), page("faq.html", &["why"])),
+            ]),
+            ..Manifest::default()
+        };
+        assert_eq!(
+            current.changes(&previous),
+            vec![
+                Change::HeadingRemoved {
+                    id:      String::from("faq"),
+                    heading: String::from("how"),
+                },
+                Change::Moved {
+                    id:   String::from("start"),
+                    from: PathBuf::from("start.html"),
+                    to:   PathBuf::from("docs/start.html"),
+                },
+                Change::HeadingRenamed {
+                    id:   String::from("start"),
+                    from: String::from("install"),
+                    to:   String::from("installing"),
+                },
+            ]
+        );
+
+        let aliases = BTreeMap::from([(String::from("install"), String::from("installing"))]);
+        let html = "<h2 id=\"installing\">Installing</h2>";
+        let aliased = add_aliases(html, &aliases);
+        assert_eq!(
+            aliased,
+            "<span id=\"install\"></span><h2 id=\"installing\">Installing</h2>"
+        );
+        assert_eq!(add_aliases(&aliased, &aliases), aliased);
+    }
+}
@@ -0,0 +1,449 @@
+//! Warnings, and machine-readable reports of what a build did.
+
+use std::{sync::Mutex, time::Duration};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{timings::Timings, Error, Path, PathBuf, Result};
+
+/// Something that's likely a mistake, but doesn't stop a page from being
+/// built. With `generation.strict` they fail the build instead.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Warning
+{
+    #[error(
+        "[{}] MissingFaviconWarning: \"{source_file}\": Requested favicon file \"{favicon}\", but it doesn't exist, \
+         so it falls back to the next one",
+        crate::NAME
+    )]
+    MissingFavicon
+    {
+        source_file: PathBuf, favicon: PathBuf
+    },
+
+    #[error(
+        "[{}] MissingTemplateWarning: \"{source_file}\": Requested template file \"{template}\", but it doesn't \
+         exist, so the page is skipped",
+        crate::NAME
+    )]
+    MissingTemplate
+    {
+        source_file: PathBuf, template: PathBuf
+    },
+
+    #[error(
+        "[{}] UnknownFenceLanguageWarning: \"{source_file}\": No syntax for the code block language \"{language}\", \
+         so it isn't highlighted",
+        crate::NAME
+    )]
+    UnknownFenceLanguage
+    {
+        source_file: PathBuf, language: String
+    },
+
+    #[error(
+        "[{}] UnknownTemplateTokenWarning: \"{template}\": \"{token}\" isn't a known template token, so it's left in \
+         the page",
+        crate::NAME
+    )]
+    UnknownTemplateToken
+    {
+        template: PathBuf, token: String
+    },
+
+    #[error(
+        "[{}] EmptyDescriptionWarning: \"{0}\": The page's description is empty",
+        crate::NAME
+    )]
+    EmptyDescription(PathBuf),
+
+    #[error(
+        "[{}] DerivedPageInfoWarning: \"{source_file}\": The page info has no {field}, so it's taken from the page",
+        crate::NAME
+    )]
+    DerivedPageInfo
+    {
+        source_file: PathBuf,
+        field:       &'static str,
+    },
+
+    #[error(
+        "[{}] DuplicateHeadingWarning: \"{source_file}\": Heading \"{text}\" has the same ID as an earlier heading, \
+         using \"{id}\"",
+        crate::NAME
+    )]
+    DuplicateHeading
+    {
+        source_file: PathBuf,
+        text:        String,
+        id:          String,
+    },
+
+    #[error(
+        "[{}] MissingAltTextWarning: \"{source_file}\": Image \"{image}\" has no alt text",
+        crate::NAME
+    )]
+    MissingAltText
+    {
+        source_file: PathBuf, image: String
+    },
+
+    #[error(
+        "[{}] LargeInlineAssetWarning: \"{asset}\": Inlines {size} bytes into every page that uses it, over the limit \
+         of {limit} bytes. Link it from the template instead, or raise `generation.inline_limits`",
+        crate::NAME
+    )]
+    LargeInlineAsset
+    {
+        asset: PathBuf, size: usize, limit: usize
+    },
+
+    #[error(
+        "[{}] UnresolvedWikiLinkWarning: \"{source_file}\": No page is titled or named \"{target}\", so [[{target}]] \
+         is left as it is",
+        crate::NAME
+    )]
+    UnresolvedWikiLink
+    {
+        source_file: PathBuf, target: String
+    },
+
+    #[error(
+        "[{}] AmbiguousWikiLinkWarning: \"{source_file}\": {pages} pages are titled or named \"{target}\", so \
+         [[{target}]] is left as it is",
+        crate::NAME
+    )]
+    AmbiguousWikiLink
+    {
+        source_file: PathBuf,
+        target:      String,
+        pages:       usize,
+    },
+
+    #[error(
+        "[{}] ErrorMarkerWarning: \"{source_file}\": Marked in the page so the build goes on: {err}",
+        crate::NAME
+    )]
+    ErrorMarker
+    {
+        source_file: PathBuf, err: String
+    },
+
+    #[error("[{}] ReadSourceDirWarning: \"{path}\": {err}", crate::NAME)]
+    ReadSourceDir
+    {
+        path: PathBuf, err: String
+    },
+}
+
+impl Warning
+{
+    /// The file the warning is about.
+    pub fn path(&self) -> &Path
+    {
+        match self {
+            Self::MissingFavicon { source_file, .. }
+            | Self::MissingTemplate { source_file, .. }
+            | Self::UnknownFenceLanguage { source_file, .. }
+            | Self::DuplicateHeading { source_file, .. }
+            | Self::MissingAltText { source_file, .. }
+            | Self::UnresolvedWikiLink { source_file, .. }
+            | Self::AmbiguousWikiLink { source_file, .. }
+            | Self::DerivedPageInfo { source_file, .. }
+            | Self::ErrorMarker { source_file, .. } => source_file,
+            Self::UnknownTemplateToken { template, .. } => template,
+            Self::LargeInlineAsset { asset, .. } => asset,
+            Self::EmptyDescription(path) | Self::ReadSourceDir { path, .. } => path,
+        }
+    }
+}
+
+/// A visible marker put in a page in place of what failed to render, with
+/// `generation.error_markers`.
+pub fn error_marker(message: &str) -> String
+{
+    format!(
+        "<span class=\"raven-error\" style=\"background:#fdd;color:#900;border:1px solid #c00;padding:0 \
+         .25em\">{}</span>",
+        htmlescape::encode_minimal(message)
+    )
+}
+
+/// The warnings of a build, kept for its report.
+#[derive(Debug)]
+pub struct Warnings
+{
+    warnings: Mutex<Vec<Warning>>,
+
+    /// If warnings are printed as well as kept.
+    print: bool,
+}
+
+impl Default for Warnings
+{
+    fn default() -> Self { Self::new(true) }
+}
+
+impl Warnings
+{
+    /// No warnings yet, printed as they're added if `print`, or only kept for
+    /// programs that read them from [`Self::take`].
+    pub fn new(print: bool) -> Self
+    {
+        Self {
+            warnings: Mutex::new(Vec::new()),
+            print,
+        }
+    }
+
+    /// Print `warning` and keep it. A warning that's already been kept since
+    /// the warnings were last taken isn't repeated.
+    pub fn warn(&self, warning: Warning)
+    {
+        let mut warnings = self.warnings.lock().unwrap();
+        if !warnings.contains(&warning) {
+            if self.print {
+                eprintln!("{warning}");
+            }
+            warnings.push(warning);
+        }
+    }
+
+    /// The warnings kept since the last call.
+    pub fn take(&self) -> Vec<Warning> { std::mem::take(&mut *self.warnings.lock().unwrap()) }
+}
+
+/// What happened to a source file during a build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageStatus
+{
+    /// Rendered and written.
+    Built,
+
+    /// Not written, because it's up to date or can't be built.
+    Skipped,
+
+    /// Written as it is, without templating.
+    Copied,
+
+    /// Not written, because building it failed.
+    Failed,
+}
+
+/// A source file and what the build did with it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageReport
+{
+    pub source:      PathBuf,
+    pub dest:        PathBuf,
+    pub status:      PageStatus,
+    pub duration_ms: f64,
+
+    /// Why the page failed to build.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PageReport
+{
+    pub fn new(source: PathBuf, dest: PathBuf, status: PageStatus, duration: Duration) -> Self
+    {
+        Self {
+            source,
+            dest,
+            status,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            error: None,
+        }
+    }
+
+    /// A page that failed to build because of `error`.
+    pub fn failed(source: PathBuf, dest: PathBuf, error: &Error, duration: Duration) -> Self
+    {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::new(source, dest, PageStatus::Failed, duration)
+        }
+    }
+}
+
+/// What a build did, for CI pipelines and deploy hooks.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BuildReport
+{
+    pub built:       usize,
+    pub skipped:     usize,
+    pub copied:      usize,
+    pub failed:      usize,
+    pub warnings:    Vec<String>,
+    pub duration_ms: f64,
+
+    /// The pages and headings whose URLs changed since the last build.
+    pub changes: Vec<String>,
+
+    /// Every source file, in the order they were found.
+    pub pages: Vec<PageReport>,
+
+    /// How long each stage took, with `--timings`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+}
+
+impl BuildReport
+{
+    pub fn new(pages: Vec<PageReport>, warnings: &[Warning], duration: Duration) -> Self
+    {
+        let count = |status| pages.iter().filter(|x| x.status == status).count();
+        Self {
+            built: count(PageStatus::Built),
+            skipped: count(PageStatus::Skipped),
+            copied: count(PageStatus::Copied),
+            failed: count(PageStatus::Failed),
+            warnings: warnings.iter().map(ToString::to_string).collect(),
+            changes: Vec::new(),
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            pages,
+            timings: None,
+        }
+    }
+
+    /// Fail the page each warning is about, for `generation.strict`. Warnings
+    /// about files that aren't pages, like templates, are added as failures of
+    /// their own.
+    pub fn promote_warnings(pages: &mut Vec<PageReport>, warnings: &[Warning])
+    {
+        for warning in warnings {
+            let error = Error::Warning(warning.clone());
+            match pages.iter_mut().find(|x| x.source == warning.path()) {
+                Some(page) if page.status == PageStatus::Failed => {}
+                Some(page) => {
+                    page.status = PageStatus::Failed;
+                    page.error = Some(error.to_string());
+                }
+                None => {
+                    pages.push(PageReport::failed(
+                        warning.path().to_path_buf(),
+                        PathBuf::new(),
+                        &error,
+                        Duration::ZERO,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Add the counts, warnings, changes, and pages of `other`, a build of
+    /// another copy of the site.
+    pub fn merge(&mut self, other: Self)
+    {
+        self.built += other.built;
+        self.skipped += other.skipped;
+        self.copied += other.copied;
+        self.failed += other.failed;
+        self.warnings.extend(other.warnings);
+        self.duration_ms += other.duration_ms;
+        self.changes.extend(other.changes);
+        self.pages.extend(other.pages);
+        match (&mut self.timings, other.timings) {
+            (Some(timings), Some(other)) => timings.merge(other),
+            (timings, other) => *timings = timings.take().or(other),
+        }
+    }
+
+    /// The pages that failed to build.
+    pub fn failures(&self) -> impl Iterator<Item = &PageReport>
+    {
+        self.pages.iter().filter(|x| x.status == PageStatus::Failed)
+    }
+
+    /// The report as pretty-printed JSON.
+    pub fn to_json(&self) -> String { serde_json::to_string_pretty(self).unwrap() }
+
+    /// Write the report as JSON to `path`, or to stdout if it's `None` or
+    /// `-`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if `path` cannot be written to.
+    pub fn write_json(&self, path: Option<&Path>) -> Result<()>
+    {
+        match path.filter(|x| *x != Path::new("-")) {
+            Some(path) => {
+                std::fs::write(path, self.to_json() + "\n").map_err(|e| {
+                    Error::Io {
+                        err:  e,
+                        path: path.to_path_buf(),
+                    }
+                })
+            }
+            None => {
+                println!("{}", self.to_json());
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that pages are counted by status, statuses are lowercase, and
+    /// failures keep their error
+    fn test_build_report()
+    {
+        let page = |status| PageReport::new(PathBuf::from("a.md"), PathBuf::from("a.html"), status, Duration::ZERO);
+        let report = BuildReport::new(
+            vec![
+                page(PageStatus::Built),
+                page(PageStatus::Skipped),
+                page(PageStatus::Built),
+                PageReport::failed(
+                    PathBuf::from("b.md"),
+                    PathBuf::from("b.html"),
+                    &Error::MissingPageInfo(PathBuf::from("b.md")),
+                    Duration::ZERO,
+                ),
+            ],
+            &[Warning::EmptyDescription(PathBuf::from("a.md"))],
+            Duration::from_millis(5),
+        );
+        assert_eq!(
+            (report.built, report.skipped, report.copied, report.failed),
+            (2, 1, 0, 1)
+        );
+        assert_eq!(report.duration_ms, 5.0);
+        assert!(report.to_json().contains("\"status\": \"skipped\""));
+        assert_eq!(report.failures().count(), 1);
+        assert!(report.to_json().contains("MissingPageInfoError"));
+    }
+
+    #[test]
+    /// Test that warnings fail the page they're about, or are added as failures
+    fn test_promote_warnings()
+    {
+        let mut pages = vec![PageReport::new(
+            PathBuf::from("a.md"),
+            PathBuf::from("a.html"),
+            PageStatus::Built,
+            Duration::ZERO,
+        )];
+        BuildReport::promote_warnings(
+            &mut pages,
+            &[
+                Warning::EmptyDescription(PathBuf::from("a.md")),
+                Warning::UnknownTemplateToken {
+                    template: PathBuf::from("template.html"),
+                    token:    String::from("[/rustic_x/]"),
+                },
+            ],
+        );
+        assert_eq!(pages.len(), 2);
+        assert!(pages.iter().all(|x| x.status == PageStatus::Failed));
+        assert!(pages[0].error.as_ref().unwrap().contains("EmptyDescriptionWarning"));
+    }
+}
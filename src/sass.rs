@@ -0,0 +1,197 @@
+//! Sass stylesheets: a stylesheet whose file ends in `.scss` or `.sass` is
+//! compiled to CSS with [grass] before it's inlined.
+//!
+//! The partials a stylesheet loads, with `@use`, `@forward`, or `@import`,
+//! are found the way Sass finds them, so a page is rebuilt when one of its
+//! stylesheet's partials changes.
+
+use std::collections::BTreeSet;
+
+use regex::Regex;
+
+use crate::{Error, Path, PathBuf, Result};
+
+/// If the stylesheet at `path` is a Sass one.
+pub fn is_sass(path: &Path) -> bool
+{
+    path.extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("scss") || x.eq_ignore_ascii_case("sass"))
+}
+
+/// Compile the Sass stylesheet at `path` to CSS.
+///
+/// # Errors
+///
+/// Will return an error if the stylesheet or a partial it loads cannot be
+/// read, or isn't valid Sass.
+pub fn compile(path: &Path) -> Result<String>
+{
+    grass::from_path(path, &grass::Options::default()).map_err(|e| {
+        Error::Sass {
+            err:  e.to_string(),
+            path: path.to_path_buf(),
+        }
+    })
+}
+
+/// The partials the Sass stylesheet at `path` loads, and the ones they load
+/// in turn, or none if it isn't a Sass one. Ones that can't be found are
+/// left out, they're reported when it's compiled.
+pub fn partials(path: &Path) -> Vec<PathBuf>
+{
+    if !is_sass(path) {
+        return Vec::new();
+    }
+    let mut found = BTreeSet::new();
+    let mut unread = vec![path.to_path_buf()];
+    while let Some(stylesheet) = unread.pop() {
+        let Ok(source) = std::fs::read_to_string(&stylesheet)
+        else {
+            continue;
+        };
+        let dir = stylesheet.parent().unwrap_or(Path::new(""));
+        for url in loads(&source) {
+            if let Some(partial) = resolve(&dir.join(url)) {
+                if found.insert(partial.clone()) {
+                    unread.push(partial);
+                }
+            }
+        }
+    }
+    found.into_iter().collect()
+}
+
+/// The URLs of what `source` loads, except Sass's built-in modules, plain CSS
+/// imports, and remote files.
+fn loads(source: &str) -> Vec<&str>
+{
+    let statements = Regex::new(r"@(?:use|forward|import)\s+([^;\n]+)").unwrap();
+    let quoted = Regex::new(r#""([^"]*)"|'([^']*)'"#).unwrap();
+    statements
+        .captures_iter(source)
+        .flat_map(|statement| {
+            let arguments = statement.get(1).unwrap().as_str();
+            let urls = quoted
+                .captures_iter(arguments)
+                .filter_map(|x| x.get(1).or_else(|| x.get(2)))
+                .map(|x| x.as_str())
+                .collect::<Vec<_>>();
+            // The indented syntax's imports can be unquoted.
+            if urls.is_empty() {
+                arguments
+                    .split([',', ' '])
+                    .map(str::trim)
+                    .filter(|x| !x.is_empty())
+                    .collect()
+            }
+            else {
+                urls
+            }
+        })
+        .filter(|url| {
+            !(url.starts_with("sass:")
+                || url.contains("://")
+                || url.starts_with("url(")
+                || Path::new(url).extension().is_some_and(|x| x == "css"))
+        })
+        .collect()
+}
+
+/// The file `url`, relative to the stylesheet loading it, refers to: itself
+/// or its partial with either syntax, or the index of the directory.
+fn resolve(url: &Path) -> Option<PathBuf>
+{
+    let name = url.file_name()?.to_string_lossy();
+    let dir = url.parent().unwrap_or(Path::new(""));
+    candidates(dir, &name)
+        .into_iter()
+        .chain(candidates(url, "index"))
+        .find(|x| x.is_file())
+}
+
+/// The files in `dir` a load of `name` could be, in the order Sass tries
+/// them.
+fn candidates(dir: &Path, name: &str) -> Vec<PathBuf>
+{
+    ["scss", "sass"]
+        .into_iter()
+        .flat_map(|extention| {
+            [
+                dir.join(format!("{name}.{extention}")),
+                dir.join(format!("_{name}.{extention}")),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that the URLs a stylesheet loads are found, and built-in modules
+    /// and plain CSS aren't
+    fn test_loads()
+    {
+        let source = "@use \"sass:math\";\n@use 'partials/colors' as c;\n@forward \"mixins\";\n@import \"a\", \
+                      'b';\n@import url(\"x.css\");\n@import \"theme.css\";\n@import \
+                      \"https://example.com/y\";\n";
+        assert_eq!(loads(source), ["partials/colors", "mixins", "a", "b"]);
+        assert_eq!(loads("@import reset, base\n"), ["reset", "base"]);
+    }
+
+    #[test]
+    /// Test that partials are found with either syntax, through the ones
+    /// that load them, and a directory's index
+    fn test_partials()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/sass-partials");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("partials/buttons")).unwrap();
+        std::fs::write(
+            dir.join("style.scss"),
+            "@use 'partials/colors';\n@use 'partials/buttons';\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("partials/_colors.scss"), "@forward 'palette';\n$red: #f00;\n").unwrap();
+        std::fs::write(dir.join("partials/palette.sass"), "$blue: #00f\n").unwrap();
+        std::fs::write(dir.join("partials/buttons/_index.scss"), "a { b: c; }\n").unwrap();
+        assert_eq!(
+            partials(&dir.join("style.scss")),
+            [
+                dir.join("partials/_colors.scss"),
+                dir.join("partials/buttons/_index.scss"),
+                dir.join("partials/palette.sass"),
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that a stylesheet is compiled with its partials, and an invalid
+    /// one fails with its path
+    fn test_compile()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/sass-compile");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("_colors.scss"), "$red: #f00;\n").unwrap();
+        std::fs::write(
+            dir.join("style.scss"),
+            "@use 'colors';\na { color: colors.$red; b { c: d; } }\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("style.sass"), "a\n  color: red\n").unwrap();
+        std::fs::write(dir.join("invalid.scss"), "a { color: $nope; }\n").unwrap();
+        assert_eq!(
+            compile(&dir.join("style.scss")).unwrap(),
+            "a {\n  color: #f00;\n}\na b {\n  c: d;\n}\n"
+        );
+        assert_eq!(compile(&dir.join("style.sass")).unwrap(), "a {\n  color: red;\n}\n");
+        assert!(matches!(
+            compile(&dir.join("invalid.scss")),
+            Err(Error::Sass { path, .. }) if path == dir.join("invalid.scss")
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
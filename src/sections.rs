@@ -0,0 +1,99 @@
+//! Page info shared by the pages of a directory.
+//!
+//! A [`SECTION_FILE`] in any source dir, or a directory in one, has page info
+//! for every page beneath it, e.g. the `template` and `style` of a blog. Each
+//! page's own page info, and its `meta_from`, are merged over it, and a
+//! deeper section's over a shallower one's.
+
+use std::fs;
+
+use crate::{build::Website, config::merge_toml_tables, Config, PageInfo, Path, PathBuf};
+
+/// The name of the page info files of sections.
+pub const SECTION_FILE: &str = "_section.toml";
+
+/// The section files that apply to the page at `source_file`, the outermost
+/// first.
+pub fn files(config: &Config, source_file: &Path) -> Vec<PathBuf>
+{
+    let Some((relative, _)) = config.source_relative(source_file)
+    else {
+        return Vec::new();
+    };
+    let mut dir = source_file.to_path_buf();
+    let mut files = Vec::new();
+    for _ in relative.components() {
+        dir.pop();
+        let file = dir.join(SECTION_FILE);
+        if file.is_file() {
+            files.push(file);
+        }
+    }
+    files.reverse();
+    files
+}
+
+/// The page info the sections of the page at `source_file` give it, merged.
+///
+/// # Errors
+///
+/// Returns a description of the problem if a section file cannot be read or
+/// parsed.
+pub fn page_info(config: &Config, source_file: &Path) -> Result<toml::Table, String>
+{
+    let mut page_info = toml::Table::new();
+    for file in files(config, source_file) {
+        let contents = fs::read_to_string(&file).map_err(|e| format!("Couldn't read \"{}\": {e}", file.display()))?;
+        let section: toml::Table =
+            toml::from_str(&contents).map_err(|e| format!("Couldn't parse \"{}\": {e}", file.display()))?;
+        merge_toml_tables(&mut page_info, section);
+    }
+    Ok(page_info)
+}
+
+/// The page info of the markdown page at `source_file`, over its sections',
+/// or `None` if it has none or it cannot be read.
+pub(crate) fn scan_page_info(config: &Config, source_file: &Path) -> Option<PageInfo>
+{
+    let source = fs::read_to_string(source_file).ok()?;
+    scan_source_page_info(config, source_file, &source)
+}
+
+/// The page info of the markdown `source` of the page at `source_file`, over
+/// its sections', without reading the page.
+pub(crate) fn scan_source_page_info(config: &Config, source_file: &Path, source: &str) -> Option<PageInfo>
+{
+    let section = page_info(config, source_file).ok()?;
+    Website::scan_page_info(source, &section)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that deeper sections' page info is merged over shallower ones'
+    fn test_page_info()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/sections");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/blog/2024")).unwrap();
+        fs::write(
+            dir.join("src").join(SECTION_FILE),
+            "template = \"site.html\"\nemoji = true",
+        )
+        .unwrap();
+        fs::write(dir.join("src/blog").join(SECTION_FILE), "template = \"blog.html\"").unwrap();
+        let config = Config {
+            source: dir.join("src"),
+            ..Config::default()
+        };
+
+        let page_info = page_info(&config, &dir.join("src/blog/2024/post.md")).unwrap();
+        assert_eq!(page_info["template"].as_str(), Some("blog.html"));
+        assert_eq!(page_info["emoji"].as_bool(), Some(true));
+        assert_eq!(files(&config, &dir.join("src/index.md")).len(), 1);
+        assert!(files(&config, &dir.join("elsewhere/index.md")).is_empty());
+    }
+}
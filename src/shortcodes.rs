@@ -0,0 +1,942 @@
+//! Block shortcodes (`{{% name args %}}`) expanded in markdown sources before
+//! they're parsed.
+//!
+//! Shortcodes expand to raw HTML surrounded by blank lines, so the markdown
+//! between them is still parsed as markdown. They must be on their own lines
+//! and are left alone inside fenced code blocks.
+//!
+//! Besides the built-in shortcodes, `{{% name args %}}` expands to the file
+//! `name.html` in the first shortcode directory that has it. In it
+//! `[/rustic_arg_1/]`, `[/rustic_arg_2/]`, ... are replaced with the
+//! positional arguments and `[/rustic_arg_<key>/]` with named ones.
+//!
+//! Callouts are expanded first, see [`crate::admonitions`]. The CSS of the
+//! built-in shortcodes and callouts a page uses is added to it once, see
+//! [`crate::styles`].
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use crate::{
+    admonitions,
+    fences::Fences,
+    placeholders::escape_attribute,
+    report::{self, Warning, Warnings},
+    styles, Error, Path, PathBuf, Result,
+};
+
+const OPEN: &str = "{{%";
+const CLOSE: &str = "%}}";
+
+/// The arguments given to a shortcode: `"quoted"` or bare positional values,
+/// and `key=value` pairs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Arguments
+{
+    pub positional: Vec<String>,
+    pub named:      HashMap<String, String>,
+}
+
+impl Arguments
+{
+    pub(crate) fn parse(s: &str) -> Self
+    {
+        let mut arguments = Self::default();
+        let mut chars = s.chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+
+            let mut key = None;
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    None => break,
+                    Some(c) if c.is_whitespace() => break,
+                    Some('"') => {
+                        for c in chars.by_ref() {
+                            if c == '"' {
+                                break;
+                            }
+                            value.push(c);
+                        }
+                    }
+                    Some('=') if key.is_none() => key = Some(std::mem::take(&mut value)),
+                    Some(c) => value.push(c),
+                }
+            }
+
+            match key {
+                Some(key) => {
+                    arguments.named.insert(key, value);
+                }
+                None => arguments.positional.push(value),
+            }
+        }
+        arguments
+    }
+
+    /// The first positional argument, or the named argument `key`.
+    pub fn first_or(&self, key: &str) -> Option<&str>
+    {
+        self.positional
+            .first()
+            .or_else(|| self.named.get(key))
+            .map(String::as_str)
+    }
+}
+
+/// An open container shortcode and how many children it has seen.
+struct Frame
+{
+    name:     String,
+    children: usize,
+
+    /// Which `tabs` or `steps` block of the page this is, used to make unique
+    /// IDs.
+    index: usize,
+
+    /// How many columns the children of a `compat` matrix have.
+    columns: usize,
+}
+
+/// Expands the shortcodes of a single source file.
+struct Expander<'a>
+{
+    source_path: &'a Path,
+
+    /// Where custom shortcodes are looked up, in order.
+    shortcode_dirs: &'a [PathBuf],
+    stack:          Vec<Frame>,
+
+    /// The titles of each `tabs` block's tabs, in the order the blocks appear.
+    tab_titles: VecDeque<Vec<String>>,
+
+    /// How many `tabs` blocks have been opened, used to make unique IDs.
+    tabs_count: usize,
+
+    /// How many `steps` blocks have been opened, used to make unique IDs.
+    steps_count: usize,
+
+    /// If a video is only loaded once it's clicked, which needs a script.
+    video_facades: bool,
+
+    /// The built-in components used, whose CSS the page needs.
+    components: BTreeSet<&'static str>,
+}
+
+/// Make panels switchable, and keep tabs in the same sync group on the same
+/// title. Without it every panel is shown.
+const TABS_SCRIPT: &str = include_str!("../assets/scripts/tabs.js");
+
+/// Replace a video's link with its player when it's clicked. Without it the
+/// link goes to the video's page.
+const VIDEO_SCRIPT: &str = r#"<script>document.querySelectorAll(".raven-video-facade").forEach(v=>v.querySelector("a").addEventListener("click",e=>{e.preventDefault();const f=document.createElement("iframe");f.src=v.dataset.ravenEmbed;f.title=v.dataset.ravenTitle;f.allow=v.dataset.ravenAllow;f.allowFullscreen=true;v.replaceChildren(f)}))</script>"#;
+
+/// What a video's player is allowed to do.
+const VIDEO_ALLOW: &str = "autoplay; encrypted-media; picture-in-picture; fullscreen";
+
+/// Expand every shortcode in `source`, inlining the CSS of the built-in ones
+/// used.
+///
+/// # Errors
+///
+/// Will return an error if a shortcode is unknown, is missing a required
+/// argument, is used outside the container it belongs to, isn't closed, or
+/// its file cannot be read.
+pub fn expand(source: &str, source_path: &Path, shortcode_dirs: &[PathBuf]) -> Result<String>
+{
+    expand_with_styles(source, source_path, shortcode_dirs, None, false, &Warnings::default())
+}
+
+/// Expand every shortcode in `source`, linking to the components stylesheet
+/// at `stylesheet_href` for the CSS of the built-in ones used, or inlining it
+/// without one. With `error_markers`, a shortcode that fails to expand is
+/// added to `warnings` and replaced with an error marker instead.
+///
+/// # Errors
+///
+/// Will return an error if a shortcode is unknown, is missing a required
+/// argument, is used outside the container it belongs to, isn't closed, or
+/// its file cannot be read.
+pub fn expand_with_styles(
+    source: &str,
+    source_path: &Path,
+    shortcode_dirs: &[PathBuf],
+    stylesheet_href: Option<&str>,
+    error_markers: bool,
+    warnings: &Warnings,
+) -> Result<String>
+{
+    let admonitions = admonitions::expand(source);
+    let source = admonitions.as_deref().unwrap_or(source);
+    if !source.contains(OPEN) && admonitions.is_none() {
+        return Ok(source.to_string());
+    }
+
+    let mut expander = Expander {
+        source_path,
+        shortcode_dirs,
+        stack: Vec::new(),
+        tab_titles: collect_tab_titles(source),
+        tabs_count: 0,
+        steps_count: 0,
+        video_facades: false,
+        components: admonitions.iter().map(|_| admonitions::COMPONENT).collect(),
+    };
+    let mut output = String::with_capacity(source.len());
+    let mut fences = Fences::default();
+    for line in source.split_inclusive('\n') {
+        if fences.contains(line) {
+            output.push_str(line);
+            continue;
+        }
+
+        let trimmed = line.trim();
+        match trimmed.strip_prefix(OPEN).and_then(|x| x.strip_suffix(CLOSE)) {
+            Some(tag) => {
+                match expander.expand_tag(tag.trim()) {
+                    Ok(html) => output.push_str(&html),
+                    Err(e) if error_markers => output.push_str(&mark_error(source_path, &e, warnings)),
+                    Err(e) => return Err(e),
+                }
+            }
+            None => output.push_str(line),
+        }
+    }
+
+    if let Some(frame) = expander.stack.pop() {
+        let e = expander.error(format!("\"{}\" is never closed", frame.name));
+        if !error_markers {
+            return Err(e);
+        }
+        output.push_str(&mark_error(source_path, &e, warnings));
+    }
+    if expander.tabs_count > 0 {
+        output.push_str("\n<script>\n");
+        output.push_str(TABS_SCRIPT);
+        output.push_str("</script>\n");
+    }
+    if expander.video_facades {
+        output.push('\n');
+        output.push_str(VIDEO_SCRIPT);
+        output.push('\n');
+    }
+    if !expander.components.is_empty() {
+        output.push('\n');
+        output.push_str(&styles::emit(&expander.components, stylesheet_href));
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Warn about `e`, and the error marker that replaces the shortcode.
+fn mark_error(source_path: &Path, e: &Error, warnings: &Warnings) -> String
+{
+    warnings.warn(Warning::ErrorMarker {
+        source_file: source_path.to_path_buf(),
+        err:         e.to_string(),
+    });
+    format!("\n<p>{}</p>\n\n", report::error_marker(&e.to_string()))
+}
+
+/// The shortcode tags in `source` that aren't in fenced code blocks.
+fn tags(source: &str) -> impl Iterator<Item = &str>
+{
+    let mut fences = Fences::default();
+    source.lines().filter_map(move |line| {
+        if fences.contains(line) {
+            return None;
+        }
+        line.trim()
+            .strip_prefix(OPEN)
+            .and_then(|x| x.strip_suffix(CLOSE))
+            .map(str::trim)
+    })
+}
+
+/// The names of the shortcodes opened in `source`, in order.
+pub(crate) fn names(source: &str) -> impl Iterator<Item = &str>
+{
+    tags(source)
+        .map(|tag| tag.split_once(char::is_whitespace).map_or(tag, |(name, _)| name))
+        .filter(|name| !name.starts_with('/'))
+}
+
+/// The file of the custom shortcode `name`, from the first of
+/// `shortcode_dirs` that has one.
+fn file(shortcode_dirs: &[PathBuf], name: &str) -> Option<PathBuf>
+{
+    shortcode_dirs
+        .iter()
+        .map(|dir| dir.join(format!("{name}.html")))
+        .find(|x| x.is_file())
+}
+
+/// The files of the custom shortcodes `source` uses.
+pub fn files(source: &str, shortcode_dirs: &[PathBuf]) -> Vec<PathBuf>
+{
+    let mut files = Vec::new();
+    for path in names(source).filter_map(|name| file(shortcode_dirs, name)) {
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Look ahead for the titles of every `tabs` block's tabs, since they're
+/// needed when the block is opened.
+fn collect_tab_titles(source: &str) -> VecDeque<Vec<String>>
+{
+    let mut finished = Vec::new();
+    let mut open: Vec<(usize, Option<Vec<String>>)> = Vec::new();
+    for tag in tags(source) {
+        let (name, arguments) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        match name {
+            "tabs" => {
+                finished.push(Vec::new());
+                open.push((finished.len() - 1, Some(Vec::new())));
+            }
+            "tab" => {
+                if let Some((_, Some(titles))) = open.last_mut() {
+                    titles.push(
+                        Arguments::parse(arguments)
+                            .first_or("title")
+                            .unwrap_or_default()
+                            .to_string(),
+                    );
+                }
+            }
+            "/tabs" => {
+                if let Some((i, Some(titles))) = open.pop() {
+                    finished[i] = titles;
+                }
+            }
+            x if x.starts_with('/') => {
+                open.pop();
+            }
+            _ if CONTAINERS.contains(&name) => open.push((0, None)),
+            _ => {}
+        }
+    }
+    finished.into()
+}
+
+/// Shortcodes that must be closed.
+const CONTAINERS: [&str; 5] = ["columns", "cards", "tabs", "steps", "compat"];
+
+/// A labelled badge, the text stays in the page so it's searchable.
+fn badge(kind: &str, text: &str, note: Option<&str>) -> String
+{
+    let note = note
+        .map(|x| {
+            format!(
+                " <span class=\"raven-badge-note\">{}</span>",
+                htmlescape::encode_minimal(x)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<p class=\"raven-badges\"><span class=\"raven-badge raven-badge-{kind}\" \
+         data-raven-badge=\"{kind}\">{}</span>{note}</p>",
+        htmlescape::encode_minimal(text)
+    )
+}
+
+/// Versions that start with a digit are prefixed with "v".
+fn display_version(version: &str) -> String
+{
+    if version.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("v{version}")
+    }
+    else {
+        version.to_string()
+    }
+}
+
+/// A cell of a `compat` matrix. `yes`, `no`, and `partial` are styled, any
+/// other value (e.g. "1.3+") counts as supported and is shown as is.
+fn support_cell(value: &str) -> String
+{
+    let (support, text) = match value.to_lowercase().as_str() {
+        "yes" | "y" | "true" => ("yes", "Yes"),
+        "no" | "n" | "false" => ("no", "No"),
+        "partial" => ("partial", "Partial"),
+        _ => ("yes", value),
+    };
+    format!(
+        "<td class=\"raven-support-{support}\" data-raven-support=\"{support}\">{}</td>",
+        htmlescape::encode_minimal(text)
+    )
+}
+
+impl<'a> Expander<'a>
+{
+    fn error(&self, err: String) -> Error
+    {
+        Error::Shortcode {
+            err,
+            path: self.source_path.to_path_buf(),
+        }
+    }
+
+    fn expand_tag(&mut self, tag: &str) -> Result<String>
+    {
+        let (name, arguments) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let arguments = Arguments::parse(arguments);
+
+        if let Some(name) = name.strip_prefix('/') {
+            return self.close(name);
+        }
+        self.components.extend(styles::component(name));
+
+        let html = match name {
+            "columns" => self.open(name, 0, "<div class=\"raven-columns\">\n<div class=\"raven-column\">"),
+            "split" => {
+                self.child_of("columns", name)?;
+                "</div>\n<div class=\"raven-column\">".to_string()
+            }
+            "cards" => self.open(name, 0, "<section class=\"raven-cards\">"),
+            "card" => {
+                let (_, children) = self.child_of("cards", name)?;
+                let title = arguments
+                    .first_or("title")
+                    .ok_or_else(|| self.error("\"card\" requires a title".to_string()))?;
+                let close_previous = if children > 1 { "</article>\n" } else { "" };
+                format!(
+                    "{close_previous}<article class=\"raven-card\">\n<header class=\"raven-card-title\">{}</header>",
+                    htmlescape::encode_minimal(title)
+                )
+            }
+            "tabs" => {
+                let titles = self.tab_titles.pop_front().unwrap_or_default();
+                self.tabs_count += 1;
+                let index = self.tabs_count;
+                let sync = match (arguments.named.get("sync"), arguments.positional.first()) {
+                    (Some(group), _) => format!(" data-raven-sync=\"{}\"", htmlescape::encode_minimal(group)),
+                    (None, Some(x)) if x == "sync" => String::from(" data-raven-sync=\"default\""),
+                    _ => String::new(),
+                };
+                let buttons = titles
+                    .iter()
+                    .enumerate()
+                    .map(|(i, title)| {
+                        let id = format!("{index}-{}", i + 1);
+                        let title = htmlescape::encode_minimal(title);
+                        format!(
+                            "<button type=\"button\" role=\"tab\" id=\"raven-tab-{id}\" \
+                             aria-controls=\"raven-tabpanel-{id}\" data-raven-tab=\"{title}\">{title}</button>"
+                        )
+                    })
+                    .collect::<String>();
+                self.open(
+                    name,
+                    index,
+                    &format!(
+                        "<div class=\"raven-tabs\"{sync}>\n<div class=\"raven-tablist\" \
+                         role=\"tablist\">{buttons}</div>"
+                    ),
+                )
+            }
+            "tab" => {
+                let (index, children) = self.child_of("tabs", name)?;
+                let id = format!("{index}-{children}");
+                let close_previous = if children > 1 { "</div>\n" } else { "" };
+                format!(
+                    "{close_previous}<div class=\"raven-tabpanel\" role=\"tabpanel\" id=\"raven-tabpanel-{id}\" \
+                     aria-labelledby=\"raven-tab-{id}\">"
+                )
+            }
+            "steps" => {
+                self.steps_count += 1;
+                self.open(name, self.steps_count, "<ol class=\"raven-steps\">")
+            }
+            "step" => {
+                let (index, children) = self.child_of("steps", name)?;
+                let id = match index {
+                    1 => format!("step-{children}"),
+                    n => format!("step-{n}-{children}"),
+                };
+                let title = arguments
+                    .first_or("title")
+                    .map(|x| format!("\n<p class=\"raven-step-title\">{}</p>", htmlescape::encode_minimal(x)))
+                    .unwrap_or_default();
+                let close_previous = if children > 1 { "</li>\n" } else { "" };
+                format!(
+                    "{close_previous}<li class=\"raven-step\" id=\"{id}\">\n<a class=\"raven-step-number\" \
+                     href=\"#{id}\" aria-label=\"Step {children}\">{children}</a>{title}"
+                )
+            }
+            "since" => {
+                let version = arguments
+                    .first_or("version")
+                    .ok_or_else(|| self.error("\"since\" requires a version".to_string()))?;
+                badge("since", &format!("Since {}", display_version(version)), None)
+            }
+            "deprecated" => {
+                let text = match arguments.first_or("version") {
+                    Some(version) => format!("Deprecated since {}", display_version(version)),
+                    None => String::from("Deprecated"),
+                };
+                badge("deprecated", &text, arguments.named.get("note").map(String::as_str))
+            }
+            "compat" => {
+                if arguments.positional.is_empty() {
+                    return Err(self.error("\"compat\" requires at least one column".to_string()));
+                }
+                let headers = arguments
+                    .positional
+                    .iter()
+                    .map(|x| format!("<th scope=\"col\">{}</th>", htmlescape::encode_minimal(x)))
+                    .collect::<String>();
+                let html = self.open(
+                    name,
+                    0,
+                    &format!("<table class=\"raven-compat\">\n<thead><tr><td></td>{headers}</tr></thead>\n<tbody>"),
+                );
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.columns = arguments.positional.len();
+                }
+                html
+            }
+            "support" => {
+                self.child_of("compat", name)?;
+                let columns = self.stack.last().map_or(0, |frame| frame.columns);
+                let Some((feature, values)) = arguments.positional.split_first()
+                else {
+                    return Err(self.error("\"support\" requires a feature name".to_string()));
+                };
+                if values.len() != columns {
+                    return Err(self.error(format!(
+                        "\"support {feature}\" has {} value(s) but the matrix has {columns} column(s)",
+                        values.len()
+                    )));
+                }
+                let cells = values.iter().map(|x| support_cell(x)).collect::<String>();
+                format!(
+                    "<tr><th scope=\"row\">{}</th>{cells}</tr>",
+                    htmlescape::encode_minimal(feature)
+                )
+            }
+            "youtube" | "vimeo" => self.video(name, &arguments)?,
+            _ => self.custom(name, &arguments)?,
+        };
+        Ok(format!("\n{html}\n\n"))
+    }
+
+    /// A responsive player of the video `id` on YouTube or Vimeo. With
+    /// `privacy`, a link to the video that's replaced with the player when
+    /// it's clicked, so nothing is loaded from the site before then.
+    fn video(&mut self, site: &str, arguments: &Arguments) -> Result<String>
+    {
+        let privacy = arguments.positional.iter().any(|x| x == "privacy")
+            || arguments.named.get("privacy").is_some_and(|x| x == "true");
+        let id = arguments
+            .named
+            .get("id")
+            .or_else(|| arguments.positional.iter().find(|x| *x != "privacy"))
+            .ok_or_else(|| self.error(format!("\"{site}\" requires a video ID")))?;
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(self.error(format!("\"{id}\" isn't a {site} video ID")));
+        }
+        let start = arguments
+            .named
+            .get("start")
+            .map(|x| x.parse::<u32>())
+            .transpose()
+            .map_err(|_| self.error(format!("\"{site}\" takes its start in seconds")))?;
+
+        let mut query = Vec::new();
+        let (name, embed, watch) = match site {
+            "youtube" => {
+                let host = if privacy {
+                    "www.youtube-nocookie.com"
+                }
+                else {
+                    "www.youtube.com"
+                };
+                query.extend(start.map(|x| format!("start={x}")));
+                let time = start.map(|x| format!("&t={x}s")).unwrap_or_default();
+                (
+                    "YouTube",
+                    format!("https://{host}/embed/{id}"),
+                    format!("https://www.youtube.com/watch?v={id}{time}"),
+                )
+            }
+            _ => {
+                if privacy {
+                    query.push(String::from("dnt=1"));
+                }
+                let time = start.map(|x| format!("#t={x}s")).unwrap_or_default();
+                (
+                    "Vimeo",
+                    format!("https://player.vimeo.com/video/{id}"),
+                    format!("https://vimeo.com/{id}{time}"),
+                )
+            }
+        };
+        let default_title = format!("{name} video");
+        let title = escape_attribute(arguments.named.get("title").unwrap_or(&default_title));
+        // Vimeo's start time is a fragment, after the query.
+        let fragment = match (site, start) {
+            ("vimeo", Some(start)) => format!("#t={start}s"),
+            _ => String::new(),
+        };
+
+        if !privacy {
+            let query = if query.is_empty() {
+                String::new()
+            }
+            else {
+                format!("?{}", query.join("&amp;"))
+            };
+            return Ok(format!(
+                "<div class=\"raven-video\"><iframe src=\"{embed}{query}{fragment}\" title=\"{title}\" \
+                 loading=\"lazy\" allow=\"{VIDEO_ALLOW}\" allowfullscreen></iframe></div>"
+            ));
+        }
+        self.video_facades = true;
+        query.push(String::from("autoplay=1"));
+        Ok(format!(
+            "<div class=\"raven-video raven-video-facade\" data-raven-embed=\"{embed}?{}{fragment}\" \
+             data-raven-title=\"{title}\" data-raven-allow=\"{VIDEO_ALLOW}\"><a class=\"raven-video-play\" \
+             href=\"{}\">Play: {title}</a></div>",
+            query.join("&amp;"),
+            escape_attribute(&watch)
+        ))
+    }
+
+    /// Expand the shortcode file for `name`.
+    fn custom(&self, name: &str, arguments: &Arguments) -> Result<String>
+    {
+        const ARGUMENT_PREFIX: &str = "[/rustic_arg_";
+        const ARGUMENT_SUFFIX: &str = "/]";
+
+        let Some(path) = file(self.shortcode_dirs, name)
+        else {
+            return Err(self.error(format!("Unknown shortcode \"{name}\"")));
+        };
+        let template = std::fs::read_to_string(&path).map_err(|e| Error::Io { err: e, path })?;
+
+        let mut html = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find(ARGUMENT_PREFIX) {
+            let Some(len) = rest[start..].find(ARGUMENT_SUFFIX)
+            else {
+                break;
+            };
+            let key = &rest[start + ARGUMENT_PREFIX.len()..start + len];
+            let value = match key.parse::<usize>() {
+                Ok(i) => i.checked_sub(1).and_then(|i| arguments.positional.get(i)),
+                Err(_) => arguments.named.get(key),
+            };
+            html.push_str(&rest[..start]);
+            html.push_str(&htmlescape::encode_minimal(value.map_or("", String::as_str)));
+            rest = &rest[start + len + ARGUMENT_SUFFIX.len()..];
+        }
+        html.push_str(rest);
+        Ok(html.trim_end().to_string())
+    }
+
+    fn open(&mut self, name: &str, index: usize, html: &str) -> String
+    {
+        self.stack.push(Frame {
+            name: name.to_string(),
+            children: 0,
+            index,
+            columns: 0,
+        });
+        html.to_string()
+    }
+
+    /// Check that `child` is directly inside `parent` and return the index of
+    /// `parent` and how many children it has, including this one.
+    fn child_of(&mut self, parent: &str, child: &str) -> Result<(usize, usize)>
+    {
+        match self.stack.last_mut() {
+            Some(frame) if frame.name == parent => {
+                frame.children += 1;
+                Ok((frame.index, frame.children))
+            }
+            _ => Err(self.error(format!("\"{child}\" must be inside \"{parent}\""))),
+        }
+    }
+
+    fn close(&mut self, name: &str) -> Result<String>
+    {
+        let frame = match self.stack.pop() {
+            Some(frame) if frame.name == name => frame,
+            Some(frame) => return Err(self.error(format!("\"/{name}\" closes \"{}\"", frame.name))),
+            None => return Err(self.error(format!("\"/{name}\" closes nothing"))),
+        };
+
+        let html = match name {
+            "columns" => "</div>\n</div>",
+            "cards" if frame.children > 0 => "</article>\n</section>",
+            "tabs" if frame.children > 0 => "</div>\n</div>",
+            "tabs" => "</div>",
+            "steps" if frame.children > 0 => "</li>\n</ol>",
+            "steps" => "</ol>",
+            "compat" => "</tbody>\n</table>",
+            _ => "</section>",
+        };
+        Ok(format!("\n{html}\n\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that columns expand to nested divs and code blocks are left alone
+    fn test_columns()
+    {
+        let source = "{{% columns %}}\nLeft\n{{% split %}}\nRight\n{{% /columns %}}\n```\n{{% split %}}\n```\n";
+        let expanded = expand(source, Path::new(""), &[]).unwrap();
+        assert!(expanded.starts_with(
+            "\n<div class=\"raven-columns\">\n<div class=\"raven-column\">\n\nLeft\n\n</div>\n<div \
+             class=\"raven-column\">\n\nRight\n\n</div>\n</div>\n\n```\n{{% split %}}\n```\n"
+        ));
+        assert!(expand("{{% split %}}\n", Path::new(""), &[]).is_err());
+        assert!(expand("{{% columns %}}\n", Path::new(""), &[]).is_err());
+    }
+
+    #[test]
+    /// Test that a page gets the CSS of each component it uses once, or a
+    /// link to the components stylesheet
+    fn test_component_styles()
+    {
+        let source = "{{% columns %}}\n{{% /columns %}}\n{{% columns %}}\n{{% /columns %}}\n{{% since 1.0 %}}\n";
+        let expanded = expand(source, Path::new(""), &[]).unwrap();
+        assert_eq!(expanded.matches("<style>").count(), 1);
+        assert_eq!(expanded.matches(":where(.raven-columns)").count(), 1);
+        assert!(expanded.contains(":where(.raven-badge)"));
+        assert!(!expanded.contains(":where(.raven-steps)"));
+
+        let expanded = expand_with_styles(
+            source,
+            Path::new(""),
+            &[],
+            Some("../raven-components.css"),
+            false,
+            &Warnings::default(),
+        )
+        .unwrap();
+        assert!(expanded.ends_with("\n<link rel=\"stylesheet\" href=\"../raven-components.css\">\n"));
+        assert!(!expand("Plain\n", Path::new(""), &[]).unwrap().contains("<style>"));
+    }
+
+    #[test]
+    /// Test that steps are numbered and anchored, and unique across blocks
+    fn test_steps()
+    {
+        let source = "{{% steps %}}\n{{% step \"Install\" %}}\nA\n{{% step %}}\nB\n{{% /steps %}}\n{{% steps %}}\n{{% \
+                      step %}}\nC\n{{% /steps %}}\n";
+        let expanded = expand(source, Path::new(""), &[]).unwrap();
+        assert!(expanded.contains(
+            "<li class=\"raven-step\" id=\"step-1\">\n<a class=\"raven-step-number\" href=\"#step-1\" \
+             aria-label=\"Step 1\">1</a>\n<p class=\"raven-step-title\">Install</p>"
+        ));
+        assert!(expanded.contains("</li>\n<li class=\"raven-step\" id=\"step-2\">"));
+        assert!(expanded.contains("id=\"step-2-1\""));
+        assert!(expand("{{% step %}}\n", Path::new(""), &[]).is_err());
+    }
+
+    #[test]
+    /// Test that steps in a step are numbered by their own block
+    fn test_nested_steps()
+    {
+        let source = "{{% steps %}}\n{{% step %}}\n{{% steps %}}\n{{% step %}}\nA\n{{% /steps %}}\n{{% step \
+                      %}}\nB\n{{% /steps %}}\n";
+        let expanded = expand(source, Path::new(""), &[]).unwrap();
+        assert!(expanded.contains("<li class=\"raven-step\" id=\"step-1\">"));
+        assert!(expanded.contains("<li class=\"raven-step\" id=\"step-2-1\">"));
+        assert!(expanded.contains("</li>\n<li class=\"raven-step\" id=\"step-2\">"));
+    }
+
+    #[test]
+    /// Test that each tab's button controls its panel, and that a sync group
+    /// is set on the block
+    fn test_tabs()
+    {
+        let source =
+            "{{% tabs sync=os %}}\n{{% tab \"Linux\" %}}\nA\n{{% tab title=\"<Windows>\" %}}\nB\n{{% /tabs %}}\n";
+        let expanded = expand(source, Path::new(""), &[]).unwrap();
+        assert!(expanded.contains(
+            "<div class=\"raven-tabs\" data-raven-sync=\"os\">\n<div class=\"raven-tablist\" role=\"tablist\"><button \
+             type=\"button\" role=\"tab\" id=\"raven-tab-1-1\" aria-controls=\"raven-tabpanel-1-1\" \
+             data-raven-tab=\"Linux\">Linux</button><button type=\"button\" role=\"tab\" id=\"raven-tab-1-2\" \
+             aria-controls=\"raven-tabpanel-1-2\" data-raven-tab=\"&lt;Windows&gt;\">&lt;Windows&gt;</button></div>"
+        ));
+        assert!(expanded.contains(
+            "</div>\n<div class=\"raven-tabpanel\" role=\"tabpanel\" id=\"raven-tabpanel-1-2\" \
+             aria-labelledby=\"raven-tab-1-2\">"
+        ));
+        assert_eq!(expanded.matches("<script>").count(), 1);
+        assert!(expanded.contains(TABS_SCRIPT));
+
+        let expanded = expand("{{% tabs sync %}}\n{{% /tabs %}}\n", Path::new(""), &[]).unwrap();
+        assert!(expanded.contains("data-raven-sync=\"default\""));
+        assert!(expand("{{% tab \"Linux\" %}}\n", Path::new(""), &[]).is_err());
+    }
+
+    #[test]
+    /// Test that the tabs of a block after a nested one still control their
+    /// own block's panels
+    fn test_nested_tabs()
+    {
+        let source = "{{% tabs %}}\n{{% tab Linux %}}\n{{% tabs %}}\n{{% tab apt %}}\nA\n{{% /tabs %}}\n{{% tab \
+                      Windows %}}\nB\n{{% /tabs %}}\n";
+        let expanded = expand(source, Path::new(""), &[]).unwrap();
+        assert!(
+            expanded.contains("id=\"raven-tab-1-2\" aria-controls=\"raven-tabpanel-1-2\" data-raven-tab=\"Windows\"")
+        );
+        assert!(expanded.contains("id=\"raven-tab-2-1\" aria-controls=\"raven-tabpanel-2-1\" data-raven-tab=\"apt\""));
+        assert!(expanded.contains("id=\"raven-tabpanel-2-1\""));
+        assert!(expanded.contains("id=\"raven-tabpanel-1-2\""));
+        assert!(!expanded.contains("raven-tabpanel-2-2"));
+    }
+
+    #[test]
+    /// Test that cards are articles with escaped titles
+    fn test_cards()
+    {
+        let source = "{{% cards %}}\n{{% card \"One\" %}}\nA\n{{% card title=\"<Two>\" %}}\nB\n{{% /cards %}}\n";
+        let expanded = expand(source, Path::new(""), &[]).unwrap();
+        assert!(expanded.contains(
+            "<section class=\"raven-cards\">\n\n\n<article class=\"raven-card\">\n<header \
+             class=\"raven-card-title\">One</header>"
+        ));
+        assert!(expanded.contains(
+            "</article>\n<article class=\"raven-card\">\n<header class=\"raven-card-title\">&lt;Two&gt;</header>"
+        ));
+        assert!(expanded.contains("</article>\n</section>"));
+        assert!(expand("{{% cards %}}\n{{% card %}}\n{{% /cards %}}\n", Path::new(""), &[]).is_err());
+        assert!(expand("{{% card \"One\" %}}\n", Path::new(""), &[]).is_err());
+    }
+
+    #[test]
+    /// Test that badges and compatibility matrices render their text
+    fn test_badges()
+    {
+        let expanded = expand("{{% since 1.2 %}}\n", Path::new(""), &[]).unwrap();
+        assert!(expanded.contains("data-raven-badge=\"since\">Since v1.2</span>"));
+        let expanded = expand("{{% deprecated \"2.0\" note=\"Use `b`\" %}}\n", Path::new(""), &[]).unwrap();
+        assert!(expanded.contains(">Deprecated since v2.0</span> <span class=\"raven-badge-note\">Use `b`</span>"));
+
+        let source = "{{% compat Linux Windows %}}\n{{% support \"Watch mode\" yes 1.3+ %}}\n{{% /compat %}}\n";
+        let expanded = expand(source, Path::new(""), &[]).unwrap();
+        assert!(expanded.contains(
+            "<tr><th scope=\"row\">Watch mode</th><td class=\"raven-support-yes\" \
+             data-raven-support=\"yes\">Yes</td><td class=\"raven-support-yes\" \
+             data-raven-support=\"yes\">1.3+</td></tr>"
+        ));
+        assert!(expand(
+            "{{% compat Linux %}}\n{{% support x yes no %}}\n{{% /compat %}}\n",
+            Path::new(""),
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    /// Test that custom shortcodes are found in order and given their arguments
+    fn test_custom_shortcode()
+    {
+        let dir = std::env::temp_dir().join("rustic_raven_test_custom_shortcode");
+        std::fs::create_dir_all(dir.join("theme")).unwrap();
+        std::fs::write(dir.join("theme/note.html"), "<aside>[/rustic_arg_1/]</aside>").unwrap();
+        std::fs::write(
+            dir.join("note.html"),
+            "<aside class=\"[/rustic_arg_kind/]\">[/rustic_arg_1/][/rustic_arg_2/]</aside>\n",
+        )
+        .unwrap();
+
+        let source = "{{% note \"<Hi>\" kind=tip %}}\n";
+        let expanded = expand(source, Path::new(""), &[dir.clone(), dir.join("theme")]).unwrap();
+        assert_eq!(expanded, "\n<aside class=\"tip\">&lt;Hi&gt;</aside>\n\n");
+        let expanded = expand(source, Path::new(""), &[dir.join("theme")]).unwrap();
+        assert_eq!(expanded, "\n<aside>&lt;Hi&gt;</aside>\n\n");
+        assert!(expand("{{% nothing %}}\n", Path::new(""), std::slice::from_ref(&dir)).is_err());
+        assert_eq!(
+            files(&format!("{source}{source}{{{{% nothing %}}}}\n"), &[dir.join("theme")]),
+            [dir.join("theme/note.html")]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that with error markers, a shortcode that fails to expand is
+    /// replaced with a marker and warned about
+    fn test_error_markers()
+    {
+        let warnings = Warnings::new(false);
+        let source = "Before\n{{% nothing %}}\nAfter\n";
+        let expanded = expand_with_styles(source, Path::new("a.md"), &[], None, true, &warnings).unwrap();
+        let err = Error::Shortcode {
+            err:  String::from("Unknown shortcode \"nothing\""),
+            path: PathBuf::from("a.md"),
+        };
+        assert_eq!(
+            expanded,
+            format!("Before\n\n<p>{}</p>\n\nAfter\n", report::error_marker(&err.to_string()))
+        );
+        assert!(matches!(
+            &warnings.take()[..],
+            [Warning::ErrorMarker { source_file, .. }] if source_file == Path::new("a.md")
+        ));
+    }
+
+    #[test]
+    /// Test that with error markers, a shortcode that's never closed is
+    /// marked at the end of the page
+    fn test_error_marker_unclosed()
+    {
+        let warnings = Warnings::new(false);
+        let source = "{{% columns %}}\nText\n";
+        let expanded = expand_with_styles(source, Path::new("a.md"), &[], None, true, &warnings).unwrap();
+        assert!(expanded.contains("class=\"raven-error\""));
+        assert!(expanded.contains("is never closed"));
+    }
+
+    #[test]
+    /// Test that videos are embedded, or only linked to until they're clicked
+    /// in privacy mode
+    fn test_videos()
+    {
+        let expanded = expand("{{% youtube dQw4w9WgXcQ start=30 %}}\n", Path::new(""), &[]).unwrap();
+        assert!(expanded
+            .contains("<iframe src=\"https://www.youtube.com/embed/dQw4w9WgXcQ?start=30\" title=\"YouTube video\""));
+        assert!(expanded.contains(":where(.raven-video)"));
+        assert!(!expanded.contains("<script>"));
+
+        let expanded = expand(
+            "{{% vimeo id=76979871 privacy title=\"Tom & Jerry\" start=5 %}}\n",
+            Path::new(""),
+            &[],
+        )
+        .unwrap();
+        assert!(
+            expanded.contains("data-raven-embed=\"https://player.vimeo.com/video/76979871?dnt=1&amp;autoplay=1#t=5s\"")
+        );
+        assert!(expanded.contains("href=\"https://vimeo.com/76979871#t=5s\">Play: Tom &amp; Jerry</a>"));
+        assert_eq!(expanded.matches("<script>").count(), 1);
+
+        assert!(expand("{{% youtube %}}\n", Path::new(""), &[]).is_err());
+        assert!(expand("{{% youtube \"a/b\" %}}\n", Path::new(""), &[]).is_err());
+        assert!(expand("{{% vimeo 1 start=soon %}}\n", Path::new(""), &[]).is_err());
+    }
+
+    #[test]
+    fn test_arguments()
+    {
+        let arguments = Arguments::parse(r#""Hello world" key=value other="a b""#);
+        assert_eq!(arguments.positional, ["Hello world"]);
+        assert_eq!(arguments.named["key"], "value");
+        assert_eq!(arguments.named["other"], "a b");
+    }
+}
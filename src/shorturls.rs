@@ -0,0 +1,124 @@
+//! Short URLs, `/s/<code>`, for the pages with `short = true`, e.g. to put
+//! on slides and printouts.
+//!
+//! A page's code is the start of the MD5 hash of its `id`, or of its source
+//! path relative to the source dir if it has none, so it stays the same from
+//! build to build, and a page with an ID keeps its code when it's moved.
+//! Codes are 4 characters long unless that clashes with another page's. Each
+//! code gets a redirect stub at `s/<code>/index.html` in the dest dir, and
+//! every code and the URL it leads to are listed in [`MAPPING_FILE`].
+
+use std::collections::BTreeMap;
+
+use crate::{
+    build::{dest_path, source_files, write_output},
+    hash,
+    links::relative_url,
+    redirects::stub,
+    report::Warnings,
+    sections, Config, PathBuf, Result,
+};
+
+/// The directory under the dest dir the short URLs are in.
+pub const SHORT_DIR: &str = "s";
+
+/// Where the codes and the URLs they lead to are written, relative to the
+/// dest dir.
+pub const MAPPING_FILE: &str = "s/short-urls.json";
+
+const CODE_LENGTH: usize = 4;
+
+/// The pages with `short = true`, by their code, with their dest path.
+pub fn short_urls(config: &Config) -> Result<BTreeMap<String, PathBuf>>
+{
+    let mut keys = Vec::new();
+    // Unreadable entries are warned about when the source files are walked.
+    for (source_file, extention) in source_files(config, &Warnings::new(false)) {
+        if !matches!(&*extention, "md" | "markdown") {
+            continue;
+        }
+        let Some(page_info) = sections::scan_page_info(config, &source_file).filter(|x| x.short == Some(true))
+        else {
+            continue;
+        };
+        let key = page_info.id.unwrap_or_else(|| {
+            let relative = config
+                .source_relative(&source_file)
+                .map_or(source_file.as_path(), |(x, _)| x);
+            relative.to_string_lossy().replace('\\', "/")
+        });
+        keys.push((key, dest_path(config, &source_file, &extention)?));
+    }
+    Ok(assign_codes(keys))
+}
+
+/// Give each key the shortest code that no key before it, in sorted order,
+/// has.
+fn assign_codes(mut keys: Vec<(String, PathBuf)>) -> BTreeMap<String, PathBuf>
+{
+    keys.sort();
+    let mut codes = BTreeMap::new();
+    for (key, dest) in keys {
+        let hash = hash::md5_hex(key.as_bytes());
+        let code = (CODE_LENGTH..=hash.len())
+            .map(|len| &hash[..len])
+            .find(|code| !codes.contains_key(*code))
+            .unwrap_or(&hash)
+            .to_string();
+        codes.insert(code, dest);
+    }
+    codes
+}
+
+/// Write the redirect stub of each short URL, and the mapping file.
+///
+/// # Errors
+///
+/// Will return an error if a page's dest path cannot be worked out, or a
+/// stub or the mapping file cannot be written.
+pub async fn write(config: &Config) -> Result<()>
+{
+    let short_urls = short_urls(config)?;
+    if short_urls.is_empty() {
+        return Ok(());
+    }
+    let mut mapping = BTreeMap::new();
+    for (code, dest) in &short_urls {
+        let stub_file = config.dest.join(SHORT_DIR).join(code).join("index.html");
+        write_output(&stub_file, stub(&relative_url(&stub_file, dest))).await?;
+        let url = dest.strip_prefix(&config.dest).unwrap_or(dest);
+        mapping.insert(
+            format!("/{SHORT_DIR}/{code}"),
+            format!("/{}", url.to_string_lossy().replace('\\', "/")),
+        );
+    }
+    let json = serde_json::to_string_pretty(&mapping).unwrap();
+    write_output(&config.dest.join(MAPPING_FILE), json + "\n").await
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::Path;
+
+    #[test]
+    /// Test that codes are 4 characters long unless they clash, and don't
+    /// depend on the other pages
+    fn test_assign_codes()
+    {
+        let page = |key: &str| (key.to_string(), PathBuf::from(format!("dest/{key}.html")));
+        let codes = assign_codes(vec![page("talk"), page("slides")]);
+        assert!(codes.keys().all(|x| x.len() == CODE_LENGTH));
+        assert_eq!(
+            assign_codes(vec![page("talk")]).into_iter().next(),
+            codes.into_iter().find(|(_, dest)| dest == Path::new("dest/talk.html"))
+        );
+
+        let hash = hash::md5_hex(b"talk");
+        let mut codes = BTreeMap::new();
+        codes.insert(hash[..4].to_string(), PathBuf::from("dest/talk.html"));
+        codes.insert(hash[..5].to_string(), PathBuf::from("dest/talk.html"));
+        assert_eq!(assign_codes(vec![page("talk"), page("talk")]), codes);
+    }
+}
@@ -0,0 +1,287 @@
+//! Snapshot testing: comparing rendered pages against checked-in copies.
+
+use std::{collections::BTreeSet, sync::OnceLock};
+
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::{
+    build::{dest_path, source_files, Website},
+    Config, Error, Path, PathBuf, Result,
+};
+
+/// How a page compared to its snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome
+{
+    Matched,
+
+    /// The page differs from its snapshot, with a diff of the two.
+    Mismatched(String),
+
+    /// The page has no snapshot.
+    Missing,
+
+    /// The snapshot has no page.
+    Stale,
+
+    /// The snapshot was written.
+    Updated,
+}
+
+/// Render every page of `site` without writing it to the dest dir, and
+/// compare each against its snapshot in the snapshots directory. With
+/// `update` the snapshots are (re)written instead, and stale ones deleted.
+///
+/// Returns the snapshot path of every page, and of every stale snapshot,
+/// with its outcome.
+///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - There are no source files
+/// - A page cannot be rendered
+/// - A snapshot cannot be read, written, or deleted
+pub async fn run(site: &Website, update: bool) -> Result<Vec<(PathBuf, Outcome)>>
+{
+    let config = site.config();
+    let snapshots = config
+        .snapshots
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(Config::DEFAULT_SNAPSHOTS_DIR));
+    let source_files = source_files(config, site.warnings());
+    if source_files.is_empty() {
+        return Err(Error::MissingSourceFiles(config.source.clone()));
+    }
+
+    let mut results = Vec::new();
+    let mut rendered = BTreeSet::new();
+    for (source_file, extention) in &source_files {
+        let Some(html) = site.render(source_file, extention).await?
+        else {
+            continue;
+        };
+        let dest_file = dest_path(config, source_file, extention)?;
+        let snapshot = snapshots.join(dest_file.strip_prefix(&config.dest).unwrap_or(&dest_file));
+        rendered.insert(snapshot.clone());
+
+        let html = normalize(&html);
+        if update {
+            write_snapshot(&snapshot, &html)?;
+            results.push((snapshot, Outcome::Updated));
+            continue;
+        }
+        if !snapshot.is_file() {
+            results.push((snapshot, Outcome::Missing));
+            continue;
+        }
+        let expected = std::fs::read_to_string(&snapshot).map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: snapshot.clone(),
+            }
+        })?;
+        let outcome = if expected == html {
+            Outcome::Matched
+        }
+        else {
+            Outcome::Mismatched(diff(&expected, &html))
+        };
+        results.push((snapshot, outcome));
+    }
+
+    let stale = WalkDir::new(&snapshots)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.file_type().is_file() && !rendered.contains(entry.path()))
+        .map(|entry| entry.path().to_path_buf())
+        .collect::<Vec<_>>();
+    for snapshot in stale {
+        if update {
+            std::fs::remove_file(&snapshot).map_err(|e| {
+                Error::Io {
+                    err:  e,
+                    path: snapshot.clone(),
+                }
+            })?;
+            println!("Deleted: \"{}\"", snapshot.display());
+        }
+        else {
+            results.push((snapshot, Outcome::Stale));
+        }
+    }
+
+    Ok(results)
+}
+
+fn write_snapshot(path: &Path, contents: &str) -> Result<()>
+{
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: parent.to_path_buf(),
+            }
+        })?;
+    }
+    std::fs::write(path, contents).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: path.to_path_buf(),
+        }
+    })
+}
+
+/// Print every outcome, with diffs for the mismatched pages, and return how
+/// many failed.
+pub fn print(results: &[(PathBuf, Outcome)]) -> usize
+{
+    let mut failed = 0;
+    for (snapshot, outcome) in results {
+        let status = match outcome {
+            Outcome::Matched => "ok",
+            Outcome::Updated => "updated",
+            Outcome::Mismatched(_) => "FAILED",
+            Outcome::Missing => "MISSING (run with --update to create it)",
+            Outcome::Stale => "STALE (no page renders to it, run with --update to delete it)",
+        };
+        println!("{} ... {status}", snapshot.display());
+        if let Outcome::Mismatched(diff) = outcome {
+            println!("{diff}");
+        }
+        if matches!(outcome, Outcome::Mismatched(_) | Outcome::Missing | Outcome::Stale) {
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// Make rendered HTML stable and diffable: dates and hex hashes are replaced
+/// with placeholders, and there's a line break between adjacent tags.
+pub fn normalize(html: &str) -> String
+{
+    static DATE: OnceLock<Regex> = OnceLock::new();
+    static HASH: OnceLock<Regex> = OnceLock::new();
+    let date = DATE.get_or_init(|| {
+        Regex::new(r"\b\d{4}-\d{2}-\d{2}(?:[T ]\d{2}:\d{2}(?::\d{2}(?:\.\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?)?\b").unwrap()
+    });
+    let hash = HASH.get_or_init(|| Regex::new(r"\b[0-9a-f]{8,}\b").unwrap());
+
+    let html = date.replace_all(html, "[date]");
+    let html = hash.replace_all(&html, "[hash]");
+    let mut normalized = html.replace("><", ">\n<");
+    if !normalized.ends_with('\n') {
+        normalized.push('\n');
+    }
+    normalized
+}
+
+/// A line diff of `old` and `new` with two lines of context, `-` marking
+/// removed lines and `+` added ones.
+pub fn diff(old: &str, new: &str) -> String
+{
+    const CONTEXT: usize = 2;
+
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let lines = changes(&old, &new);
+
+    let changed = lines.iter().map(|(change, _)| *change != ' ').collect::<Vec<_>>();
+    let mut output = String::new();
+    let mut last_shown = None;
+    for (i, (change, line)) in lines.iter().enumerate() {
+        let near_change = changed[i.saturating_sub(CONTEXT)..(i + CONTEXT + 1).min(lines.len())]
+            .iter()
+            .any(|x| *x);
+        if !near_change {
+            continue;
+        }
+        if last_shown.is_some_and(|x: usize| x + 1 != i) {
+            output.push_str("  ...\n");
+        }
+        output.push_str(&format!("{change} {line}\n"));
+        last_shown = Some(i);
+    }
+    output
+}
+
+/// Every item of `old` and `new` in order with its change: `' '` if it's in
+/// both, `'-'` if it was removed, and `'+'` if it was added.
+pub(crate) fn changes<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)>
+{
+    // Largest number of item pairs compared, the rest is shown as replaced.
+    const MAX_COMPARISONS: usize = 4_000_000;
+
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_middle, new_middle) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut changes = old[..prefix].iter().map(|x| (' ', *x)).collect::<Vec<_>>();
+    if old_middle.len() * new_middle.len() > MAX_COMPARISONS {
+        changes.extend(old_middle.iter().map(|x| ('-', *x)));
+        changes.extend(new_middle.iter().map(|x| ('+', *x)));
+    }
+    else {
+        // The length of the longest common subsequence of the remaining items.
+        let mut lcs = vec![vec![0u32; new_middle.len() + 1]; old_middle.len() + 1];
+        for i in (0..old_middle.len()).rev() {
+            for j in (0..new_middle.len()).rev() {
+                lcs[i][j] = if old_middle[i] == new_middle[j] {
+                    lcs[i + 1][j + 1] + 1
+                }
+                else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old_middle.len() || j < new_middle.len() {
+            if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+                changes.push((' ', old_middle[i]));
+                i += 1;
+                j += 1;
+            }
+            else if j == new_middle.len() || (i < old_middle.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                changes.push(('-', old_middle[i]));
+                i += 1;
+            }
+            else {
+                changes.push(('+', new_middle[j]));
+                j += 1;
+            }
+        }
+    }
+    changes.extend(old[old.len() - suffix..].iter().map(|x| (' ', *x)));
+    changes
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that dates and hashes are normalized and tags split onto lines
+    fn test_normalize()
+    {
+        assert_eq!(
+            normalize("<p>2023-03-14T10:00:00Z</p><a href=\"app.3f9a2c1d.js\">"),
+            "<p>[date]</p>\n<a href=\"app.[hash].js\">\n"
+        );
+    }
+
+    #[test]
+    /// Test that diffs only show changed lines and their context
+    fn test_diff()
+    {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nb\nc\nd\nE\nf\ng\nh\n";
+        assert_eq!(diff(old, new), "  c\n  d\n- e\n+ E\n  f\n  g\n");
+    }
+}
@@ -0,0 +1,151 @@
+//! Checks, before a build writes anything, that its output fits: on the dest
+//! dir's volume, and under `generation.max_output_size`.
+//!
+//! A page that was built before is expected to be as big as its last output.
+//! A new one is estimated from its source, plus the stylesheet and favicon
+//! inlined into it.
+
+use std::fs;
+
+use crate::{
+    build::{dest_path_in, PageInfos},
+    Config, Error, Path, PathBuf, Result,
+};
+
+/// The estimated size of the build's output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Estimate
+{
+    /// The bytes every page will take up once written.
+    pub total: u64,
+
+    /// The bytes the dest dir will grow by, over what the pages' last
+    /// outputs already take up.
+    pub growth: u64,
+}
+
+impl Estimate
+{
+    /// Estimate the output of building `sources`, as returned by
+    /// `walk_directory`, whose page info was scanned into `page_infos`.
+    pub fn new(config: &Config, page_infos: &PageInfos, sources: &[(PathBuf, String)]) -> Self
+    {
+        let file_size = |path: &Path| fs::metadata(path).map(|x| x.len()).unwrap_or(0);
+        let mut inlined = file_size(&config.resolve(&config.default.stylesheet));
+        if config.embed_favicon() {
+            inlined += file_size(&config.resolve(&config.default.favicon));
+        }
+
+        let mut estimate = Self::default();
+        for (source, extention) in sources {
+            let last = dest_path_in(config, page_infos, source, extention)
+                .ok()
+                .and_then(|dest| fs::metadata(dest).ok())
+                .map(|x| x.len());
+            let size = match extention.as_str() {
+                "md" | "markdown" => file_size(source) + inlined,
+                _ => file_size(source),
+            };
+            estimate.total += last.unwrap_or(size);
+            estimate.growth += size.saturating_sub(last.unwrap_or(0));
+        }
+        estimate
+    }
+}
+
+/// Fail early, instead of partway through writing, if the output of
+/// building `sources` is over `generation.max_output_size` or doesn't fit
+/// in the dest dir's volume. Their page info was scanned into `page_infos`.
+///
+/// # Errors
+///
+/// Will return an error if the output doesn't fit.
+pub fn preflight(config: &Config, page_infos: &PageInfos, sources: &[(PathBuf, String)]) -> Result<()>
+{
+    let estimate = Estimate::new(config, page_infos, sources);
+    let limit = config
+        .generation
+        .as_ref()
+        .and_then(|generation| generation.max_output_size);
+    if let Some(limit) = limit.filter(|limit| estimate.total > *limit) {
+        return Err(Error::OutputTooLarge {
+            estimated: estimate.total,
+            limit,
+        });
+    }
+    match available_space(&config.dest) {
+        Some(available) if available < estimate.growth => {
+            Err(Error::InsufficientSpace {
+                dest: config.dest.clone(),
+                needed: estimate.growth,
+                available,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The bytes free for unprivileged users on the volume `path` is, or will
+/// be, on. `None` if it cannot be found out.
+pub fn available_space(path: &Path) -> Option<u64>
+{
+    // The dest dir may not exist yet, so ask about its nearest ancestor.
+    let existing = path
+        .ancestors()
+        .map(|x| {
+            if x.as_os_str().is_empty() {
+                Path::new(".")
+            }
+            else {
+                x
+            }
+        })
+        .find(|x| x.exists())?;
+    fs2::available_space(existing).ok()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that new pages count towards growth, rebuilt ones their last
+    /// output, and that the size limit is enforced
+    fn test_preflight()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/space");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("dest")).unwrap();
+        fs::write(dir.join("src/new.md"), "a".repeat(100)).unwrap();
+        fs::write(dir.join("src/old.md"), "a".repeat(100)).unwrap();
+        fs::write(dir.join("dest/old.html"), "a".repeat(300)).unwrap();
+
+        let mut config = Config {
+            source: dir.join("src"),
+            dest: dir.join("dest"),
+            ..Config::default()
+        };
+        let sources = vec![
+            (dir.join("src/new.md"), String::from("md")),
+            (dir.join("src/old.md"), String::from("md")),
+        ];
+        assert_eq!(
+            Estimate::new(&config, &PageInfos::new(), &sources),
+            Estimate {
+                total:  400,
+                growth: 100,
+            }
+        );
+        preflight(&config, &PageInfos::new(), &sources).unwrap();
+
+        config.set_jobs(1);
+        config.generation.as_mut().unwrap().max_output_size = Some(399);
+        assert!(matches!(
+            preflight(&config, &PageInfos::new(), &sources),
+            Err(Error::OutputTooLarge { estimated: 400, .. })
+        ));
+        assert!(available_space(&dir.join("dest/missing")).is_some_and(|x| x > 0));
+    }
+}
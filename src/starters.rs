@@ -0,0 +1,400 @@
+//! Starter templates new projects are initialized from.
+//!
+//! The bundled starters are the example projects in `examples/`, which
+//! `cargo test` builds, so what `raven new --template` creates is known to
+//! work.
+
+use walkdir::WalkDir;
+
+use crate::{defaults, Config, Error, Path, PathBuf, Result};
+
+/// A project skeleton bundled into the executable.
+///
+/// File paths are relative to the project directory, paths starting with
+/// `src/` are put in the configured source directory instead.
+pub struct Starter
+{
+    pub name:        &'static str,
+    pub description: &'static str,
+
+    /// The starter's `raven.toml`, the default configuration if `None`.
+    pub config: Option<&'static str>,
+    pub files:  &'static [(&'static str, &'static str)],
+}
+
+pub const STARTERS: [Starter; 6] = [
+    Starter {
+        name:        "default",
+        description: "A single page with the default template and stylesheet",
+        config:      None,
+        files:       &[
+            ("template.html", defaults::DEFAULT_HTML_TEMPLATE_SRC),
+            ("style.css", defaults::DEFAULT_CSS_STYLESHEET_SRC),
+            ("src/index.md", defaults::DEFAULT_MD_STARTER_SRC),
+        ],
+    },
+    Starter {
+        name:        "blog",
+        config:      Some(include_str!("../examples/blog/raven.toml")),
+        description: "A list of posts and a post archetype",
+        files:       &[
+            ("template.html", include_str!("../examples/blog/template.html")),
+            ("style.css", include_str!("../examples/blog/style.css")),
+            (
+                "archetypes/default.md",
+                include_str!("../examples/blog/archetypes/default.md"),
+            ),
+            ("src/index.md", include_str!("../examples/blog/src/index.md")),
+            (
+                "src/hello-world.md",
+                include_str!("../examples/blog/src/hello-world.md"),
+            ),
+        ],
+    },
+    Starter {
+        name:        "docs",
+        config:      Some(include_str!("../examples/docs/raven.toml")),
+        description: "Documentation pages with a sidebar",
+        files:       &[
+            ("template.html", include_str!("../examples/docs/template.html")),
+            ("style.css", include_str!("../examples/docs/style.css")),
+            ("src/index.md", include_str!("../examples/docs/src/index.md")),
+            (
+                "src/getting-started.md",
+                include_str!("../examples/docs/src/getting-started.md"),
+            ),
+            (
+                "src/configuration.md",
+                include_str!("../examples/docs/src/configuration.md"),
+            ),
+        ],
+    },
+    Starter {
+        name:        "portfolio",
+        config:      Some(include_str!("../examples/portfolio/raven.toml")),
+        description: "A grid of projects and an about page",
+        files:       &[
+            ("template.html", include_str!("../examples/portfolio/template.html")),
+            ("style.css", include_str!("../examples/portfolio/style.css")),
+            ("src/index.md", include_str!("../examples/portfolio/src/index.md")),
+            ("src/about.md", include_str!("../examples/portfolio/src/about.md")),
+        ],
+    },
+    Starter {
+        name:        "multilingual",
+        description: "Pages in several languages, each with its own template",
+        config:      Some(include_str!("../examples/multilingual/raven.toml")),
+        files:       &[
+            ("template.html", include_str!("../examples/multilingual/template.html")),
+            (
+                "template.de.html",
+                include_str!("../examples/multilingual/template.de.html"),
+            ),
+            (
+                "template.fr.html",
+                include_str!("../examples/multilingual/template.fr.html"),
+            ),
+            ("style.css", include_str!("../examples/multilingual/style.css")),
+            ("src/index.md", include_str!("../examples/multilingual/src/index.md")),
+            ("src/about.md", include_str!("../examples/multilingual/src/about.md")),
+            (
+                "src/de/index.md",
+                include_str!("../examples/multilingual/src/de/index.md"),
+            ),
+            (
+                "src/fr/index.md",
+                include_str!("../examples/multilingual/src/fr/index.md"),
+            ),
+        ],
+    },
+    Starter {
+        name:        "mdbook",
+        description: "An mdBook project, navigated by its SUMMARY.md",
+        config:      Some(include_str!("../examples/mdbook/raven.toml")),
+        files:       &[
+            ("book.toml", include_str!("../examples/mdbook/book.toml")),
+            ("template.html", include_str!("../examples/mdbook/template.html")),
+            ("style.css", include_str!("../examples/mdbook/style.css")),
+            ("src/SUMMARY.md", include_str!("../examples/mdbook/src/SUMMARY.md")),
+            (
+                "src/introduction.md",
+                include_str!("../examples/mdbook/src/introduction.md"),
+            ),
+            (
+                "src/getting-started.md",
+                include_str!("../examples/mdbook/src/getting-started.md"),
+            ),
+            (
+                "src/guide/installation.md",
+                include_str!("../examples/mdbook/src/guide/installation.md"),
+            ),
+            (
+                "src/guide/usage.md",
+                include_str!("../examples/mdbook/src/guide/usage.md"),
+            ),
+            (
+                "src/contributors.md",
+                include_str!("../examples/mdbook/src/contributors.md"),
+            ),
+        ],
+    },
+];
+
+/// Where a new project's files come from.
+#[derive(Debug, Clone)]
+pub enum StarterSource
+{
+    /// One of the bundled `STARTERS`.
+    Bundled(&'static str),
+
+    /// A directory copied into the project as is, except for its `.git` and
+    /// dest directories. If it has no
+    /// `raven.toml`, one is generated and its `src/` files are put in the
+    /// configured source directory like a bundled starter's.
+    Directory(PathBuf),
+}
+
+impl StarterSource
+{
+    /// A bundled starter named `name`, or a directory at the path `name`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if `name` is neither a bundled starter nor a
+    /// directory.
+    pub fn parse(name: &str) -> Result<Self>
+    {
+        if let Some(starter) = STARTERS.iter().find(|x| x.name == name) {
+            return Ok(Self::Bundled(starter.name));
+        }
+        let path = PathBuf::from(name);
+        if path.is_dir() {
+            return Ok(Self::Directory(path.canonicalize().unwrap_or(path)));
+        }
+        Err(Error::UnknownStarter {
+            name:      name.to_string(),
+            available: STARTERS.iter().map(|x| x.name).collect::<Vec<_>>().join(", "),
+        })
+    }
+
+    /// The configuration a new project starts with: the starter's own, or
+    /// the default one.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the starter's configuration cannot be parsed.
+    pub fn config(&self) -> Result<Config>
+    {
+        match self {
+            Self::Bundled(name) => {
+                let starter = STARTERS.iter().find(|x| x.name == *name).unwrap();
+                match starter.config {
+                    Some(config) => {
+                        toml::from_str(config)
+                            .map_err(|e| Error::ConfigParse(format!("Couldn't parse the \"{name}\" starter: {e}")))
+                    }
+                    None => Ok(Config::default()),
+                }
+            }
+            Self::Directory(_) => {
+                match self.config_file() {
+                    Some(path) => Config::from_toml(&path),
+                    None => Ok(Config::default()),
+                }
+            }
+        }
+    }
+
+    /// The configuration file of a directory starter, if it has one.
+    pub fn config_file(&self) -> Option<PathBuf>
+    {
+        match self {
+            Self::Bundled(_) => None,
+            Self::Directory(path) => Some(path.join(Config::DEFAULT_CONFIG_FILE)).filter(|x| x.is_file()),
+        }
+    }
+
+    /// Write the starter's files into the current directory, except for the
+    /// ones the configured theme already has.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if a file cannot be read or written.
+    pub fn write(&self, config: &Config) -> Result<()>
+    {
+        match self {
+            Self::Bundled(name) => {
+                let starter = STARTERS.iter().find(|x| x.name == *name).unwrap();
+                for (path, contents) in starter.files {
+                    if provided_by_theme(config, Path::new(path)) {
+                        continue;
+                    }
+                    write_file(&project_path(config, Path::new(path)), contents.as_bytes())?;
+                }
+            }
+            Self::Directory(directory) => {
+                let has_config = self.config_file().is_some();
+                // Generated files aren't part of the starter.
+                let dest = directory.join(&config.dest);
+                let files = WalkDir::new(directory)
+                    .into_iter()
+                    .filter_entry(|entry| entry.depth() == 0 || !(entry.file_name() == ".git" || entry.path() == dest))
+                    .filter_map(std::result::Result::ok)
+                    .filter(|entry| entry.file_type().is_file());
+                for entry in files {
+                    let relative = entry.path().strip_prefix(directory).unwrap_or(entry.path());
+                    let contents = std::fs::read(entry.path()).map_err(|e| {
+                        Error::Io {
+                            err:  e,
+                            path: entry.path().to_path_buf(),
+                        }
+                    })?;
+                    if provided_by_theme(config, relative) {
+                        continue;
+                    }
+                    let path = if has_config {
+                        relative.to_path_buf()
+                    }
+                    else {
+                        project_path(config, relative)
+                    };
+                    write_file(&path, &contents)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// If the project's theme has the starter file at `path`, so the project
+/// shouldn't override it. Pages are never provided by a theme.
+fn provided_by_theme(config: &Config, path: &Path) -> bool
+{
+    !path.starts_with("src") && config.theme.as_ref().is_some_and(|theme| theme.join(path).exists())
+}
+
+/// Where a starter file goes: `src/` is replaced with the source directory.
+fn project_path(config: &Config, path: &Path) -> PathBuf
+{
+    match path.strip_prefix("src") {
+        Ok(rest) => config.source.join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+pub(crate) fn write_file(path: &Path, contents: &[u8]) -> Result<()>
+{
+    if let Some(parent) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            Error::Io {
+                err:  e,
+                path: parent.to_path_buf(),
+            }
+        })?;
+    }
+    std::fs::write(path, contents).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: path.to_path_buf(),
+        }
+    })?;
+    println!("Created: \"{}\"", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that a name is a bundled starter, then a directory
+    fn test_parse()
+    {
+        assert!(matches!(
+            StarterSource::parse("blog"),
+            Ok(StarterSource::Bundled("blog"))
+        ));
+
+        let dir = std::env::temp_dir().join("rustic-raven-tests/starter-parse");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = StarterSource::parse(&dir.to_string_lossy()).unwrap();
+        assert!(matches!(source, StarterSource::Directory(path) if path == dir.canonicalize().unwrap()));
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert!(matches!(
+            StarterSource::parse("rustic-raven-no-such-starter"),
+            Err(Error::UnknownStarter { available, .. }) if available.starts_with("default, blog")
+        ));
+    }
+
+    #[test]
+    /// Test that every bundled starter's configuration parses
+    fn test_bundled_configs()
+    {
+        for starter in &STARTERS {
+            let config = StarterSource::Bundled(starter.name).config().unwrap();
+            assert_eq!(config.source, PathBuf::from("src"), "{}", starter.name);
+        }
+    }
+
+    #[test]
+    /// Test that a directory starter uses its own configuration file if it
+    /// has one
+    fn test_directory_config()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/starter-config");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = StarterSource::Directory(dir.clone());
+        assert_eq!(source.config_file(), None);
+        assert_eq!(source.config().unwrap().source, Config::default().source);
+
+        let config = Config {
+            source: PathBuf::from("pages"),
+            ..Config::default()
+        };
+        std::fs::write(
+            dir.join(Config::DEFAULT_CONFIG_FILE),
+            toml::to_string_pretty(&config).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(source.config_file(), Some(dir.join(Config::DEFAULT_CONFIG_FILE)));
+        assert_eq!(source.config().unwrap().source, PathBuf::from("pages"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    /// Test that starter files under `src/` go in the source directory
+    fn test_project_path()
+    {
+        let config = Config {
+            source: PathBuf::from("content"),
+            ..Config::default()
+        };
+        assert_eq!(
+            project_path(&config, Path::new("src/blog/post.md")),
+            Path::new("content/blog/post.md")
+        );
+        assert_eq!(project_path(&config, Path::new("style.css")), Path::new("style.css"));
+    }
+
+    #[test]
+    /// Test that the theme's files aren't overridden, but its pages are
+    fn test_provided_by_theme()
+    {
+        let theme = std::env::temp_dir().join("rustic-raven-tests/starter-theme");
+        std::fs::create_dir_all(theme.join("src")).unwrap();
+        std::fs::write(theme.join("template.html"), "").unwrap();
+        std::fs::write(theme.join("src/index.md"), "").unwrap();
+
+        let config = Config {
+            theme: Some(theme.clone()),
+            ..Config::default()
+        };
+        assert!(provided_by_theme(&config, Path::new("template.html")));
+        assert!(!provided_by_theme(&config, Path::new("style.css")));
+        assert!(!provided_by_theme(&config, Path::new("src/index.md")));
+        assert!(!provided_by_theme(&Config::default(), Path::new("template.html")));
+        std::fs::remove_dir_all(theme).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+//! Statistics of a project, for `raven stats`, to track how a site grows:
+//! its pages per section, words, largest pages, and output size with and
+//! without minification.
+//!
+//! Every page is rendered in memory, nothing is written. The output size is
+//! of the pages, stylesheets, and scripts the build writes, not of the other
+//! files in the dest dir.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    build::{source_files, Website},
+    extensions,
+    headless::plain_text,
+    minify, Config, Error, Path, PathBuf, Result,
+};
+
+/// How many of the largest pages are listed.
+const LARGEST_PAGES: usize = 10;
+
+/// A markdown page's size.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageSize
+{
+    pub source: PathBuf,
+    pub words:  usize,
+
+    /// The size of the page as it's written, before minification.
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SiteStats
+{
+    pub pages: usize,
+    pub words: usize,
+
+    /// How many markdown pages are in each directory of the source dir, by
+    /// its first component. Pages at the top are under `/`.
+    pub sections: BTreeMap<String, usize>,
+
+    /// The markdown pages with the most output, biggest first.
+    pub largest: Vec<PageSize>,
+
+    /// The output's size without minification.
+    pub unminified_bytes: usize,
+
+    /// The output's size minified, as `generation.process` would.
+    pub minified_bytes: usize,
+}
+
+impl SiteStats
+{
+    /// Render every page of the project, counting what's in it.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the syntaxes cannot be loaded, or a page cannot
+    /// be read or rendered.
+    pub async fn from_config(config: &Config) -> Result<Self>
+    {
+        // Render without minification, so both sizes can be measured.
+        let mut config = config.clone();
+        if let Some(generation) = &mut config.generation {
+            generation.process = None;
+        }
+        let site = Website::from_config(config)?;
+        let config = site.config();
+
+        let mut stats = Self::default();
+        let mut pages = Vec::new();
+        for (source_file, extention) in source_files(config, site.warnings()) {
+            if extention == extensions::COPY {
+                let bytes = std::fs::metadata(&source_file).map_or(0, |x| x.len() as usize);
+                stats.unminified_bytes += bytes;
+                stats.minified_bytes += bytes;
+                continue;
+            }
+            let source = tokio::fs::read_to_string(&source_file).await.map_err(|e| {
+                Error::Io {
+                    err:  e,
+                    path: source_file.clone(),
+                }
+            })?;
+            let (output, minified) = match &*extention {
+                "md" | "markdown" => {
+                    let (html, page_info) = site.parse_markdown(&source, source_file.clone())?;
+                    let words = plain_text(&html).split_whitespace().count();
+                    let html = site
+                        .integrate_html_into_template(page_info, source_file.clone(), html)
+                        .await?;
+                    let html = site.post_process(&source_file, html);
+                    stats.pages += 1;
+                    stats.words += words;
+                    *stats.sections.entry(section(config, &source_file)).or_default() += 1;
+                    pages.push(PageSize {
+                        source: source_file,
+                        words,
+                        bytes: html.len(),
+                    });
+                    let minified = minify::html(&html);
+                    (html, minified)
+                }
+                "css" => {
+                    let minified = minify::css(&source);
+                    (source, minified)
+                }
+                "js" => {
+                    let minified = minify::js(&source);
+                    (source, minified)
+                }
+                _ => {
+                    let html = site.render(&source_file, &extention).await?.unwrap_or(source);
+                    let minified = minify::html(&html);
+                    (html, minified)
+                }
+            };
+            stats.unminified_bytes += output.len();
+            stats.minified_bytes += minified.len();
+        }
+        pages.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.source.cmp(&b.source)));
+        pages.truncate(LARGEST_PAGES);
+        stats.largest = pages;
+        Ok(stats)
+    }
+
+    pub fn print(&self)
+    {
+        println!("Pages: {} ({} words)", self.pages, self.words);
+        println!("Pages per section:");
+        for (section, pages) in &self.sections {
+            println!("    {section} ({pages} page(s))");
+        }
+        println!("Largest pages:");
+        for page in &self.largest {
+            println!(
+                "    {} ({}, {} words)",
+                page.source.display(),
+                human_size(page.bytes),
+                page.words
+            );
+        }
+        println!("Output size:");
+        println!("    Unminified: {}", human_size(self.unminified_bytes));
+        let saved = match self.unminified_bytes {
+            0 => 0.0,
+            total => (1.0 - self.minified_bytes as f64 / total as f64) * 100.0,
+        };
+        println!(
+            "    Minified: {} ({saved:.1}% smaller)",
+            human_size(self.minified_bytes)
+        );
+    }
+
+    /// The statistics as pretty-printed JSON.
+    pub fn to_json(&self) -> String { serde_json::to_string_pretty(self).unwrap() }
+}
+
+/// The first directory of `source_file` under the source dir, or `/` if it's
+/// at the top.
+fn section(config: &Config, source_file: &Path) -> String
+{
+    let relative = config.source_relative(source_file).map_or(source_file, |(x, _)| x);
+    let mut components = relative.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_string_lossy().to_string(),
+        _ => String::from("/"),
+    }
+}
+
+/// `bytes` in B, KiB, or MiB.
+fn human_size(bytes: usize) -> String
+{
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that pages are put in their top directory's section, and sizes are
+    /// human-readable
+    fn test_sections()
+    {
+        let config = Config {
+            source: PathBuf::from("src"),
+            ..Config::default()
+        };
+        assert_eq!(section(&config, Path::new("src/index.md")), "/");
+        assert_eq!(section(&config, Path::new("src/blog/2024/post.md")), "blog");
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1_048_576), "3.0 MiB");
+    }
+}
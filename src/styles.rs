@@ -0,0 +1,130 @@
+//! The CSS of the built-in shortcodes and callouts, emitted only for the
+//! pages that use them, once per page, instead of being part of every
+//! stylesheet.
+//!
+//! With `generation.component_styles = "inline"` (the default) a page gets a
+//! `<style>` with the CSS of the components it uses. With `"linked"` every
+//! component's CSS is written to one stylesheet in the dest dir, and pages
+//! that use a component link to it.
+//!
+//! The selectors are wrapped in `:where()`, so any rule in the site's own
+//! stylesheet overrides them.
+
+use std::collections::BTreeSet;
+
+use crate::{ComponentStyles, Config};
+
+/// The stylesheet, relative to the dest dir, linked pages use.
+pub const COMPONENTS_STYLESHEET: &str = "raven-components.css";
+
+/// The CSS of each built-in component.
+const COMPONENTS: &[(&str, &str)] = &[
+    (
+        "columns",
+        ":where(.raven-columns){display:flex;flex-wrap:wrap;gap:1em}:where(.raven-column){flex:1 1 15em}",
+    ),
+    (
+        "cards",
+        ":where(.raven-cards){display:grid;grid-template-columns:repeat(auto-fill,minmax(15em,1fr));gap:1em}:where(.\
+         raven-card){border:1px solid;border-radius:5px;padding:1em}:where(.raven-card-title){font-weight:700}",
+    ),
+    (
+        "tabs",
+        ":where(.raven-tablist){display:flex;gap:.25em}:where(.raven-tablist button){background:0 \
+         0;color:inherit;border:0;border-bottom:2px solid transparent;padding:.25em \
+         .75em;cursor:pointer}:where(.raven-tablist \
+         button[aria-selected=true]){border-bottom-color:currentColor;font-weight:700}",
+    ),
+    (
+        "steps",
+        ":where(.raven-steps){list-style:none;padding-left:0}:where(.raven-step){position:relative;padding-left:3em;\
+         margin-bottom:1em}:where(.raven-step-number){position:absolute;left:0;top:0;width:2em;height:2em;line-height:\
+         2em;border-radius:50%;border:2px \
+         solid;text-align:center;font-weight:700;color:inherit;text-decoration:none}:where(.\
+         raven-step-title){font-weight:700;margin-top:.4em}",
+    ),
+    (
+        "badge",
+        ":where(.raven-badge){display:inline-block;border:1px solid;border-radius:1em;padding:0 \
+         .6em;font-size:.85em;font-weight:700}:where(.raven-badge-since){color:#b8bb26}:where(.\
+         raven-badge-deprecated){color:#fb4934}:where(.raven-badge-note){font-size:.85em}",
+    ),
+    (
+        "admonition",
+        ":where(.admonition){border-left:4px solid;border-radius:5px;padding:.25em 1em;margin:1em \
+         0}:where(.admonition-title){font-weight:700}:where(.admonition.note){border-color:#83a598}:where(.admonition.\
+         tip){border-color:#b8bb26}:where(.admonition.important){border-color:#d3869b}:where(.admonition.\
+         warning){border-color:#fabd2f}:where(.admonition.caution){border-color:#fb4934}",
+    ),
+    (
+        "compat",
+        ":where(.raven-compat){border-collapse:collapse}:where(.raven-compat td,.raven-compat th){border:1px \
+         solid;padding:.25em \
+         .75em;text-align:center}:where(.raven-support-yes){color:#b8bb26}:where(.raven-support-no){color:#fb4934}:\
+         where(.raven-support-partial){color:#fabd2f}",
+    ),
+    (
+        "video",
+        ":where(.raven-video){position:relative;aspect-ratio:16/9;width:100%}:where(.raven-video \
+         iframe){position:absolute;inset:0;width:100%;height:100%;border:0}:where(.raven-video-facade){display:flex;\
+         align-items:center;justify-content:center;background:#000}:where(.raven-video-play){color:#fff;font-weight:\
+         700;text-decoration:none;padding:.5em 1em;border:2px solid;border-radius:5px}",
+    ),
+];
+
+/// The component the built-in shortcode `name` belongs to, if it has CSS.
+pub(crate) fn component(name: &str) -> Option<&'static str>
+{
+    let component = match name {
+        "columns" | "split" => "columns",
+        "cards" | "card" => "cards",
+        "tabs" | "tab" => "tabs",
+        "steps" | "step" => "steps",
+        "since" | "deprecated" => "badge",
+        "compat" | "support" => "compat",
+        "youtube" | "vimeo" => "video",
+        _ => return None,
+    };
+    Some(component)
+}
+
+/// The CSS of every component, written to [`COMPONENTS_STYLESHEET`] when
+/// it's linked.
+pub fn stylesheet() -> String { COMPONENTS.iter().map(|(_, css)| *css).collect() }
+
+/// Whether pages link to [`COMPONENTS_STYLESHEET`] instead of inlining
+/// their components' CSS.
+pub fn linked(config: &Config) -> bool
+{
+    config
+        .generation
+        .as_ref()
+        .and_then(|generation| generation.component_styles)
+        .unwrap_or_default()
+        == ComponentStyles::Linked
+}
+
+/// The HTML that styles `components` on a page: a `<style>` with their CSS,
+/// or a `<link>` to `href` if the stylesheet is linked.
+pub(crate) fn emit(components: &BTreeSet<&str>, href: Option<&str>) -> String
+{
+    if components.is_empty() {
+        return String::new();
+    }
+    match href {
+        Some(href) => {
+            format!(
+                "<link rel=\"stylesheet\" href=\"{}\">",
+                htmlescape::encode_minimal(href)
+            )
+        }
+        None => {
+            let css = COMPONENTS
+                .iter()
+                .filter(|(name, _)| components.contains(name))
+                .map(|(_, css)| *css)
+                .collect::<String>();
+            format!("<style>{css}</style>")
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! A cache of the compiled syntaxes, so a project with a syntaxes dir doesn't
+//! compile its `.sublime-syntax` files on every run.
+//!
+//! The built [`SyntaxSet`] is dumped to [`CACHE_FILE`] in the project's root
+//! with the hash of the syntaxes dir it was built from, and loaded instead of
+//! building it again while that hash stays the same.
+
+use serde::{Deserialize, Serialize};
+use syntect::{dumps, parsing::SyntaxSet};
+use walkdir::WalkDir;
+
+use crate::{config::project_root, hash, Config, Path, PathBuf};
+
+/// Where the compiled syntaxes are kept, relative to the project's root.
+pub const CACHE_FILE: &str = ".raven-syntaxes.packdump";
+
+#[derive(Deserialize)]
+struct Cache
+{
+    key:        String,
+    syntax_set: SyntaxSet,
+}
+
+/// A [`Cache`] being written, without copying the syntaxes.
+#[derive(Serialize)]
+struct CacheRef<'a>
+{
+    key:        String,
+    syntax_set: &'a SyntaxSet,
+}
+
+/// The hash of the `.sublime-syntax` files in `syntax_dir`, and the version of
+/// this program, which the cache is only valid for. `None` if there's no
+/// syntaxes dir, or it cannot be read.
+fn key(syntax_dir: &Path) -> Option<String>
+{
+    if !syntax_dir.is_dir() {
+        return None;
+    }
+    let mut hashed = format!("{} {}\n", crate::NAME, env!("CARGO_PKG_VERSION")).into_bytes();
+    for entry in WalkDir::new(syntax_dir).sort_by_file_name() {
+        let entry = entry.ok()?;
+        if entry.path().extension().is_none_or(|x| x != "sublime-syntax") {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(syntax_dir).unwrap_or(entry.path());
+        hashed.extend(relative.to_string_lossy().as_bytes());
+        hashed.push(0);
+        hashed.extend(hash::md5_hex(&std::fs::read(entry.path()).ok()?).as_bytes());
+        hashed.push(b'\n');
+    }
+    Some(hash::md5_hex(&hashed))
+}
+
+/// Where the cache is kept.
+pub(crate) fn cache_file() -> PathBuf { project_root().join(CACHE_FILE) }
+
+/// The syntaxes cached at `cache_file`, if they were built from the syntaxes
+/// dir as it is now.
+pub(crate) fn load(config: &Config, cache_file: &Path) -> Option<SyntaxSet>
+{
+    let key = key(&config.syntaxes)?;
+    let cache: Cache = dumps::from_uncompressed_dump_file(cache_file).ok()?;
+    (cache.key == key).then_some(cache.syntax_set)
+}
+
+/// Keep `syntax_set`, built from the syntaxes dir, at `cache_file` for the
+/// next run. Failing to is only a missed speedup, so it isn't an error.
+pub(crate) fn save(config: &Config, cache_file: &Path, syntax_set: &SyntaxSet)
+{
+    let Some(key) = key(&config.syntaxes)
+    else {
+        return;
+    };
+    let cache = CacheRef { key, syntax_set };
+    if dumps::dump_to_uncompressed_file(&cache, cache_file).is_err() {
+        let _ = std::fs::remove_file(cache_file);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that the key changes with the syntax files, and only them
+    fn test_key()
+    {
+        let dir = std::env::temp_dir().join(format!("raven-syntax-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.sublime-syntax"), "name: A\n").unwrap();
+        let before = key(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "Not a syntax").unwrap();
+        assert_eq!(key(&dir).unwrap(), before);
+        std::fs::write(dir.join("a.sublime-syntax"), "name: B\n").unwrap();
+        assert_ne!(key(&dir).unwrap(), before);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(key(&dir), None);
+    }
+
+    #[test]
+    /// Test that saved syntaxes are loaded while the syntaxes dir is the same,
+    /// and not once it changes
+    fn test_load()
+    {
+        let dir = std::env::temp_dir().join("rustic-raven-tests/syntax-cache");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("syntaxes")).unwrap();
+        std::fs::write(dir.join("syntaxes/a.sublime-syntax"), "name: A\n").unwrap();
+        let config = Config {
+            syntaxes: dir.join("syntaxes"),
+            ..Config::default()
+        };
+        let cache_file = dir.join(CACHE_FILE);
+        assert!(load(&config, &cache_file).is_none());
+        save(&config, &cache_file, &SyntaxSet::load_defaults_newlines());
+        let loaded = load(&config, &cache_file).unwrap();
+        assert_eq!(
+            loaded.syntaxes().len(),
+            SyntaxSet::load_defaults_newlines().syntaxes().len()
+        );
+
+        std::fs::write(dir.join("syntaxes/a.sublime-syntax"), "name: B\n").unwrap();
+        assert!(load(&config, &cache_file).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
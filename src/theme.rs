@@ -0,0 +1,186 @@
+//! Installing themes from git repositories, and previewing them with sample
+//! content.
+
+use std::process::Command;
+
+use crate::{starters::write_file, Config, Error, Path, PathBuf, Result};
+
+/// The `raven.toml` of the sample project `raven theme dev` builds.
+const SAMPLE_CONFIG: &str = include_str!("../samples/theme/raven.toml");
+
+/// The pages of the sample project, by their paths relative to it: long and
+/// short titles and pages, every markdown element, every built-in shortcode
+/// and callout, and a dated post in a section.
+pub const SAMPLE_FILES: [(&str, &str); 6] = [
+    ("src/index.md", include_str!("../samples/theme/src/index.md")),
+    ("src/typography.md", include_str!("../samples/theme/src/typography.md")),
+    ("src/shortcodes.md", include_str!("../samples/theme/src/shortcodes.md")),
+    ("src/long-title.md", include_str!("../samples/theme/src/long-title.md")),
+    ("src/short.md", include_str!("../samples/theme/src/short.md")),
+    (
+        "src/posts/first-post.md",
+        include_str!("../samples/theme/src/posts/first-post.md"),
+    ),
+];
+
+/// The name a theme cloned from `url` is installed as: the last segment of
+/// the URL without a `.git` suffix.
+pub fn name_from_url(url: &str) -> Option<String>
+{
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()?
+        .trim_end_matches(".git");
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+/// Clone the repository at `url` into `themes/<name>` in the current
+/// directory, and return the theme's path.
+///
+/// Only the latest commit is cloned and its `.git` directory is removed, so
+/// the theme's files can be committed along with the site.
+///
+/// # Errors
+///
+/// Will return an error if:
+///
+/// - No theme name was given and none can be made from `url`
+/// - The theme's directory already exists
+/// - `git` cannot be run, or the clone fails
+pub fn install(url: &str, name: Option<&str>) -> Result<PathBuf>
+{
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            name_from_url(url)
+                .ok_or_else(|| Error::ThemeInstall(format!("Couldn't make a theme name from \"{url}\", pass one")))?
+        }
+    };
+    let path = Path::new(Config::DEFAULT_THEMES_DIR).join(name);
+    if path.exists() {
+        return Err(Error::ThemeInstall(format!(
+            "\"{}\" already exists, remove it to reinstall the theme",
+            path.display()
+        )));
+    }
+
+    let mut command = Command::new("git");
+    command.arg("clone").arg("--depth").arg("1").arg(url).arg(&path);
+    let status = command
+        .status()
+        .map_err(|e| Error::ThemeInstall(format!("Couldn't run {command:?}: {e}")))?;
+    if !status.success() {
+        return Err(Error::ThemeInstall(format!("{command:?} failed: {status}")));
+    }
+
+    let git_dir = path.join(".git");
+    std::fs::remove_dir_all(&git_dir).map_err(|e| Error::Io { err: e, path: git_dir })?;
+    Ok(path)
+}
+
+/// Set `theme` in the configuration file at `config_path`, keeping the rest
+/// of the file as it is.
+///
+/// # Errors
+///
+/// Will return an error if the configuration file cannot be read or written.
+pub fn set_theme(config_path: &Path, theme: &Path) -> Result<()>
+{
+    let contents = std::fs::read_to_string(config_path).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: config_path.to_path_buf(),
+        }
+    })?;
+    std::fs::write(config_path, with_theme(&contents, theme)).map_err(|e| {
+        Error::Io {
+            err:  e,
+            path: config_path.to_path_buf(),
+        }
+    })
+}
+
+/// Write the sample project into the current directory, using the theme at
+/// `theme` if there is one, and return its configuration.
+///
+/// # Errors
+///
+/// Will return an error if a file cannot be written, or the configuration
+/// cannot be read back.
+pub fn write_sample(theme: Option<&Path>) -> Result<Config>
+{
+    let config = match theme {
+        Some(theme) => with_theme(SAMPLE_CONFIG, theme),
+        None => SAMPLE_CONFIG.to_string(),
+    };
+    let config_path = PathBuf::from(Config::DEFAULT_CONFIG_FILE);
+    write_file(&config_path, config.as_bytes())?;
+    for (path, contents) in SAMPLE_FILES {
+        write_file(Path::new(path), contents.as_bytes())?;
+    }
+    Config::from_toml(&config_path)
+}
+
+/// `config` with its top-level `theme` key replaced, or added before the
+/// first table if it has none.
+fn with_theme(config: &str, theme: &Path) -> String
+{
+    let line = format!(
+        "theme = {}",
+        toml::Value::String(theme.to_string_lossy().replace('\\', "/"))
+    );
+    let mut lines = config.lines().map(String::from).collect::<Vec<_>>();
+    let top_level = lines
+        .iter()
+        .position(|x| x.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..top_level].iter().position(|x| {
+        x.trim_start()
+            .strip_prefix("theme")
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    });
+    match existing {
+        Some(i) => lines[i] = line,
+        None => {
+            // Keep the blank line separating the top-level keys from the first
+            // table.
+            let mut i = top_level;
+            while i > 0 && lines[i - 1].trim().is_empty() {
+                i -= 1;
+            }
+            lines.insert(i, line);
+        }
+    }
+    let mut config = lines.join("\n");
+    config.push('\n');
+    config
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that the theme is added to, or replaced in, the top-level keys
+    fn test_with_theme()
+    {
+        assert_eq!(
+            name_from_url("https://github.com/user/raven-theme.git/").as_deref(),
+            Some("raven-theme")
+        );
+        assert_eq!(name_from_url("git@github.com:theme").as_deref(), Some("theme"));
+
+        let config = "source = \"src\"\n\n[default]\ntheme = \"x\"\n";
+        assert_eq!(
+            with_theme(config, Path::new("themes/a")),
+            "source = \"src\"\ntheme = \"themes/a\"\n\n[default]\ntheme = \"x\"\n"
+        );
+        let config = "# The theme\ntheme = \"themes/a\"\n";
+        assert_eq!(
+            with_theme(config, Path::new("themes/b")),
+            "# The theme\ntheme = \"themes/b\"\n"
+        );
+    }
+}
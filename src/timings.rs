@@ -0,0 +1,266 @@
+//! How long each stage of a build takes, per page and in total, with
+//! `raven build --timings`, to find slow pages and themes.
+//!
+//! Timings are only kept if `generation.timings` is set. Each stage is
+//! recorded in the build's [`Recorder`] against the page's source file while
+//! it's built, and [`Recorder::take`] collects them into the build's
+//! [`Timings`].
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{Path, PathBuf};
+
+/// A stage of building a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage
+{
+    /// Parsing the markdown into HTML, without highlighting.
+    Parse,
+
+    /// Highlighting code blocks.
+    Highlight,
+
+    /// Putting the page into its template.
+    Template,
+
+    /// Minifying the page, or a stylesheet or script.
+    Minify,
+
+    /// Writing the page to the dest dir.
+    Write,
+}
+
+impl Stage
+{
+    pub const ALL: [Self; 5] = [Self::Parse, Self::Highlight, Self::Template, Self::Minify, Self::Write];
+
+    fn name(self) -> &'static str
+    {
+        match self {
+            Self::Parse => "parse",
+            Self::Highlight => "highlight",
+            Self::Template => "template",
+            Self::Minify => "minify",
+            Self::Write => "write",
+        }
+    }
+}
+
+/// How long each stage took, in milliseconds.
+pub type StageTimings = BTreeMap<Stage, f64>;
+
+/// How long a page's stages took.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageTimings
+{
+    pub source:   PathBuf,
+    pub stages:   StageTimings,
+    pub total_ms: f64,
+}
+
+/// How long a build's stages took.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Timings
+{
+    /// Finding the source files.
+    pub walk_ms: f64,
+
+    /// Every page's stages, added up.
+    pub stages: StageTimings,
+
+    /// The pages, slowest first.
+    pub pages: Vec<PageTimings>,
+}
+
+/// The stages a build's pages took, as they're built.
+#[derive(Debug, Default)]
+pub struct Recorder
+{
+    recorded: Mutex<BTreeMap<PathBuf, BTreeMap<Stage, Duration>>>,
+    enabled:  bool,
+}
+
+impl Recorder
+{
+    /// A recorder that only keeps timings if `enabled`.
+    pub fn new(enabled: bool) -> Self
+    {
+        Self {
+            recorded: Mutex::default(),
+            enabled,
+        }
+    }
+
+    /// If timings are kept.
+    pub fn enabled(&self) -> bool { self.enabled }
+
+    /// Add `duration` to the `stage` of the page built from `source_file`.
+    pub fn record(&self, source_file: &Path, stage: Stage, duration: Duration)
+    {
+        if !self.enabled {
+            return;
+        }
+        *self
+            .recorded
+            .lock()
+            .unwrap()
+            .entry(source_file.to_path_buf())
+            .or_default()
+            .entry(stage)
+            .or_default() += duration;
+    }
+
+    /// Run `f`, recording how long it takes as the `stage` of the page built
+    /// from `source_file`.
+    pub fn time<T>(&self, source_file: &Path, stage: Stage, f: impl FnOnce() -> T) -> T
+    {
+        let started = Instant::now();
+        let result = f();
+        self.record(source_file, stage, started.elapsed());
+        result
+    }
+
+    /// The timings recorded since the last call, with `walk` as how long
+    /// finding the source files took.
+    pub fn take(&self, walk: Duration) -> Timings
+    {
+        let recorded = std::mem::take(&mut *self.recorded.lock().unwrap());
+        Timings::new(recorded, walk)
+    }
+}
+
+impl Timings
+{
+    /// The timings of the `recorded` stages of each page.
+    fn new(recorded: BTreeMap<PathBuf, BTreeMap<Stage, Duration>>, walk: Duration) -> Self
+    {
+        let mut timings = Self {
+            walk_ms: ms(walk),
+            ..Self::default()
+        };
+        for (source, mut stages) in recorded {
+            // Highlighting happens while parsing, so it's taken out of it.
+            if let (Some(highlight), Some(parse)) =
+                (stages.get(&Stage::Highlight).copied(), stages.get_mut(&Stage::Parse))
+            {
+                *parse = parse.saturating_sub(highlight);
+            }
+            let stages = stages.into_iter().map(|(stage, x)| (stage, ms(x))).collect();
+            timings.add_page(source, stages);
+        }
+        timings.sort();
+        timings
+    }
+
+    fn add_page(&mut self, source: PathBuf, stages: StageTimings)
+    {
+        for (stage, ms) in &stages {
+            *self.stages.entry(*stage).or_default() += ms;
+        }
+        self.pages.push(PageTimings {
+            source,
+            total_ms: stages.values().sum(),
+            stages,
+        });
+    }
+
+    fn sort(&mut self) { self.pages.sort_by(|a, b| b.total_ms.total_cmp(&a.total_ms)); }
+
+    /// Add the timings of `other`, a build of another copy of the site.
+    pub fn merge(&mut self, other: Self)
+    {
+        self.walk_ms += other.walk_ms;
+        for page in other.pages {
+            self.add_page(page.source, page.stages);
+        }
+        self.sort();
+    }
+
+    /// The timings as a table of the `limit` slowest pages, then the total
+    /// of each stage.
+    pub fn table(&self, limit: usize) -> String
+    {
+        let rows = self
+            .pages
+            .iter()
+            .take(limit)
+            .map(|page| (page.source.display().to_string(), &page.stages, page.total_ms))
+            .chain(std::iter::once((
+                String::from("Total"),
+                &self.stages,
+                self.stages.values().sum(),
+            )))
+            .collect::<Vec<_>>();
+        let width = rows
+            .iter()
+            .map(|(source, ..)| source.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut table = format!("{:width$}", "Page");
+        for stage in Stage::ALL {
+            write!(table, "  {:>10}", stage.name()).unwrap();
+        }
+        writeln!(table, "  {:>10}", "total").unwrap();
+        for (source, stages, total) in rows {
+            write!(table, "{source:width$}").unwrap();
+            for stage in Stage::ALL {
+                write!(table, "  {:>8.2}ms", stages.get(&stage).unwrap_or(&0.0)).unwrap();
+            }
+            writeln!(table, "  {total:>8.2}ms").unwrap();
+        }
+        if self.pages.len() > limit {
+            writeln!(table, "({} faster page(s) not shown)", self.pages.len() - limit).unwrap();
+        }
+        writeln!(table, "Finding the source files took {:.2}ms", self.walk_ms).unwrap();
+        table
+    }
+}
+
+fn ms(duration: Duration) -> f64 { duration.as_secs_f64() * 1000.0 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that highlighting is taken out of parsing, stages are added up,
+    /// and pages are slowest first
+    fn test_timings()
+    {
+        let recorder = Recorder::new(true);
+        recorder.record(Path::new("timings/a.md"), Stage::Parse, Duration::from_millis(5));
+        recorder.record(Path::new("timings/a.md"), Stage::Highlight, Duration::from_millis(3));
+        recorder.time(Path::new("timings/b.md"), Stage::Write, || {
+            std::thread::sleep(Duration::from_millis(10))
+        });
+        let disabled = Recorder::new(false);
+        disabled.record(Path::new("timings/c.md"), Stage::Write, Duration::from_millis(1));
+        assert!(disabled.take(Duration::ZERO).pages.is_empty());
+
+        let timings = recorder.take(Duration::from_millis(1));
+        assert!(recorder.take(Duration::ZERO).pages.is_empty());
+        assert_eq!(
+            timings
+                .pages
+                .iter()
+                .map(|x| x.source.to_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["timings/b.md", "timings/a.md"]
+        );
+        assert_eq!(timings.pages[1].stages[&Stage::Parse], 2.0);
+        assert_eq!(timings.pages[1].total_ms, 5.0);
+        let table = timings.table(1);
+        assert!(table.starts_with("Page          "));
+        assert!(table.contains("(1 faster page(s) not shown)"));
+    }
+}
@@ -0,0 +1,186 @@
+//! `{{ site.name }}`, `{{ page.title }}`, and the like, replaced in markdown
+//! pages before they're parsed with `generation.variables_in_markdown`, so
+//! prose can mention e.g. the current version without being edited for each
+//! release.
+//!
+//! `{{ site.extra.<name> }}` is a value of `[site.extra]` in the config, and
+//! `{{ page.extra.<name> }}` one of the page info's `[extra]`, with more `.`s
+//! for the values of tables in them. Values are put in as they're written, so
+//! they can have markdown in them. Anything else in `{{ }}` is left alone, as
+//! is everything in fenced code blocks, the `pageinfo` block among them, and
+//! inline code, so pages can show the variables themselves.
+
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+use toml::Value;
+
+use crate::{fences::Fences, placeholders::site_name, Config, Error, PageInfo, Path, Result};
+
+/// `source`, of the page at `source_file` with `page_info`, with its
+/// variables replaced.
+///
+/// # Errors
+///
+/// Will return an error if a variable has no value.
+pub fn substitute(source: &str, source_file: &Path, config: &Config, page_info: Option<&PageInfo>) -> Result<String>
+{
+    static VARIABLE: OnceLock<Regex> = OnceLock::new();
+    let variable = VARIABLE.get_or_init(|| Regex::new(r"\{\{\s*((?:site|page)\.[\w.-]+)\s*\}\}").unwrap());
+    let mut unknown = None;
+    let mut replace = |text: &str, output: &mut String| {
+        output.push_str(&variable.replace_all(text, |x: &Captures| {
+            value(&x[1], config, page_info).unwrap_or_else(|| {
+                unknown.get_or_insert_with(|| x[1].to_string());
+                x[0].to_string()
+            })
+        }));
+    };
+
+    let mut output = String::with_capacity(source.len());
+    let mut fences = Fences::default();
+    for line in source.split_inclusive('\n') {
+        if fences.contains(line) {
+            output.push_str(line);
+            continue;
+        }
+        let mut rest = line;
+        while let Some((text, code, after)) = code_span(rest) {
+            replace(text, &mut output);
+            output.push_str(code);
+            rest = after;
+        }
+        replace(rest, &mut output);
+    }
+    match unknown {
+        Some(variable) => {
+            Err(Error::UnknownVariable {
+                path: source_file.to_path_buf(),
+                variable,
+            })
+        }
+        None => Ok(output),
+    }
+}
+
+/// `line` split around its first inline code span: the text before it, the
+/// span, and the text after it.
+fn code_span(line: &str) -> Option<(&str, &str, &str)>
+{
+    let run = |at: usize| line[at..].len() - line[at..].trim_start_matches('`').len();
+    let mut search = 0;
+    while let Some(start) = line[search..].find('`').map(|x| x + search) {
+        let length = run(start);
+        let mut end = start + length;
+        // A span is closed by a run of as many backticks.
+        while let Some(close) = line[end..].find('`').map(|x| x + end) {
+            if run(close) == length {
+                return Some((&line[..start], &line[start..close + length], &line[close + length..]));
+            }
+            end = close + run(close);
+        }
+        search = start + length;
+    }
+    None
+}
+
+/// The value of `variable`, e.g. `site.name`, if it has one.
+fn value(variable: &str, config: &Config, page_info: Option<&PageInfo>) -> Option<String>
+{
+    let (scope, name) = variable.split_once('.')?;
+    if let Some(path) = name.strip_prefix("extra.") {
+        let extra = match scope {
+            "site" => config.site.as_ref()?.extra.as_ref(),
+            _ => page_info?.extra.as_ref(),
+        }?;
+        let mut keys = path.split('.');
+        let mut value = extra.get(keys.next()?)?;
+        for key in keys {
+            value = value.get(key)?;
+        }
+        return display(value);
+    }
+
+    match (scope, name) {
+        ("site", "name") => {
+            match page_info {
+                Some(page_info) => Some(site_name(config, page_info)),
+                None => config.default.meta.as_ref().map(|meta| meta.site_name.clone()),
+            }
+        }
+        ("page", "title") => Some(page_info?.title.clone()),
+        ("page", "description") => Some(page_info?.description.clone()),
+        ("page", "date") => page_info?.date.map(|x| x.to_string()),
+        ("page", "id") => page_info?.id.clone(),
+        ("page", "keywords") => page_info?.keywords.as_ref().map(|x| x.join(", ")),
+        ("page", "lang") => page_info?.lang.clone().or_else(|| config.default.lang.clone()),
+        _ => None,
+    }
+}
+
+/// `value` as it's put in a page, or `None` for a table.
+fn display(value: &Value) -> Option<String>
+{
+    match value {
+        Value::String(x) => Some(x.clone()),
+        Value::Array(x) => x.iter().map(display).collect::<Option<Vec<_>>>().map(|x| x.join(", ")),
+        Value::Table(_) => None,
+        x => Some(x.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that site and page values are put in, other `{{ }}` is left
+    /// alone, and a variable without a value is an error
+    fn test_substitute()
+    {
+        let mut config = Config::default();
+        config.default.meta = Some(crate::DefaultMeta {
+            site_name: String::from("Raven"),
+            authors:   Vec::new(),
+        });
+        config.site = Some(
+            toml::from_str("[extra]\nversion = \"1.4.2\"\nedition = 2021\nrepo = { url = \"https://example.com\" }")
+                .unwrap(),
+        );
+        let page_info =
+            PageInfo::parse("title = \"Install\"\ndescription = \"How\"\n[extra]\ntargets = [\"linux\", \"macos\"]")
+                .unwrap();
+
+        let source = "# {{ page.title }}\n\n{{site.name}} v{{ site.extra.version }} (edition {{ site.extra.edition \
+                      }}) from {{ site.extra.repo.url }} for {{ page.extra.targets }}, `{{ user.name }}`\n";
+        assert_eq!(
+            substitute(source, Path::new("page.md"), &config, Some(&page_info)).unwrap(),
+            "# Install\n\nRaven v1.4.2 (edition 2021) from https://example.com for linux, macos, `{{ user.name }}`\n"
+        );
+        for unknown in ["{{ site.extra.missing }}", "{{ site.extra.repo }}", "{{ page.slogan }}"] {
+            assert!(substitute(unknown, Path::new("page.md"), &config, Some(&page_info)).is_err());
+        }
+        assert!(substitute("{{ page.title }}", Path::new("page.md"), &config, None).is_err());
+    }
+
+    #[test]
+    /// Test that variables in code blocks, inline code, and the pageinfo block
+    /// are left as they are, even without values
+    fn test_substitute_code()
+    {
+        let config = Config::default();
+        let page_info = PageInfo::parse("title = \"Templates\"\ndescription = \"\"").unwrap();
+
+        let source = "```pageinfo\ntitle = \"{{ page.title }}\"\n```\n# {{ page.title }}\n\n````html\n<title>{{ \
+                      site.title }}</title>\n```\n{{ page.title }}\n````\n\nUse ``{{ site.foo }}`` or `{{ page.title \
+                      }}`, not {{ page.title }}.\n";
+        assert_eq!(
+            substitute(source, Path::new("page.md"), &config, Some(&page_info)).unwrap(),
+            "```pageinfo\ntitle = \"{{ page.title }}\"\n```\n# Templates\n\n````html\n<title>{{ site.title \
+             }}</title>\n```\n{{ page.title }}\n````\n\nUse ``{{ site.foo }}`` or `{{ page.title }}`, not Templates.\n"
+        );
+        assert_eq!(code_span("a `` b ` c `` d"), Some(("a ", "`` b ` c ``", " d")));
+        assert!(code_span("a ``b` c").is_none());
+    }
+}
@@ -0,0 +1,432 @@
+//! `raven watch`: build the site, then rebuild it whenever a file it's built
+//! from changes, printing notices about the edited pages.
+//!
+//! The files are polled, rather than watched through the OS, so it works the
+//! same on every platform and on network drives. After each rebuild, every
+//! edited page that was built gets concise notices of what's likely a
+//! mistake: links in it that were broken by the edit, images without alt
+//! text, and other source files written to the same place. With `--diff`
+//! it also gets a word diff of its text, to see what the edit changed without
+//! opening it.
+
+use std::{collections::BTreeMap, fmt, time::SystemTime};
+
+use regex::Regex;
+
+use crate::{
+    build::{build_with_report, dest_path, walk_sources, Website},
+    cancel::Cancellation,
+    headless::plain_text,
+    report::Warnings,
+    sass,
+    snapshot::changes,
+    Config, Error, Path, PathBuf, Result,
+};
+
+/// How often the files are checked for changes.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How many unchanged words are shown on each side of a change in a word
+/// diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// When each file a build reads was last modified.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot(BTreeMap<PathBuf, SystemTime>);
+
+impl Snapshot
+{
+    /// The source files, and the default template and stylesheet, with its
+    /// partials if it's a Sass one, as they are now.
+    pub fn take(config: &Config) -> Self
+    {
+        let stylesheet = config.resolve(&config.default.stylesheet);
+        let files = walk_sources(config, &Warnings::new(false))
+            .into_iter()
+            .map(|(source_file, _)| source_file)
+            .chain(sass::partials(&stylesheet))
+            .chain([config.resolve(&config.default.template), stylesheet]);
+        Self(
+            files
+                .filter_map(|path| {
+                    let modified = path.metadata().ok()?.modified().ok()?;
+                    Some((path, modified))
+                })
+                .collect(),
+        )
+    }
+
+    /// The files that were added, modified, or removed by the time of
+    /// `newer`.
+    pub fn changed(&self, newer: &Self) -> Vec<PathBuf>
+    {
+        let modified = newer.0.iter().filter(|(path, time)| self.0.get(*path) != Some(time));
+        let removed = self.0.keys().filter(|path| !newer.0.contains_key(*path));
+        modified.map(|(path, _)| path).chain(removed).cloned().collect()
+    }
+}
+
+/// Something in an edited page that's likely a mistake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notice
+{
+    /// A link to a file that isn't in the dest dir, which the edit added.
+    BrokenLink(String),
+
+    /// An image, by its `src`, without alt text.
+    MissingAltText(String),
+
+    /// Another source file is written to the same place.
+    SlugCollision
+    {
+        other: PathBuf, dest: PathBuf
+    },
+}
+
+impl fmt::Display for Notice
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::BrokenLink(link) => write!(f, "Broken link \"{link}\""),
+            Self::MissingAltText(image) => write!(f, "Image \"{image}\" has no alt text"),
+            Self::SlugCollision { other, dest } => {
+                write!(f, "Written to \"{}\", like \"{}\"", dest.display(), other.display())
+            }
+        }
+    }
+}
+
+/// Build the site, then rebuild it whenever a file it's built from changes,
+/// until `cancellation` is cancelled, e.g. on Ctrl-C. A build that fails is
+/// reported, and the next change is waited for. With `diff` the word diff of
+/// each edited page is printed too.
+///
+/// # Errors
+///
+/// Will return an error if the site cannot be set up to build.
+pub async fn watch(config: Config, cancellation: Cancellation, diff: bool) -> Result<()>
+{
+    let mut snapshot = Snapshot::take(&config);
+    rebuild(&config, &cancellation, &[], diff).await?;
+    println!("Watching for changes, press Ctrl-C to stop");
+    while !cancellation.is_cancelled() {
+        tokio::task::spawn_blocking(|| std::thread::sleep(POLL_INTERVAL))
+            .await
+            .map_err(|_| Error::AysncJoin)?;
+        let newer = Snapshot::take(&config);
+        let changed = snapshot.changed(&newer);
+        snapshot = newer;
+        if !changed.is_empty() && !cancellation.is_cancelled() {
+            rebuild(&config, &cancellation, &changed, diff).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Build the site incrementally, then print the notices about the pages of
+/// `changed`, and their word diffs with `diff`.
+async fn rebuild(config: &Config, cancellation: &Cancellation, changed: &[PathBuf], diff: bool) -> Result<()>
+{
+    let source_files = walk_sources(config, &Warnings::new(false));
+    let pages = changed
+        .iter()
+        .filter_map(|source_file| {
+            let (_, extention) = source_files.iter().find(|(x, _)| x == source_file)?;
+            let dest_file = dest_path(config, source_file, extention).ok()?;
+            let before = std::fs::read_to_string(&dest_file).ok();
+            Some((source_file, extention, dest_file, before))
+        })
+        .collect::<Vec<_>>();
+
+    let mut site = Website::from_config(config.clone())?;
+    site.set_cancellation(cancellation.clone());
+    match build_with_report(site, false).await {
+        Ok(report) => {
+            println!(
+                "Built {} page(s), skipped {}, copied {}, {} failed, in {:.0}ms",
+                report.built, report.skipped, report.copied, report.failed, report.duration_ms
+            );
+        }
+        Err(Error::Cancelled) => return Ok(()),
+        Err(e) => e.report(),
+    }
+
+    for (source_file, extention, dest_file, before) in pages {
+        let Ok(after) = std::fs::read_to_string(&dest_file)
+        else {
+            continue;
+        };
+        let mut notices = notices(&config.dest, &dest_file, before.as_deref(), &after);
+        notices.extend(slug_collisions(config, source_file, extention, &source_files));
+        for notice in notices {
+            println!("  \"{}\": {notice}", source_file.display());
+        }
+        if let Some(words) = before.filter(|_| diff).and_then(|before| word_diff(&before, &after)) {
+            println!("  \"{}\": {words}", source_file.display());
+        }
+    }
+    Ok(())
+}
+
+/// The notices about the page written to `dest_file` in `dest_dir`, whose
+/// HTML went from `before` to `after`.
+pub fn notices(dest_dir: &Path, dest_file: &Path, before: Option<&str>, after: &str) -> Vec<Notice>
+{
+    let was_broken = before.map(|x| broken_links(dest_dir, dest_file, x)).unwrap_or_default();
+    let mut notices = broken_links(dest_dir, dest_file, after)
+        .into_iter()
+        .filter(|x| !was_broken.contains(x))
+        .map(Notice::BrokenLink)
+        .collect::<Vec<_>>();
+    let images = Regex::new(r"<img\b[^>]*>").unwrap();
+    let alt = Regex::new(r#"\balt\s*=\s*"\s*[^"\s]"#).unwrap();
+    let src = Regex::new(r#"\bsrc\s*=\s*"([^"]*)""#).unwrap();
+    notices.extend(
+        images
+            .find_iter(after)
+            .filter(|image| !alt.is_match(image.as_str()))
+            .map(|image| {
+                let src = src.captures(image.as_str()).map_or("", |x| x.get(1).unwrap().as_str());
+                Notice::MissingAltText(src.to_string())
+            }),
+    );
+    notices
+}
+
+/// A word diff of the text of a page whose HTML went from `before` to
+/// `after`: removed words `[-like this-]` and added ones `{+like this+}`, with
+/// a few words on each side of them. `None` if the text didn't change.
+pub fn word_diff(before: &str, after: &str) -> Option<String>
+{
+    let (before, after) = (plain_text(before), plain_text(after));
+    let old = before.split_whitespace().collect::<Vec<_>>();
+    let new = after.split_whitespace().collect::<Vec<_>>();
+    let words = changes(&old, &new);
+    let changed = words.iter().map(|(change, _)| *change != ' ').collect::<Vec<_>>();
+    if !changed.contains(&true) {
+        return None;
+    }
+
+    // The words shown, with consecutive ones of the same change together.
+    let mut runs: Vec<(char, String)> = Vec::new();
+    let mut last_shown = None;
+    for (i, (change, word)) in words.iter().enumerate() {
+        let near_change = changed[i.saturating_sub(DIFF_CONTEXT)..(i + DIFF_CONTEXT + 1).min(words.len())]
+            .iter()
+            .any(|x| *x);
+        if !near_change {
+            continue;
+        }
+        if last_shown.map_or(i > 0, |x: usize| x + 1 != i) {
+            runs.push((' ', String::from("...")));
+        }
+        match runs.last_mut() {
+            Some((last, run)) if last == change && last_shown == Some(i.wrapping_sub(1)) => {
+                run.push(' ');
+                run.push_str(word);
+            }
+            _ => runs.push((*change, word.to_string())),
+        }
+        last_shown = Some(i);
+    }
+    if last_shown.is_some_and(|x| x + 1 != words.len()) {
+        runs.push((' ', String::from("...")));
+    }
+    let runs = runs.into_iter().map(|(change, run)| {
+        match change {
+            '-' => format!("[-{run}-]"),
+            '+' => format!("{{+{run}+}}"),
+            _ => run,
+        }
+    });
+    Some(runs.collect::<Vec<_>>().join(" "))
+}
+
+/// The local links in `html`, the page written to `dest_file`, to files that
+/// aren't in `dest_dir`.
+fn broken_links(dest_dir: &Path, dest_file: &Path, html: &str) -> Vec<String>
+{
+    let links = Regex::new(r#"\b(?:href|src)\s*=\s*"([^"]*)""#).unwrap();
+    let mut broken = links
+        .captures_iter(html)
+        .map(|x| x.get(1).unwrap().as_str().replace("&amp;", "&"))
+        .filter(|link| {
+            let is_local = !(link.is_empty()
+                || link.contains("://")
+                || link.starts_with("//")
+                || link.starts_with('#')
+                || ["mailto:", "tel:", "data:", "javascript:"]
+                    .iter()
+                    .any(|x| link.starts_with(x)));
+            let path = link.split(['#', '?']).next().unwrap_or_default();
+            if !is_local || path.is_empty() {
+                return false;
+            }
+            let target = match path.strip_prefix('/') {
+                Some(path) => dest_dir.join(path),
+                None => dest_file.parent().unwrap_or(dest_dir).join(path),
+            };
+            !target.is_file() && !target.join("index.html").is_file()
+        })
+        .collect::<Vec<_>>();
+    broken.dedup();
+    broken
+}
+
+/// The other files of `source_files` written to the same place as
+/// `source_file`.
+fn slug_collisions(
+    config: &Config,
+    source_file: &Path,
+    extention: &str,
+    source_files: &[(PathBuf, String)],
+) -> Vec<Notice>
+{
+    let Ok(dest) = dest_path(config, source_file, extention)
+    else {
+        return Vec::new();
+    };
+    source_files
+        .iter()
+        .filter(|(other, extention)| {
+            other != source_file && dest_path(config, other, extention).is_ok_and(|x| x == dest)
+        })
+        .map(|(other, _)| {
+            Notice::SlugCollision {
+                other: other.clone(),
+                dest:  dest.clone(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    /// Test that added, modified, and removed files are changed, and the
+    /// others aren't
+    fn test_changed()
+    {
+        let then = SystemTime::UNIX_EPOCH;
+        let later = then + std::time::Duration::from_secs(1);
+        let older = Snapshot(BTreeMap::from([
+            (PathBuf::from("same.md"), then),
+            (PathBuf::from("edited.md"), then),
+            (PathBuf::from("removed.md"), then),
+        ]));
+        let newer = Snapshot(BTreeMap::from([
+            (PathBuf::from("same.md"), then),
+            (PathBuf::from("edited.md"), later),
+            (PathBuf::from("added.md"), later),
+        ]));
+        assert_eq!(
+            older.changed(&newer),
+            [
+                PathBuf::from("added.md"),
+                PathBuf::from("edited.md"),
+                PathBuf::from("removed.md")
+            ]
+        );
+        assert!(newer.changed(&newer).is_empty());
+    }
+
+    #[test]
+    /// Test that only the links an edit broke are noticed, and local links to
+    /// files or directories that exist aren't
+    fn test_broken_links()
+    {
+        let dest = std::env::temp_dir().join("rustic-raven-tests/watch-links");
+        std::fs::create_dir_all(dest.join("blog/docs")).unwrap();
+        std::fs::write(dest.join("index.html"), "").unwrap();
+        std::fs::write(dest.join("blog/docs/index.html"), "").unwrap();
+        let page = dest.join("blog/post.html");
+        let before = "<a href=\"old.html\">";
+        let after = "<a href=\"old.html\"> <a href=\"/index.html#top\"> <a href=\"docs/?q=1\"> <a \
+                     href=\"https://example.com/x\"> <a href=\"#top\"> <a href=\"mailto:a@example.com\"> <a \
+                     href=\"../missing.html\">";
+        assert_eq!(
+            notices(&dest, &page, Some(before), after),
+            [Notice::BrokenLink(String::from("../missing.html"))]
+        );
+        std::fs::remove_dir_all(dest).unwrap();
+    }
+
+    #[test]
+    /// Test that images without alt text are noticed
+    fn test_missing_alt_text()
+    {
+        let dest = std::env::temp_dir().join("rustic-raven-tests/watch-alt");
+        let after = "<img src=\"https://example.com/a.png\" alt=\"A\"><img src=\"https://example.com/b.png\" alt=\"\"> \
+                     <img alt=\" \" src=\"https://example.com/c.png\"/>";
+        assert_eq!(
+            notices(&dest, &dest.join("post.html"), None, after),
+            [
+                Notice::MissingAltText(String::from("https://example.com/b.png")),
+                Notice::MissingAltText(String::from("https://example.com/c.png"))
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that a word diff shows the changed words with a few on each side
+    fn test_word_diff()
+    {
+        let before = "<h1>Fish</h1><p>The quick brown fox jumps over the lazy dog, twice a day.</p>";
+        let after = "<h1>Fish</h1><p>The quick red fox jumps over the lazy dog, twice a day.</p>";
+        assert_eq!(
+            word_diff(before, after).unwrap(),
+            "Fish The quick [-brown-] {+red+} fox jumps over ..."
+        );
+    }
+
+    #[test]
+    /// Test that consecutive changed words are shown together, and changes
+    /// far apart are shown separately
+    fn test_word_diff_runs()
+    {
+        let before = "<p>one two three four five six seven eight nine ten eleven twelve</p>";
+        let after = "<p>one 2 3 four five six seven eight nine ten eleven 12</p>";
+        assert_eq!(
+            word_diff(before, after).unwrap(),
+            "one [-two three-] {+2 3+} four five six ... nine ten eleven [-twelve-] {+12+}"
+        );
+    }
+
+    #[test]
+    /// Test that a page whose text didn't change has no word diff
+    fn test_word_diff_unchanged()
+    {
+        assert_eq!(
+            word_diff("<p>Same <em>text</em></p>", "<p>Same <strong>text</strong></p>"),
+            None
+        );
+    }
+
+    #[test]
+    /// Test that another source file written to the same place is noticed
+    fn test_slug_collisions()
+    {
+        let config = Config {
+            source: PathBuf::from("src"),
+            dest: PathBuf::from("dest"),
+            ..Config::default()
+        };
+        let source_files = [
+            (PathBuf::from("src/post.md"), String::from("md")),
+            (PathBuf::from("src/post.html"), String::from("html")),
+            (PathBuf::from("src/other.md"), String::from("md")),
+        ];
+        assert_eq!(
+            slug_collisions(&config, Path::new("src/post.md"), "md", &source_files),
+            [Notice::SlugCollision {
+                other: PathBuf::from("src/post.html"),
+                dest:  PathBuf::from("dest/post.html"),
+            }]
+        );
+        assert!(slug_collisions(&config, Path::new("src/other.md"), "md", &source_files).is_empty());
+    }
+}
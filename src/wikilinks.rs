@@ -0,0 +1,193 @@
+//! Wiki-style `[[Page Name]]` links, resolved to the page with that title or
+//! file name when `markdown.wiki_links` is set.
+//!
+//! `[[Other Page]]` becomes a link to the page titled "Other Page", or whose
+//! file is `other-page.md`, relative to the page it's on. Names are compared
+//! slugified, so case, spaces, and dashes don't matter. `[[Other Page|the
+//! text]]` changes the link's text, and `[[Other Page#Some Heading]]` links
+//! to a heading on it. A link that matches no page, or more than one, is
+//! warned about and left as it is. Links in fenced code blocks are left
+//! alone.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    build::dest_path,
+    fences::Fences,
+    graph::DependencyGraph,
+    links::relative_url,
+    markdown::slugify,
+    report::{Warning, Warnings},
+    Config, Path, PathBuf, Result,
+};
+
+const OPEN: &str = "[[";
+const CLOSE: &str = "]]";
+
+/// The pages of a project, by their slugified titles and file names.
+#[derive(Debug, Clone, Default)]
+pub struct WikiIndex
+{
+    names: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl WikiIndex
+{
+    /// Index the pages of the configured source directory. Pages that cannot
+    /// be read are left out, their build fails on its own.
+    pub fn from_config(config: &Config) -> Self
+    {
+        let mut index = Self::default();
+        if let Ok(graph) = DependencyGraph::from_config(config) {
+            index.extend(&graph);
+        }
+        index
+    }
+
+    /// Add the markdown pages of `graph`.
+    pub fn extend(&mut self, graph: &DependencyGraph)
+    {
+        for (source_file, page) in &graph.pages {
+            let markdown = source_file
+                .extension()
+                .is_some_and(|x| x.eq_ignore_ascii_case("md") || x.eq_ignore_ascii_case("markdown"));
+            if !markdown {
+                continue;
+            }
+            let stem = source_file.file_stem().map(|x| x.to_string_lossy().to_string());
+            let mut names = page
+                .title
+                .iter()
+                .chain(stem.iter())
+                .map(|x| slugify(x))
+                .collect::<Vec<_>>();
+            names.dedup();
+            for name in names {
+                self.names.entry(name).or_default().push(source_file.clone());
+            }
+        }
+    }
+
+    /// Replace every `[[Page Name]]` in the markdown `source` of the page at
+    /// `source_path` with a markdown link to the page. Links to no page, or to
+    /// more than one, are added to `warnings` and left as they are.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the dest path of a page cannot be worked out.
+    pub fn resolve_links(
+        &self,
+        source: &str,
+        source_path: &Path,
+        config: &Config,
+        warnings: &Warnings,
+    ) -> Result<String>
+    {
+        if !source.contains(OPEN) {
+            return Ok(source.to_string());
+        }
+
+        let dest_of = |source_file: &Path| {
+            let extention = source_file
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            dest_path(config, source_file, &extention)
+        };
+        let from = dest_of(source_path)?;
+        let mut output = String::with_capacity(source.len());
+        let mut fences = Fences::default();
+        for line in source.split_inclusive('\n') {
+            if fences.contains(line) {
+                output.push_str(line);
+                continue;
+            }
+
+            let mut rest = line;
+            while let Some(start) = rest.find(OPEN) {
+                let Some(len) = rest[start..].find(CLOSE)
+                else {
+                    break;
+                };
+                let link = &rest[start + OPEN.len()..start + len];
+                let (target, text) = link.split_once('|').unwrap_or((link, link));
+                let (name, heading) = target.split_once('#').unwrap_or((target, ""));
+                output.push_str(&rest[..start]);
+                match self.names.get(&slugify(name)).map(Vec::as_slice) {
+                    Some([page]) => {
+                        let mut url = relative_url(&from, &dest_of(page)?);
+                        if !heading.is_empty() {
+                            url.push('#');
+                            url.push_str(&slugify(heading));
+                        }
+                        output.push_str(&format!("[{}]({url})", text.trim()));
+                    }
+                    found => {
+                        let source_file = source_path.to_path_buf();
+                        let target = name.trim().to_string();
+                        warnings.warn(match found {
+                            Some(pages) => {
+                                Warning::AmbiguousWikiLink {
+                                    source_file,
+                                    target,
+                                    pages: pages.len(),
+                                }
+                            }
+                            None => Warning::UnresolvedWikiLink { source_file, target },
+                        });
+                        output.push_str(&rest[start..start + len + CLOSE.len()]);
+                    }
+                }
+                rest = &rest[start + len + CLOSE.len()..];
+            }
+            output.push_str(rest);
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::graph::PageDependencies;
+
+    #[test]
+    /// Test that links resolve by title or file name, with their text and
+    /// heading, and that unknown and ambiguous ones are left alone
+    fn test_resolve_links()
+    {
+        let mut graph = DependencyGraph::default();
+        let page = |title: &str| {
+            PageDependencies {
+                title: Some(title.to_string()),
+                ..PageDependencies::default()
+            }
+        };
+        graph
+            .pages
+            .insert(PathBuf::from("src/garden/rust-notes.md"), page("Rust"));
+        graph.pages.insert(PathBuf::from("src/a.md"), page("Same"));
+        graph.pages.insert(PathBuf::from("src/b.md"), page("Same"));
+        let mut index = WikiIndex::default();
+        index.extend(&graph);
+
+        let config = Config::default();
+        let warnings = Warnings::new(false);
+        let source = "See [[Rust Notes]], [[rust|the notes]] and [[Rust#Error Handling]].\n```\n[[Rust]]\n```\n";
+        assert_eq!(
+            index
+                .resolve_links(source, Path::new("src/index.md"), &config, &warnings)
+                .unwrap(),
+            "See [Rust Notes](garden/rust-notes.html), [the notes](garden/rust-notes.html) and [Rust#Error \
+             Handling](garden/rust-notes.html#error-handling).\n```\n[[Rust]]\n```\n"
+        );
+        assert_eq!(
+            index
+                .resolve_links("[[Same]] [[Missing]]", Path::new("src/index.md"), &config, &warnings)
+                .unwrap(),
+            "[[Same]] [[Missing]]"
+        );
+        assert_eq!(warnings.take().len(), 2);
+    }
+}
@@ -0,0 +1,138 @@
+//! Builds every project in `examples/` and checks the result.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use rustic_raven::{
+    build::{build, Website},
+    check::check,
+    starters::STARTERS,
+    theme::SAMPLE_FILES,
+    Config,
+};
+use walkdir::WalkDir;
+
+fn examples() -> Vec<PathBuf>
+{
+    let mut examples = std::fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("examples"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    examples.sort();
+    examples
+}
+
+/// The files in `dir`, relative to it.
+fn files(dir: &Path) -> BTreeSet<PathBuf>
+{
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.path().strip_prefix(dir).unwrap().to_path_buf())
+        .collect()
+}
+
+/// Every local `href` and `src` in `html`, without fragments or queries.
+fn local_links(html: &str) -> Vec<String>
+{
+    let mut links = Vec::new();
+    for attribute in ["href=\"", "src=\""] {
+        for (start, _) in html.match_indices(attribute) {
+            let value = &html[start + attribute.len()..];
+            let value = &value[..value.find('"').unwrap()];
+            let value = value.split(['#', '?']).next().unwrap();
+            if !(value.is_empty() || value.contains(':') || value.starts_with("//")) {
+                links.push(value.to_string());
+            }
+        }
+    }
+    links
+}
+
+#[tokio::test]
+/// Test that every example, and the theme sample, checks cleanly, builds a
+/// page for every source, and has no broken local links
+async fn test_examples()
+{
+    let theme_sample = Path::new(env!("CARGO_MANIFEST_DIR")).join("samples/theme");
+    for example in examples().into_iter().chain([theme_sample]) {
+        let name = example.file_name().unwrap().to_string_lossy().to_string();
+        let dir = std::env::temp_dir().join(format!("rustic_raven_example_{name}"));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        for file in files(&example).iter().filter(|path| !path.starts_with("dest")) {
+            std::fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            std::fs::copy(example.join(file), dir.join(file)).unwrap();
+        }
+        std::env::set_current_dir(&dir).unwrap();
+
+        let config = Config::from_toml(&PathBuf::from(Config::DEFAULT_CONFIG_FILE)).unwrap();
+        let problems = check(config.clone()).await;
+        assert!(problems.is_empty(), "{name}: {problems:?}");
+        build(Website::from_config(config.clone()).unwrap(), true)
+            .await
+            .unwrap();
+
+        for source in files(&config.source) {
+            let page = config.dest.join(source.with_extension("html"));
+            assert!(page.is_file(), "{name}: \"{}\" wasn't built", page.display());
+        }
+        for page in files(&config.dest) {
+            let html = std::fs::read_to_string(config.dest.join(&page)).unwrap();
+            for link in local_links(&html) {
+                let target = config.dest.join(page.parent().unwrap()).join(&link);
+                assert!(
+                    target.exists(),
+                    "{name}: \"{}\" links to \"{link}\", which doesn't exist",
+                    page.display()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+/// Test that every example is a bundled starter with the same files, so the
+/// starters can't drift from what's tested
+fn test_starters_match_examples()
+{
+    for example in examples() {
+        let name = example.file_name().unwrap().to_string_lossy().to_string();
+        let starter = STARTERS
+            .iter()
+            .find(|x| x.name == name)
+            .unwrap_or_else(|| panic!("\"{name}\" isn't a bundled starter"));
+
+        let mut expected = files(&example)
+            .into_iter()
+            .filter(|path| !path.starts_with("dest"))
+            .collect::<BTreeSet<_>>();
+        assert!(
+            expected.remove(Path::new(Config::DEFAULT_CONFIG_FILE)) == starter.config.is_some(),
+            "{name}: the starter's config doesn't match the example's raven.toml"
+        );
+        let bundled = starter.files.iter().map(|(path, _)| PathBuf::from(path)).collect();
+        assert_eq!(
+            expected, bundled,
+            "{name}: the starter's files don't match the example's"
+        );
+    }
+}
+
+#[test]
+/// Test that `raven theme dev` bundles every page of the theme sample
+fn test_theme_sample_is_bundled()
+{
+    let sample = Path::new(env!("CARGO_MANIFEST_DIR")).join("samples/theme");
+    let expected = files(&sample.join("src"))
+        .into_iter()
+        .map(|path| Path::new("src").join(path))
+        .collect::<BTreeSet<_>>();
+    let bundled = SAMPLE_FILES.iter().map(|(path, _)| PathBuf::from(path)).collect();
+    assert_eq!(expected, bundled);
+}